@@ -0,0 +1,2 @@
+-- Pinned downloads are exempt from cleanup and quota eviction.
+ALTER TABLE Download ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,8 @@
+-- URLs stashed for later, kept apart from the download queue.
+CREATE TABLE IF NOT EXISTS
+    SavedUrl (
+        id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+        url TEXT UNIQUE NOT NULL,
+        note TEXT NOT NULL DEFAULT '',
+        tags TEXT NOT NULL DEFAULT '[]'
+    );
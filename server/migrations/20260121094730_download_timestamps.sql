@@ -0,0 +1,4 @@
+-- Lifecycle timestamps maintained by the download manager, stored as RFC 3339 UTC.
+ALTER TABLE Download ADD COLUMN created_at DATETIME;
+ALTER TABLE Download ADD COLUMN started_at DATETIME;
+ALTER TABLE Download ADD COLUMN finished_at DATETIME;
@@ -0,0 +1,10 @@
+-- Latest progress snapshot per download, flushed in batches by the progress writer.
+CREATE TABLE IF NOT EXISTS
+    DownloadProgress (
+        url TEXT PRIMARY KEY NOT NULL,
+        percent TEXT NOT NULL,
+        size_downloaded TEXT NOT NULL,
+        speed TEXT NOT NULL,
+        eta TEXT NOT NULL,
+        updated_at DATETIME NOT NULL
+    );
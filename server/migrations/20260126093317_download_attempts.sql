@@ -0,0 +1,3 @@
+-- Retry bookkeeping, restored with the rest of the download on boot.
+ALTER TABLE Download ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE Download ADD COLUMN last_error TEXT;
@@ -0,0 +1,51 @@
+-- Give downloads a stable numeric id for keyset pagination, urls stay unique.
+CREATE TABLE
+    Download_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+        url TEXT UNIQUE NOT NULL,
+        status TEXT NOT NULL,
+        container TEXT NOT NULL,
+        name_format TEXT NOT NULL,
+        quality TEXT NOT NULL,
+        pinned BOOLEAN NOT NULL DEFAULT false,
+        created_at DATETIME,
+        started_at DATETIME,
+        finished_at DATETIME,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT
+    );
+
+INSERT INTO
+    Download_new (
+        url,
+        status,
+        container,
+        name_format,
+        quality,
+        pinned,
+        created_at,
+        started_at,
+        finished_at,
+        attempts,
+        last_error
+    )
+SELECT
+    url,
+    status,
+    container,
+    name_format,
+    quality,
+    pinned,
+    created_at,
+    started_at,
+    finished_at,
+    attempts,
+    last_error
+FROM
+    Download
+ORDER BY
+    created_at;
+
+DROP TABLE Download;
+
+ALTER TABLE Download_new RENAME TO Download;
@@ -0,0 +1,2 @@
+-- Where yt-dlp wrote the finished file, as reported in its output.
+ALTER TABLE Download ADD COLUMN file_path TEXT;
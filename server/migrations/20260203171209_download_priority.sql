@@ -0,0 +1,2 @@
+-- Queued downloads with a higher priority are started first.
+ALTER TABLE Download ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,14 @@
+-- One row per yt-dlp run, so a download's retries can be looked back on.
+CREATE TABLE IF NOT EXISTS
+    DownloadAttempt (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        download_id INTEGER NOT NULL,
+        attempt INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT,
+        transient BOOLEAN NOT NULL DEFAULT FALSE,
+        started_at DATETIME NOT NULL,
+        finished_at DATETIME NOT NULL
+    );
+
+CREATE INDEX IF NOT EXISTS DownloadAttemptDownloadId ON DownloadAttempt (download_id);
@@ -0,0 +1,2 @@
+-- Downloads submitted with a start time wait as Scheduled until then.
+ALTER TABLE Download ADD COLUMN start_at DATETIME;
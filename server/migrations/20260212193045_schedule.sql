@@ -0,0 +1,14 @@
+-- Recurring downloads of a url, usually a playlist or channel, on a cron expression.
+CREATE TABLE IF NOT EXISTS
+    Schedule (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        url TEXT NOT NULL,
+        cron TEXT NOT NULL,
+        container TEXT NOT NULL,
+        name_format TEXT NOT NULL,
+        quality TEXT NOT NULL,
+        priority INTEGER NOT NULL DEFAULT 0,
+        enabled BOOLEAN NOT NULL DEFAULT TRUE,
+        created_at DATETIME NOT NULL,
+        last_run_at DATETIME
+    );
@@ -0,0 +1,4 @@
+-- Per-download yt-dlp rate limits, with an instance wide default in Config.
+ALTER TABLE Config ADD COLUMN rate_limit TEXT;
+ALTER TABLE Download ADD COLUMN rate_limit TEXT;
+ALTER TABLE Schedule ADD COLUMN rate_limit TEXT;
@@ -0,0 +1,13 @@
+-- What ffprobe found in each completed file.
+CREATE TABLE IF NOT EXISTS
+    DownloadMedia (
+        download_id INTEGER PRIMARY KEY NOT NULL,
+        container TEXT,
+        video_codec TEXT,
+        audio_codec TEXT,
+        width INTEGER,
+        height INTEGER,
+        duration_secs REAL,
+        bitrate INTEGER,
+        probed_at DATETIME NOT NULL
+    );
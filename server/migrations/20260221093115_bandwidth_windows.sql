@@ -0,0 +1,2 @@
+-- A JSON list of time windows with their own rate limits, see core::bandwidth.
+ALTER TABLE Config ADD COLUMN bandwidth_windows TEXT;
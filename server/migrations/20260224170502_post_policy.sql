@@ -0,0 +1,10 @@
+-- Rules run against the media info of completed downloads, see core::policy.
+CREATE TABLE IF NOT EXISTS
+    PostPolicy (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        video_codec TEXT,
+        container TEXT,
+        action TEXT NOT NULL,
+        target TEXT NOT NULL,
+        created_at DATETIME NOT NULL
+    );
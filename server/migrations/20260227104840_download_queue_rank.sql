@@ -0,0 +1,2 @@
+-- A download's place among those with the same priority, set by reordering the queue.
+ALTER TABLE Download ADD COLUMN queue_rank INTEGER;
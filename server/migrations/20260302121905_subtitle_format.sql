@@ -0,0 +1,3 @@
+-- Subtitle conversion as a per-download post-processing option.
+ALTER TABLE Download ADD COLUMN subtitle_format TEXT;
+ALTER TABLE Schedule ADD COLUMN subtitle_format TEXT;
@@ -0,0 +1,3 @@
+-- Splitting audio extractions into per-chapter tracks.
+ALTER TABLE Download ADD COLUMN split_chapters BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE Schedule ADD COLUMN split_chapters BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Whether interrupted downloads resume at startup, NULL defers to the environment.
+ALTER TABLE Config ADD COLUMN auto_resume BOOLEAN;
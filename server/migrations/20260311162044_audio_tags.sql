@@ -0,0 +1,5 @@
+-- Audio extraction with a per-download tag template, stored as a JSON object.
+ALTER TABLE Download ADD COLUMN audio_format TEXT;
+ALTER TABLE Download ADD COLUMN tag_template TEXT;
+ALTER TABLE Schedule ADD COLUMN audio_format TEXT;
+ALTER TABLE Schedule ADD COLUMN tag_template TEXT;
@@ -0,0 +1,3 @@
+-- The extractor and video id a download's url resolved to, so other links to it are rejected.
+ALTER TABLE Download ADD COLUMN video_id TEXT;
+CREATE INDEX IF NOT EXISTS download_video_id ON Download (video_id);
@@ -0,0 +1,5 @@
+-- How repeated videos are handled, and the frame hashes used to spot copies under other ids.
+ALTER TABLE Config ADD COLUMN duplicate_policy TEXT NOT NULL DEFAULT 'skip';
+ALTER TABLE Config ADD COLUMN perceptual_hash BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE Download ADD COLUMN content_hash INTEGER;
+ALTER TABLE Download ADD COLUMN duplicate_of INTEGER;
@@ -0,0 +1,4 @@
+-- Skips videos already recorded in yt-dlp's download archive, globally or per download and schedule.
+ALTER TABLE Config ADD COLUMN download_archive BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE Download ADD COLUMN download_archive BOOLEAN;
+ALTER TABLE Schedule ADD COLUMN download_archive BOOLEAN;
@@ -0,0 +1,3 @@
+-- When a download gave up for good, backfilled from the finish time of those already failed.
+ALTER TABLE Download ADD COLUMN failed_at DATETIME;
+UPDATE Download SET failed_at = finished_at WHERE status = 'Failed';
@@ -0,0 +1,2 @@
+-- Whether YouTube Shorts and clip links are rewritten to watch urls, kept or rejected.
+ALTER TABLE Config ADD COLUMN short_form_policy TEXT NOT NULL DEFAULT 'rewrite';
@@ -0,0 +1,10 @@
+-- User agent, referer and extra headers passed to yt-dlp, see core::headers.
+CREATE TABLE IF NOT EXISTS
+    HeaderRule (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        domain TEXT,
+        user_agent TEXT,
+        referer TEXT,
+        headers TEXT NOT NULL DEFAULT '{}',
+        created_at DATETIME NOT NULL
+    );
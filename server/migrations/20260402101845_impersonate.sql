@@ -0,0 +1,2 @@
+-- The browser yt-dlp impersonates through curl_cffi, unset sends its own fingerprint.
+ALTER TABLE Config ADD COLUMN impersonate TEXT;
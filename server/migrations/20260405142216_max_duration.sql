@@ -0,0 +1,3 @@
+-- How long a download may run before it's stopped as TimedOut.
+ALTER TABLE Download ADD COLUMN max_duration_secs INTEGER;
+ALTER TABLE Schedule ADD COLUMN max_duration_secs INTEGER;
@@ -0,0 +1,3 @@
+-- Pages through finished downloads by status, see core::history.
+CREATE INDEX IF NOT EXISTS download_status_finished_at ON Download (status, finished_at);
+CREATE INDEX IF NOT EXISTS download_status_created_at ON Download (status, created_at);
@@ -0,0 +1,2 @@
+-- A JSON list of domains with the hours downloads from them may start in, see core::windows.
+ALTER TABLE Config ADD COLUMN download_windows TEXT;
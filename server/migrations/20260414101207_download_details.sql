@@ -0,0 +1,6 @@
+-- What yt-dlp said about a download's video when it was enqueued, see formats::VideoDetails.
+ALTER TABLE Download ADD COLUMN title TEXT;
+ALTER TABLE Download ADD COLUMN uploader TEXT;
+ALTER TABLE Download ADD COLUMN duration_secs REAL;
+ALTER TABLE Download ADD COLUMN upload_date TEXT;
+ALTER TABLE Download ADD COLUMN thumbnail_url TEXT;
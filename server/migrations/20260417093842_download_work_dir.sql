@@ -0,0 +1,2 @@
+-- The directory a download keeps its fragments and intermediate files in, see Download::work_dir.
+ALTER TABLE Download ADD COLUMN work_dir TEXT;
@@ -0,0 +1,17 @@
+-- Labels downloads are filed under, see core::tagging.
+CREATE TABLE IF NOT EXISTS
+    Tag (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        subdirectory TEXT,
+        created_at DATETIME NOT NULL
+    );
+
+CREATE TABLE IF NOT EXISTS
+    DownloadTag (
+        download_id INTEGER NOT NULL,
+        tag_id INTEGER NOT NULL REFERENCES Tag (id) ON DELETE CASCADE,
+        PRIMARY KEY (download_id, tag_id)
+    );
+
+CREATE INDEX IF NOT EXISTS DownloadTagTagId ON DownloadTag (tag_id);
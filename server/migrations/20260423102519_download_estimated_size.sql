@@ -0,0 +1,2 @@
+-- How much space a download is expected to take, see core::storage.
+ALTER TABLE Download ADD COLUMN estimated_size REAL;
@@ -0,0 +1,15 @@
+-- Hardlinks and symlinks made to completed downloads in other library folders, see core::links.
+ALTER TABLE Download ADD COLUMN library_links TEXT;
+ALTER TABLE Schedule ADD COLUMN library_links TEXT;
+
+CREATE TABLE IF NOT EXISTS
+    DownloadLink (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        download_id INTEGER NOT NULL,
+        path TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        created_at DATETIME NOT NULL,
+        UNIQUE (download_id, path)
+    );
+
+CREATE INDEX IF NOT EXISTS DownloadLinkDownloadId ON DownloadLink (download_id);
@@ -0,0 +1,4 @@
+-- Limits on how much finished download history is kept, unset keeps everything.
+ALTER TABLE Config ADD COLUMN history_max_age_days INTEGER;
+ALTER TABLE Config ADD COLUMN history_max_rows INTEGER;
+ALTER TABLE Config ADD COLUMN history_prune_files BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Why the download last failed, sorted from last_error, see core::retry::FailureCause.
+ALTER TABLE Download ADD COLUMN failure_cause TEXT;
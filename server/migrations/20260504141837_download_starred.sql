@@ -0,0 +1,2 @@
+-- Downloads the user marked as favorites, kept through history pruning.
+ALTER TABLE Download ADD COLUMN starred BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,12 @@
+-- One row per background job run, so a job that keeps failing can be spotted and looked into.
+CREATE TABLE IF NOT EXISTS
+    JobRun (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        job TEXT NOT NULL,
+        succeeded BOOLEAN NOT NULL,
+        error TEXT,
+        started_at DATETIME NOT NULL,
+        finished_at DATETIME NOT NULL
+    );
+
+CREATE INDEX IF NOT EXISTS JobRunJobStartedAt ON JobRun (job, started_at);
@@ -0,0 +1,3 @@
+-- The stored yt-dlp config file a download or schedule runs with, by name.
+ALTER TABLE Download ADD COLUMN ytdlp_config TEXT;
+ALTER TABLE Schedule ADD COLUMN ytdlp_config TEXT;
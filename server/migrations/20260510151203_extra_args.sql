@@ -0,0 +1,3 @@
+-- Extra yt-dlp flags a download or schedule runs with, as a json array.
+ALTER TABLE Download ADD COLUMN extra_args TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE Schedule ADD COLUMN extra_args TEXT NOT NULL DEFAULT '[]';
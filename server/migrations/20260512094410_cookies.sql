@@ -0,0 +1,3 @@
+-- The stored cookies.txt file a download or schedule runs with, by name.
+ALTER TABLE Download ADD COLUMN cookies TEXT;
+ALTER TABLE Schedule ADD COLUMN cookies TEXT;
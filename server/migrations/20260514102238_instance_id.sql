@@ -0,0 +1,2 @@
+-- Tells this instance apart from others in the stats it exports, made up on first startup.
+ALTER TABLE Config ADD COLUMN instance_id TEXT;
@@ -0,0 +1,2 @@
+-- Passed to yt-dlp's --cookies-from-browser, e.g. firefox or chrome:Profile 1.
+ALTER TABLE Config ADD COLUMN cookies_from_browser TEXT;
@@ -0,0 +1,4 @@
+-- Passed to yt-dlp's --proxy, globally from Config and per download or schedule.
+ALTER TABLE Config ADD COLUMN proxy_url TEXT;
+ALTER TABLE Download ADD COLUMN proxy TEXT;
+ALTER TABLE Schedule ADD COLUMN proxy TEXT;
@@ -0,0 +1,4 @@
+-- Passed to yt-dlp's --xff, globally from Config and per download or schedule.
+ALTER TABLE Config ADD COLUMN geo_bypass_country TEXT;
+ALTER TABLE Download ADD COLUMN geo_bypass_country TEXT;
+ALTER TABLE Schedule ADD COLUMN geo_bypass_country TEXT;
@@ -0,0 +1,8 @@
+-- Lets the downloads list filter by creation time, file size and domain through indexes. Hosts
+-- are kept with their labels reversed, e.g. com.youtube.www, so a domain and its subdomains are
+-- one range of the index.
+ALTER TABLE Download ADD COLUMN file_size INTEGER;
+ALTER TABLE Download ADD COLUMN reversed_host TEXT;
+CREATE INDEX IF NOT EXISTS download_created_at ON Download (created_at);
+CREATE INDEX IF NOT EXISTS download_file_size ON Download (file_size);
+CREATE INDEX IF NOT EXISTS download_reversed_host ON Download (reversed_host);
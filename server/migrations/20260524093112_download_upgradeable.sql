@@ -0,0 +1,3 @@
+-- Whether the last upgrade check found a better format than the download's, kept so the
+-- downloads list can filter on it across restarts.
+ALTER TABLE Download ADD COLUMN upgradeable BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,103 @@
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use super::state::AppState;
+use crate::core::backup::{self, RestoreError};
+use crate::core::messages::Message;
+use crate::error::ApiError;
+use crate::Database;
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/backup", get(get_backup))
+        // Backups grow with the download history, well past the default limit.
+        .route(
+            "/restore",
+            post(restore_backup).layer(DefaultBodyLimit::disable()),
+        )
+}
+
+// <----- Functions ----->
+
+/// Streams a consistent copy of the database, taken while downloads keep running.
+async fn get_backup(State(db): State<Database>) -> Result<impl IntoResponse, ApiError> {
+    let snapshot = db
+        .path
+        .with_file_name(format!(".backup-{}.db", Uuid::new_v4()));
+    backup::snapshot(&db.write, &snapshot)
+        .await
+        .map_err(ApiError::internal)?;
+    let file = tokio::fs::File::open(&snapshot).await;
+    // The open file keeps the snapshot readable, unlinking it now means nothing is left over if
+    // the client goes away halfway.
+    let _ = tokio::fs::remove_file(&snapshot).await;
+    let file = file.map_err(ApiError::internal)?;
+
+    let disposition = format!(
+        "attachment; filename=\"vscraper-{}.db\"",
+        Utc::now().format("%Y%m%d%H%M%S")
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    ))
+}
+
+/// Takes a backup made by [`get_backup`] as the request body and stages it to replace the
+/// database on the next start. The database in use is moved aside then, not deleted.
+async fn restore_backup(
+    State(db): State<Database>,
+    body: Body,
+) -> Result<(StatusCode, Json<Message>), ApiError> {
+    let upload = db
+        .path
+        .with_file_name(format!(".upload-{}.db", Uuid::new_v4()));
+    let result = match save_upload(body, &upload).await {
+        Ok(()) => backup::stage(&db.path, &upload).await,
+        Err(err) => Err(RestoreError::Io(err)),
+    };
+    let _ = tokio::fs::remove_file(&upload).await;
+
+    match result {
+        Ok(()) => Ok((StatusCode::ACCEPTED, Json(Message::new("restore.staged")))),
+        Err(RestoreError::NotDatabase) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("restore.not_database"),
+        )),
+        Err(RestoreError::Corrupt(error)) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("restore.corrupt").with("error", error),
+        )),
+        Err(RestoreError::NewerSchema(version)) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("restore.newer_schema").with("version", version),
+        )),
+        Err(RestoreError::Io(err)) => Err(ApiError::internal(err)),
+    }
+}
+
+async fn save_upload(body: Body, path: &std::path::Path) -> std::io::Result<()> {
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        file.write_all(&chunk.map_err(std::io::Error::other)?)
+            .await?;
+    }
+    file.flush().await
+}
@@ -0,0 +1,43 @@
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+
+use super::state::AppState;
+use crate::core::archive;
+use crate::core::messages::Message;
+use crate::core::ytdlp::YtdlpClient;
+use crate::error::ApiError;
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/", get(get_archive).put(set_archive))
+}
+
+// <----- Functions ----->
+
+/// The videos yt-dlp has recorded as downloaded, one `extractor id` entry each.
+async fn get_archive(
+    State(ytdlp_client): State<YtdlpClient>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    archive::load(ytdlp_client.download_archive_path())
+        .await
+        .map(Json)
+        .map_err(ApiError::internal)
+}
+
+/// Replaces the archive, e.g. to drop an entry so its video is fetched again.
+async fn set_archive(
+    State(ytdlp_client): State<YtdlpClient>,
+    Json(entries): Json<Vec<String>>,
+) -> Result<StatusCode, ApiError> {
+    if let Some(entry) = entries.iter().find(|entry| !archive::is_valid_entry(entry)) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("archive.invalid_entry").with("entry", entry),
+        ));
+    }
+
+    archive::save(ytdlp_client.download_archive_path(), &entries)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(StatusCode::OK)
+}
@@ -1,30 +1,153 @@
+use crate::Database;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
     routing::{get, post},
     Json, Router,
 };
-use serde::Serialize;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::SqlitePool;
+use tracing::error;
+
+use super::state::AppState;
+use super::ytdlp;
+use crate::core::bandwidth::BandwidthWindow;
+use crate::core::canonical::ShortFormPolicy;
+use crate::core::clock::{self, LocalTime};
+use crate::core::cookies::{self, BrowserCheck};
+use crate::core::duplicates::{DuplicatePolicy, DuplicateSettings};
+use crate::core::events::Event;
+use crate::core::headers;
+use crate::core::impersonate;
+use crate::core::messages::Message;
+use crate::core::retention::RetentionSettings;
+use crate::core::windows::DownloadWindow;
+use crate::error::ApiError;
 
 #[derive(Clone, Debug, Serialize)]
 struct Config {
     id: Option<i64>,
     skip_homepage: Option<bool>,
+    /// Resumes interrupted downloads at startup, unset falls back to `RESUME_INTERRUPTED`.
+    auto_resume: Option<bool>,
+    rate_limit: Option<String>,
+    bandwidth_windows: Option<sqlx::types::Json<Vec<BandwidthWindow>>>,
+    download_windows: Option<sqlx::types::Json<Vec<DownloadWindow>>>,
+    duplicate_policy: DuplicatePolicy,
+    perceptual_hash: bool,
+    /// Whether downloads without their own preference skip videos already in the download archive.
+    download_archive: bool,
+    short_form_policy: ShortFormPolicy,
+    /// Passed to `--impersonate`, e.g. `chrome` or `safari-15.5:macos-14`.
+    impersonate: Option<String>,
+    /// Passed to `--cookies-from-browser`, e.g. `firefox` or `chrome:Profile 1`.
+    cookies_from_browser: Option<String>,
+    /// Passed to `--proxy` for downloads without their own, e.g. `socks5://10.0.0.2:1080`.
+    proxy_url: Option<String>,
+    /// Passed to `--xff` for downloads without their own, e.g. `US`.
+    geo_bypass_country: Option<String>,
+    history_max_age_days: Option<i64>,
+    history_max_rows: Option<i64>,
+    history_prune_files: bool,
+}
+
+#[derive(Deserialize)]
+struct RateLimitRequest {
+    rate_limit: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProxyRequest {
+    proxy_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GeoBypassRequest {
+    country: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ImpersonateRequest {
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BrowserCookiesRequest {
+    browser: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ImpersonateTargets {
+    targets: Vec<String>,
 }
 
-pub fn routes(db: SqlitePool) -> Router {
+#[derive(Deserialize)]
+struct BandwidthRequest {
+    windows: Vec<BandwidthWindow>,
+}
+
+#[derive(Deserialize)]
+struct DownloadWindowsRequest {
+    windows: Vec<DownloadWindow>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct InstanceTime {
+    timezone: String,
+    now: LocalTime,
+}
+
+pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/", get(get_config))
+        .route("/archive/{preference}", post(set_download_archive))
+        .route("/auto-resume/{preference}", post(set_auto_resume))
+        .route("/bandwidth", post(set_bandwidth_windows))
+        .route(
+            "/cookies-from-browser",
+            get(check_browser_cookies).post(set_browser_cookies),
+        )
+        .route("/download-windows", post(set_download_windows))
+        .route("/duplicates", post(set_duplicate_settings))
+        .route("/geo-bypass", post(set_geo_bypass))
         .route("/homepage/{preference}", post(set_skip_homepage))
-        .with_state(db)
+        .route(
+            "/impersonate",
+            get(get_impersonate_targets).post(set_impersonate),
+        )
+        .route("/proxy", post(set_proxy))
+        .route("/rate-limit", post(set_rate_limit))
+        .route("/retention", post(set_retention))
+        .route("/short-form/{policy}", post(set_short_form_policy))
+        .route("/time", get(get_time))
 }
 
-async fn get_config(State(db): State<SqlitePool>) -> Result<Json<Value>, StatusCode> {
-    let cfg = sqlx::query_as!(Config, "SELECT * FROM Config WHERE id = 1")
-        .fetch_one(&db)
-        .await;
+async fn get_config(State(app_state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let cfg = sqlx::query_as!(
+        Config,
+        r#"SELECT
+            id,
+            skip_homepage,
+            auto_resume,
+            rate_limit,
+            bandwidth_windows as "bandwidth_windows: sqlx::types::Json<Vec<BandwidthWindow>>",
+            download_windows as "download_windows: sqlx::types::Json<Vec<DownloadWindow>>",
+            duplicate_policy as "duplicate_policy: DuplicatePolicy",
+            perceptual_hash,
+            download_archive,
+            short_form_policy as "short_form_policy: ShortFormPolicy",
+            impersonate,
+            cookies_from_browser,
+            proxy_url,
+            geo_bypass_country,
+            history_max_age_days,
+            history_max_rows,
+            history_prune_files
+        FROM Config WHERE id = 1"#
+    )
+    .fetch_one(&app_state.db.read)
+    .await;
 
     match cfg {
         Ok(cfg) => Ok(Json(serde_json::json!(cfg))),
@@ -32,8 +155,34 @@ async fn get_config(State(db): State<SqlitePool>) -> Result<Json<Value>, StatusC
     }
 }
 
+/// Whether downloads interrupted by a crash or restart should resume at startup, if it's been set.
+pub async fn auto_resume(db: &Database) -> Option<bool> {
+    sqlx::query_scalar!("SELECT auto_resume FROM Config WHERE id = 1")
+        .fetch_one(&db.read)
+        .await
+        .unwrap_or_else(|err| {
+            error!("failed to read auto_resume from config: {}", err);
+            None
+        })
+}
+
+/// The browsers the installed yt-dlp can impersonate, empty when it can't.
+async fn get_impersonate_targets(State(app_state): State<AppState>) -> Json<ImpersonateTargets> {
+    Json(ImpersonateTargets {
+        targets: app_state.ytdlp_client.impersonate_targets().to_vec(),
+    })
+}
+
+/// The instance timezone and the current time in it, so clients can show schedules as the server reads them.
+async fn get_time() -> Json<InstanceTime> {
+    Json(InstanceTime {
+        timezone: clock::timezone().name().to_string(),
+        now: LocalTime::from(Utc::now()),
+    })
+}
+
 async fn set_skip_homepage(
-    State(db): State<SqlitePool>,
+    State(app_state): State<AppState>,
     Path(preference): Path<bool>,
 ) -> Result<StatusCode, StatusCode> {
     let status = sqlx::query_as!(
@@ -41,14 +190,339 @@ async fn set_skip_homepage(
         "UPDATE Config SET skip_homepage = $1 WHERE id=1",
         preference
     )
-    .execute(&db)
+    .execute(&app_state.db.write)
     .await;
 
     match status {
         Ok(result) => match result.rows_affected() {
-            1 => Ok(StatusCode::OK),
+            1 => {
+                send_config_event(&app_state, "skip_homepage", Value::Bool(preference));
+                Ok(StatusCode::OK)
+            }
             _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
         },
         Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
+
+/// Sets whether interrupted downloads resume at the next startup.
+async fn set_auto_resume(
+    State(app_state): State<AppState>,
+    Path(preference): Path<bool>,
+) -> Result<StatusCode, ApiError> {
+    sqlx::query!(
+        "UPDATE Config SET auto_resume = $1 WHERE id = 1",
+        preference
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    send_config_event(&app_state, "auto_resume", Value::Bool(preference));
+    Ok(StatusCode::OK)
+}
+
+/// Sets whether downloads that don't say otherwise use the download archive.
+async fn set_download_archive(
+    State(app_state): State<AppState>,
+    Path(preference): Path<bool>,
+) -> Result<StatusCode, ApiError> {
+    sqlx::query!(
+        "UPDATE Config SET download_archive = $1 WHERE id = 1",
+        preference
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    send_config_event(&app_state, "download_archive", Value::Bool(preference));
+    Ok(StatusCode::OK)
+}
+
+/// Replaces the bandwidth windows, restarting running downloads whose limit changed.
+async fn set_bandwidth_windows(
+    State(app_state): State<AppState>,
+    Json(request): Json<BandwidthRequest>,
+) -> Result<StatusCode, ApiError> {
+    for window in &request.windows {
+        ytdlp::check_rate_limit(window.rate_limit.as_deref())?;
+    }
+
+    let windows = sqlx::types::Json(request.windows);
+    sqlx::query!(
+        "UPDATE Config SET bandwidth_windows = $1 WHERE id = 1",
+        windows
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = serde_json::to_value(&windows.0).unwrap_or(Value::Null);
+    send_config_event(&app_state, "bandwidth_windows", value);
+    app_state.ytdlp_client.apply_rate_limit().await;
+    Ok(StatusCode::OK)
+}
+
+/// Replaces the download windows. Queued downloads they now let through start straight away.
+async fn set_download_windows(
+    State(app_state): State<AppState>,
+    Json(mut request): Json<DownloadWindowsRequest>,
+) -> Result<StatusCode, ApiError> {
+    for window in &mut request.windows {
+        window.domain = window.domain.to_lowercase();
+        if !headers::is_valid_domain(&window.domain) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("windows.invalid_domain").with("domain", &window.domain),
+            ));
+        }
+    }
+
+    let windows = sqlx::types::Json(request.windows);
+    sqlx::query!(
+        "UPDATE Config SET download_windows = $1 WHERE id = 1",
+        windows
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = serde_json::to_value(&windows.0).unwrap_or(Value::Null);
+    send_config_event(&app_state, "download_windows", value);
+    app_state.ytdlp_client.reload_windows().await;
+    Ok(StatusCode::OK)
+}
+
+/// Sets how urls leading to a video the manager already has are handled.
+async fn set_duplicate_settings(
+    State(app_state): State<AppState>,
+    Json(settings): Json<DuplicateSettings>,
+) -> Result<StatusCode, ApiError> {
+    sqlx::query!(
+        "UPDATE Config SET duplicate_policy = $1, perceptual_hash = $2 WHERE id = 1",
+        settings.policy,
+        settings.perceptual_hash
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = serde_json::to_value(settings).unwrap_or(Value::Null);
+    send_config_event(&app_state, "duplicates", value);
+    Ok(StatusCode::OK)
+}
+
+/// Checks that the configured browser's cookies can be read, `null` if none is configured.
+async fn check_browser_cookies(
+    State(app_state): State<AppState>,
+) -> Result<Json<Option<BrowserCheck>>, ApiError> {
+    let browser = sqlx::query_scalar!("SELECT cookies_from_browser FROM Config WHERE id = 1")
+        .fetch_one(&app_state.db.read)
+        .await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(
+        browser
+            .as_deref()
+            .and_then(cookies::parse_browser_spec)
+            .map(|spec| cookies::check_browser(&spec, &home_dir())),
+    ))
+}
+
+/// Sets the browser yt-dlp takes cookies from, `null` stops it. Refused when the browser's
+/// cookies can't be read, since yt-dlp would fail every download over it.
+async fn set_browser_cookies(
+    State(app_state): State<AppState>,
+    Json(request): Json<BrowserCookiesRequest>,
+) -> Result<Json<Option<BrowserCheck>>, ApiError> {
+    let check = match &request.browser {
+        Some(browser) => {
+            let spec = cookies::parse_browser_spec(browser).ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    Message::new("cookies.unknown_browser")
+                        .with("browser", browser)
+                        .with("browsers", cookies::BROWSERS.join(", ")),
+                )
+            })?;
+            let check = cookies::check_browser(&spec, &home_dir());
+            if let Some(error) = check.error {
+                return Err(ApiError::new(StatusCode::BAD_REQUEST, error));
+            }
+            Some(check)
+        }
+        None => None,
+    };
+
+    sqlx::query!(
+        "UPDATE Config SET cookies_from_browser = $1 WHERE id = 1",
+        request.browser
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = request.browser.map_or(Value::Null, Value::String);
+    send_config_event(&app_state, "cookies_from_browser", value);
+    Ok(Json(check))
+}
+
+/// Where yt-dlp looks for browser profiles, the home of the user the server runs as.
+fn home_dir() -> std::path::PathBuf {
+    std::env::var_os("HOME").unwrap_or_default().into()
+}
+
+/// Sets the browser yt-dlp impersonates, `null` goes back to its own fingerprint.
+async fn set_impersonate(
+    State(app_state): State<AppState>,
+    Json(request): Json<ImpersonateRequest>,
+) -> Result<StatusCode, ApiError> {
+    if let Some(target) = &request.target {
+        let targets = app_state.ytdlp_client.impersonate_targets();
+        if targets.is_empty() {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("impersonate.unsupported"),
+            ));
+        }
+        if !impersonate::supports(targets, target) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("impersonate.unknown_target")
+                    .with("target", target)
+                    .with("targets", targets.join(", ")),
+            ));
+        }
+    }
+
+    sqlx::query!(
+        "UPDATE Config SET impersonate = $1 WHERE id = 1",
+        request.target
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = request.target.map_or(Value::Null, Value::String);
+    send_config_event(&app_state, "impersonate", value);
+    Ok(StatusCode::OK)
+}
+
+/// Sets the country yt-dlp fakes for downloads that don't ask for their own, `null` leaves it to
+/// yt-dlp.
+async fn set_geo_bypass(
+    State(app_state): State<AppState>,
+    Json(request): Json<GeoBypassRequest>,
+) -> Result<StatusCode, ApiError> {
+    let country = request.country.filter(|country| !country.is_empty());
+    ytdlp::check_geo_bypass_country(country.as_deref())?;
+
+    sqlx::query!(
+        "UPDATE Config SET geo_bypass_country = $1 WHERE id = 1",
+        country
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = country.map_or(Value::Null, Value::String);
+    send_config_event(&app_state, "geo_bypass_country", value);
+    Ok(StatusCode::OK)
+}
+
+/// Sets the proxy for downloads that don't ask for their own, `null` removes it. Applies from
+/// the next run of yt-dlp, running downloads keep their connection.
+async fn set_proxy(
+    State(app_state): State<AppState>,
+    Json(request): Json<ProxyRequest>,
+) -> Result<StatusCode, ApiError> {
+    let proxy_url = request.proxy_url.filter(|proxy_url| !proxy_url.is_empty());
+    ytdlp::check_proxy(proxy_url.as_deref())?;
+
+    sqlx::query!("UPDATE Config SET proxy_url = $1 WHERE id = 1", proxy_url)
+        .execute(&app_state.db.write)
+        .await
+        .map_err(ApiError::internal)?;
+
+    let value = proxy_url.map_or(Value::Null, Value::String);
+    send_config_event(&app_state, "proxy_url", value);
+    Ok(StatusCode::OK)
+}
+
+/// Sets the rate limit for downloads that don't ask for their own, `null` removes it.
+async fn set_rate_limit(
+    State(app_state): State<AppState>,
+    Json(request): Json<RateLimitRequest>,
+) -> Result<StatusCode, ApiError> {
+    ytdlp::check_rate_limit(request.rate_limit.as_deref())?;
+
+    sqlx::query!(
+        "UPDATE Config SET rate_limit = $1 WHERE id = 1",
+        request.rate_limit
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = request.rate_limit.map_or(Value::Null, Value::String);
+    send_config_event(&app_state, "rate_limit", value);
+    app_state.ytdlp_client.apply_rate_limit().await;
+    Ok(StatusCode::OK)
+}
+
+/// Sets how much of the history is kept, pruning what's past the new limits straight away.
+async fn set_retention(
+    State(app_state): State<AppState>,
+    Json(settings): Json<RetentionSettings>,
+) -> Result<StatusCode, ApiError> {
+    if let Some(limit) = settings.invalid_limit() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("retention.invalid_limit").with("limit", limit),
+        ));
+    }
+    sqlx::query!(
+        r#"UPDATE Config SET
+            history_max_age_days = $1,
+            history_max_rows = $2,
+            history_prune_files = $3
+        WHERE id = 1"#,
+        settings.max_age_days,
+        settings.max_rows,
+        settings.remove_files
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = serde_json::to_value(settings).unwrap_or(Value::Null);
+    send_config_event(&app_state, "retention", value);
+    let ytdlp_client = app_state.ytdlp_client.clone();
+    tokio::spawn(async move { ytdlp_client.prune_history().await });
+    Ok(StatusCode::OK)
+}
+
+/// Sets whether Shorts and clip links are rewritten to watch urls, kept or rejected.
+async fn set_short_form_policy(
+    State(app_state): State<AppState>,
+    Path(policy): Path<ShortFormPolicy>,
+) -> Result<StatusCode, ApiError> {
+    sqlx::query!(
+        "UPDATE Config SET short_form_policy = $1 WHERE id = 1",
+        policy
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let value = serde_json::to_value(policy).unwrap_or(Value::Null);
+    send_config_event(&app_state, "short_form_policy", value);
+    Ok(StatusCode::OK)
+}
+
+fn send_config_event(app_state: &AppState, key: &str, value: Value) {
+    app_state.events.publish(Event::Config {
+        key: key.to_string(),
+        value,
+    });
+}
@@ -0,0 +1,82 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, put},
+    Json, Router,
+};
+
+use super::state::AppState;
+use crate::core::cookies::{self, CookieError, CookieFile};
+use crate::core::messages::Message;
+use crate::core::ytdlp::YtdlpClient;
+use crate::error::ApiError;
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_cookie_files))
+        .route("/{name}", put(save_cookie_file).delete(delete_cookie_file))
+}
+
+// <----- Functions ----->
+
+async fn get_cookie_files(
+    State(ytdlp_client): State<YtdlpClient>,
+) -> Result<Json<Vec<CookieFile>>, ApiError> {
+    cookies::list(ytdlp_client.cookies_dir())
+        .await
+        .map(Json)
+        .map_err(ApiError::internal)
+}
+
+/// Uploads a cookies.txt file as the request body, replacing one with the same name. There's no
+/// way to read it back, since it holds logins.
+async fn save_cookie_file(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(name): Path<String>,
+    contents: String,
+) -> Result<StatusCode, ApiError> {
+    cookie_path(&ytdlp_client, &name)?;
+    match cookies::save(ytdlp_client.cookies_dir(), &name, &contents).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(CookieError::InvalidLine(line)) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("cookies.invalid_line").with("line", line),
+        )),
+        Err(CookieError::Empty) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("cookies.empty"),
+        )),
+        Err(CookieError::Io(err)) => Err(ApiError::internal(err)),
+    }
+}
+
+async fn delete_cookie_file(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let path = cookie_path(&ytdlp_client, &name)?;
+    match tokio::fs::remove_file(&path).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            Message::new("cookies.unknown").with("name", &name),
+        )),
+        Err(err) => Err(ApiError::internal(err)),
+    }
+}
+
+fn cookie_path(ytdlp_client: &YtdlpClient, name: &str) -> Result<std::path::PathBuf, ApiError> {
+    match cookies::is_valid_name(name) {
+        true => Ok(cookies::path(ytdlp_client.cookies_dir(), name)),
+        false => Err(invalid_name(name)),
+    }
+}
+
+pub fn invalid_name(name: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::BAD_REQUEST,
+        Message::new("cookies.invalid_name").with("name", name),
+    )
+}
@@ -0,0 +1,88 @@
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::info;
+use url::Url;
+
+use super::state::AppState;
+use super::ytdlp;
+use crate::core::ytdlp::{YtdlpClient, SYNTHETIC_HOST};
+
+/// Synthetic downloads a single request may create.
+const MAX_SYNTHETIC: usize = 10_000;
+
+// <----- Requests ----->
+
+#[derive(Deserialize)]
+struct SyntheticRequest {
+    count: usize,
+    #[serde(default = "default_steps")]
+    steps: u32,
+    #[serde(default = "default_step_ms")]
+    step_ms: u64,
+}
+
+#[derive(Serialize)]
+struct SyntheticResult {
+    created: usize,
+}
+
+#[derive(Serialize)]
+struct ClearResult {
+    removed: usize,
+}
+
+fn default_steps() -> u32 {
+    20
+}
+
+fn default_step_ms() -> u64 {
+    500
+}
+
+// <----- Routes ----->
+
+/// Load testing helpers, only mounted when DEBUG_ENDPOINTS is set.
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/synthetic", post(create_synthetic).delete(clear_synthetic))
+}
+
+// <----- Functions ----->
+
+/// Starts `count` made up downloads that go through the queue, progress events and the db
+/// like real ones, without running yt-dlp.
+async fn create_synthetic(
+    State(app_state): State<AppState>,
+    Json(request): Json<SyntheticRequest>,
+) -> (StatusCode, Json<SyntheticResult>) {
+    let count = request.count.min(MAX_SYNTHETIC);
+    let run = Utc::now().timestamp_millis();
+    let step_interval = Duration::from_millis(request.step_ms.max(1));
+
+    for n in 0..count {
+        let url = Url::parse(&format!("https://{}/{}/{}", SYNTHETIC_HOST, run, n))
+            .expect("synthetic urls are always valid");
+        ytdlp::spawn_synthetic(app_state.clone(), url, request.steps, step_interval);
+    }
+    info!("started {} synthetic downloads", count);
+
+    (
+        StatusCode::ACCEPTED,
+        Json(SyntheticResult { created: count }),
+    )
+}
+
+/// Forgets every synthetic download that has finished.
+async fn clear_synthetic(State(ytdlp_client): State<YtdlpClient>) -> Json<ClearResult> {
+    let mut removed = 0;
+    for download in ytdlp_client.get_downloads().await {
+        if download.url.host_str() == Some(SYNTHETIC_HOST)
+            && ytdlp_client.remove_download(&download.url).await.is_ok()
+        {
+            removed += 1;
+        }
+    }
+
+    Json(ClearResult { removed })
+}
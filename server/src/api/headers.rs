@@ -0,0 +1,123 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use super::state::AppState;
+use crate::core::headers::{self, HeaderRule};
+use crate::core::messages::Message;
+use crate::error::ApiError;
+use crate::Database;
+
+// <----- Requests ----->
+
+#[derive(Deserialize)]
+struct HeaderRuleRequest {
+    /// Unset applies the rule to every url.
+    domain: Option<String>,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_header_rules).post(create_header_rule))
+        .route("/{id}", delete(delete_header_rule))
+}
+
+// <----- Functions ----->
+
+/// Adds a rule, used by every later yt-dlp run for the urls it matches.
+async fn create_header_rule(
+    State(db): State<Database>,
+    Json(request): Json<HeaderRuleRequest>,
+) -> Result<(StatusCode, Json<HeaderRule>), ApiError> {
+    let domain = request.domain.map(|domain| domain.to_lowercase());
+    if let Some(domain) = domain
+        .as_deref()
+        .filter(|domain| !headers::is_valid_domain(domain))
+    {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("headers.invalid_domain").with("domain", domain),
+        ));
+    }
+    let invalid_header = request
+        .headers
+        .keys()
+        .any(|name| !headers::is_valid_name(name))
+        || request
+            .user_agent
+            .iter()
+            .chain(&request.referer)
+            .chain(request.headers.values())
+            .any(|value| !headers::is_valid_value(value));
+    if invalid_header {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("headers.invalid_header"),
+        ));
+    }
+
+    let headers = sqlx::types::Json(request.headers);
+    let created_at = Utc::now();
+    let id = sqlx::query!(
+        r#"INSERT INTO HeaderRule (domain, user_agent, referer, headers, created_at)
+        VALUES ($1, $2, $3, $4, $5)"#,
+        domain,
+        request.user_agent,
+        request.referer,
+        headers,
+        created_at
+    )
+    .execute(&db.write)
+    .await
+    .map_err(ApiError::internal)?
+    .last_insert_rowid();
+
+    Ok((
+        StatusCode::CREATED,
+        Json(HeaderRule {
+            id,
+            domain,
+            user_agent: request.user_agent,
+            referer: request.referer,
+            headers,
+            created_at,
+        }),
+    ))
+}
+
+async fn delete_header_rule(
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let result = sqlx::query!("DELETE FROM HeaderRule WHERE id = $1", id)
+        .execute(&db.write)
+        .await
+        .map_err(ApiError::internal)?;
+
+    match result.rows_affected() {
+        0 => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            Message::new("headers.unknown"),
+        )),
+        _ => Ok(StatusCode::OK),
+    }
+}
+
+async fn get_header_rules(State(db): State<Database>) -> Result<Json<Vec<HeaderRule>>, ApiError> {
+    headers::load(&db.read)
+        .await
+        .map(Json)
+        .map_err(ApiError::internal)
+}
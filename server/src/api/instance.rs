@@ -0,0 +1,22 @@
+use axum::{extract::State, routing::get, Json, Router};
+
+use super::state::AppState;
+use crate::core::instance::InstanceStats;
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/", get(get_stats))
+}
+
+// <----- Functions ----->
+
+/// The instance's identity and totals in a stable schema, for dashboards that aggregate several
+/// instances. Nothing here names a url, title or file, so it's also served under `/public`.
+pub async fn get_stats(State(app_state): State<AppState>) -> Json<InstanceStats> {
+    Json(InstanceStats::new(
+        &app_state.instance,
+        app_state.ytdlp_client.download_counts(),
+        app_state.ytdlp_client.download_path(),
+    ))
+}
@@ -0,0 +1,108 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use super::state::AppState;
+use crate::core::jobs::{JobError, JobInfo, JobRun, JobScheduler};
+use crate::core::messages::Message;
+use crate::error::ApiError;
+
+/// The most runs `/runs` answers with.
+const MAX_RUNS: i64 = 200;
+
+// <----- Requests ----->
+
+#[derive(Deserialize)]
+struct RunsQuery {
+    #[serde(default = "default_runs_limit")]
+    limit: i64,
+}
+
+fn default_runs_limit() -> i64 {
+    50
+}
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_jobs))
+        .route("/{name}", get(get_job))
+        .route("/{name}/pause", post(pause_job))
+        .route("/{name}/resume", post(resume_job))
+        .route("/{name}/run", post(run_job))
+        .route("/{name}/runs", get(get_runs))
+}
+
+// <----- Functions ----->
+
+async fn get_jobs(State(jobs): State<JobScheduler>) -> Json<Vec<JobInfo>> {
+    Json(jobs.list())
+}
+
+async fn get_job(
+    State(jobs): State<JobScheduler>,
+    Path(name): Path<String>,
+) -> Result<Json<JobInfo>, ApiError> {
+    jobs.get(&name)
+        .map(Json)
+        .ok_or_else(|| job_error(JobError::Unknown, &name))
+}
+
+async fn pause_job(
+    State(jobs): State<JobScheduler>,
+    Path(name): Path<String>,
+) -> Result<Json<JobInfo>, ApiError> {
+    jobs.set_paused(&name, true)
+        .map(Json)
+        .map_err(|err| job_error(err, &name))
+}
+
+async fn resume_job(
+    State(jobs): State<JobScheduler>,
+    Path(name): Path<String>,
+) -> Result<Json<JobInfo>, ApiError> {
+    jobs.set_paused(&name, false)
+        .map(Json)
+        .map_err(|err| job_error(err, &name))
+}
+
+/// Starts the job now, outside its trigger. Answers once it's started, not once it's done.
+async fn run_job(
+    State(jobs): State<JobScheduler>,
+    Path(name): Path<String>,
+) -> Result<(StatusCode, Json<JobInfo>), ApiError> {
+    jobs.run_now(&name)
+        .map(|job| (StatusCode::ACCEPTED, Json(job)))
+        .map_err(|err| job_error(err, &name))
+}
+
+/// The job's past runs, newest first.
+async fn get_runs(
+    State(jobs): State<JobScheduler>,
+    Path(name): Path<String>,
+    Query(query): Query<RunsQuery>,
+) -> Result<Json<Vec<JobRun>>, ApiError> {
+    match jobs.runs(&name, query.limit.clamp(1, MAX_RUNS)).await {
+        Ok(Some(runs)) => Ok(Json(runs)),
+        Ok(None) => Err(job_error(JobError::Unknown, &name)),
+        Err(err) => Err(ApiError::internal(err)),
+    }
+}
+
+fn job_error(err: JobError, name: &str) -> ApiError {
+    match err {
+        JobError::Unknown => ApiError::new(
+            StatusCode::NOT_FOUND,
+            Message::new("job.unknown").with("name", name),
+        ),
+        JobError::Running => ApiError::new(
+            StatusCode::CONFLICT,
+            Message::new("job.running").with("name", name),
+        ),
+    }
+}
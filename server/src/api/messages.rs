@@ -0,0 +1,14 @@
+use axum::{extract::Path, routing::get, Json, Router};
+use std::collections::BTreeMap;
+
+use super::state::AppState;
+use crate::core::messages;
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/{locale}", get(get_messages))
+}
+
+/// The message templates for a locale, so frontends can render error and event keys themselves.
+async fn get_messages(Path(locale): Path<String>) -> Json<BTreeMap<String, String>> {
+    Json(messages::templates(&locale))
+}
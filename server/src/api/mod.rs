@@ -1,13 +1,234 @@
-use std::path::PathBuf;
+use std::time::Duration;
 
-use axum::Router;
-use sqlx::SqlitePool;
+use axum::{middleware, Router};
+use chrono::NaiveTime;
+use tracing::{info, warn};
 
+use crate::Database;
+
+use crate::core::events::EventBus;
+use crate::core::jobs::Trigger;
+use crate::core::upgrade::{ScanSettings, UpgradeScanner};
+use crate::core::ytdlp::{ClientSettings, YtdlpClient};
+
+mod admin;
+mod archive;
 mod config;
+mod cookies;
+mod debug;
+mod headers;
+mod instance;
+mod jobs;
+mod messages;
+mod policy;
+mod public;
+mod saved;
+mod schedule;
+mod state;
+mod tags;
+mod timeout;
+mod workers;
 mod ytdlp;
+mod ytdlp_configs;
+
+use state::AppState;
+pub use timeout::RequestTimeouts;
+pub use ytdlp::WebsocketLimits;
+
+/// How often finished downloads past the history limits, and logs past theirs, are pruned.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How the api is served, everything besides the [`ClientSettings`] downloads run with.
+pub struct ApiSettings {
+    pub debug_endpoints: bool,
+    /// Tells this instance apart on multi-instance dashboards, see `/api/instance`.
+    pub instance_name: Option<String>,
+    /// Serves the reduced, unauthenticated status under `/public`.
+    pub public_status: bool,
+    /// Resumes interrupted downloads at startup unless the config says otherwise.
+    pub resume_interrupted: bool,
+    pub timeouts: RequestTimeouts,
+    pub upgrade_scan: UpgradeScanConfig,
+    pub websocket_limits: WebsocketLimits,
+}
+
+pub struct UpgradeScanConfig {
+    /// Time of day on the instance clock to scan at, takes over from `interval`.
+    pub at: Option<NaiveTime>,
+    pub delay: Duration,
+    pub interval: Option<Duration>,
+    pub settings: ScanSettings,
+}
+
+/// Winds the api down once the server has been asked to stop, see [`YtdlpClient::shutdown`].
+#[derive(Clone)]
+pub struct Shutdown {
+    ytdlp_client: YtdlpClient,
+}
+
+impl Shutdown {
+    pub async fn run(&self) {
+        self.ytdlp_client.shutdown().await;
+    }
+}
+
+/// Hands the server's recurring work to the job scheduler, see `/api/jobs`.
+fn register_jobs(app_state: &AppState, upgrade_scan: UpgradeScanConfig) {
+    let jobs = &app_state.jobs;
+
+    let state = app_state.clone();
+    jobs.register(
+        "history-retention",
+        Trigger::every(RETENTION_INTERVAL),
+        true,
+        move || {
+            let ytdlp_client = state.ytdlp_client.clone();
+            async move {
+                ytdlp_client.prune_history().await;
+                Ok(())
+            }
+        },
+    );
+
+    let state = app_state.clone();
+    jobs.register(
+        "log-retention",
+        Trigger::every(RETENTION_INTERVAL),
+        false,
+        move || {
+            let ytdlp_client = state.ytdlp_client.clone();
+            async move {
+                ytdlp_client
+                    .prune_logs()
+                    .await
+                    .map_err(|err| format!("couldn't trash logs: {}", err))?;
+                ytdlp_client
+                    .prune_progress()
+                    .await
+                    .map_err(|err| format!("couldn't drop stale progress: {}", err))?;
+                Ok(())
+            }
+        },
+    );
+
+    // A run missed while the server was down happens at the first poll after startup.
+    let state = app_state.clone();
+    jobs.register(
+        "schedules",
+        Trigger::every(schedule::POLL_INTERVAL),
+        true,
+        move || schedule::run_due(state.clone()),
+    );
+
+    let trigger = match (upgrade_scan.at, upgrade_scan.interval) {
+        (Some(at), _) => Trigger::DailyAt(at),
+        (None, Some(interval)) => Trigger::every(interval),
+        (None, None) => return,
+    };
+    let state = app_state.clone();
+    let settings = upgrade_scan.settings;
+    jobs.register("upgrade-scan", trigger, false, move || {
+        let scan = ytdlp::run_upgrade_scan(state.clone(), settings.clone());
+        async move {
+            match scan.await {
+                Some(_) => Ok(()),
+                None => Err(String::from("an upgrade scan is already running")),
+            }
+        }
+    });
+}
+
+pub async fn routes(
+    db: Database,
+    client_settings: ClientSettings,
+    settings: ApiSettings,
+) -> (Router, Shutdown) {
+    let ApiSettings {
+        debug_endpoints,
+        instance_name,
+        public_status,
+        resume_interrupted,
+        timeouts,
+        upgrade_scan,
+        websocket_limits,
+    } = settings;
+    let events = EventBus::with_capacity(client_settings.log_limits.event_buffer);
+    let ytdlp_client = YtdlpClient::new(db.write.clone(), client_settings, events.clone()).await;
+    let upgrade_scanner = UpgradeScanner::new(ytdlp_client.clone(), upgrade_scan.delay);
+    let instance = match crate::core::instance::identity(&db.write, instance_name).await {
+        Ok(instance) => instance,
+        Err(err) => panic!("failed to load the instance id: {}", err),
+    };
+    let app_state = AppState::new(
+        db,
+        events,
+        instance,
+        ytdlp_client,
+        upgrade_scanner,
+        websocket_limits,
+    );
+
+    ytdlp::resume_scheduled(app_state.clone()).await;
+    if config::auto_resume(&app_state.db)
+        .await
+        .unwrap_or(resume_interrupted)
+    {
+        ytdlp::resume_interrupted(app_state.clone()).await;
+    }
+
+    register_jobs(&app_state, upgrade_scan);
+
+    let shutdown = Shutdown {
+        ytdlp_client: app_state.ytdlp_client.clone(),
+    };
+    let router = Router::new()
+        .nest("/archive", archive::routes())
+        .nest("/config", config::routes())
+        .nest("/cookies", cookies::routes())
+        .nest("/download", ytdlp::routes())
+        .nest("/headers", headers::routes())
+        .nest("/instance", instance::routes())
+        .nest("/jobs", jobs::routes())
+        .nest("/messages", messages::routes())
+        .nest("/policy", policy::routes())
+        .nest("/saved", saved::routes())
+        .nest("/schedule", schedule::routes())
+        .nest("/tags", tags::routes())
+        .nest("/ytdlp-configs", ytdlp_configs::routes());
+
+    let router = match public_status {
+        true => {
+            info!("public status page is enabled");
+            router.nest("/public", public::routes())
+        }
+        false => router,
+    };
+    let router = match debug_endpoints {
+        true => {
+            warn!("debug endpoints are enabled");
+            router.nest("/debug", debug::routes())
+        }
+        false => router,
+    };
 
-pub async fn routes(db: SqlitePool, ytdlp_path: String, download_path: PathBuf) -> Router {
-    Router::new()
-        .nest("/config", config::routes(db.clone()))
-        .nest("/download", ytdlp::routes(db, ytdlp_path, download_path).await)
+    let slow_routes = Router::new()
+        .nest("/admin", admin::routes())
+        .nest("/download", ytdlp::slow_routes())
+        .nest("/saved", saved::slow_routes())
+        .nest("/schedule", schedule::slow_routes())
+        .layer(middleware::from_fn_with_state(
+            timeouts.slow_request,
+            timeout::limit,
+        ));
+    // A layer only wraps the routes added before it, so the slow routes keep their own limit and
+    // the worker routes go without one.
+    let router = router
+        .layer(middleware::from_fn_with_state(
+            timeouts.request,
+            timeout::limit,
+        ))
+        .merge(slow_routes)
+        .nest("/workers", workers::routes())
+        .with_state(app_state);
+    (router, shutdown)
 }
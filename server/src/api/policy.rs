@@ -0,0 +1,102 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+
+use super::state::AppState;
+use crate::core::messages::Message;
+use crate::core::policy::{self, Policy, PolicyAction};
+use crate::error::ApiError;
+use crate::Database;
+
+// <----- Requests ----->
+
+#[derive(Deserialize)]
+struct PolicyRequest {
+    video_codec: Option<String>,
+    container: Option<String>,
+    action: PolicyAction,
+    target: String,
+}
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_policies).post(create_policy))
+        .route("/{id}", delete(delete_policy))
+}
+
+// <----- Functions ----->
+
+/// Adds a policy, run after every later download that matches it.
+async fn create_policy(
+    State(db): State<Database>,
+    Json(request): Json<PolicyRequest>,
+) -> Result<(StatusCode, Json<Policy>), ApiError> {
+    let target = request.target.to_lowercase();
+    if !policy::is_valid_target(request.action, &target) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("policy.invalid_target").with("target", &request.target),
+        ));
+    }
+
+    let video_codec = request.video_codec.map(|codec| codec.to_lowercase());
+    let container = request.container.map(|container| container.to_lowercase());
+    let created_at = Utc::now();
+    let id = sqlx::query!(
+        r#"INSERT INTO PostPolicy (video_codec, container, action, target, created_at)
+        VALUES ($1, $2, $3, $4, $5)"#,
+        video_codec,
+        container,
+        request.action,
+        target,
+        created_at
+    )
+    .execute(&db.write)
+    .await
+    .map_err(ApiError::internal)?
+    .last_insert_rowid();
+
+    Ok((
+        StatusCode::CREATED,
+        Json(Policy {
+            id,
+            video_codec,
+            container,
+            action: request.action,
+            target,
+            created_at,
+        }),
+    ))
+}
+
+async fn delete_policy(
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let result = sqlx::query!("DELETE FROM PostPolicy WHERE id = $1", id)
+        .execute(&db.write)
+        .await
+        .map_err(ApiError::internal)?;
+
+    match result.rows_affected() {
+        0 => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            Message::new("policy.unknown"),
+        )),
+        _ => Ok(StatusCode::OK),
+    }
+}
+
+async fn get_policies(State(db): State<Database>) -> Result<Json<Vec<Policy>>, ApiError> {
+    policy::load(&db.read)
+        .await
+        .map(Json)
+        .map_err(ApiError::internal)
+}
@@ -0,0 +1,21 @@
+use axum::{extract::State, routing::get, Json, Router};
+
+use super::instance;
+use super::state::AppState;
+use crate::core::ytdlp::{PublicStatus, YtdlpClient};
+
+// <----- Routes ----->
+
+/// A status surface safe to expose without the rest of the api, only mounted when PUBLIC_STATUS
+/// is set. Nothing here names a url, title or file.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/instance", get(instance::get_stats))
+        .route("/status", get(get_status))
+}
+
+// <----- Functions ----->
+
+async fn get_status(State(ytdlp_client): State<YtdlpClient>) -> Json<PublicStatus> {
+    Json(ytdlp_client.get_public_status())
+}
@@ -0,0 +1,345 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tracing::error;
+use url::Url;
+
+use super::state::AppState;
+use super::ytdlp;
+use crate::core::canonical;
+use crate::core::messages::Message;
+use crate::core::ytdlp::{DownloadOptions, DownloadRequest};
+use crate::error::ApiError;
+
+// <----- SavedUrl ----->
+
+struct SavedUrlRow {
+    id: i64,
+    url: String,
+    note: String,
+    tags: String,
+}
+
+#[derive(Serialize)]
+struct SavedUrl {
+    id: i64,
+    url: String,
+    note: String,
+    tags: Vec<String>,
+}
+
+impl From<SavedUrlRow> for SavedUrl {
+    fn from(row: SavedUrlRow) -> Self {
+        SavedUrl {
+            id: row.id,
+            url: row.url,
+            note: row.note,
+            tags: serde_json::from_str(&row.tags).unwrap_or_default(),
+        }
+    }
+}
+
+// <----- Requests ----->
+
+#[derive(Deserialize)]
+struct SaveRequest {
+    url: Url,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SavedQuery {
+    tag: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EnqueueRequest {
+    options: DownloadOptions,
+    #[serde(default)]
+    pinned: bool,
+}
+
+#[derive(Deserialize)]
+struct BulkEnqueueRequest {
+    ids: Vec<i64>,
+    options: DownloadOptions,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct EnqueueResult {
+    id: i64,
+    accepted: bool,
+    title: Option<String>,
+    estimated_size: Option<f64>,
+    reason: Option<Message>,
+}
+
+#[derive(Serialize)]
+struct BulkEnqueueResult {
+    dry_run: bool,
+    accepted: usize,
+    estimated_size: Option<f64>,
+    results: Vec<EnqueueResult>,
+}
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_saved).post(save_url))
+        .route("/{id}", delete(delete_saved))
+        .route("/{id}/enqueue", post(enqueue_saved))
+}
+
+/// Bulk enqueueing probes every saved url, so it gets the slow request timeout.
+pub fn slow_routes() -> Router<AppState> {
+    Router::new().route("/enqueue", post(enqueue_saved_bulk))
+}
+
+// <----- Functions ----->
+
+async fn delete_saved(State(app_state): State<AppState>, Path(id): Path<i64>) -> StatusCode {
+    match sqlx::query!("DELETE FROM SavedUrl WHERE id = $1", id)
+        .execute(&app_state.db.write)
+        .await
+    {
+        Ok(result) => match result.rows_affected() {
+            0 => StatusCode::NOT_FOUND,
+            _ => StatusCode::OK,
+        },
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn enqueue_saved(
+    State(app_state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(request): Json<EnqueueRequest>,
+) -> Result<StatusCode, ApiError> {
+    let saved = match fetch_saved(&app_state.db.read, id).await {
+        Ok(Some(saved)) => saved,
+        Ok(None) => {
+            return Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                Message::new("saved.unknown"),
+            ))
+        }
+        Err(err) => return Err(ApiError::internal(err)),
+    };
+
+    enqueue(&app_state, saved, request.options, request.pinned).await?;
+
+    Ok(StatusCode::CREATED)
+}
+
+async fn enqueue_saved_bulk(
+    State(app_state): State<AppState>,
+    Json(request): Json<BulkEnqueueRequest>,
+) -> Json<BulkEnqueueResult> {
+    if request.dry_run {
+        return Json(preview_saved_bulk(&app_state, request.ids).await);
+    }
+
+    let mut results = Vec::with_capacity(request.ids.len());
+
+    for id in request.ids {
+        let result = match fetch_saved(&app_state.db.read, id).await {
+            Ok(Some(saved)) => enqueue(&app_state, saved, request.options.clone(), request.pinned)
+                .await
+                .map_err(|err| err.message),
+            Ok(None) => Err(Message::new("saved.unknown")),
+            Err(err) => Err(Message::new("internal").with("error", err)),
+        };
+
+        results.push(match result {
+            Ok(_) => EnqueueResult {
+                id,
+                accepted: true,
+                title: None,
+                estimated_size: None,
+                reason: None,
+            },
+            Err(reason) => EnqueueResult {
+                id,
+                accepted: false,
+                title: None,
+                estimated_size: None,
+                reason: Some(reason),
+            },
+        });
+    }
+
+    Json(BulkEnqueueResult {
+        dry_run: false,
+        accepted: results.iter().filter(|result| result.accepted).count(),
+        estimated_size: None,
+        results,
+    })
+}
+
+/// Reports what a bulk enqueue would do, without starting downloads or touching the saved list.
+async fn preview_saved_bulk(app_state: &AppState, ids: Vec<i64>) -> BulkEnqueueResult {
+    let mut results = Vec::with_capacity(ids.len());
+    let mut to_probe = Vec::new();
+
+    for id in ids {
+        let url = match fetch_saved(&app_state.db.read, id).await {
+            Ok(Some(saved)) => {
+                Url::parse(&saved.url).map_err(|err| Message::new("url.invalid").with("error", err))
+            }
+            Ok(None) => Err(Message::new("saved.unknown")),
+            Err(err) => Err(Message::new("internal").with("error", err)),
+        };
+
+        let reason = match url {
+            Ok(url) => {
+                to_probe.push((results.len(), url));
+                None
+            }
+            Err(reason) => Some(reason),
+        };
+        results.push(EnqueueResult {
+            id,
+            accepted: false,
+            title: None,
+            estimated_size: None,
+            reason,
+        });
+    }
+
+    let urls = to_probe.iter().map(|(_, url)| url.clone()).collect();
+    let checks = ytdlp::preview_downloads(app_state, urls).await;
+    for ((index, _), check) in to_probe.into_iter().zip(checks) {
+        let result = &mut results[index];
+        result.accepted = check.available;
+        result.title = check.title;
+        result.estimated_size = check.estimated_size;
+        result.reason = check
+            .reason
+            .map(|reason| Message::new("download.unavailable").with("reason", reason));
+    }
+
+    let accepted: Vec<&EnqueueResult> = results.iter().filter(|result| result.accepted).collect();
+    BulkEnqueueResult {
+        dry_run: true,
+        accepted: accepted.len(),
+        estimated_size: accepted
+            .iter()
+            .filter_map(|result| result.estimated_size)
+            .reduce(|a, b| a + b),
+        results,
+    }
+}
+
+async fn get_saved(
+    State(app_state): State<AppState>,
+    Query(query): Query<SavedQuery>,
+) -> Result<Json<Vec<SavedUrl>>, StatusCode> {
+    let rows = sqlx::query_as!(SavedUrlRow, "SELECT * FROM SavedUrl ORDER BY id")
+        .fetch_all(&app_state.db.read)
+        .await;
+
+    match rows {
+        Ok(rows) => Ok(Json(
+            rows.into_iter()
+                .map(SavedUrl::from)
+                .filter(|saved| {
+                    query
+                        .tag
+                        .as_ref()
+                        .is_none_or(|tag| saved.tags.contains(tag))
+                })
+                .collect(),
+        )),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn save_url(
+    State(app_state): State<AppState>,
+    Json(request): Json<SaveRequest>,
+) -> Result<(StatusCode, Json<SavedUrl>), StatusCode> {
+    let url = canonical::normalize(request.url).to_string();
+    let tags = serde_json::to_string(&request.tags).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let result = sqlx::query!(
+        "INSERT INTO SavedUrl (url, note, tags) VALUES ($1, $2, $3) ON CONFLICT(url) DO NOTHING",
+        url,
+        request.note,
+        tags
+    )
+    .execute(&app_state.db.write)
+    .await;
+
+    match result {
+        Ok(result) => match result.rows_affected() {
+            1 => Ok((
+                StatusCode::CREATED,
+                Json(SavedUrl {
+                    id: result.last_insert_rowid(),
+                    url,
+                    note: request.note,
+                    tags: request.tags,
+                }),
+            )),
+            _ => Err(StatusCode::CONFLICT),
+        },
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Hands the saved url to the download queue and drops it from the saved list once accepted.
+async fn enqueue(
+    app_state: &AppState,
+    saved: SavedUrl,
+    options: DownloadOptions,
+    pinned: bool,
+) -> Result<(), ApiError> {
+    let url = Url::parse(&saved.url).map_err(|err| {
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("url.invalid").with("error", err),
+        )
+    })?;
+
+    ytdlp::enqueue_download(
+        app_state.clone(),
+        DownloadRequest {
+            url,
+            options,
+            pinned,
+            start_at: None,
+            tags: Vec::new(),
+            video: None,
+        },
+    )
+    .await?;
+
+    if let Err(err) = sqlx::query!("DELETE FROM SavedUrl WHERE id = $1", saved.id)
+        .execute(&app_state.db.write)
+        .await
+    {
+        error!("failed to remove enqueued saved url {}: {}", saved.id, err);
+    }
+
+    Ok(())
+}
+
+async fn fetch_saved(db: &SqlitePool, id: i64) -> sqlx::Result<Option<SavedUrl>> {
+    sqlx::query_as!(SavedUrlRow, "SELECT * FROM SavedUrl WHERE id = $1", id)
+        .fetch_optional(db)
+        .await
+        .map(|row| row.map(SavedUrl::from))
+}
@@ -0,0 +1,496 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{error, info};
+use url::Url;
+
+use super::state::AppState;
+use super::ytdlp;
+use crate::core::canonical;
+use crate::core::clock::LocalTime;
+use crate::core::jobs::JobResult;
+use crate::core::links::LibraryLink;
+use crate::core::messages::Message;
+use crate::core::recurring;
+use crate::core::ytdlp::{DownloadOptions, DownloadRequest};
+use crate::error::ApiError;
+use crate::Database;
+
+/// How often the scheduler looks for due schedules.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+// <----- Schedule ----->
+
+struct ScheduleRow {
+    id: i64,
+    url: String,
+    cron: String,
+    container: String,
+    name_format: String,
+    quality: String,
+    priority: i64,
+    rate_limit: Option<String>,
+    subtitle_format: Option<String>,
+    split_chapters: bool,
+    audio_format: Option<String>,
+    tag_template: Option<sqlx::types::Json<BTreeMap<String, String>>>,
+    download_archive: Option<bool>,
+    max_duration_secs: Option<i64>,
+    library_links: Option<sqlx::types::Json<Vec<LibraryLink>>>,
+    ytdlp_config: Option<String>,
+    extra_args: sqlx::types::Json<Vec<String>>,
+    cookies: Option<String>,
+    proxy: Option<String>,
+    geo_bypass_country: Option<String>,
+    enabled: bool,
+    created_at: DateTime<Utc>,
+    last_run_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct Schedule {
+    id: i64,
+    url: String,
+    cron: String,
+    options: DownloadOptions,
+    enabled: bool,
+    created_at: DateTime<Utc>,
+    last_run_at: Option<DateTime<Utc>>,
+    next_run: Option<LocalTime>,
+}
+
+impl ScheduleRow {
+    /// When the schedule is next due, counting from its last run or its creation.
+    fn next_run(&self) -> Option<DateTime<Utc>> {
+        let schedule = recurring::parse(&self.cron).ok()?;
+        recurring::next_run(&schedule, self.last_run_at.unwrap_or(self.created_at))
+    }
+}
+
+impl From<ScheduleRow> for Schedule {
+    fn from(row: ScheduleRow) -> Self {
+        let next_run = match row.enabled {
+            true => row.next_run().map(LocalTime::from),
+            false => None,
+        };
+
+        Schedule {
+            id: row.id,
+            url: row.url,
+            cron: row.cron,
+            options: DownloadOptions {
+                container: row.container,
+                name_format: row.name_format,
+                quality: row.quality,
+                priority: row.priority,
+                rate_limit: row.rate_limit,
+                subtitle_format: row.subtitle_format,
+                split_chapters: row.split_chapters,
+                audio_format: row.audio_format,
+                tag_template: row.tag_template.map(|tag_template| tag_template.0),
+                download_archive: row.download_archive,
+                max_duration_secs: row.max_duration_secs,
+                library_links: row.library_links.map(|library_links| library_links.0),
+                ytdlp_config: row.ytdlp_config,
+                extra_args: row.extra_args.0,
+                cookies: row.cookies,
+                proxy: row.proxy,
+                geo_bypass_country: row.geo_bypass_country,
+            },
+            enabled: row.enabled,
+            created_at: row.created_at,
+            last_run_at: row.last_run_at,
+            next_run,
+        }
+    }
+}
+
+// <----- Requests ----->
+
+#[derive(Deserialize)]
+struct ScheduleRequest {
+    url: Url,
+    cron: String,
+    options: DownloadOptions,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+#[derive(Serialize)]
+struct RunResult {
+    enqueued: usize,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_schedules).post(create_schedule))
+        .route(
+            "/{id}",
+            get(get_schedule)
+                .put(update_schedule)
+                .delete(delete_schedule),
+        )
+}
+
+/// Running a schedule now lists its whole playlist or channel, so it gets the slow request
+/// timeout.
+pub fn slow_routes() -> Router<AppState> {
+    Router::new().route("/{id}/run", post(run_schedule_now))
+}
+
+// <----- Functions ----->
+
+/// Runs the schedules that are due, polled every [`POLL_INTERVAL`] by the job scheduler.
+pub async fn run_due(app_state: AppState) -> JobResult {
+    let rows = fetch_schedules(&app_state.db)
+        .await
+        .map_err(|err| format!("failed to load schedules: {}", err))?;
+
+    let now = Utc::now();
+    let mut failed = 0;
+    for row in rows {
+        if row.enabled && row.next_run().is_some_and(|next_run| next_run <= now) {
+            let id = row.id;
+            if let Err(err) = run_schedule(&app_state, row).await {
+                error!("schedule {} failed to run: {}", id, err.message);
+                failed += 1;
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} schedules failed to run", failed)),
+    }
+}
+
+/// Enqueues every video behind the schedule's url that the manager doesn't already have.
+async fn run_schedule(app_state: &AppState, row: ScheduleRow) -> Result<usize, ApiError> {
+    let now = Utc::now();
+    // Marked as run up front so a url that keeps failing isn't retried on every poll.
+    sqlx::query!(
+        "UPDATE Schedule SET last_run_at = $1 WHERE id = $2",
+        now,
+        row.id
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    let url = Url::parse(&row.url).map_err(|err| {
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("url.invalid").with("error", err),
+        )
+    })?;
+    let ytdlp_client = app_state.ytdlp_client.clone();
+    let entries = ytdlp_client.list_entries(&url).await.map_err(|err| {
+        ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            Message::new("schedule.listing_failed").with("error", err),
+        )
+    })?;
+
+    let schedule = Schedule::from(row);
+    let mut enqueued = 0;
+    for entry in entries {
+        let entry = canonical::normalize(entry);
+        if ytdlp_client.downloads.contains_key(&entry) {
+            continue;
+        }
+        let added = ytdlp::spawn_download(
+            app_state.clone(),
+            DownloadRequest {
+                url: entry.clone(),
+                options: schedule.options.clone(),
+                pinned: false,
+                start_at: None,
+                tags: Vec::new(),
+                video: None,
+            },
+        )
+        .await;
+        match added {
+            Ok(()) => enqueued += 1,
+            Err(err) => error!(
+                "schedule {} skipped url: {}, err: {}",
+                schedule.id, entry, err
+            ),
+        }
+    }
+    info!(
+        "schedule {} enqueued {} new downloads from: {}",
+        schedule.id, enqueued, schedule.url
+    );
+
+    Ok(enqueued)
+}
+
+async fn create_schedule(
+    State(app_state): State<AppState>,
+    Json(request): Json<ScheduleRequest>,
+) -> Result<(StatusCode, Json<Schedule>), ApiError> {
+    validate_cron(&request.cron)?;
+    ytdlp::check_options(&request.options)?;
+    let url = canonical::normalize(request.url).to_string();
+    let tag_template = request.options.tag_template.as_ref().map(sqlx::types::Json);
+    let library_links = request
+        .options
+        .library_links
+        .as_ref()
+        .map(sqlx::types::Json);
+    let extra_args = sqlx::types::Json(&request.options.extra_args);
+    let now = Utc::now();
+
+    let id = sqlx::query!(
+        r#"INSERT INTO Schedule (
+            url,
+            cron,
+            container,
+            name_format,
+            quality,
+            priority,
+            rate_limit,
+            subtitle_format,
+            split_chapters,
+            audio_format,
+            tag_template,
+            download_archive,
+            max_duration_secs,
+            library_links,
+            ytdlp_config,
+            extra_args,
+            cookies,
+            proxy,
+            geo_bypass_country,
+            enabled,
+            created_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20,
+            $21
+        )"#,
+        url,
+        request.cron,
+        request.options.container,
+        request.options.name_format,
+        request.options.quality,
+        request.options.priority,
+        request.options.rate_limit,
+        request.options.subtitle_format,
+        request.options.split_chapters,
+        request.options.audio_format,
+        tag_template,
+        request.options.download_archive,
+        request.options.max_duration_secs,
+        library_links,
+        request.options.ytdlp_config,
+        extra_args,
+        request.options.cookies,
+        request.options.proxy,
+        request.options.geo_bypass_country,
+        request.enabled,
+        now
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?
+    .last_insert_rowid();
+
+    let schedule = fetch_schedule(&app_state.db, id).await?;
+    Ok((StatusCode::CREATED, Json(Schedule::from(schedule))))
+}
+
+async fn delete_schedule(State(app_state): State<AppState>, Path(id): Path<i64>) -> StatusCode {
+    match sqlx::query!("DELETE FROM Schedule WHERE id = $1", id)
+        .execute(&app_state.db.write)
+        .await
+    {
+        Ok(result) => match result.rows_affected() {
+            0 => StatusCode::NOT_FOUND,
+            _ => StatusCode::OK,
+        },
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn get_schedule(
+    State(app_state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<Schedule>, ApiError> {
+    Ok(Json(Schedule::from(
+        fetch_schedule(&app_state.db, id).await?,
+    )))
+}
+
+async fn get_schedules(State(app_state): State<AppState>) -> Result<Json<Vec<Schedule>>, ApiError> {
+    let rows = fetch_schedules(&app_state.db)
+        .await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(rows.into_iter().map(Schedule::from).collect()))
+}
+
+async fn run_schedule_now(
+    State(app_state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<RunResult>, ApiError> {
+    let row = fetch_schedule(&app_state.db, id).await?;
+    let enqueued = run_schedule(&app_state, row).await?;
+
+    Ok(Json(RunResult { enqueued }))
+}
+
+async fn update_schedule(
+    State(app_state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(request): Json<ScheduleRequest>,
+) -> Result<Json<Schedule>, ApiError> {
+    validate_cron(&request.cron)?;
+    ytdlp::check_options(&request.options)?;
+    let url = canonical::normalize(request.url).to_string();
+    let tag_template = request.options.tag_template.as_ref().map(sqlx::types::Json);
+    let library_links = request
+        .options
+        .library_links
+        .as_ref()
+        .map(sqlx::types::Json);
+    let extra_args = sqlx::types::Json(&request.options.extra_args);
+
+    let result = sqlx::query!(
+        r#"UPDATE Schedule
+        SET url = $1, cron = $2, container = $3, name_format = $4, quality = $5, priority = $6,
+            rate_limit = $7, subtitle_format = $8, split_chapters = $9, audio_format = $10,
+            tag_template = $11, download_archive = $12, max_duration_secs = $13,
+            library_links = $14, ytdlp_config = $15, extra_args = $16, cookies = $17,
+            proxy = $18, geo_bypass_country = $19, enabled = $20
+        WHERE id = $21"#,
+        url,
+        request.cron,
+        request.options.container,
+        request.options.name_format,
+        request.options.quality,
+        request.options.priority,
+        request.options.rate_limit,
+        request.options.subtitle_format,
+        request.options.split_chapters,
+        request.options.audio_format,
+        tag_template,
+        request.options.download_archive,
+        request.options.max_duration_secs,
+        library_links,
+        request.options.ytdlp_config,
+        extra_args,
+        request.options.cookies,
+        request.options.proxy,
+        request.options.geo_bypass_country,
+        request.enabled,
+        id
+    )
+    .execute(&app_state.db.write)
+    .await
+    .map_err(ApiError::internal)?;
+
+    if result.rows_affected() == 0 {
+        return Err(unknown_schedule());
+    }
+
+    Ok(Json(Schedule::from(
+        fetch_schedule(&app_state.db, id).await?,
+    )))
+}
+
+fn validate_cron(cron: &str) -> Result<(), ApiError> {
+    recurring::parse(cron).map(|_| ()).map_err(|err| {
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("schedule.invalid_cron").with("error", err),
+        )
+    })
+}
+
+fn unknown_schedule() -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, Message::new("schedule.unknown"))
+}
+
+async fn fetch_schedule(db: &Database, id: i64) -> Result<ScheduleRow, ApiError> {
+    sqlx::query_as!(
+        ScheduleRow,
+        r#"SELECT
+            id,
+            url,
+            cron,
+            container,
+            name_format,
+            quality,
+            priority,
+            rate_limit,
+            subtitle_format,
+            split_chapters,
+            audio_format,
+            tag_template as "tag_template: sqlx::types::Json<BTreeMap<String, String>>",
+            download_archive,
+            max_duration_secs,
+            library_links as "library_links: sqlx::types::Json<Vec<LibraryLink>>",
+            ytdlp_config,
+            extra_args as "extra_args: sqlx::types::Json<Vec<String>>",
+            cookies,
+            proxy,
+            geo_bypass_country,
+            enabled,
+            created_at as "created_at: DateTime<Utc>",
+            last_run_at as "last_run_at: DateTime<Utc>"
+        FROM Schedule WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(&db.read)
+    .await
+    .map_err(ApiError::internal)?
+    .ok_or_else(unknown_schedule)
+}
+
+async fn fetch_schedules(db: &Database) -> sqlx::Result<Vec<ScheduleRow>> {
+    sqlx::query_as!(
+        ScheduleRow,
+        r#"SELECT
+            id,
+            url,
+            cron,
+            container,
+            name_format,
+            quality,
+            priority,
+            rate_limit,
+            subtitle_format,
+            split_chapters,
+            audio_format,
+            tag_template as "tag_template: sqlx::types::Json<BTreeMap<String, String>>",
+            download_archive,
+            max_duration_secs,
+            library_links as "library_links: sqlx::types::Json<Vec<LibraryLink>>",
+            ytdlp_config,
+            extra_args as "extra_args: sqlx::types::Json<Vec<String>>",
+            cookies,
+            proxy,
+            geo_bypass_country,
+            enabled,
+            created_at as "created_at: DateTime<Utc>",
+            last_run_at as "last_run_at: DateTime<Utc>"
+        FROM Schedule ORDER BY id"#
+    )
+    .fetch_all(&db.read)
+    .await
+}
@@ -0,0 +1,75 @@
+use axum::extract::FromRef;
+
+use super::ytdlp::{OpenSockets, Subscriptions, WebsocketLimits};
+use crate::core::events::EventBus;
+use crate::core::instance::InstanceIdentity;
+use crate::core::jobs::JobScheduler;
+use crate::core::upgrade::UpgradeScanner;
+use crate::core::ytdlp::YtdlpClient;
+use crate::Database;
+
+/// Everything the routes and background tasks share. Handlers take the whole state or, through
+/// the [`FromRef`] impls, just the part they need, e.g. `State<Database>`.
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Database,
+    pub events: EventBus,
+    pub instance: InstanceIdentity,
+    pub jobs: JobScheduler,
+    pub upgrade_scanner: UpgradeScanner,
+    pub ytdlp_client: YtdlpClient,
+    pub(super) sockets: OpenSockets,
+    pub(super) subscriptions: Subscriptions,
+}
+
+impl AppState {
+    pub fn new(
+        db: Database,
+        events: EventBus,
+        instance: InstanceIdentity,
+        ytdlp_client: YtdlpClient,
+        upgrade_scanner: UpgradeScanner,
+        websocket_limits: WebsocketLimits,
+    ) -> AppState {
+        AppState {
+            jobs: JobScheduler::new(db.clone(), events.clone()),
+            db,
+            events,
+            instance,
+            upgrade_scanner,
+            ytdlp_client,
+            sockets: OpenSockets::new(websocket_limits),
+            subscriptions: Subscriptions::default(),
+        }
+    }
+}
+
+impl FromRef<AppState> for Database {
+    fn from_ref(app_state: &AppState) -> Database {
+        app_state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for EventBus {
+    fn from_ref(app_state: &AppState) -> EventBus {
+        app_state.events.clone()
+    }
+}
+
+impl FromRef<AppState> for JobScheduler {
+    fn from_ref(app_state: &AppState) -> JobScheduler {
+        app_state.jobs.clone()
+    }
+}
+
+impl FromRef<AppState> for UpgradeScanner {
+    fn from_ref(app_state: &AppState) -> UpgradeScanner {
+        app_state.upgrade_scanner.clone()
+    }
+}
+
+impl FromRef<AppState> for YtdlpClient {
+    fn from_ref(app_state: &AppState) -> YtdlpClient {
+        app_state.ytdlp_client.clone()
+    }
+}
@@ -0,0 +1,151 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, put},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::state::AppState;
+use crate::core::messages::Message;
+use crate::core::tagging::{self, Tag};
+use crate::error::ApiError;
+use crate::Database;
+
+// <----- Requests ----->
+
+#[derive(Deserialize)]
+struct TagRequest {
+    name: String,
+    /// Unset leaves downloads with the tag in the download path.
+    subdirectory: Option<String>,
+}
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_tags).post(create_tag))
+        .route("/{id}", put(update_tag).delete(delete_tag))
+}
+
+// <----- Functions ----->
+
+fn check_tag(request: &TagRequest) -> Result<(), ApiError> {
+    if !tagging::is_valid_name(&request.name) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("tag.invalid_name").with("name", &request.name),
+        ));
+    }
+    if let Some(subdirectory) = request
+        .subdirectory
+        .as_deref()
+        .filter(|subdirectory| !tagging::is_valid_subdirectory(subdirectory))
+    {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("tag.invalid_subdirectory").with("subdirectory", subdirectory),
+        ));
+    }
+    Ok(())
+}
+
+fn tag_exists(name: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::CONFLICT,
+        Message::new("tag.exists").with("name", name),
+    )
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .is_some_and(|err| err.is_unique_violation())
+}
+
+async fn create_tag(
+    State(db): State<Database>,
+    Json(request): Json<TagRequest>,
+) -> Result<(StatusCode, Json<Tag>), ApiError> {
+    check_tag(&request)?;
+
+    let created_at = Utc::now();
+    let id = sqlx::query!(
+        "INSERT INTO Tag (name, subdirectory, created_at) VALUES ($1, $2, $3)",
+        request.name,
+        request.subdirectory,
+        created_at
+    )
+    .execute(&db.write)
+    .await
+    .map_err(|err| match is_unique_violation(&err) {
+        true => tag_exists(&request.name),
+        false => ApiError::internal(err),
+    })?
+    .last_insert_rowid();
+
+    Ok((
+        StatusCode::CREATED,
+        Json(Tag {
+            id,
+            name: request.name,
+            subdirectory: request.subdirectory,
+            created_at,
+        }),
+    ))
+}
+
+/// Untags every download filed under the tag. Their files stay where they are.
+async fn delete_tag(
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let result = sqlx::query!("DELETE FROM Tag WHERE id = $1", id)
+        .execute(&db.write)
+        .await
+        .map_err(ApiError::internal)?;
+
+    match result.rows_affected() {
+        0 => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            Message::new("tag.unknown"),
+        )),
+        _ => Ok(StatusCode::OK),
+    }
+}
+
+async fn get_tags(State(db): State<Database>) -> Result<Json<Vec<Tag>>, ApiError> {
+    tagging::load(&db.read)
+        .await
+        .map(Json)
+        .map_err(ApiError::internal)
+}
+
+/// Renames the tag or moves it to another subdirectory. Files already downloaded stay where they
+/// are, later downloads and retries use the new subdirectory.
+async fn update_tag(
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+    Json(request): Json<TagRequest>,
+) -> Result<Json<Tag>, ApiError> {
+    check_tag(&request)?;
+
+    let tag = sqlx::query_as!(
+        Tag,
+        r#"UPDATE Tag SET name = $1, subdirectory = $2 WHERE id = $3
+        RETURNING id, name, subdirectory, created_at as "created_at: DateTime<Utc>""#,
+        request.name,
+        request.subdirectory,
+        id
+    )
+    .fetch_optional(&db.write)
+    .await
+    .map_err(|err| match is_unique_violation(&err) {
+        true => tag_exists(&request.name),
+        false => ApiError::internal(err),
+    })?;
+
+    tag.map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, Message::new("tag.unknown")))
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::core::messages::Message;
+use crate::error::ApiError;
+
+/// How long a handler gets before the request is answered with a 504.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestTimeouts {
+    pub request: Duration,
+    /// For routes whose work grows with the request, like batches, uploads and backups.
+    pub slow_request: Duration,
+}
+
+/// Middleware that gives the handler `limit` to respond. Only the handler is timed, a response
+/// body streaming out afterwards or an upgraded websocket isn't cut off.
+pub async fn limit(State(limit): State<Duration>, request: Request, next: Next) -> Response {
+    match tokio::time::timeout(limit, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => ApiError::new(
+            StatusCode::GATEWAY_TIMEOUT,
+            Message::new("request.timed_out").with("secs", limit.as_secs()),
+        )
+        .into_response(),
+    }
+}
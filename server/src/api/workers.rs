@@ -0,0 +1,178 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
+
+use crate::core::messages::Message;
+use crate::core::workers::{
+    Job, JobExit, JobOutput, OutputReply, Registered, Registration, WorkerError, WorkerInfo,
+    CLAIM_WAIT,
+};
+use crate::core::ytdlp::YtdlpClient;
+use crate::error::ApiError;
+
+use super::state::AppState;
+
+// <----- Routes ----->
+
+/// Served without the request timeout, claims wait on jobs and uploads take as long as the file.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_workers))
+        .route("/register", post(register_worker))
+        .route("/{worker}/claim", post(claim_job))
+        .route("/{worker}/jobs/{job}/output", post(report_output))
+        .route("/{worker}/jobs/{job}/files/{*path}", put(upload_file))
+        .route("/{worker}/jobs/{job}/exit", post(report_exit))
+}
+
+// <----- Functions ----->
+
+async fn get_workers(State(ytdlp_client): State<YtdlpClient>) -> Json<Vec<WorkerInfo>> {
+    Json(ytdlp_client.workers().list())
+}
+
+async fn register_worker(
+    State(ytdlp_client): State<YtdlpClient>,
+    headers: HeaderMap,
+    Json(registration): Json<Registration>,
+) -> Result<Json<Registered>, ApiError> {
+    authorize(&ytdlp_client, &headers)?;
+    Ok(Json(ytdlp_client.workers().register(registration)))
+}
+
+/// The worker's next job, or no content once [`CLAIM_WAIT`] passes without one.
+async fn claim_job(
+    State(ytdlp_client): State<YtdlpClient>,
+    headers: HeaderMap,
+    Path(worker): Path<String>,
+) -> Result<Response, ApiError> {
+    authorize(&ytdlp_client, &headers)?;
+    let job: Option<Job> = ytdlp_client
+        .workers()
+        .claim(&worker, CLAIM_WAIT)
+        .await
+        .map_err(|err| worker_error(err, &worker, ""))?;
+    Ok(match job {
+        Some(job) => Json(job).into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    })
+}
+
+async fn report_output(
+    State(ytdlp_client): State<YtdlpClient>,
+    headers: HeaderMap,
+    Path((worker, job)): Path<(String, String)>,
+    Json(output): Json<JobOutput>,
+) -> Result<Json<OutputReply>, ApiError> {
+    authorize(&ytdlp_client, &headers)?;
+    ytdlp_client
+        .workers()
+        .report(&worker, &job, output)
+        .map(Json)
+        .map_err(|err| worker_error(err, &worker, &job))
+}
+
+/// Takes a file a job wrote, by its path under the download path, streamed as the body.
+async fn upload_file(
+    State(ytdlp_client): State<YtdlpClient>,
+    headers: HeaderMap,
+    Path((worker, job, path)): Path<(String, String, String)>,
+    body: Body,
+) -> Result<StatusCode, ApiError> {
+    authorize(&ytdlp_client, &headers)?;
+    let destination = ytdlp_client
+        .workers()
+        .upload_path(&worker, &job, &path)
+        .map_err(|err| worker_error(err, &worker, &job))?
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("worker.invalid_path").with("path", &path),
+            )
+        })?;
+
+    if let Some(parent) = destination.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(ApiError::internal)?;
+    }
+    // Written aside first so a cut off upload never passes for the finished file.
+    let mut partial = destination.clone().into_os_string();
+    partial.push(".upload");
+    let mut file = tokio::fs::File::create(&partial)
+        .await
+        .map_err(ApiError::internal)?;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(ApiError::internal)?;
+        file.write_all(&chunk).await.map_err(ApiError::internal)?;
+    }
+    file.flush().await.map_err(ApiError::internal)?;
+    tokio::fs::rename(&partial, &destination)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(StatusCode::OK)
+}
+
+async fn report_exit(
+    State(ytdlp_client): State<YtdlpClient>,
+    headers: HeaderMap,
+    Path((worker, job)): Path<(String, String)>,
+    Json(exit): Json<JobExit>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&ytdlp_client, &headers)?;
+    ytdlp_client
+        .workers()
+        .finish(&worker, &job, exit)
+        .map(|()| StatusCode::NO_CONTENT)
+        .map_err(|err| worker_error(err, &worker, &job))
+}
+
+/// Checks the request carries the worker token as a bearer token.
+fn authorize(ytdlp_client: &YtdlpClient, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(token) = ytdlp_client.worker_token() else {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            Message::new("worker.disabled"),
+        ));
+    };
+    let sent = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Compared in full whatever the first difference, so the time taken doesn't leak the token.
+    let matches = sent.len() == token.len()
+        && sent
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    match matches {
+        true => Ok(()),
+        false => Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            Message::new("worker.unauthorized"),
+        )),
+    }
+}
+
+fn worker_error(err: WorkerError, worker: &str, job: &str) -> ApiError {
+    match err {
+        WorkerError::UnknownWorker => ApiError::new(
+            StatusCode::NOT_FOUND,
+            Message::new("worker.unknown").with("worker", worker),
+        ),
+        WorkerError::UnknownJob => ApiError::new(
+            StatusCode::NOT_FOUND,
+            Message::new("worker.unknown_job").with("job", job),
+        ),
+    }
+}
@@ -1,63 +1,391 @@
-use axum::extract::ws::WebSocket;
-use axum::extract::{FromRef, State, WebSocketUpgrade};
-use axum::http::StatusCode;
+use axum::extract::ws::{self, close_code, CloseFrame, WebSocket};
+use axum::extract::{ConnectInfo, Path, Query, State, WebSocketUpgrade};
+use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
 use axum::routing::{any, get, post};
-use axum::{Json, Router};
-use futures_util::{sink::SinkExt, stream::StreamExt};
+use axum::{Extension, Json, Router};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures_util::{
+    sink::SinkExt,
+    stream::{self, StreamExt},
+};
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::broadcast::Sender;
-use tokio::sync::{broadcast, mpsc, Mutex};
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
 use tracing::{error, info};
 use url::Url;
 
-use crate::core::ytdlp::{self, DownloadOptions, Status, YtdlpClient};
+use super::state::AppState;
+use crate::core::canonical::{self, ShortForm, ShortFormPolicy};
+use crate::core::cookies;
+use crate::core::events::{self, EventSubscriber};
+use crate::core::filters::DownloadFilter;
+use crate::core::formats::{FormatListing, UpgradeReport};
+use crate::core::history::{self, HistoryPage, HistorySort};
+use crate::core::links;
+use crate::core::messages::Message;
+use crate::core::queue::DomainThrottle;
+use crate::core::storage;
+use crate::core::tagging::{self, Tag};
+use crate::core::tags;
+use crate::core::transcode;
+use crate::core::upgrade::{self, ScanResult, ScanSettings};
+use crate::core::ytdlp::{
+    self, DownloadDetail, DownloadInfo, DownloadOptions, DownloadRequest, DownloadUsage, Signal,
+    Simulation, Status, UrlCheck, UrlSupport, YtdlpClient,
+};
+use crate::core::ytdlp_configs;
+use crate::error::ApiError;
 
-// <----- AppState ----->
+// <----- OpenSockets ----->
 
+/// How many event websockets can be open at once, so a misbehaving client can't slow the event
+/// fan-out for everyone by opening hundreds.
+#[derive(Clone, Copy, Debug)]
+pub struct WebsocketLimits {
+    pub max_connections: usize,
+    /// Clients behind the same reverse proxy share one address and so one cap.
+    pub max_per_ip: usize,
+}
+
+#[derive(Default)]
+struct SocketCounts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Counts the open websockets against [`WebsocketLimits`].
 #[derive(Clone)]
-struct AppState {
-    ytdlp_client: YtdlpClient,
-    tx: Arc<Mutex<Sender<String>>>,
+pub(super) struct OpenSockets {
+    counts: Arc<std::sync::Mutex<SocketCounts>>,
+    limits: WebsocketLimits,
+}
+
+impl OpenSockets {
+    pub(super) fn new(limits: WebsocketLimits) -> OpenSockets {
+        OpenSockets {
+            counts: Arc::default(),
+            limits,
+        }
+    }
+
+    /// Takes a slot for a socket from `ip`, or says which limit is in the way. Sockets whose
+    /// address isn't known only count towards the overall limit.
+    fn open(&self, ip: Option<IpAddr>) -> std::result::Result<SocketSlot, usize> {
+        let mut counts = self.counts.lock().unwrap_or_else(|err| err.into_inner());
+        if counts.total >= self.limits.max_connections {
+            return Err(self.limits.max_connections);
+        }
+        if let Some(ip) = ip {
+            let per_ip = counts.per_ip.entry(ip).or_default();
+            if *per_ip >= self.limits.max_per_ip {
+                return Err(self.limits.max_per_ip);
+            }
+            *per_ip += 1;
+        }
+        counts.total += 1;
+        Ok(SocketSlot {
+            ip,
+            sockets: self.clone(),
+        })
+    }
 }
 
-impl FromRef<AppState> for YtdlpClient {
-    fn from_ref(app_state: &AppState) -> YtdlpClient {
-        app_state.ytdlp_client.clone()
+/// An open socket's place in [`OpenSockets`], given back when dropped.
+struct SocketSlot {
+    ip: Option<IpAddr>,
+    sockets: OpenSockets,
+}
+
+impl Drop for SocketSlot {
+    fn drop(&mut self) {
+        let mut counts = self
+            .sockets
+            .counts
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        counts.total -= 1;
+        if let Some(ip) = self.ip {
+            if let Some(per_ip) = counts.per_ip.get_mut(&ip) {
+                *per_ip -= 1;
+                if *per_ip == 0 {
+                    counts.per_ip.remove(&ip);
+                }
+            }
+        }
     }
 }
 
-// <----- DownloadRequest ----->
+// <----- Subscriptions ----->
 
-#[derive(Deserialize, Serialize)]
-struct DownloadRequest {
-    url: Url,
+/// Open websockets by the client id they were opened with, so a client reconnecting, e.g. a
+/// reloaded tab, replaces its old socket instead of getting every event twice.
+#[derive(Clone, Default)]
+pub(super) struct Subscriptions(Arc<DashMap<String, Arc<Notify>>>);
+
+impl Subscriptions {
+    /// Registers a new socket for `client`, telling the one it replaces to close.
+    fn replace(&self, client: &str) -> Arc<Notify> {
+        let replaced = Arc::new(Notify::new());
+        if let Some(old) = self.0.insert(client.to_string(), replaced.clone()) {
+            old.notify_one();
+        }
+        replaced
+    }
+
+    /// Forgets `client`'s socket unless a newer one has taken its place.
+    fn remove(&self, client: &str, replaced: &Arc<Notify>) {
+        self.0
+            .remove_if(client, |_, current| Arc::ptr_eq(current, replaced));
+    }
+}
+
+/// Why the server closed a websocket, sent as the close frame's reason so the client can tell
+/// whether to reconnect.
+#[derive(Clone, Copy, Debug)]
+enum CloseReason {
+    /// The server is going away, reconnect once it's back.
+    ServerShutdown,
+    /// Another socket opened with the same client id, don't reconnect.
+    SubscriptionReplaced,
+}
+
+impl CloseReason {
+    fn frame(self) -> CloseFrame {
+        let (code, reason) = match self {
+            CloseReason::ServerShutdown => (close_code::AWAY, "server-shutdown"),
+            // 4000 and up are left to applications.
+            CloseReason::SubscriptionReplaced => (4000, "subscription-replaced"),
+        };
+        CloseFrame {
+            code,
+            reason: reason.into(),
+        }
+    }
+}
+
+// <----- ValidateOptionsRequest ----->
+
+#[derive(Deserialize)]
+struct ValidateOptionsRequest {
     options: DownloadOptions,
+    /// Dry runs against the configured check url when unset.
+    url: Option<Url>,
 }
 
-// <----- Routes ----->
+// <----- BatchResult ----->
+
+#[derive(Serialize)]
+struct BatchResult {
+    url: Url,
+    accepted: bool,
+    reason: Option<Message>,
+}
+
+// <----- DeleteQuery ----->
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    /// Also removes the downloaded file and its sidecars from disk.
+    #[serde(default)]
+    remove_files: bool,
+}
+
+#[derive(Serialize)]
+struct DeleteResult {
+    removed_files: Vec<PathBuf>,
+}
+
+// <----- DownloadsQuery ----->
 
-pub async fn routes(db: SqlitePool, ytdlp_path: String, download_path: PathBuf) -> Router {
-    let (tx, _) = broadcast::channel::<String>(100);
-    let ytdlp_client = YtdlpClient::new(db, ytdlp_path, download_path).await;
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DownloadsSort {
+    #[default]
+    CreatedAt,
+    StartedAt,
+    FinishedAt,
+    Elapsed,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize)]
+struct DownloadsQuery {
+    pinned: Option<bool>,
+    starred: Option<bool>,
+    upgradeable: Option<bool>,
+    /// Only downloads whose file has a video stream in this codec, e.g. `av1`.
+    video_codec: Option<String>,
+    /// Only completed downloads whose file doesn't have a video stream in this codec.
+    not_video_codec: Option<String>,
+    /// Only downloads created at or after this time, e.g. `2026-09-01T00:00:00Z`.
+    created_after: Option<DateTime<Utc>>,
+    /// Only downloads created before this time.
+    created_before: Option<DateTime<Utc>>,
+    /// Only downloads whose file holds at least this many bytes.
+    min_size: Option<u64>,
+    /// Only downloads whose file holds at most this many bytes.
+    max_size: Option<u64>,
+    /// Only downloads from this host or its subdomains, e.g. `youtube.com`.
+    domain: Option<String>,
+    #[serde(default)]
+    sort: DownloadsSort,
+    #[serde(default)]
+    order: SortOrder,
+    after: Option<i64>,
+    limit: Option<usize>,
+}
+
+// <----- HistoryQuery ----->
+
+const MAX_HISTORY_PAGE: u32 = 200;
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// One of the finished statuses, or all of them if unset.
+    status: Option<Status>,
+    #[serde(default = "default_history_page")]
+    page: u32,
+    #[serde(default = "default_history_per_page")]
+    per_page: u32,
+    #[serde(default)]
+    sort: HistorySort,
+    /// Only downloads filed under the tag with this name.
+    tag: Option<String>,
+    starred: Option<bool>,
+}
+
+fn default_history_page() -> u32 {
+    1
+}
+
+fn default_history_per_page() -> u32 {
+    50
+}
+
+// <----- WebsocketQuery ----->
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FrameFormat {
+    #[default]
+    Json,
+    Msgpack,
+}
+
+#[derive(Deserialize)]
+struct WebsocketQuery {
+    /// Picked by the client, reconnecting with the same id closes its previous socket.
+    client: Option<String>,
+    events: Option<String>,
+    #[serde(default)]
+    format: FrameFormat,
+}
+
+// <----- PinRequest ----->
+
+#[derive(Deserialize)]
+struct PinRequest {
+    url: Url,
+    pinned: bool,
+}
 
-    let safe_tx = Arc::new(Mutex::new(tx));
+// <----- StarResult ----->
 
+#[derive(Serialize)]
+struct StarResult {
+    starred: bool,
+}
+
+// <----- PriorityRequest ----->
+
+#[derive(Deserialize)]
+struct PriorityRequest {
+    url: Url,
+    priority: i64,
+}
+
+// <----- BulkResult ----->
+
+/// How one download took a bulk pause, resume or cancel.
+#[derive(Serialize)]
+struct BulkResult {
+    status: Option<Status>,
+    error: Option<String>,
+}
+
+impl From<ytdlp::Result<Status>> for BulkResult {
+    fn from(result: ytdlp::Result<Status>) -> Self {
+        match result {
+            Ok(status) => BulkResult {
+                status: Some(status),
+                error: None,
+            },
+            Err(err) => BulkResult {
+                status: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+// <----- ReorderRequest ----->
+
+#[derive(Deserialize)]
+struct ReorderRequest {
+    urls: Vec<Url>,
+}
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
     Router::new()
-        .route("/", post(download_from_options))
+        .route("/", get(get_downloads).post(download_from_options))
         .route("/cancel", post(cancel_download))
+        .route("/cancel-all", post(cancel_all))
         .route("/check", post(check_url_availability))
+        .route("/formats", post(list_formats))
+        .route("/history", get(get_history))
+        .route("/options/validate", post(validate_options))
         .route("/pause", post(pause_download))
+        .route("/pause-all", post(pause_all))
+        .route("/pin", post(pin_download))
+        .route("/{id}", get(get_download_detail).delete(delete_download))
+        .route("/{id}/log", get(get_log))
+        .route("/{id}/star", post(toggle_star))
+        .route("/{id}/tags", get(get_tags).put(set_tags))
+        .route("/{id}/thumbnail", get(get_thumbnail))
+        .route("/priority", post(set_priority))
+        .route("/processes", get(get_process_usage))
+        .route("/queue", get(get_queue))
+        .route("/queue/export", get(export_queue))
+        .route("/queue/reorder", post(reorder_queue))
+        .route("/queue/throttles", get(get_throttles))
+        .route("/resume-all", post(resume_all))
+        .route("/upgrade", post(check_upgrade))
         .route("/urls", get(get_urls))
-        .with_state(AppState {
-            tx: safe_tx.clone(),
-            ytdlp_client,
-        })
         .route("/ws", any(download_websocket))
-        .with_state(safe_tx)
+}
+
+/// The routes that probe or enqueue a whole list of urls, which get the slow request timeout.
+pub fn slow_routes() -> Router<AppState> {
+    Router::new()
+        .route("/batch", post(enqueue_batch))
+        .route("/check-batch", post(check_url_batch))
+        .route("/queue/import", post(enqueue_batch))
+        .route("/upgrade/scan", post(scan_for_upgrades))
 }
 
 // <----- Functions ----->
@@ -78,73 +406,818 @@ async fn cancel_download(
     }
 }
 
+async fn cancel_all(State(ytdlp_client): State<YtdlpClient>) -> Json<BTreeMap<Url, BulkResult>> {
+    Json(bulk_results(ytdlp_client.halt_all(Signal::Cancel).await))
+}
+
+fn bulk_results(results: BTreeMap<Url, ytdlp::Result<Status>>) -> BTreeMap<Url, BulkResult> {
+    results
+        .into_iter()
+        .map(|(url, result)| (url, BulkResult::from(result)))
+        .collect()
+}
+
 async fn check_url_availability(
     State(ytdlp_client): State<YtdlpClient>,
-    Json(download): Json<DownloadRequest>,
-) -> Result<StatusCode, (StatusCode, String)> {
+    Json(url): Json<Url>,
+) -> Result<Json<UrlSupport>, ApiError> {
     match ytdlp_client
-        .check_url_availability(&download.url, &download.options)
+        .check_url_support(&canonical::normalize(url))
         .await
     {
-        Ok(_) => Ok(StatusCode::OK),
+        Ok(support) => Ok(Json(support)),
+        Err(err) => {
+            error!("check failed: {:?}", err);
+            Err(ApiError::internal(err))
+        }
+    }
+}
+
+/// The formats a url's video comes in, for a quality picker to offer.
+async fn list_formats(
+    State(ytdlp_client): State<YtdlpClient>,
+    Json(url): Json<Url>,
+) -> Result<Json<FormatListing>, ApiError> {
+    match ytdlp_client.list_formats(&canonical::normalize(url)).await {
+        Ok(listing) => Ok(Json(listing)),
+        Err(ytdlp::Error::General { err }) => Err(ApiError::internal(err.kind())),
+        Err(ytdlp::Error::ProbeTimedOut) => Err(probe_timed_out()),
+        Err(err) => {
+            error!("format listing failed: {:?}", err);
+            Err(ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                Message::new("download.formats_failed"),
+            ))
+        }
+    }
+}
+
+/// Checks `options` the way a download would, then dry runs yt-dlp with them to catch flags it
+/// rejects or can't combine, so bad options are caught when a schedule is saved.
+async fn validate_options(
+    State(ytdlp_client): State<YtdlpClient>,
+    Json(request): Json<ValidateOptionsRequest>,
+) -> Result<Json<Simulation>, ApiError> {
+    check_options(&request.options)?;
+    let url = match request.url {
+        Some(url) => canonical::normalize(url),
+        None => ytdlp_client.options_check_url().clone(),
+    };
+    match ytdlp_client.simulate(&url, &request.options).await {
+        Ok(simulation) => Ok(Json(simulation)),
+        Err(ytdlp::Error::ForbiddenConfig { option }) => {
+            Err(super::ytdlp_configs::forbidden_option(&option))
+        }
+        Err(ytdlp::Error::ProbeTimedOut) => Err(probe_timed_out()),
+        Err(err) => {
+            error!("options validation failed: {:?}", err);
+            Err(ApiError::internal(err))
+        }
+    }
+}
+
+async fn check_upgrade(
+    State(ytdlp_client): State<YtdlpClient>,
+    Json(url): Json<Url>,
+) -> Result<Json<UpgradeReport>, ApiError> {
+    match ytdlp_client.check_upgrade(&url).await {
+        Ok(report) => Ok(Json(report)),
         Err(err) => match err {
-            ytdlp::Error::General { err } => {
-                Err((StatusCode::INTERNAL_SERVER_ERROR, err.kind().to_string()))
-            }
+            ytdlp::Error::DownloadNotPresent => Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                Message::new("download.unknown"),
+            )),
+            ytdlp::Error::NotCompleted => Err(ApiError::new(
+                StatusCode::CONFLICT,
+                Message::new("download.not_completed"),
+            )),
+            ytdlp::Error::General { err } => Err(ApiError::internal(err.kind())),
+            ytdlp::Error::ProbeTimedOut => Err(probe_timed_out()),
             _ => {
-                error!("check failed: {:?}", err);
-                Err((StatusCode::BAD_REQUEST, String::from("Bad download")))
+                error!("upgrade check failed: {:?}", err);
+                Err(ApiError::new(
+                    StatusCode::BAD_GATEWAY,
+                    Message::new("download.formats_failed"),
+                ))
             }
         },
     }
 }
 
+async fn check_url_batch(
+    State(ytdlp_client): State<YtdlpClient>,
+    Json(urls): Json<Vec<Url>>,
+) -> Json<Vec<UrlCheck>> {
+    let urls = urls.into_iter().map(canonical::normalize).collect();
+    Json(ytdlp_client.check_urls(urls).await)
+}
+
+/// Removes the download's record, and its files if asked to. Queued and running downloads have
+/// to be canceled first.
+async fn delete_download(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(id): Path<i64>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<Json<DeleteResult>, ApiError> {
+    match ytdlp_client.delete_download(id, query.remove_files).await {
+        Ok(removed_files) => Ok(Json(DeleteResult { removed_files })),
+        Err(ytdlp::Error::DownloadAlreadyPresent) => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            Message::new("download.active"),
+        )),
+        Err(_) => Err(unknown_download()),
+    }
+}
+
 async fn download_from_options(
     State(app_state): State<AppState>,
     Json(download): Json<DownloadRequest>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    if let Err(err) = app_state
+) -> Result<StatusCode, ApiError> {
+    enqueue_download(app_state, download).await?;
+
+    Ok(StatusCode::CREATED)
+}
+
+/// Checks a pasted list of downloads a few at a time and starts the ones that pass.
+/// Repeats within the list and urls the manager already has are rejected without a probe.
+async fn enqueue_batch(
+    State(app_state): State<AppState>,
+    Json(downloads): Json<Vec<DownloadRequest>>,
+) -> Json<Vec<BatchResult>> {
+    let mut seen = HashSet::new();
+    let checked: Vec<(DownloadRequest, Result<(), Message>)> = stream::iter(downloads)
+        .map(|mut download| {
+            download.url = canonical::normalize(download.url);
+            let duplicate = !seen.insert(download.url.clone());
+            let app_state = &app_state;
+            async move {
+                let result = if duplicate {
+                    Err(Message::new("download.duplicate"))
+                } else if app_state.ytdlp_client.downloads.contains_key(&download.url) {
+                    Err(Message::new("download.present"))
+                } else {
+                    check_download(app_state, &mut download)
+                        .await
+                        .map_err(|err| err.message)
+                };
+                (download, result)
+            }
+        })
+        .buffered(ytdlp::BATCH_PROBE_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut results = Vec::with_capacity(checked.len());
+    let mut seen_videos = HashSet::new();
+    for (download, mut result) in checked {
+        // Different urls in the list can still lead to the same video.
+        if let Some(video) = &download.video {
+            if result.is_ok() && !seen_videos.insert(video.video_id.clone()) {
+                result = Err(Message::new("download.duplicate"));
+            }
+        }
+        let url = download.url.clone();
+        if result.is_ok() {
+            result = spawn_download(app_state.clone(), download)
+                .await
+                .map_err(|err| enqueue_error(err).message);
+        }
+        results.push(BatchResult {
+            url,
+            accepted: result.is_ok(),
+            reason: result.err(),
+        });
+    }
+    info!(
+        "batch enqueued {} of {} downloads",
+        results.iter().filter(|result| result.accepted).count(),
+        results.len()
+    );
+
+    Json(results)
+}
+
+/// Probes the urls without starting anything, rejecting ones the manager already has.
+pub async fn preview_downloads(app_state: &AppState, urls: Vec<Url>) -> Vec<UrlCheck> {
+    let ytdlp_client = &app_state.ytdlp_client;
+    let urls = urls.into_iter().map(canonical::normalize).collect();
+
+    ytdlp_client
+        .check_urls(urls)
+        .await
+        .into_iter()
+        .map(|mut check| {
+            if ytdlp_client.downloads.contains_key(&check.url) {
+                check.available = false;
+                check.reason = Some(ytdlp::Error::DownloadAlreadyPresent.to_string());
+            }
+            check
+        })
+        .collect()
+}
+
+/// Checks the download under its canonical url then spawns it, see [`spawn_download`].
+/// # Errors
+/// Returns the status code and message to respond with when the check fails.
+pub async fn enqueue_download(
+    app_state: AppState,
+    mut download: DownloadRequest,
+) -> Result<(), ApiError> {
+    download.url = canonical::normalize(download.url);
+    check_download(&app_state, &mut download).await?;
+    spawn_download(app_state, download)
+        .await
+        .map_err(enqueue_error)
+}
+
+/// Also records which video the url resolved to, so other urls for it are turned away when added.
+/// Shorts and clip links are rewritten or rejected first, see [`ShortFormPolicy`].
+async fn check_download(
+    app_state: &AppState,
+    download: &mut DownloadRequest,
+) -> Result<(), ApiError> {
+    if app_state.ytdlp_client.is_shutting_down() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            Message::new("server.shutting_down"),
+        ));
+    }
+    check_options(&download.options)?;
+    check_tags(&download.tags)?;
+
+    let short_form = canonical::short_form(&download.url);
+    let short_form_policy = match short_form {
+        Some(_) => app_state.ytdlp_client.short_form_policy().await,
+        None => ShortFormPolicy::Keep,
+    };
+    match (&short_form, short_form_policy) {
+        (Some(_), ShortFormPolicy::Reject) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("url.short_form").with("url", &download.url),
+            ))
+        }
+        (Some(ShortForm::Shorts(id)), ShortFormPolicy::Rewrite) => {
+            download.url = canonical::watch_url(id);
+        }
+        _ => {}
+    }
+
+    let video = match app_state
         .ytdlp_client
         .check_url_availability(&download.url, &download.options)
         .await
     {
-        return match err {
-            ytdlp::Error::FailedCheck => {
-                error!("check failed: {:?}", err);
-                Err((StatusCode::BAD_REQUEST, String::from("Bad download")))
-            }
-            ytdlp::Error::General { err } => {
-                Err((StatusCode::INTERNAL_SERVER_ERROR, err.kind().to_string()))
-            }
-            _ => unreachable!(),
-        };
+        Ok(video) => video,
+        Err(err) => {
+            return match err {
+                ytdlp::Error::FailedCheck => {
+                    error!("check failed: {:?}", err);
+                    Err(ApiError::new(
+                        StatusCode::BAD_REQUEST,
+                        Message::new("download.bad"),
+                    ))
+                }
+                ytdlp::Error::ForbiddenConfig { option } => {
+                    Err(super::ytdlp_configs::forbidden_option(&option))
+                }
+                ytdlp::Error::General { err } => Err(ApiError::internal(err.kind())),
+                ytdlp::Error::ProbeTimedOut => Err(probe_timed_out()),
+                _ => unreachable!(),
+            };
+        }
+    };
+
+    // A clip's id isn't the video's, only the check finds out which video it was cut from.
+    if let (Some(ShortForm::Clip), ShortFormPolicy::Rewrite) = (&short_form, short_form_policy) {
+        if let Some((_, id)) = video
+            .as_ref()
+            .and_then(|video| video.video_id.split_once(':'))
+        {
+            download.url = canonical::watch_url(id);
+        }
     }
 
-    let (download_update_tx, mut download_update_rx) = mpsc::channel(100);
+    if let Some(estimated_size) = video
+        .as_ref()
+        .and_then(|video| video.details.estimated_size)
+    {
+        if let Err(shortfall) = app_state.ytdlp_client.check_free_space(estimated_size) {
+            return Err(ApiError::new(
+                StatusCode::INSUFFICIENT_STORAGE,
+                Message::new("storage.insufficient")
+                    .with("needed", storage::megabytes(shortfall.needed))
+                    .with("available", storage::megabytes(shortfall.available)),
+            ));
+        }
+    }
+    download.video = video;
 
-    tokio::task::spawn(async move {
-        while let Some(string) = download_update_rx.recv().await {
-            if let Err(err) = app_state.tx.lock().await.send(string) {
-                error!("failed to send download message to frontend: {}", err);
-            }
+    Ok(())
+}
+
+fn check_tags(names: &[String]) -> Result<(), ApiError> {
+    match names.iter().find(|name| !tagging::is_valid_name(name)) {
+        Some(name) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("tag.invalid_name").with("name", name),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Rejects options that would only fail once the download runs.
+pub fn check_options(options: &DownloadOptions) -> Result<(), ApiError> {
+    check_rate_limit(options.rate_limit.as_deref())?;
+    check_proxy(options.proxy.as_deref())?;
+    check_geo_bypass_country(options.geo_bypass_country.as_deref())?;
+
+    if ytdlp::parse_quality(&options.quality).is_none() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("download.invalid_quality").with("quality", &options.quality),
+        ));
+    }
+    if !ytdlp::is_valid_container(&options.container) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("download.invalid_container")
+                .with("container", &options.container)
+                .with("containers", ytdlp::CONTAINERS.join(", ")),
+        ));
+    }
+    if !ytdlp::is_valid_name_format(&options.name_format) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("download.invalid_name_format").with("name_format", &options.name_format),
+        ));
+    }
+
+    if let Some(arg) = ytdlp::disallowed_extra_arg(&options.extra_args) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("download.invalid_extra_arg")
+                .with("arg", arg)
+                .with("allowed", ytdlp::EXTRA_ARGS.join(", ")),
+        ));
+    }
+    if let Some(name) = options
+        .cookies
+        .as_deref()
+        .filter(|name| !cookies::is_valid_name(name))
+    {
+        return Err(super::cookies::invalid_name(name));
+    }
+    if let Some(name) = options
+        .ytdlp_config
+        .as_deref()
+        .filter(|name| !ytdlp_configs::is_valid_name(name))
+    {
+        return Err(super::ytdlp_configs::invalid_name(name));
+    }
+
+    if let Some(link) = options
+        .library_links
+        .iter()
+        .flatten()
+        .find(|link| !links::is_valid_dir(&link.dir))
+    {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("download.invalid_link_dir").with("dir", link.dir.display()),
+        ));
+    }
+    if let Some(max_duration_secs) = options.max_duration_secs.filter(|secs| *secs <= 0) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("download.invalid_max_duration").with("secs", max_duration_secs),
+        ));
+    }
+
+    if let Some(format) = &options.subtitle_format {
+        if !transcode::SUBTITLE_FORMATS.contains(&format.as_str()) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("subtitle.invalid_format").with("format", format),
+            ));
         }
-    });
+    }
+    if let Some(format) = &options.audio_format {
+        if !tags::AUDIO_FORMATS.contains(&format.as_str()) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("audio.invalid_format").with("format", format),
+            ));
+        }
+    }
+    if let Some(tag_template) = &options.tag_template {
+        if let Some(tag) = tag_template.keys().find(|tag| !tags::is_valid_name(tag)) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("tags.invalid_name").with("tag", tag),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects a country yt-dlp's geo bypass wouldn't understand.
+pub fn check_geo_bypass_country(country: Option<&str>) -> Result<(), ApiError> {
+    match country {
+        Some(country) if !ytdlp::is_valid_geo_bypass_country(country) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("geo_bypass.invalid_country").with("country", country),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Rejects a proxy yt-dlp wouldn't understand.
+pub fn check_proxy(proxy: Option<&str>) -> Result<(), ApiError> {
+    match proxy {
+        Some(proxy) if !ytdlp::is_valid_proxy(proxy) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("proxy.invalid")
+                .with("proxy", proxy)
+                .with("schemes", ytdlp::PROXY_SCHEMES.join(", ")),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Rejects a rate limit yt-dlp wouldn't understand.
+pub fn check_rate_limit(rate_limit: Option<&str>) -> Result<(), ApiError> {
+    match rate_limit {
+        Some(rate_limit) if !ytdlp::is_valid_rate_limit(rate_limit) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("rate_limit.invalid").with("rate_limit", rate_limit),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Restarts downloads cut off by the last shutdown, skipping the availability check they passed.
+pub async fn resume_interrupted(app_state: AppState) {
+    let interrupted = app_state.ytdlp_client.get_interrupted().await;
+    if !interrupted.is_empty() {
+        info!("resuming {} interrupted downloads", interrupted.len());
+    }
+
+    for download in interrupted {
+        let url = download.url.clone();
+        let resumed = spawn_download(
+            app_state.clone(),
+            DownloadRequest {
+                url: download.url,
+                options: download.options,
+                pinned: download.pinned,
+                start_at: None,
+                tags: Vec::new(),
+                video: None,
+            },
+        )
+        .await;
+        if let Err(err) = resumed {
+            error!("failed to resume url: {}, err: {}", url, err);
+        }
+    }
+}
+
+/// Puts every paused download back in the queue. yt-dlp picks up from the partial files.
+async fn resume_all(State(app_state): State<AppState>) -> Json<BTreeMap<Url, BulkResult>> {
+    let mut results = BTreeMap::new();
+    for download in app_state.ytdlp_client.get_paused().await {
+        let url = download.url.clone();
+        let resumed = spawn_download(
+            app_state.clone(),
+            DownloadRequest {
+                url: download.url,
+                options: download.options,
+                pinned: download.pinned,
+                start_at: None,
+                tags: Vec::new(),
+                video: None,
+            },
+        )
+        .await;
+        results.insert(url, BulkResult::from(resumed.map(|_| Status::Queued)));
+    }
+
+    Json(results)
+}
+
+/// Re-arms downloads that were waiting on a start time when the server last stopped.
+pub async fn resume_scheduled(app_state: AppState) {
+    let scheduled = app_state.ytdlp_client.get_scheduled().await;
+    if !scheduled.is_empty() {
+        info!("re-arming {} scheduled downloads", scheduled.len());
+    }
+
+    for download in scheduled {
+        let url = download.url.clone();
+        let rearmed = spawn_download(
+            app_state.clone(),
+            DownloadRequest {
+                url: download.url,
+                options: download.options,
+                pinned: download.pinned,
+                start_at: download.start_at,
+                tags: Vec::new(),
+                video: None,
+            },
+        )
+        .await;
+        if let Err(err) = rearmed {
+            error!("failed to re-arm url: {}, err: {}", url, err);
+        }
+    }
+}
+
+/// Adds the download and runs it in the background, forwarding its progress to the websocket
+/// subscribers.
+/// # Errors
+/// Possible error variants are: DownloadAlreadyPresent, SameVideo, ShuttingDown
+pub async fn spawn_download(
+    app_state: AppState,
+    mut download: DownloadRequest,
+) -> ytdlp::Result<()> {
+    let download_kill_rx = app_state.ytdlp_client.track_download(&mut download).await?;
+    let download_update_tx = app_state.events.forwarder();
 
     tokio::task::spawn(async move {
         let _ = app_state
             .ytdlp_client
-            .download_from_options(&download.url, &download.options, Some(download_update_tx))
+            .download_from_options(download, download_kill_rx, Some(download_update_tx))
             .await;
     });
+    Ok(())
+}
 
-    Ok(StatusCode::CREATED)
+fn enqueue_error(err: ytdlp::Error) -> ApiError {
+    match err {
+        ytdlp::Error::DownloadAlreadyPresent => {
+            ApiError::new(StatusCode::CONFLICT, Message::new("download.present"))
+        }
+        ytdlp::Error::SameVideo { id, url } => ApiError::new(
+            StatusCode::CONFLICT,
+            Message::new("download.same_video")
+                .with("url", url)
+                .with("id", id),
+        ),
+        ytdlp::Error::ShuttingDown => ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            Message::new("server.shutting_down"),
+        ),
+        _ => unreachable!(),
+    }
+}
+
+/// Starts a synthetic download, see [`YtdlpClient::run_synthetic`].
+pub fn spawn_synthetic(app_state: AppState, url: Url, steps: u32, step_interval: Duration) {
+    let download_update_tx = app_state.events.forwarder();
+
+    tokio::task::spawn(async move {
+        if let Err(err) = app_state
+            .ytdlp_client
+            .run_synthetic(&url, steps, step_interval, Some(download_update_tx))
+            .await
+        {
+            error!("synthetic download {} didn't start: {}", url, err);
+        }
+    });
 }
 
 async fn download_websocket(
     ws: WebSocketUpgrade,
-    State(tx): State<Arc<Mutex<broadcast::Sender<String>>>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_download_websocket(socket, tx))
+    State(app_state): State<AppState>,
+    Query(query): Query<WebsocketQuery>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let ip = connect_info.map(|Extension(ConnectInfo(address))| address.ip());
+    let slot = app_state.sockets.open(ip).map_err(|limit| {
+        ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            Message::new("websocket.too_many").with("limit", limit),
+        )
+    })?;
+    let categories = match query.events {
+        Some(events) => Some(events::parse_categories(&events).map_err(|category| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("events.unknown_category").with("category", category),
+            )
+        })?),
+        None => None,
+    };
+    let subscriber = app_state.events.subscribe(categories);
+    let shutdown = app_state.ytdlp_client.shutdown_signal();
+    let subscriptions = app_state.subscriptions.clone();
+
+    Ok(ws.on_upgrade(move |socket| async move {
+        let _slot = slot;
+        let replaced = query
+            .client
+            .as_deref()
+            .map(|client| subscriptions.replace(client));
+        handle_download_websocket(
+            socket,
+            subscriber,
+            shutdown,
+            replaced.as_deref(),
+            query.format,
+        )
+        .await;
+        if let (Some(client), Some(replaced)) = (&query.client, &replaced) {
+            subscriptions.remove(client, replaced);
+        }
+    }))
+}
+
+async fn get_download_detail(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(id): Path<i64>,
+) -> Result<Json<DownloadDetail>, StatusCode> {
+    match ytdlp_client.get_download_detail(id).await {
+        Some(detail) => Ok(Json(detail)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Serves everything yt-dlp wrote while running the download, across its attempts.
+async fn get_log(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let not_found = || ApiError::new(StatusCode::NOT_FOUND, Message::new("download.no_log"));
+    let log = ytdlp_client.get_log_path(id).ok_or_else(not_found)?;
+    let bytes = tokio::fs::read(&log).await.map_err(|_| not_found())?;
+
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], bytes))
+}
+
+/// Serves the download's thumbnail, or the storyboard made when it didn't come with one.
+async fn get_thumbnail(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let not_found = || ApiError::new(StatusCode::NOT_FOUND, Message::new("download.no_thumbnail"));
+    let thumbnail = ytdlp_client.get_thumbnail(id).ok_or_else(not_found)?;
+    let bytes = tokio::fs::read(&thumbnail).await.map_err(|_| not_found())?;
+
+    let content_type = match thumbnail
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    };
+    Ok(([(header::CONTENT_TYPE, content_type)], bytes))
+}
+
+async fn get_downloads(
+    State(ytdlp_client): State<YtdlpClient>,
+    Query(query): Query<DownloadsQuery>,
+) -> Result<Json<Vec<DownloadInfo>>, ApiError> {
+    let filter = DownloadFilter {
+        created_after: query.created_after,
+        created_before: query.created_before,
+        min_size: query.min_size,
+        max_size: query.max_size,
+        domain: query.domain.clone(),
+    };
+    let matching = match filter.is_empty() {
+        true => None,
+        false => Some(
+            ytdlp_client
+                .filter_downloads(&filter)
+                .await
+                .map_err(ApiError::internal)?,
+        ),
+    };
+
+    let mut downloads: Vec<DownloadInfo> = ytdlp_client
+        .get_downloads()
+        .await
+        .into_iter()
+        .filter(|download| {
+            matching
+                .as_ref()
+                .is_none_or(|matching| matching.contains(&download.id))
+        })
+        .filter(|download| query.pinned.is_none_or(|pinned| download.pinned == pinned))
+        .filter(|download| {
+            query
+                .starred
+                .is_none_or(|starred| download.starred == starred)
+        })
+        .filter(|download| {
+            query
+                .upgradeable
+                .is_none_or(|upgradeable| download.upgradeable == upgradeable)
+        })
+        .filter(|download| {
+            query.video_codec.as_deref().is_none_or(|codec| {
+                download
+                    .media
+                    .as_ref()
+                    .is_some_and(|media| media.has_video_codec(codec))
+            })
+        })
+        .filter(|download| {
+            query.not_video_codec.as_deref().is_none_or(|codec| {
+                download
+                    .media
+                    .as_ref()
+                    .is_some_and(|media| !media.has_video_codec(codec))
+            })
+        })
+        .collect();
+
+    // Ties break on id so every row has a fixed place for cursors to point at.
+    let compare = |a: &DownloadInfo, b: &DownloadInfo| {
+        let ordering = match query.sort {
+            DownloadsSort::CreatedAt => a.created_at.cmp(&b.created_at),
+            DownloadsSort::StartedAt => a.started_at.cmp(&b.started_at),
+            DownloadsSort::FinishedAt => a.finished_at.cmp(&b.finished_at),
+            DownloadsSort::Elapsed => a
+                .elapsed_secs
+                .unwrap_or(0.0)
+                .total_cmp(&b.elapsed_secs.unwrap_or(0.0)),
+        }
+        .then(a.id.cmp(&b.id));
+        match query.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    };
+    downloads.sort_by(compare);
+
+    if let Some(after) = query.after {
+        let cursor = match downloads.iter().find(|download| download.id == after) {
+            Some(cursor) => cursor.clone(),
+            None => {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    Message::new("cursor.unknown"),
+                ))
+            }
+        };
+        downloads.retain(|download| compare(download, &cursor).is_gt());
+    }
+
+    if let Some(limit) = query.limit {
+        downloads.truncate(limit);
+    }
+
+    Ok(Json(downloads))
+}
+
+/// Pages through completed, failed and canceled downloads, newest first by default.
+async fn get_history(
+    State(ytdlp_client): State<YtdlpClient>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryPage>, ApiError> {
+    let statuses = match query.status {
+        Some(status) if history::FINISHED.contains(&status) => vec![status],
+        Some(status) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                Message::new("history.invalid_status").with("status", format!("{:?}", status)),
+            ))
+        }
+        None => history::FINISHED.to_vec(),
+    };
+
+    ytdlp_client
+        .get_history(
+            &statuses,
+            query.tag.as_deref(),
+            query.starred,
+            query.sort,
+            query.page.max(1),
+            query.per_page.clamp(1, MAX_HISTORY_PAGE),
+        )
+        .await
+        .map(Json)
+        .map_err(ApiError::internal)
+}
+
+async fn get_process_usage(State(ytdlp_client): State<YtdlpClient>) -> Json<Vec<DownloadUsage>> {
+    Json(ytdlp_client.get_process_usage().await)
+}
+
+async fn get_tags(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<Tag>>, ApiError> {
+    match ytdlp_client.get_tags(id).await {
+        Ok(Some(tags)) => Ok(Json(tags)),
+        Ok(None) => Err(unknown_download()),
+        Err(err) => Err(ApiError::internal(err)),
+    }
 }
 
 async fn get_urls(State(ytdlp_client): State<YtdlpClient>) -> Result<String, StatusCode> {
@@ -157,31 +1230,59 @@ async fn get_urls(State(ytdlp_client): State<YtdlpClient>) -> Result<String, Sta
     }
 }
 
-async fn handle_download_websocket(socket: WebSocket, tx: Arc<Mutex<broadcast::Sender<String>>>) {
-    let mut rx = tx.lock().await.subscribe();
-
-    let (mut ws_tx, mut ws_rx) = socket.split();
-
-    // tokio::spawn(async move {
-    //     // Broadcast incoming messages from clients to all
-    //     while let Some(Ok(message)) = ws_rx.next().await {
-    //         if let axum::extract::ws::Message::Text(text) = message {
-    //             if let Err(e) = tx.lock().await.send(text.to_string()) {
-    //                 eprintln!("Error broadcasting message: {:?}", e);
-    //             }
-    //         }
-    //     }
-    // });
+async fn handle_download_websocket(
+    socket: WebSocket,
+    mut subscriber: EventSubscriber,
+    mut shutdown: watch::Receiver<bool>,
+    replaced: Option<&Notify>,
+    format: FrameFormat,
+) {
+    let (mut ws_tx, _ws_rx) = socket.split();
 
     // Broadcast to this client any messages received by the server
-    while let Ok(message) = rx.recv().await {
-        if let Err(e) = ws_tx
-            .send(axum::extract::ws::Message::Text(message.into()))
-            .await
-        {
+    let reason = loop {
+        let event = tokio::select! {
+            event = subscriber.recv() => match event {
+                Ok(event) => event,
+                // Every sender is gone, which only happens as the server stops.
+                Err(_) => break CloseReason::ServerShutdown,
+            },
+            _ = async { shutdown.wait_for(|shutting_down| *shutting_down).await.is_ok() } => {
+                break CloseReason::ServerShutdown;
+            }
+            _ = async {
+                match replaced {
+                    Some(replaced) => replaced.notified().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                break CloseReason::SubscriptionReplaced;
+            }
+        };
+        let message = match format {
+            FrameFormat::Json => serde_json::to_string(&event)
+                .map(|text| ws::Message::Text(text.into()))
+                .map_err(|err| err.to_string()),
+            FrameFormat::Msgpack => rmp_serde::to_vec_named(&event)
+                .map(|bytes| ws::Message::Binary(bytes.into()))
+                .map_err(|err| err.to_string()),
+        };
+        let message = match message {
+            Ok(message) => message,
+            Err(err) => {
+                error!("failed to serialize message for client: {}", err);
+                continue;
+            }
+        };
+
+        if let Err(e) = ws_tx.send(message).await {
             error!("sending message to client, client disconnected: {}", e);
             return;
         }
+    };
+
+    if let Err(e) = ws_tx.send(ws::Message::Close(Some(reason.frame()))).await {
+        error!("closing websocket for {:?}: {}", reason, e);
     }
 }
 
@@ -196,4 +1297,185 @@ async fn pause_download(
         },
         Err(_) => StatusCode::BAD_REQUEST,
     }
-}
\ No newline at end of file
+}
+
+async fn pause_all(State(ytdlp_client): State<YtdlpClient>) -> Json<BTreeMap<Url, BulkResult>> {
+    Json(bulk_results(ytdlp_client.halt_all(Signal::Pause).await))
+}
+
+async fn pin_download(
+    State(ytdlp_client): State<YtdlpClient>,
+    Json(pin): Json<PinRequest>,
+) -> StatusCode {
+    match ytdlp_client.set_pinned(&pin.url, pin.pinned).await {
+        Ok(_) => StatusCode::OK,
+        Err(_) => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Flips whether the download is starred.
+async fn toggle_star(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(id): Path<i64>,
+) -> Result<Json<StarResult>, ApiError> {
+    ytdlp_client
+        .toggle_starred(id)
+        .await
+        .map(|starred| Json(StarResult { starred }))
+        .ok_or_else(unknown_download)
+}
+
+/// Runs an upgrade scan and re-downloads the items whose upgrade clears the thresholds.
+/// Returns `None` if a scan is already in progress.
+pub async fn run_upgrade_scan(app_state: AppState, settings: ScanSettings) -> Option<ScanResult> {
+    let mut result = app_state.upgrade_scanner.scan().await?;
+
+    for report in result
+        .upgradeable
+        .iter()
+        .filter(|report| settings.should_enqueue(report))
+    {
+        let download = match app_state.ytdlp_client.get_download(&report.url).await {
+            Some(download) => download,
+            None => continue,
+        };
+        let mut upgrade = DownloadRequest {
+            url: report.url.clone(),
+            options: upgrade::upgraded_options(&download.options, report),
+            pinned: download.pinned,
+            start_at: None,
+            tags: Vec::new(),
+            video: None,
+        };
+
+        if let Err(err) = check_download(&app_state, &mut upgrade).await {
+            error!(
+                "skipping upgrade of url: {}, err: {}",
+                report.url, err.message
+            );
+            continue;
+        }
+
+        if let Err(err) = app_state.ytdlp_client.start_upgrade(&report.url) {
+            error!("skipping upgrade of url: {}, err: {}", report.url, err);
+            continue;
+        }
+        match spawn_download(app_state.clone(), upgrade).await {
+            Ok(()) => {
+                info!("enqueueing upgrade of url: {}", report.url);
+                result.enqueued.push(report.url.clone());
+            }
+            Err(err) => {
+                app_state.ytdlp_client.cancel_upgrade(&report.url);
+                error!("skipping upgrade of url: {}, err: {}", report.url, err);
+            }
+        }
+    }
+
+    Some(result)
+}
+
+fn probe_timed_out() -> ApiError {
+    ApiError::new(StatusCode::GATEWAY_TIMEOUT, Message::new("ytdlp.timed_out"))
+}
+
+fn unknown_download() -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, Message::new("download.unknown"))
+}
+
+async fn scan_for_upgrades(
+    State(app_state): State<AppState>,
+    Json(settings): Json<ScanSettings>,
+) -> Result<Json<ScanResult>, ApiError> {
+    match run_upgrade_scan(app_state, settings).await {
+        Some(result) => Ok(Json(result)),
+        None => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            Message::new("upgrade.scan_running"),
+        )),
+    }
+}
+
+/// The pending downloads as requests, in the shape `/queue/import` takes them back, so the queue
+/// can be backed up or moved to another instance. Imports are checked like any other batch.
+async fn export_queue(State(ytdlp_client): State<YtdlpClient>) -> impl IntoResponse {
+    let requests: Vec<DownloadRequest> = ytdlp_client
+        .get_pending()
+        .await
+        .into_iter()
+        .map(|download| DownloadRequest {
+            url: download.url,
+            options: download.options,
+            pinned: download.pinned,
+            start_at: download.start_at,
+            tags: Vec::new(),
+            video: None,
+        })
+        .collect();
+
+    (
+        [(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"queue.json\"",
+        )],
+        Json(requests),
+    )
+}
+
+async fn get_queue(State(ytdlp_client): State<YtdlpClient>) -> Json<Vec<DownloadInfo>> {
+    Json(ytdlp_client.get_queue().await)
+}
+
+/// Sites the queue is holding back after they answered with too many requests.
+async fn get_throttles(State(ytdlp_client): State<YtdlpClient>) -> Json<Vec<DomainThrottle>> {
+    Json(ytdlp_client.throttles())
+}
+
+/// Reorders queued downloads, returning the queue as it now stands.
+async fn reorder_queue(
+    State(ytdlp_client): State<YtdlpClient>,
+    Json(request): Json<ReorderRequest>,
+) -> Result<Json<Vec<DownloadInfo>>, ApiError> {
+    let unique: HashSet<&Url> = request.urls.iter().collect();
+    if unique.len() != request.urls.len() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            Message::new("queue.repeated_url"),
+        ));
+    }
+
+    match ytdlp_client.reorder_queue(&request.urls).await {
+        Ok(_) => Ok(Json(ytdlp_client.get_queue().await)),
+        Err(_) => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            Message::new("queue.not_queued"),
+        )),
+    }
+}
+
+async fn set_priority(
+    State(ytdlp_client): State<YtdlpClient>,
+    Json(request): Json<PriorityRequest>,
+) -> StatusCode {
+    match ytdlp_client
+        .set_priority(&request.url, request.priority)
+        .await
+    {
+        Ok(_) => StatusCode::OK,
+        Err(_) => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Replaces the download's tags with the named ones, creating those that don't exist yet.
+async fn set_tags(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(id): Path<i64>,
+    Json(names): Json<Vec<String>>,
+) -> Result<Json<Vec<Tag>>, ApiError> {
+    check_tags(&names)?;
+    match ytdlp_client.set_tags(id, &names).await {
+        Ok(Some(tags)) => Ok(Json(tags)),
+        Ok(None) => Err(unknown_download()),
+        Err(err) => Err(ApiError::internal(err)),
+    }
+}
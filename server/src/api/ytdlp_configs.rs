@@ -0,0 +1,114 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+
+use super::state::AppState;
+use crate::core::messages::Message;
+use crate::core::ytdlp::YtdlpClient;
+use crate::core::ytdlp_configs::{self, ConfigError, ConfigFile};
+use crate::error::ApiError;
+
+// <----- Routes ----->
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_configs))
+        .route("/validate", post(validate_config))
+        .route(
+            "/{name}",
+            get(get_config).put(save_config).delete(delete_config),
+        )
+}
+
+// <----- Functions ----->
+
+async fn get_configs(
+    State(ytdlp_client): State<YtdlpClient>,
+) -> Result<Json<Vec<ConfigFile>>, ApiError> {
+    ytdlp_configs::list(ytdlp_client.ytdlp_config_dir())
+        .await
+        .map(Json)
+        .map_err(ApiError::internal)
+}
+
+async fn get_config(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let path = config_path(&ytdlp_client, &name)?;
+    match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => Ok((
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            contents,
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(unknown_config(&name)),
+        Err(err) => Err(ApiError::internal(err)),
+    }
+}
+
+/// Uploads a config file as the request body, replacing one with the same name.
+async fn save_config(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(name): Path<String>,
+    contents: String,
+) -> Result<StatusCode, ApiError> {
+    config_path(&ytdlp_client, &name)?;
+    match ytdlp_configs::save(ytdlp_client.ytdlp_config_dir(), &name, &contents).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(ConfigError::Forbidden(option)) => Err(forbidden_option(&option)),
+        Err(ConfigError::Io(err)) => Err(ApiError::internal(err)),
+    }
+}
+
+/// Checks a config file without saving it.
+async fn validate_config(contents: String) -> Result<StatusCode, ApiError> {
+    match ytdlp_configs::forbidden_option(&contents) {
+        Some(option) => Err(forbidden_option(&option)),
+        None => Ok(StatusCode::OK),
+    }
+}
+
+/// Downloads already set to use the config fail on their next run, as yt-dlp can't find it.
+async fn delete_config(
+    State(ytdlp_client): State<YtdlpClient>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let path = config_path(&ytdlp_client, &name)?;
+    match tokio::fs::remove_file(&path).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(unknown_config(&name)),
+        Err(err) => Err(ApiError::internal(err)),
+    }
+}
+
+fn config_path(ytdlp_client: &YtdlpClient, name: &str) -> Result<std::path::PathBuf, ApiError> {
+    match ytdlp_configs::is_valid_name(name) {
+        true => Ok(ytdlp_configs::path(ytdlp_client.ytdlp_config_dir(), name)),
+        false => Err(invalid_name(name)),
+    }
+}
+
+pub fn invalid_name(name: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::BAD_REQUEST,
+        Message::new("ytdlp_config.invalid_name").with("name", name),
+    )
+}
+
+pub fn forbidden_option(option: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::BAD_REQUEST,
+        Message::new("ytdlp_config.forbidden_option").with("option", option),
+    )
+}
+
+fn unknown_config(name: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        Message::new("ytdlp_config.unknown").with("name", name),
+    )
+}
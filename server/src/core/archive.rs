@@ -0,0 +1,43 @@
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Whether `entry` reads like a line yt-dlp writes to its archive, an extractor and a video id
+/// separated by a space, e.g. `youtube dQw4w9WgXcQ`.
+pub fn is_valid_entry(entry: &str) -> bool {
+    let mut parts = entry.split(' ');
+    matches!(
+        (parts.next(), parts.next(), parts.next()),
+        (Some(extractor), Some(id), None) if !extractor.is_empty() && !id.is_empty()
+    )
+}
+
+/// The entries in the archive, empty if yt-dlp hasn't written it yet.
+/// # Errors
+/// Why the archive couldn't be read.
+pub async fn load(path: &Path) -> std::io::Result<Vec<String>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Replaces the archive with `entries`, written to a temporary file first so yt-dlp never reads
+/// half of it.
+/// # Errors
+/// Why the archive couldn't be written.
+pub async fn save(path: &Path, entries: &[String]) -> std::io::Result<()> {
+    let mut contents = entries.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+
+    let temporary = path.with_extension("tmp");
+    tokio::fs::write(&temporary, contents).await?;
+    tokio::fs::rename(&temporary, path).await
+}
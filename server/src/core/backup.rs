@@ -0,0 +1,122 @@
+use chrono::Utc;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection, SqlitePool};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
+use tracing::info;
+
+use crate::MIGRATOR;
+
+/// The first bytes of every SQLite database file.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Why an uploaded file can't be restored.
+#[derive(Debug)]
+pub enum RestoreError {
+    NotDatabase,
+    Corrupt(String),
+    /// Written by a newer version, whose migrations this one doesn't know.
+    NewerSchema(i64),
+    Io(std::io::Error),
+}
+
+/// `path` with `suffix` tacked onto the file name, e.g. `sqlite.db` to `sqlite.db.restore`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Where an uploaded database waits for the next start to take the live one's place.
+pub fn staged_path(db_path: &Path) -> PathBuf {
+    with_suffix(db_path, ".restore")
+}
+
+/// Writes a consistent copy of the database to `to` while it keeps taking writes.
+pub async fn snapshot(db: &SqlitePool, to: &Path) -> sqlx::Result<()> {
+    sqlx::query("VACUUM INTO $1")
+        .bind(to.to_string_lossy())
+        .execute(db)
+        .await
+        .map(|_| ())
+}
+
+/// Checks that `upload` is an intact database this version can migrate, then moves it to where
+/// [`apply_staged`] picks it up. An earlier upload still waiting is replaced.
+pub async fn stage(db_path: &Path, upload: &Path) -> Result<(), RestoreError> {
+    let mut header = [0; SQLITE_HEADER.len()];
+    let mut file = tokio::fs::File::open(upload)
+        .await
+        .map_err(RestoreError::Io)?;
+    if file.read_exact(&mut header).await.is_err() || header[..] != *SQLITE_HEADER {
+        return Err(RestoreError::NotDatabase);
+    }
+    drop(file);
+
+    let mut connection = SqliteConnectOptions::new()
+        .filename(upload)
+        .read_only(true)
+        .connect()
+        .await
+        .map_err(|_| RestoreError::NotDatabase)?;
+    let integrity: String = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_one(&mut connection)
+        .await
+        .map_err(|err| RestoreError::Corrupt(err.to_string()))?;
+    // Only our own databases have run migrations.
+    let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations")
+        .fetch_one(&mut connection)
+        .await
+        .map_err(|_| RestoreError::NotDatabase)?;
+    let _ = connection.close().await;
+
+    if integrity != "ok" {
+        return Err(RestoreError::Corrupt(integrity));
+    }
+    let latest = MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or_default();
+    match version {
+        None => return Err(RestoreError::NotDatabase),
+        Some(version) if version > latest => return Err(RestoreError::NewerSchema(version)),
+        Some(_) => {}
+    }
+
+    tokio::fs::rename(upload, staged_path(db_path))
+        .await
+        .map_err(RestoreError::Io)
+}
+
+/// Swaps a staged upload in for the database at `db_path`, moving the old one and its WAL aside
+/// under the time it was replaced. Has to run before anything opens the database.
+pub fn apply_staged(db_path: &Path) {
+    let staged = staged_path(db_path);
+    if !staged.exists() {
+        return;
+    }
+
+    let aside = with_suffix(
+        db_path,
+        &format!(".{}.replaced", Utc::now().format("%Y%m%d%H%M%S")),
+    );
+    if db_path.exists() {
+        fs::rename(db_path, &aside).expect("couldn't move the database aside to restore over it");
+    }
+    // A WAL left behind would be replayed into the restored database.
+    for suffix in ["-wal", "-shm"] {
+        let file = with_suffix(db_path, suffix);
+        if file.exists() {
+            fs::rename(&file, with_suffix(&aside, suffix))
+                .expect("couldn't move the database's WAL aside to restore over it");
+        }
+    }
+    fs::rename(&staged, db_path).expect("couldn't move the staged backup into place");
+    info!(
+        "restored the database from an uploaded backup, the old one is at: {}",
+        aside.display()
+    );
+}
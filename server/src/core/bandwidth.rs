@@ -0,0 +1,34 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::clock;
+
+/// A stretch of the day with its own rate limit, `None` lifts the limit altogether.
+/// A window whose end comes before its start runs past midnight, one that ends where it starts
+/// covers the whole day.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BandwidthWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub rate_limit: Option<String>,
+}
+
+impl BandwidthWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        clock::within(self.start, self.end, time)
+    }
+}
+
+/// The rate limit in force at `now` on the instance clock. The first window containing it wins,
+/// outside of every window `default` applies.
+pub fn rate_limit_at(
+    windows: &[BandwidthWindow],
+    default: Option<String>,
+    now: DateTime<Utc>,
+) -> Option<String> {
+    let time = clock::local(now).time();
+    match windows.iter().find(|window| window.contains(time)) {
+        Some(window) => window.rate_limit.clone(),
+        None => default,
+    }
+}
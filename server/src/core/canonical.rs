@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Query parameters that only track where a link was shared from, dropped on every site.
+const TRACKING_PARAMETERS: &[&str] = &[
+    "fbclid", "gclid", "igshid", "mc_cid", "mc_eid", "si", "yclid",
+];
+/// Prefixes of whole families of tracking parameters, e.g. `utm_source`.
+const TRACKING_PREFIXES: &[&str] = &["utm_"];
+/// YouTube's own sharing and navigation parameters, which don't change the video.
+const YOUTUBE_TRACKING_PARAMETERS: &[&str] = &["ab_channel", "feature", "pp"];
+const YOUTUBE_HOSTS: &[&str] = &["m.youtube.com", "youtube.com", "www.youtube.com"];
+/// Paths that hold a single video's id under another name, e.g. `/embed/<id>`.
+const YOUTUBE_VIDEO_PATHS: &[&str] = &["embed", "live", "v"];
+
+/// What happens to YouTube Shorts and clip links, which extractors treat differently from the
+/// watch page of the same video.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum ShortFormPolicy {
+    /// Downloads them from the watch url. A clip becomes the whole video it was cut from.
+    #[default]
+    Rewrite,
+    /// Downloads them as submitted.
+    Keep,
+    /// Rejects the url.
+    Reject,
+}
+
+/// A YouTube link to a video that isn't its watch url.
+pub enum ShortForm {
+    /// `/shorts/<id>`, whose id is the video's.
+    Shorts(String),
+    /// `/clip/<id>`, whose id only yt-dlp can trace back to the video.
+    Clip,
+}
+
+/// The url the manager stores for `url`, so the same video pasted from different places is only
+/// kept once. Tracking parameters are dropped and YouTube's short links and alternative paths
+/// become `https://www.youtube.com/watch?v=<id>`, keeping parameters like `t` and `list`.
+/// Shorts and clips are left to [`ShortFormPolicy`].
+pub fn normalize(url: Url) -> Url {
+    let host = url.host_str().unwrap_or_default().to_string();
+    let youtube = host == "youtu.be" || YOUTUBE_HOSTS.contains(&host.as_str());
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+
+    let video_id = match (host.as_str(), segments.as_slice()) {
+        ("youtu.be", [id]) => Some(id.to_string()),
+        (_, [path, id]) if youtube && YOUTUBE_VIDEO_PATHS.contains(path) => Some(id.to_string()),
+        _ => None,
+    };
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !is_tracking(key, youtube))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    let stripped = pairs.len() != url.query_pairs().count();
+
+    let mut normalized = match &video_id {
+        Some(video_id) => watch_url(video_id),
+        None if youtube && host != "www.youtube.com" => {
+            let mut www = url.clone();
+            let _ = www.set_scheme("https");
+            let _ = www.set_host(Some("www.youtube.com"));
+            www
+        }
+        None if stripped => url.clone(),
+        None => return url,
+    };
+
+    if video_id.is_none() {
+        normalized.set_query(None);
+    }
+    if !pairs.is_empty() {
+        normalized.query_pairs_mut().extend_pairs(pairs);
+    }
+    normalized
+}
+
+/// Whether `url` is a YouTube Shorts or clip link, see [`ShortFormPolicy`].
+pub fn short_form(url: &Url) -> Option<ShortForm> {
+    if !YOUTUBE_HOSTS.contains(&url.host_str().unwrap_or_default()) {
+        return None;
+    }
+
+    let mut segments = url.path_segments()?.filter(|segment| !segment.is_empty());
+    match (segments.next(), segments.next(), segments.next()) {
+        (Some("shorts"), Some(id), None) => Some(ShortForm::Shorts(id.to_string())),
+        (Some("clip"), Some(_), None) => Some(ShortForm::Clip),
+        _ => None,
+    }
+}
+
+/// The watch url of the YouTube video `video_id`.
+pub fn watch_url(video_id: &str) -> Url {
+    let mut watch =
+        Url::parse("https://www.youtube.com/watch").expect("couldn't parse youtube url");
+    watch.query_pairs_mut().append_pair("v", video_id);
+    watch
+}
+
+fn is_tracking(key: &str, youtube: bool) -> bool {
+    TRACKING_PARAMETERS.contains(&key)
+        || TRACKING_PREFIXES
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+        || (youtube && YOUTUBE_TRACKING_PARAMETERS.contains(&key))
+}
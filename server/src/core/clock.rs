@@ -0,0 +1,78 @@
+use chrono::{DateTime, Days, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::sync::OnceLock;
+use tracing::error;
+
+static TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+/// A moment as stored, in UTC, alongside the same moment on the instance's wall clock.
+#[derive(Clone, Debug, Serialize)]
+pub struct LocalTime {
+    pub utc: DateTime<Utc>,
+    pub local: DateTime<Tz>,
+}
+
+impl From<DateTime<Utc>> for LocalTime {
+    fn from(utc: DateTime<Utc>) -> LocalTime {
+        LocalTime {
+            utc,
+            local: local(utc),
+        }
+    }
+}
+
+/// Sets the timezone schedules are read in. Call once at startup.
+pub fn init(timezone: Tz) {
+    if TIMEZONE.set(timezone).is_err() {
+        error!("instance timezone was already initialized");
+    }
+}
+
+/// The instance timezone, UTC until `init` is called.
+pub fn timezone() -> Tz {
+    *TIMEZONE.get_or_init(|| Tz::UTC)
+}
+
+pub fn local(utc: DateTime<Utc>) -> DateTime<Tz> {
+    utc.with_timezone(&timezone())
+}
+
+/// Whether `time` falls in the stretch of the day from `start` up to `end`. One whose end comes
+/// before its start runs past midnight, one that ends where it starts covers the whole day.
+pub fn within(start: NaiveTime, end: NaiveTime, time: NaiveTime) -> bool {
+    match start.cmp(&end) {
+        Ordering::Less => start <= time && time < end,
+        Ordering::Greater => time >= start || time < end,
+        Ordering::Equal => true,
+    }
+}
+
+/// The next time the instance's wall clock reads `at`, after `after`.
+/// A time skipped by a daylight saving change runs at the first valid moment after it.
+pub fn next_daily(at: NaiveTime, after: DateTime<Utc>) -> DateTime<Utc> {
+    let timezone = timezone();
+    let mut day = local(after).date_naive();
+    loop {
+        let candidate = timezone
+            .from_local_datetime(&day.and_time(at))
+            .earliest()
+            .or_else(|| {
+                // The wall clock jumps over `at`, so take the first minute that exists.
+                (1..=180).find_map(|minutes| {
+                    timezone
+                        .from_local_datetime(
+                            &(day.and_time(at) + chrono::Duration::minutes(minutes)),
+                        )
+                        .earliest()
+                })
+            })
+            .map(|candidate| candidate.with_timezone(&Utc));
+
+        if let Some(candidate) = candidate.filter(|candidate| *candidate > after) {
+            return candidate;
+        }
+        day = day + Days::new(1);
+    }
+}
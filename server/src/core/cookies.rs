@@ -0,0 +1,239 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use super::messages::Message;
+
+/// Marks a cookie line that would otherwise read as a comment.
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// The browsers yt-dlp's `--cookies-from-browser` reads.
+pub const BROWSERS: &[&str] = &[
+    "brave", "chrome", "chromium", "edge", "firefox", "opera", "safari", "vivaldi", "whale",
+];
+
+/// A stored cookies.txt file, listed without its cookies since they're credentials.
+#[derive(Clone, Debug, Serialize)]
+pub struct CookieFile {
+    pub name: String,
+    pub size: u64,
+    pub cookies: usize,
+    /// The sites the cookies are for.
+    pub domains: Vec<String>,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum CookieError {
+    /// The 1-based line that isn't a cookie in the Netscape format.
+    InvalidLine(usize),
+    Empty,
+    Io(io::Error),
+}
+
+/// Names are used as file names and usually name the site, such as `youtube.com`, so they're
+/// kept to letters, digits, `.`, `-` and `_`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+pub fn path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.txt", name))
+}
+
+/// The domains of the cookies in a file in the Netscape format yt-dlp reads, one cookie per
+/// line as seven tab separated fields.
+pub fn parse_domains(contents: &str) -> Result<Vec<String>, CookieError> {
+    let mut domains = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        let cookie = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(cookie) => cookie,
+            None if line.trim().is_empty() || line.starts_with('#') => continue,
+            None => line,
+        };
+        let fields: Vec<&str> = cookie.split('\t').collect();
+        if fields.len() != 7 || fields[0].is_empty() {
+            return Err(CookieError::InvalidLine(number + 1));
+        }
+        domains.push(fields[0].trim_start_matches('.').to_lowercase());
+    }
+    match domains.is_empty() {
+        true => Err(CookieError::Empty),
+        false => Ok(domains),
+    }
+}
+
+pub async fn list(dir: &Path) -> io::Result<Vec<CookieFile>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut files = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let Some(name) = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .filter(|_| path.extension().is_some_and(|extension| extension == "txt"))
+        else {
+            continue;
+        };
+        let metadata = entry.metadata().await?;
+        // yt-dlp writes the jar back after each run, so what's there may have changed since upload.
+        let domains = tokio::fs::read_to_string(&path)
+            .await
+            .ok()
+            .and_then(|contents| parse_domains(&contents).ok())
+            .unwrap_or_default();
+        files.push(CookieFile {
+            name: name.to_string(),
+            size: metadata.len(),
+            cookies: domains.len(),
+            domains: domains
+                .into_iter()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+            modified_at: metadata.modified().ok().map(DateTime::from),
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+/// Checks and writes a cookies file readable only by the server, replacing one with the same
+/// name.
+pub async fn save(dir: &Path, name: &str, contents: &str) -> Result<(), CookieError> {
+    parse_domains(contents)?;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(CookieError::Io)?;
+    let path = path(dir, name);
+    tokio::fs::write(&path, contents)
+        .await
+        .map_err(CookieError::Io)?;
+    tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+        .await
+        .map_err(CookieError::Io)
+}
+
+/// What `--cookies-from-browser` is given, `BROWSER[+KEYRING][:PROFILE][::CONTAINER]`, without
+/// the keyring and container since they don't change where the cookies are.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BrowserSpec {
+    pub browser: String,
+    /// A profile name or the path of its folder, yt-dlp picks the most recently used if unset.
+    pub profile: Option<String>,
+}
+
+/// Whether yt-dlp will be able to read the configured browser's cookies.
+#[derive(Clone, Debug, Serialize)]
+pub struct BrowserCheck {
+    pub browser: String,
+    pub readable: bool,
+    pub cookie_file: Option<PathBuf>,
+    pub error: Option<Message>,
+}
+
+/// Parses a `--cookies-from-browser` value, `None` for a browser yt-dlp doesn't read.
+pub fn parse_browser_spec(spec: &str) -> Option<BrowserSpec> {
+    let spec = spec.split_once("::").map_or(spec, |(spec, _)| spec).trim();
+    let (browser, profile) = match spec.split_once(':') {
+        Some((browser, profile)) => (browser, Some(profile.to_string())),
+        None => (spec, None),
+    };
+    let browser = browser
+        .split_once('+')
+        .map_or(browser, |(browser, _)| browser)
+        .to_lowercase();
+    BROWSERS.contains(&browser.as_str()).then_some(BrowserSpec {
+        browser,
+        profile: profile.filter(|profile| !profile.is_empty()),
+    })
+}
+
+/// Where a browser keeps its profiles on Linux, the platform the server ships on.
+fn browser_root(browser: &str, home: &Path) -> PathBuf {
+    let config = home.join(".config");
+    match browser {
+        "brave" => config.join("BraveSoftware/Brave-Browser"),
+        "chrome" => config.join("google-chrome"),
+        "chromium" => config.join("chromium"),
+        "edge" => config.join("microsoft-edge"),
+        "firefox" => home.join(".mozilla/firefox"),
+        "opera" => config.join("opera"),
+        "safari" => home.join("Library/Cookies"),
+        "vivaldi" => config.join("vivaldi"),
+        _ => config.join("naver-whale"),
+    }
+}
+
+/// The cookie database in a profile folder, newer Chromium versions keep it under `Network`.
+fn cookie_file_in(browser: &str, profile: &Path) -> Option<PathBuf> {
+    let candidates: &[&str] = match browser {
+        "firefox" => &["cookies.sqlite"],
+        "safari" => &["Cookies.binarycookies"],
+        _ => &["Network/Cookies", "Cookies"],
+    };
+    candidates
+        .iter()
+        .map(|candidate| profile.join(candidate))
+        .find(|path| path.is_file())
+}
+
+/// Finds the profile's cookie database the way yt-dlp would and checks it can be opened. Without
+/// a profile every folder under the browser's is tried, like yt-dlp picking the last used one.
+pub fn check_browser(spec: &BrowserSpec, home: &Path) -> BrowserCheck {
+    let root = browser_root(&spec.browser, home);
+    let cookie_file = match &spec.profile {
+        Some(profile) if Path::new(profile).is_absolute() => {
+            cookie_file_in(&spec.browser, Path::new(profile))
+        }
+        Some(profile) => cookie_file_in(&spec.browser, &root.join(profile)),
+        None => cookie_file_in(&spec.browser, &root).or_else(|| {
+            std::fs::read_dir(&root)
+                .ok()?
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| cookie_file_in(&spec.browser, &entry.path()))
+                .max_by_key(|path| path.metadata().and_then(|m| m.modified()).ok())
+        }),
+    };
+
+    let error = match &cookie_file {
+        Some(path) => std::fs::File::open(path).err().map(|err| {
+            Message::new("cookies.browser_unreadable")
+                .with("path", path.display())
+                .with("error", err)
+        }),
+        None => {
+            let searched = match &spec.profile {
+                Some(profile) if Path::new(profile).is_absolute() => PathBuf::from(profile),
+                Some(profile) => root.join(profile),
+                None => root,
+            };
+            Some(
+                Message::new("cookies.browser_profile_missing")
+                    .with("browser", &spec.browser)
+                    .with("path", searched.display()),
+            )
+        }
+    };
+
+    BrowserCheck {
+        browser: spec.browser.clone(),
+        readable: error.is_none(),
+        cookie_file,
+        error,
+    }
+}
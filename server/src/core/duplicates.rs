@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+/// Width of the grayscale frame a content hash is taken from, one more than the bits per row.
+pub const FRAME_WIDTH: u32 = 9;
+pub const FRAME_HEIGHT: u32 = 8;
+/// Hashes differing in at most this many bits are taken to be the same video.
+const MAX_DISTANCE: u32 = 6;
+
+/// What happens when a url resolves to a video the manager already has under another url.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Rejects the url.
+    #[default]
+    Skip,
+    /// Downloads it anyway, marking it as a duplicate of the first.
+    Allow,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct DuplicateSettings {
+    pub policy: DuplicatePolicy,
+    /// Hashes a frame of every completed video to catch copies that don't share a video id,
+    /// such as re-uploads and mirrors.
+    #[serde(default)]
+    pub perceptual_hash: bool,
+}
+
+/// A difference hash of a `FRAME_WIDTH` by `FRAME_HEIGHT` grayscale frame, one bit per pair of
+/// neighbouring pixels. Stored as an `i64` for SQLite.
+pub fn dhash(pixels: &[u8]) -> Option<i64> {
+    if pixels.len() != (FRAME_WIDTH * FRAME_HEIGHT) as usize {
+        return None;
+    }
+
+    let hash = pixels
+        .chunks(FRAME_WIDTH as usize)
+        .flat_map(|row| row.windows(2).map(|pair| pair[0] > pair[1]))
+        .fold(0u64, |hash, brighter| (hash << 1) | u64::from(brighter));
+    Some(hash as i64)
+}
+
+/// Whether two content hashes are close enough to be the same video.
+pub fn is_match(a: i64, b: i64) -> bool {
+    (a ^ b).count_ones() <= MAX_DISTANCE
+}
@@ -0,0 +1,175 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tracing::{trace, warn};
+use url::Url;
+
+use super::messages::Message;
+use super::queue::DomainThrottle;
+use super::ytdlp::{DownloadProgress, Status};
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Progress(DownloadProgress),
+    Status {
+        url: Url,
+        status: Status,
+    },
+    Config {
+        key: String,
+        value: serde_json::Value,
+    },
+    SystemWarning {
+        #[serde(flatten)]
+        message: Message,
+    },
+    /// A site answered with too many requests and the queue is holding it back.
+    ThrottledByOrigin(DomainThrottle),
+    /// The download is no longer tracked, see [`super::ytdlp::YtdlpClient::remove_download`].
+    Removed {
+        id: i64,
+        url: Url,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EventCategory {
+    Config,
+    Progress,
+    Status,
+    System,
+}
+
+impl Event {
+    pub fn category(&self) -> EventCategory {
+        match self {
+            Event::Progress(_) => EventCategory::Progress,
+            Event::Status { .. } => EventCategory::Status,
+            Event::Config { .. } => EventCategory::Config,
+            Event::SystemWarning { .. } => EventCategory::System,
+            Event::ThrottledByOrigin(_) => EventCategory::System,
+            Event::Removed { .. } => EventCategory::Status,
+        }
+    }
+}
+
+/// Fails with the category name that wasn't recognized.
+impl FromStr for EventCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "config" => Ok(EventCategory::Config),
+            "progress" => Ok(EventCategory::Progress),
+            "status" => Ok(EventCategory::Status),
+            "system" => Ok(EventCategory::System),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+/// Parses a comma separated list of categories such as `progress,status`.
+pub fn parse_categories(categories: &str) -> Result<HashSet<EventCategory>, String> {
+    categories
+        .split(',')
+        .map(str::trim)
+        .filter(|category| !category.is_empty())
+        .map(EventCategory::from_str)
+        .collect()
+}
+
+/// How many events a subscriber can fall behind by before it starts missing them, unless
+/// configured otherwise.
+const CAPACITY: usize = 100;
+
+/// Carries events from their publishers, the download manager, config and storage, to the
+/// consumers subscribed to them, such as the websocket. Publishers don't know who's listening, so
+/// a new transport only has to subscribe.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+    capacity: usize,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus::with_capacity(CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> EventBus {
+        let capacity = capacity.max(1);
+        let (tx, _) = broadcast::channel(capacity);
+        EventBus { tx, capacity }
+    }
+
+    /// Sends `event` to the current subscribers, it's dropped when there are none.
+    pub fn publish(&self, event: Event) {
+        if self.tx.send(event).is_err() {
+            trace!("no subscribers for the event");
+        }
+    }
+
+    /// Subscribes to every category when `categories` is `None`.
+    pub fn subscribe(&self, categories: Option<HashSet<EventCategory>>) -> EventSubscriber {
+        EventSubscriber::new(self.tx.subscribe(), categories)
+    }
+
+    /// A channel for one download's events, each published as it arrives.
+    pub fn forwarder(&self) -> mpsc::Sender<Event> {
+        let (tx, mut rx) = mpsc::channel(self.capacity);
+        let bus = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                bus.publish(event);
+            }
+        });
+        tx
+    }
+}
+
+/// A broadcast receiver that only yields the event categories its subscriber asked for.
+pub struct EventSubscriber {
+    categories: Option<HashSet<EventCategory>>,
+    rx: broadcast::Receiver<Event>,
+}
+
+impl EventSubscriber {
+    fn new(
+        rx: broadcast::Receiver<Event>,
+        categories: Option<HashSet<EventCategory>>,
+    ) -> EventSubscriber {
+        EventSubscriber { categories, rx }
+    }
+
+    /// Waits for the next wanted event, skipping over any the subscriber fell behind on.
+    /// # Errors
+    /// Returns `RecvError::Closed` once every sender is gone.
+    pub async fn recv(&mut self) -> Result<Event, RecvError> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) => {
+                    let wanted = self
+                        .categories
+                        .as_ref()
+                        .is_none_or(|categories| categories.contains(&event.category()));
+                    if wanted {
+                        return Ok(event);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("event subscriber lagged, skipped {} events", skipped);
+                }
+                Err(RecvError::Closed) => return Err(RecvError::Closed),
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use url::Url;
+
+/// Narrows the downloads list by what the Download table keeps indexed, for housekeeping like
+/// everything over 2 GB from last month.
+#[derive(Clone, Debug, Default)]
+pub struct DownloadFilter {
+    /// Only downloads created at or after this time.
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only downloads created before this time.
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only downloads whose finished file holds at least this many bytes.
+    pub min_size: Option<u64>,
+    /// Only downloads whose finished file holds at most this many bytes.
+    pub max_size: Option<u64>,
+    /// Only downloads from this host or its subdomains, e.g. `youtube.com`.
+    pub domain: Option<String>,
+}
+
+impl DownloadFilter {
+    pub fn is_empty(&self) -> bool {
+        self.created_after.is_none()
+            && self.created_before.is_none()
+            && self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.domain.is_none()
+    }
+}
+
+/// The host of `url` with its labels reversed, `www.youtube.com` becomes `com.youtube.www`, as
+/// kept in the Download table's `reversed_host`.
+pub fn reversed_host(url: &Url) -> Option<String> {
+    url.host_str().map(reverse_labels)
+}
+
+fn reverse_labels(host: &str) -> String {
+    host.split('.').rev().collect::<Vec<_>>().join(".")
+}
+
+/// The ids of the downloads `filter` lets through. Downloads without a finished file never pass
+/// a size bound.
+pub async fn matching_ids(db: &SqlitePool, filter: &DownloadFilter) -> sqlx::Result<HashSet<i64>> {
+    let domain = filter
+        .domain
+        .as_deref()
+        .map(|domain| reverse_labels(&domain.trim_end_matches('.').to_lowercase()));
+
+    let mut conditions = Vec::new();
+    if filter.created_after.is_some() {
+        conditions.push("created_at >= ?");
+    }
+    if filter.created_before.is_some() {
+        conditions.push("created_at < ?");
+    }
+    if filter.min_size.is_some() {
+        conditions.push("file_size >= ?");
+    }
+    if filter.max_size.is_some() {
+        conditions.push("file_size <= ?");
+    }
+    // Subdomains sort between `<domain>.` and `<domain>/`, the character after the dot.
+    if domain.is_some() {
+        conditions
+            .push("(reversed_host = ? OR (reversed_host > ? || '.' AND reversed_host < ? || '/'))");
+    }
+    if conditions.is_empty() {
+        conditions.push("1");
+    }
+
+    let ids = format!("SELECT id FROM Download WHERE {}", conditions.join(" AND "));
+    let mut ids = sqlx::query_scalar(&ids);
+    if let Some(created_after) = filter.created_after {
+        ids = ids.bind(created_after);
+    }
+    if let Some(created_before) = filter.created_before {
+        ids = ids.bind(created_before);
+    }
+    if let Some(min_size) = filter.min_size {
+        ids = ids.bind(i64::try_from(min_size).unwrap_or(i64::MAX));
+    }
+    if let Some(max_size) = filter.max_size {
+        ids = ids.bind(i64::try_from(max_size).unwrap_or(i64::MAX));
+    }
+    if let Some(domain) = &domain {
+        ids = ids.bind(domain).bind(domain).bind(domain);
+    }
+    Ok(ids.fetch_all(db).await?.into_iter().collect())
+}
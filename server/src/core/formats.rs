@@ -0,0 +1,265 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// A same-height format only counts as an upgrade when its bitrate is at least this much higher.
+const MIN_BITRATE_IMPROVEMENT: f64 = 1.2;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Format {
+    pub format_id: String,
+    /// yt-dlp's short description, e.g. `720p` or `medium`.
+    pub format_note: Option<String>,
+    pub ext: Option<String>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub fps: Option<f64>,
+    pub tbr: Option<f64>,
+    pub vcodec: Option<String>,
+    pub acodec: Option<String>,
+    pub filesize: Option<f64>,
+    pub filesize_approx: Option<f64>,
+}
+
+/// The subset of `yt-dlp -J` output the server cares about.
+#[derive(Clone, Debug, Deserialize)]
+pub struct VideoMetadata {
+    pub extractor: Option<String>,
+    pub title: Option<String>,
+    pub filesize: Option<f64>,
+    pub filesize_approx: Option<f64>,
+    #[serde(default)]
+    pub formats: Vec<Format>,
+    pub requested_formats: Option<Vec<Format>>,
+}
+
+/// The formats a video can be downloaded in, for picking a quality before enqueueing it.
+#[derive(Clone, Debug, Serialize)]
+pub struct FormatListing {
+    pub url: Url,
+    pub title: Option<String>,
+    /// As yt-dlp orders them, worst first.
+    pub formats: Vec<Format>,
+}
+
+/// What yt-dlp says about a video when it's enqueued, kept with its download so it can be shown
+/// by name instead of url.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct VideoDetails {
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    pub duration_secs: Option<f64>,
+    pub upload_date: Option<NaiveDate>,
+    /// Where the site serves its thumbnail, unlike the one saved next to the file.
+    pub thumbnail_url: Option<String>,
+    /// Bytes the chosen formats should take, from the size the site reported or yt-dlp's guess.
+    pub estimated_size: Option<f64>,
+}
+
+/// A line of `yt-dlp --dump-json`, one per video.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DumpedVideo {
+    pub extractor_key: Option<String>,
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    pub duration: Option<f64>,
+    /// As `YYYYMMDD`.
+    pub upload_date: Option<String>,
+    pub thumbnail: Option<String>,
+    pub filesize: Option<f64>,
+    pub filesize_approx: Option<f64>,
+}
+
+/// The single video the availability check found behind a url.
+#[derive(Clone, Debug)]
+pub struct CheckedVideo {
+    /// The extractor and id, e.g. `Youtube:dQw4w9WgXcQ`, to tell apart urls for the same video.
+    pub video_id: String,
+    pub details: VideoDetails,
+}
+
+impl DumpedVideo {
+    /// `None` when yt-dlp didn't say which extractor and id the video has.
+    pub fn into_checked(self) -> Option<CheckedVideo> {
+        Some(CheckedVideo {
+            video_id: format!("{}:{}", self.extractor_key?, self.id?),
+            details: VideoDetails {
+                title: self.title,
+                uploader: self.uploader,
+                duration_secs: self.duration,
+                upload_date: self
+                    .upload_date
+                    .and_then(|date| NaiveDate::parse_from_str(&date, "%Y%m%d").ok()),
+                thumbnail_url: self.thumbnail,
+                estimated_size: self.filesize.or(self.filesize_approx),
+            },
+        })
+    }
+}
+
+/// The entries of a playlist or channel from `yt-dlp -J --flat-playlist`, absent for single videos.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlaylistListing {
+    pub entries: Option<Vec<PlaylistEntry>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlaylistEntry {
+    pub url: Option<String>,
+    pub webpage_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UpgradeReport {
+    pub url: Url,
+    pub current: Option<Format>,
+    pub best: Option<Format>,
+    pub upgradeable: bool,
+    pub improvement_percent: Option<f64>,
+    pub reasons: Vec<String>,
+}
+
+impl Format {
+    pub fn estimated_size(&self) -> Option<f64> {
+        self.filesize.or(self.filesize_approx)
+    }
+
+    fn is_video(&self) -> bool {
+        self.vcodec
+            .as_deref()
+            .is_some_and(|vcodec| vcodec != "none")
+    }
+
+    /// Only when yt-dlp says so, a format with no codec information may still carry audio.
+    fn lacks_audio(&self) -> bool {
+        self.acodec.as_deref() == Some("none")
+    }
+
+    /// Ranks codecs by compression efficiency, higher is better.
+    fn codec_rank(&self) -> u8 {
+        match self.vcodec.as_deref() {
+            Some(vcodec) if vcodec.starts_with("av01") => 4,
+            Some(vcodec) if vcodec.starts_with("vp9") || vcodec.starts_with("vp09") => 3,
+            Some(vcodec) if vcodec.starts_with("hev1") || vcodec.starts_with("hvc1") => 2,
+            Some(vcodec) if vcodec.starts_with("avc1") => 1,
+            _ => 0,
+        }
+    }
+}
+
+impl VideoMetadata {
+    /// Leaves out the formats with neither video nor audio, such as storyboard images.
+    pub fn into_listing(self, url: Url) -> FormatListing {
+        FormatListing {
+            url,
+            title: self.title,
+            formats: self
+                .formats
+                .into_iter()
+                .filter(|format| format.is_video() || !format.lacks_audio())
+                .collect(),
+        }
+    }
+
+    /// Size of the format(s) yt-dlp would pick by default.
+    pub fn estimated_size(&self) -> Option<f64> {
+        match &self.requested_formats {
+            Some(requested_formats) => requested_formats
+                .iter()
+                .filter_map(|format| format.estimated_size())
+                .reduce(|a, b| a + b),
+            None => self.filesize.or(self.filesize_approx),
+        }
+    }
+
+    pub fn best_video_format(&self) -> Option<&Format> {
+        self.formats
+            .iter()
+            .filter(|format| format.is_video())
+            .max_by(|a, b| {
+                (a.height, a.codec_rank())
+                    .cmp(&(b.height, b.codec_rank()))
+                    .then(a.tbr.unwrap_or(0.0).total_cmp(&b.tbr.unwrap_or(0.0)))
+            })
+    }
+
+    /// Finds the video half of a downloaded format selection such as `137+140`.
+    pub fn find_video_format(&self, format_id: &str) -> Option<&Format> {
+        format_id.split('+').find_map(|id| {
+            self.formats
+                .iter()
+                .find(|format| format.format_id == id && format.is_video())
+        })
+    }
+}
+
+/// Compares the downloaded format with the best one currently available.
+///
+/// When the downloaded format can't be found in `metadata` (e.g. it was never recorded),
+/// `fallback_height` is used as the current resolution.
+pub fn compare(
+    url: Url,
+    metadata: &VideoMetadata,
+    format_id: Option<&str>,
+    fallback_height: Option<u64>,
+) -> UpgradeReport {
+    let current = format_id.and_then(|format_id| metadata.find_video_format(format_id));
+    let best = metadata.best_video_format();
+    let mut reasons = Vec::new();
+    let mut improvement_percent = None;
+
+    if let Some(best) = best {
+        let current_height = current.and_then(|format| format.height).or(fallback_height);
+
+        match (current_height, best.height) {
+            (Some(current_height), Some(best_height)) if best_height > current_height => {
+                reasons.push(format!(
+                    "resolution {}p -> {}p",
+                    current_height, best_height
+                ));
+                improvement_percent =
+                    Some(percent_increase(current_height as f64, best_height as f64));
+            }
+            (None, Some(best_height)) => {
+                reasons.push(format!("resolution unknown -> {}p", best_height));
+            }
+            _ => {}
+        }
+
+        if let Some(current) = current {
+            if current.height == best.height {
+                if best.codec_rank() > current.codec_rank() {
+                    reasons.push(format!(
+                        "codec {} -> {}",
+                        current.vcodec.as_deref().unwrap_or("unknown"),
+                        best.vcodec.as_deref().unwrap_or("unknown")
+                    ));
+                }
+
+                if let (Some(current_tbr), Some(best_tbr)) = (current.tbr, best.tbr) {
+                    if best_tbr >= current_tbr * MIN_BITRATE_IMPROVEMENT {
+                        reasons.push(format!("bitrate {:.0}k -> {:.0}k", current_tbr, best_tbr));
+                        improvement_percent = Some(percent_increase(current_tbr, best_tbr));
+                    }
+                }
+            }
+        }
+    }
+
+    UpgradeReport {
+        url,
+        current: current.cloned(),
+        best: best.cloned(),
+        upgradeable: !reasons.is_empty(),
+        improvement_percent,
+        reasons,
+    }
+}
+
+fn percent_increase(from: f64, to: f64) -> f64 {
+    match from > 0.0 {
+        true => (to / from - 1.0) * 100.0,
+        false => 100.0,
+    }
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::Json;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use url::Url;
+
+/// What yt-dlp sends in place of its own client fingerprint, for every url or only those on
+/// `domain` and its subdomains.
+#[derive(Clone, Debug, Serialize)]
+pub struct HeaderRule {
+    pub id: i64,
+    pub domain: Option<String>,
+    pub user_agent: Option<String>,
+    pub referer: Option<String>,
+    /// Extra headers by name, passed as `--add-header`.
+    pub headers: Json<BTreeMap<String, String>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl HeaderRule {
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(domain) = &self.domain else {
+            return true;
+        };
+        url.host_str()
+            .is_some_and(|host| is_on_domain(host, domain))
+    }
+
+    /// How specific the rule is, later rules override the earlier ones they overlap with.
+    fn specificity(&self) -> usize {
+        self.domain.as_ref().map_or(0, |domain| domain.len() + 1)
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+pub fn is_on_domain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+/// Whether `domain` is a bare host name like `example.com`, without a scheme, port or path.
+pub fn is_valid_domain(domain: &str) -> bool {
+    !domain.is_empty()
+        && domain.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Whether `name` can be sent as an HTTP header name.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+/// Whether `value` fits on one header line.
+pub fn is_valid_value(value: &str) -> bool {
+    !value.contains(['\r', '\n'])
+}
+
+/// The yt-dlp arguments for the rules matching `url`, the global ones first and each more
+/// specific domain laid over them.
+pub fn ytdlp_args(rules: &[HeaderRule], url: &Url) -> Vec<String> {
+    let mut matching: Vec<&HeaderRule> = rules.iter().filter(|rule| rule.matches(url)).collect();
+    matching.sort_by_key(|rule| rule.specificity());
+
+    let mut user_agent = None;
+    let mut referer = None;
+    let mut headers = BTreeMap::new();
+    for rule in matching {
+        user_agent = rule.user_agent.clone().or(user_agent);
+        referer = rule.referer.clone().or(referer);
+        for (name, value) in rule.headers.iter() {
+            headers.insert(name.to_lowercase(), (name.clone(), value.clone()));
+        }
+    }
+
+    let mut args = Vec::new();
+    if let Some(user_agent) = user_agent {
+        args.extend([String::from("--user-agent"), user_agent]);
+    }
+    if let Some(referer) = referer {
+        args.extend([String::from("--referer"), referer]);
+    }
+    for (name, value) in headers.into_values() {
+        args.extend([String::from("--add-header"), format!("{}:{}", name, value)]);
+    }
+    args
+}
+
+/// Every header rule, oldest first.
+pub async fn load(db: &SqlitePool) -> sqlx::Result<Vec<HeaderRule>> {
+    sqlx::query_as!(
+        HeaderRule,
+        r#"SELECT
+            id,
+            domain,
+            user_agent,
+            referer,
+            headers as "headers: Json<BTreeMap<String, String>>",
+            created_at as "created_at: DateTime<Utc>"
+        FROM HeaderRule ORDER BY id"#
+    )
+    .fetch_all(db)
+    .await
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::ytdlp::{DownloadInfo, Status};
+
+/// The statuses a download can end up in for good, which make up its history.
+pub const FINISHED: &[Status] = &[Status::Completed, Status::Failed, Status::Canceled];
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistorySort {
+    #[default]
+    Newest,
+    Oldest,
+    NewestCreated,
+    OldestCreated,
+}
+
+impl HistorySort {
+    /// Ties break on id so pages don't overlap. Each order is served by an index on the
+    /// status and the time it sorts by.
+    fn order_by(self) -> &'static str {
+        match self {
+            HistorySort::Newest => "finished_at DESC, id DESC",
+            HistorySort::Oldest => "finished_at ASC, id ASC",
+            HistorySort::NewestCreated => "created_at DESC, id DESC",
+            HistorySort::OldestCreated => "created_at ASC, id ASC",
+        }
+    }
+}
+
+/// One page of finished downloads.
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub downloads: Vec<DownloadInfo>,
+    pub page: u32,
+    pub per_page: u32,
+    /// Finished downloads matching the filter across every page.
+    pub total: i64,
+}
+
+/// The urls on one page of downloads in `statuses`, and how many there are in all. With a `tag`,
+/// only the downloads filed under it, with `starred`, only those starred or not.
+pub async fn page(
+    db: &SqlitePool,
+    statuses: &[Status],
+    tag: Option<&str>,
+    starred: Option<bool>,
+    sort: HistorySort,
+    page: u32,
+    per_page: u32,
+) -> sqlx::Result<(Vec<String>, i64)> {
+    let mut filter = format!("status IN ({})", vec!["?"; statuses.len()].join(", "));
+    if tag.is_some() {
+        filter.push_str(
+            " AND id IN (SELECT download_id FROM DownloadTag \
+            JOIN Tag ON Tag.id = DownloadTag.tag_id WHERE Tag.name = ?)",
+        );
+    }
+    if starred.is_some() {
+        filter.push_str(" AND starred = ?");
+    }
+
+    let count = format!("SELECT COUNT(*) FROM Download WHERE {filter}");
+    let mut count = sqlx::query_scalar(&count);
+    for status in statuses {
+        count = count.bind(status);
+    }
+    if let Some(tag) = tag {
+        count = count.bind(tag);
+    }
+    if let Some(starred) = starred {
+        count = count.bind(starred);
+    }
+    let total = count.fetch_one(db).await?;
+
+    let urls = format!(
+        "SELECT url FROM Download WHERE {filter} ORDER BY {} LIMIT ? OFFSET ?",
+        sort.order_by()
+    );
+    let mut urls = sqlx::query_scalar(&urls);
+    for status in statuses {
+        urls = urls.bind(status);
+    }
+    if let Some(tag) = tag {
+        urls = urls.bind(tag);
+    }
+    if let Some(starred) = starred {
+        urls = urls.bind(starred);
+    }
+    let urls = urls
+        .bind(per_page as i64)
+        .bind(page.saturating_sub(1) as i64 * per_page as i64)
+        .fetch_all(db)
+        .await?;
+
+    Ok((urls, total))
+}
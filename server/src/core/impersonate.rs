@@ -0,0 +1,26 @@
+/// The targets `yt-dlp --list-impersonate-targets` lists as usable, e.g. `chrome-124`, lowercased.
+/// Rows yt-dlp marks as unavailable are left out, as are all of them when it doesn't know the flag.
+pub fn parse_targets(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("---"))
+        .skip(1)
+        .filter(|line| !line.contains("unavailable") && !line.contains("not available"))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Whether `target`, as given to `--impersonate`, is one of `targets`. The client may be named
+/// with or without its version and followed by an OS, e.g. `chrome`, `chrome-124` or
+/// `chrome-124:macos-14`.
+pub fn supports(targets: &[String], target: &str) -> bool {
+    let client = target.split(':').next().unwrap_or_default().to_lowercase();
+    !client.is_empty()
+        && targets.iter().any(|available| {
+            *available == client
+                || available
+                    .strip_prefix(client.as_str())
+                    .is_some_and(|version| version.starts_with('-'))
+        })
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::path::Path;
+
+/// Bumped whenever a field of [`InstanceStats`] is renamed, removed or changes meaning. Fields
+/// are only ever added within a version, so dashboards can ignore what they don't know.
+pub const STATS_SCHEMA: u32 = 1;
+
+/// Which instance this is, kept across restarts so a dashboard pulling from several can tell
+/// them apart.
+#[derive(Clone, Debug, Serialize)]
+pub struct InstanceIdentity {
+    /// Made up the first time the database is used and never changed after.
+    pub id: String,
+    /// Set by the operator, e.g. whose downloads these are.
+    pub name: Option<String>,
+    pub version: &'static str,
+    pub started_at: DateTime<Utc>,
+}
+
+/// How many downloads are in each state, active and in the history.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DownloadCounts {
+    pub total: usize,
+    pub running: usize,
+    /// Queued and scheduled downloads waiting on their turn.
+    pub queued: usize,
+    pub paused: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub canceled: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StorageStats {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// The numbers one instance reports for aggregating across instances, see [`STATS_SCHEMA`].
+#[derive(Clone, Debug, Serialize)]
+pub struct InstanceStats {
+    pub schema: u32,
+    pub instance: InstanceIdentity,
+    pub uptime_secs: i64,
+    pub downloads: DownloadCounts,
+    /// Of the download folder's disk, unset if it can't be read.
+    pub storage: Option<StorageStats>,
+    pub generated_at: DateTime<Utc>,
+}
+
+impl InstanceStats {
+    pub fn new(
+        instance: &InstanceIdentity,
+        downloads: DownloadCounts,
+        download_path: &Path,
+    ) -> InstanceStats {
+        let now = Utc::now();
+        let storage = match (
+            fs4::total_space(download_path),
+            fs4::available_space(download_path),
+        ) {
+            (Ok(total_bytes), Ok(available_bytes)) => Some(StorageStats {
+                total_bytes,
+                available_bytes,
+            }),
+            _ => None,
+        };
+
+        InstanceStats {
+            schema: STATS_SCHEMA,
+            instance: instance.clone(),
+            uptime_secs: (now - instance.started_at).num_seconds(),
+            downloads,
+            storage,
+            generated_at: now,
+        }
+    }
+}
+
+/// Loads the instance's id, making one up the first time.
+pub async fn identity(db: &SqlitePool, name: Option<String>) -> sqlx::Result<InstanceIdentity> {
+    let generated = uuid::Uuid::new_v4().to_string();
+    sqlx::query!(
+        "UPDATE Config SET instance_id = $1 WHERE id = 1 AND instance_id IS NULL",
+        generated
+    )
+    .execute(db)
+    .await?;
+    let id = sqlx::query_scalar!("SELECT instance_id FROM Config WHERE id = 1")
+        .fetch_one(db)
+        .await?
+        .unwrap_or(generated);
+
+    Ok(InstanceIdentity {
+        id,
+        name: name.filter(|name| !name.trim().is_empty()),
+        version: env!("CARGO_PKG_VERSION"),
+        started_at: Utc::now(),
+    })
+}
@@ -0,0 +1,367 @@
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, error, warn};
+
+use super::clock::{self, LocalTime};
+use super::events::{Event, EventBus};
+use super::messages::Message;
+use crate::Database;
+
+/// Failed runs in a row after which subscribers are warned that a job is broken.
+const FAILURE_ALERT_THRESHOLD: u32 = 3;
+
+/// Runs kept per job, older ones are dropped as new ones are recorded.
+const MAX_RUNS_KEPT: i64 = 200;
+
+/// When a job runs on its own.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// Every so many seconds, counted from the end of the last run.
+    EverySecs(u64),
+    /// Once a day when the instance's wall clock reads this time.
+    DailyAt(NaiveTime),
+}
+
+impl Trigger {
+    pub fn every(interval: Duration) -> Trigger {
+        Trigger::EverySecs(interval.as_secs())
+    }
+
+    fn next_after(self, after: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Trigger::EverySecs(secs) => after + TimeDelta::seconds(secs as i64),
+            Trigger::DailyAt(at) => clock::next_daily(at, after),
+        }
+    }
+}
+
+/// What a job came to, an error is logged and kept for the job listing.
+pub type JobResult = Result<(), String>;
+
+type JobFn = Arc<dyn Fn() -> BoxFuture<'static, JobResult> + Send + Sync>;
+
+#[derive(Debug)]
+pub enum JobError {
+    Unknown,
+    Running,
+}
+
+/// A registered job as the api lists it.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobInfo {
+    pub name: String,
+    pub trigger: Trigger,
+    pub paused: bool,
+    pub running: bool,
+    pub last_run_at: Option<LocalTime>,
+    pub last_error: Option<String>,
+    /// Failed runs since the last one that succeeded.
+    pub consecutive_failures: u32,
+    /// Unset while paused.
+    pub next_run_at: Option<LocalTime>,
+}
+
+/// One past run of a job.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobRun {
+    pub succeeded: bool,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: i64,
+}
+
+struct JobState {
+    trigger: Trigger,
+    paused: bool,
+    running: bool,
+    /// Set by [`JobScheduler::run_now`], taken by the job's loop.
+    requested: bool,
+    last_run_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    consecutive_failures: u32,
+    next_run_at: DateTime<Utc>,
+    wake: Arc<Notify>,
+}
+
+impl JobState {
+    fn info(&self, name: &str) -> JobInfo {
+        JobInfo {
+            name: name.to_string(),
+            trigger: self.trigger,
+            paused: self.paused,
+            running: self.running,
+            last_run_at: self.last_run_at.map(LocalTime::from),
+            last_error: self.last_error.clone(),
+            consecutive_failures: self.consecutive_failures,
+            next_run_at: (!self.paused).then(|| LocalTime::from(self.next_run_at)),
+        }
+    }
+}
+
+/// Runs the server's recurring background work, such as polling schedules and pruning the
+/// history, each job on its own task. Jobs can be listed, run on demand and paused. Pausing
+/// lasts until the server restarts. Every run is recorded, and a job that keeps failing raises a
+/// system warning.
+#[derive(Clone)]
+pub struct JobScheduler {
+    db: Database,
+    events: EventBus,
+    jobs: Arc<Mutex<BTreeMap<String, JobState>>>,
+}
+
+impl JobScheduler {
+    pub fn new(db: Database, events: EventBus) -> JobScheduler {
+        JobScheduler {
+            db,
+            events,
+            jobs: Arc::default(),
+        }
+    }
+
+    /// Starts running `job` on `trigger`, the first time right away when `run_at_start` is set.
+    pub fn register<F, Fut>(&self, name: &str, trigger: Trigger, run_at_start: bool, job: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = JobResult> + Send + 'static,
+    {
+        let now = Utc::now();
+        let wake = Arc::new(Notify::new());
+        let state = JobState {
+            trigger,
+            paused: false,
+            running: false,
+            requested: false,
+            last_run_at: None,
+            last_error: None,
+            consecutive_failures: 0,
+            next_run_at: match run_at_start {
+                true => now,
+                false => trigger.next_after(now),
+            },
+            wake: wake.clone(),
+        };
+        if self.lock().insert(name.to_string(), state).is_some() {
+            error!("job: {} was registered twice", name);
+        }
+
+        let job: JobFn = Arc::new(move || Box::pin(job()));
+        let scheduler = self.clone();
+        let name = name.to_string();
+        tokio::spawn(async move { scheduler.run_loop(&name, job, wake).await });
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.lock()
+            .iter()
+            .map(|(name, state)| state.info(name))
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<JobInfo> {
+        self.lock().get(name).map(|state| state.info(name))
+    }
+
+    /// Runs the job as soon as it's free, paused or not.
+    pub fn run_now(&self, name: &str) -> Result<JobInfo, JobError> {
+        let mut jobs = self.lock();
+        let state = jobs.get_mut(name).ok_or(JobError::Unknown)?;
+        if state.running {
+            return Err(JobError::Running);
+        }
+        state.requested = true;
+        state.wake.notify_one();
+        Ok(state.info(name))
+    }
+
+    /// Stops or restarts the job's own runs. A run already going carries on.
+    pub fn set_paused(&self, name: &str, paused: bool) -> Result<JobInfo, JobError> {
+        let mut jobs = self.lock();
+        let state = jobs.get_mut(name).ok_or(JobError::Unknown)?;
+        if state.paused && !paused {
+            // Picks up where it would be had it never stopped, at once if a run was missed.
+            state.next_run_at = state.next_run_at.max(Utc::now());
+        }
+        state.paused = paused;
+        state.wake.notify_one();
+        Ok(state.info(name))
+    }
+
+    /// The job's most recent runs, newest first, or `None` if there's no such job.
+    pub async fn runs(&self, name: &str, limit: i64) -> sqlx::Result<Option<Vec<JobRun>>> {
+        if !self.lock().contains_key(name) {
+            return Ok(None);
+        }
+        let rows = sqlx::query!(
+            r#"SELECT succeeded, error, started_at as "started_at: DateTime<Utc>", finished_at as "finished_at: DateTime<Utc>"
+            FROM JobRun WHERE job = $1 ORDER BY started_at DESC, id DESC LIMIT $2"#,
+            name,
+            limit
+        )
+        .fetch_all(&self.db.read)
+        .await?;
+
+        Ok(Some(
+            rows.into_iter()
+                .map(|row| JobRun {
+                    succeeded: row.succeeded,
+                    error: row.error,
+                    started_at: row.started_at,
+                    finished_at: row.finished_at,
+                    duration_ms: (row.finished_at - row.started_at).num_milliseconds(),
+                })
+                .collect(),
+        ))
+    }
+
+    async fn run_loop(&self, name: &str, job: JobFn, wake: Arc<Notify>) {
+        self.restore_last_run(name).await;
+        loop {
+            let wait = match self.lock().get(name) {
+                Some(state) if state.requested => Some(Duration::ZERO),
+                Some(state) if state.paused => None,
+                Some(state) => Some(
+                    (state.next_run_at - Utc::now())
+                        .to_std()
+                        .unwrap_or_default(),
+                ),
+                None => return,
+            };
+            match wait {
+                Some(wait) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(wait) => {}
+                        _ = wake.notified() => {}
+                    }
+                }
+                None => wake.notified().await,
+            }
+
+            {
+                let mut jobs = self.lock();
+                let Some(state) = jobs.get_mut(name) else {
+                    return;
+                };
+                let due = !state.paused && state.next_run_at <= Utc::now();
+                if !state.requested && !due {
+                    continue;
+                }
+                state.requested = false;
+                state.running = true;
+            }
+
+            debug!("running job: {}", name);
+            let started_at = Utc::now();
+            let result = job().await;
+            let finished_at = Utc::now();
+            if let Err(err) = &result {
+                error!("job: {} failed, err: {}", name, err);
+            }
+            self.record_run(name, &result, started_at, finished_at)
+                .await;
+
+            let failures = match self.lock().get_mut(name) {
+                Some(state) => {
+                    state.running = false;
+                    state.last_run_at = Some(finished_at);
+                    state.consecutive_failures = match result {
+                        Ok(()) => 0,
+                        Err(_) => state.consecutive_failures + 1,
+                    };
+                    state.last_error = result.err();
+                    state.next_run_at = state.trigger.next_after(finished_at);
+                    state.consecutive_failures
+                }
+                None => return,
+            };
+            // Warned once as the job crosses the threshold, and again if it breaks after recovering.
+            if failures == FAILURE_ALERT_THRESHOLD {
+                self.alert(name, failures);
+            }
+        }
+    }
+
+    fn alert(&self, name: &str, failures: u32) {
+        let error = self
+            .lock()
+            .get(name)
+            .and_then(|state| state.last_error.clone())
+            .unwrap_or_default();
+        warn!("job: {} has failed {} times in a row", name, failures);
+        self.events.publish(Event::SystemWarning {
+            message: Message::new("job.failing")
+                .with("name", name)
+                .with("failures", failures)
+                .with("error", error),
+        });
+    }
+
+    async fn record_run(
+        &self,
+        name: &str,
+        result: &JobResult,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+    ) {
+        let succeeded = result.is_ok();
+        let error = result.as_ref().err();
+        let inserted = sqlx::query!(
+            "INSERT INTO JobRun (job, succeeded, error, started_at, finished_at)
+            VALUES ($1, $2, $3, $4, $5)",
+            name,
+            succeeded,
+            error,
+            started_at,
+            finished_at
+        )
+        .execute(&self.db.write)
+        .await;
+        let pruned = match inserted {
+            Ok(_) => {
+                sqlx::query!(
+                    "DELETE FROM JobRun WHERE job = $1 AND id NOT IN
+                    (SELECT id FROM JobRun WHERE job = $1 ORDER BY id DESC LIMIT $2)",
+                    name,
+                    MAX_RUNS_KEPT
+                )
+                .execute(&self.db.write)
+                .await
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = pruned {
+            error!("failed to record a run of job: {}, err: {}", name, err);
+        }
+    }
+
+    /// Picks up the last run and the failures leading up to it from before a restart.
+    async fn restore_last_run(&self, name: &str) {
+        let runs = match self.runs(name, FAILURE_ALERT_THRESHOLD as i64 * 10).await {
+            Ok(runs) => runs.unwrap_or_default(),
+            Err(err) => {
+                error!("failed to load the runs of job: {}, err: {}", name, err);
+                return;
+            }
+        };
+        let Some(last) = runs.first() else {
+            return;
+        };
+        let failures = runs.iter().take_while(|run| !run.succeeded).count() as u32;
+        if let Some(state) = self.lock().get_mut(name) {
+            state.last_run_at = Some(last.finished_at);
+            state.last_error = last.error.clone();
+            state.consecutive_failures = failures;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, JobState>> {
+        self.jobs.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
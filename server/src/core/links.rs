@@ -0,0 +1,106 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// Shares the file's data, so the link keeps working if the download is moved or deleted.
+    /// Only works within one filesystem.
+    Hardlink,
+    Symlink,
+}
+
+/// Another library folder a completed download's file is linked into, e.g. both an archive and
+/// a media server's library. The link takes the file's name.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LibraryLink {
+    pub dir: PathBuf,
+    pub kind: LinkKind,
+}
+
+/// Whether `dir` can take links, only absolute paths are, so they don't depend on where the
+/// server was started from.
+pub fn is_valid_dir(dir: &Path) -> bool {
+    dir.is_absolute()
+}
+
+/// Links `file` into every folder in `links`, making the folders as needed, and records the links
+/// against the download. A file already in the way is left alone unless it's one of the
+/// download's own links from an earlier run.
+/// Returns the links that were made.
+pub async fn create(
+    db: &SqlitePool,
+    download_id: i64,
+    file: &Path,
+    links: &[LibraryLink],
+) -> Vec<PathBuf> {
+    let Some(file_name) = file.file_name() else {
+        return Vec::new();
+    };
+    let file = std::path::absolute(file).unwrap_or_else(|_| file.to_path_buf());
+    let existing = paths(db, download_id).await.unwrap_or_default();
+
+    let mut created = Vec::new();
+    for link in links {
+        let path = link.dir.join(file_name);
+        if existing.contains(&path) {
+            let _ = tokio::fs::remove_file(&path).await;
+        }
+        if let Err(err) = make_link(&file, &path, link.kind).await {
+            warn!(
+                "couldn't link {} to {}, err: {}",
+                file.display(),
+                path.display(),
+                err
+            );
+            continue;
+        }
+
+        let stored_path = path.to_string_lossy();
+        let now = Utc::now();
+        let result = sqlx::query!(
+            r#"INSERT INTO DownloadLink (download_id, path, kind, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (download_id, path) DO UPDATE SET
+                kind = excluded.kind,
+                created_at = excluded.created_at"#,
+            download_id,
+            stored_path,
+            link.kind,
+            now
+        )
+        .execute(db)
+        .await;
+        if let Err(err) = result {
+            warn!("failed to record link: {}, err: {}", path.display(), err);
+        }
+        created.push(path);
+    }
+    created
+}
+
+async fn make_link(file: &Path, path: &Path, kind: LinkKind) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    match kind {
+        LinkKind::Hardlink => tokio::fs::hard_link(file, path).await,
+        LinkKind::Symlink => tokio::fs::symlink(file, path).await,
+    }
+}
+
+/// Where the download's file has been linked to.
+pub async fn paths(db: &SqlitePool, download_id: i64) -> sqlx::Result<Vec<PathBuf>> {
+    let paths = sqlx::query_scalar!(
+        "SELECT path FROM DownloadLink WHERE download_id = $1 ORDER BY id",
+        download_id
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(paths.into_iter().map(PathBuf::from).collect())
+}
@@ -0,0 +1,211 @@
+use chrono::Utc;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Caps on how much of the downloads' output and events is kept, on disk and in memory, so a
+/// long running instance doesn't grow without bound.
+#[derive(Clone, Copy, Debug)]
+pub struct LogLimits {
+    /// Past this a download's log stops growing, so a looping yt-dlp can't fill the disk.
+    pub max_bytes: u64,
+    /// Like `max_bytes`, in lines.
+    pub max_lines: u64,
+    /// All logs together, past which the finished downloads' logs are trashed oldest first. 0
+    /// leaves them be.
+    pub total_max_bytes: u64,
+    /// Recent lines of output held in memory for each download's detail.
+    pub tail_lines: usize,
+    /// Events a subscriber can fall behind by before it starts missing them.
+    pub event_buffer: usize,
+}
+
+impl Default for LogLimits {
+    fn default() -> Self {
+        LogLimits {
+            max_bytes: 5_000_000,
+            max_lines: 100_000,
+            total_max_bytes: 500_000_000,
+            tail_lines: 50,
+            event_buffer: 100,
+        }
+    }
+}
+
+/// The folder in the download path every download's yt-dlp output is kept in, see [`DownloadLog`].
+pub fn dir(download_path: &Path) -> PathBuf {
+    download_path.join(".logs")
+}
+
+/// Where a download's yt-dlp output is kept, a file named after its id in `dir`.
+pub fn path(dir: &Path, download_id: i64) -> PathBuf {
+    dir.join(format!("{}.log", download_id))
+}
+
+/// Everything yt-dlp wrote on stdout and stderr across a download's attempts, kept on disk so a
+/// failure can be looked into after the fact. Writes after a failure to open or write the file
+/// are dropped, the download goes on regardless.
+#[derive(Clone)]
+pub struct DownloadLog {
+    file: Arc<Mutex<Option<LogFile>>>,
+    limits: LogLimits,
+}
+
+struct LogFile {
+    file: File,
+    written: u64,
+    lines: u64,
+}
+
+impl LogFile {
+    fn is_full(&self, limits: &LogLimits) -> bool {
+        self.written >= limits.max_bytes || self.lines >= limits.max_lines
+    }
+}
+
+impl DownloadLog {
+    /// Opens the download's log for another attempt, marking where it starts.
+    pub async fn open(
+        dir: &Path,
+        download_id: i64,
+        attempt: u32,
+        limits: &LogLimits,
+    ) -> DownloadLog {
+        let path = path(dir, download_id);
+        let file = match open_file(dir, &path).await {
+            Ok(file) => Some(file),
+            Err(err) => {
+                warn!("couldn't open log: {}, err: {}", path.display(), err);
+                None
+            }
+        };
+        let log = DownloadLog {
+            file: Arc::new(Mutex::new(file)),
+            limits: *limits,
+        };
+        log.write(&format!(
+            "--- attempt {} at {} ---",
+            attempt,
+            Utc::now().to_rfc3339()
+        ))
+        .await;
+        log
+    }
+
+    pub async fn write(&self, line: &str) {
+        let mut guard = self.file.lock().await;
+        let Some(log) = guard.as_mut() else {
+            return;
+        };
+        if log.is_full(&self.limits) {
+            return;
+        }
+
+        let full = log.written + line.len() as u64 + 1 > self.limits.max_bytes
+            || log.lines + 1 >= self.limits.max_lines;
+        let line = match full {
+            true => "--- log is full, later output is left out ---\n".to_string(),
+            false => format!("{}\n", line),
+        };
+        match log.file.write_all(line.as_bytes()).await {
+            Ok(()) if full => {
+                log.written = self.limits.max_bytes;
+                log.lines = self.limits.max_lines;
+            }
+            Ok(()) => {
+                log.written += line.len() as u64;
+                log.lines += 1;
+            }
+            Err(err) => {
+                warn!("couldn't write to a download log, err: {}", err);
+                *guard = None;
+            }
+        }
+    }
+}
+
+async fn open_file(dir: &Path, path: &Path) -> std::io::Result<LogFile> {
+    tokio::fs::create_dir_all(dir).await?;
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    let written = file.metadata().await?.len();
+    let lines = match written {
+        0 => 0,
+        _ => tokio::fs::read(path)
+            .await?
+            .iter()
+            .filter(|byte| **byte == b'\n')
+            .count() as u64,
+    };
+    Ok(LogFile {
+        file,
+        written,
+        lines,
+    })
+}
+
+/// Trashes the logs in `dir` that have outgrown `limits`, or belong to no download in `known`,
+/// leaving those in `active` alone. Once the rest fit, the oldest are trashed until all of
+/// them fit `total_max_bytes`. Returns how many were trashed.
+pub async fn prune(
+    dir: &Path,
+    known: &HashSet<i64>,
+    active: &HashSet<i64>,
+    limits: &LogLimits,
+) -> std::io::Result<usize> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let mut kept: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+    let mut total = 0;
+    let mut trashed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let Some(id) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<i64>().ok())
+            .filter(|_| path.extension().is_some_and(|extension| extension == "log"))
+        else {
+            continue;
+        };
+        let metadata = entry.metadata().await?;
+        total += metadata.len();
+        if active.contains(&id) {
+            continue;
+        }
+        // Logs written before the caps were lowered can be past them.
+        if !known.contains(&id) || metadata.len() > limits.max_bytes {
+            tokio::fs::remove_file(&path).await?;
+            total -= metadata.len();
+            trashed += 1;
+            continue;
+        }
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        kept.push((modified, metadata.len(), path));
+    }
+
+    if limits.total_max_bytes > 0 {
+        kept.sort_by_key(|(modified, _, _)| *modified);
+        for (_, size, path) in kept {
+            if total <= limits.total_max_bytes {
+                break;
+            }
+            tokio::fs::remove_file(&path).await?;
+            total -= size;
+            trashed += 1;
+        }
+    }
+    Ok(trashed)
+}
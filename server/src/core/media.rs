@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// What ffprobe found in a completed file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct MediaInfo {
+    pub container: Option<String>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub duration_secs: Option<f64>,
+    /// Bits per second across all streams.
+    pub bitrate: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    format: ProbeFormat,
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+#[derive(Default, Deserialize)]
+struct ProbeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<i64>,
+    height: Option<i64>,
+}
+
+impl MediaInfo {
+    /// Whether the file has a video stream in `codec`, compared without case.
+    pub fn has_video_codec(&self, codec: &str) -> bool {
+        self.video_codec
+            .as_deref()
+            .is_some_and(|video_codec| video_codec.eq_ignore_ascii_case(codec))
+    }
+}
+
+/// Runs ffprobe on `file`.
+/// # Errors
+/// Why ffprobe couldn't be run or what it complained about.
+pub async fn probe(
+    ffprobe_path: &str,
+    file: &Path,
+    timeout: Duration,
+) -> Result<MediaInfo, String> {
+    let output = Command::new(ffprobe_path)
+        .arg("-v")
+        .arg("error")
+        .arg("-print_format")
+        .arg("json")
+        .arg("-show_format")
+        .arg("-show_streams")
+        .arg(file)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(timeout, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(err)) => return Err(err.to_string()),
+        Err(_) => return Err(format!("ffprobe timed out after {:?}", timeout)),
+    };
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    let probe: ProbeOutput =
+        serde_json::from_slice(&output.stdout).map_err(|err| err.to_string())?;
+    Ok(media_info(file, probe))
+}
+
+fn media_info(file: &Path, probe: ProbeOutput) -> MediaInfo {
+    let stream = |codec_type: &str| {
+        probe
+            .streams
+            .iter()
+            .find(|stream| stream.codec_type.as_deref() == Some(codec_type))
+    };
+    let video = stream("video");
+    let audio = stream("audio");
+
+    // ffprobe names a family of formats for most containers, e.g. `mov,mp4,m4a,3gp,3g2,mj2`.
+    let container = file
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .or_else(|| {
+            probe
+                .format
+                .format_name
+                .as_deref()
+                .and_then(|format_name| format_name.split(',').next())
+                .map(String::from)
+        });
+
+    MediaInfo {
+        container,
+        video_codec: video.and_then(|video| video.codec_name.clone()),
+        audio_codec: audio.and_then(|audio| audio.codec_name.clone()),
+        width: video.and_then(|video| video.width),
+        height: video.and_then(|video| video.height),
+        duration_secs: probe
+            .format
+            .duration
+            .and_then(|duration| duration.parse().ok()),
+        bitrate: probe
+            .format
+            .bit_rate
+            .and_then(|bit_rate| bit_rate.parse().ok()),
+    }
+}
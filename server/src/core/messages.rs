@@ -0,0 +1,386 @@
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::{error, info};
+
+const DEFAULT_LOCALE: &str = "en";
+
+/// The built in English catalog, other locales fall back to it key by key.
+const ENGLISH: &[(&str, &str)] = &[
+    (
+        "archive.invalid_entry",
+        "Not a download archive entry: {entry}, use the extractor and video id, e.g. youtube dQw4w9WgXcQ",
+    ),
+    (
+        "audio.invalid_format",
+        "Can't extract audio as: {format}, use aac, alac, flac, m4a, mp3, opus, vorbis or wav",
+    ),
+    (
+        "cookies.browser_profile_missing",
+        "Found no {browser} cookies in: {path}, check the profile",
+    ),
+    (
+        "cookies.browser_unreadable",
+        "Can't read the browser cookies in: {path}, {error}",
+    ),
+    (
+        "cookies.empty",
+        "The cookies file has no cookies, export it in the Netscape cookies.txt format",
+    ),
+    (
+        "cookies.invalid_line",
+        "Line {line} of the cookies file isn't a cookie in the Netscape cookies.txt format",
+    ),
+    (
+        "cookies.invalid_name",
+        "Invalid cookies name: {name}, use letters, digits, ., - and _",
+    ),
+    ("cookies.unknown", "Unknown cookies file: {name}"),
+    (
+        "cookies.unknown_browser",
+        "yt-dlp can't read cookies from: {browser}, use one of: {browsers}",
+    ),
+    ("cursor.unknown", "Unknown cursor"),
+    (
+        "db.write_failed",
+        "Can't write to the database, changes are held in memory until it recovers: {error}",
+    ),
+    (
+        "download.active",
+        "The download is still queued or running, cancel it first",
+    ),
+    ("download.bad", "Bad download"),
+    ("download.duplicate", "Listed more than once in this batch"),
+    ("download.formats_failed", "Failed to fetch formats"),
+    (
+        "download.invalid_container",
+        "Can't save as: {container}, use one of: {containers}",
+    ),
+    (
+        "download.invalid_extra_arg",
+        "Can't pass {arg} to yt-dlp, extra arguments can be: {allowed}",
+    ),
+    (
+        "download.invalid_link_dir",
+        "Library folders to link into need an absolute path, not: {dir}",
+    ),
+    (
+        "download.invalid_max_duration",
+        "The time limit has to be a positive number of seconds, not: {secs}",
+    ),
+    (
+        "download.invalid_name_format",
+        "The name format: {name_format} has to stay inside the download folder",
+    ),
+    (
+        "download.invalid_quality",
+        "Invalid quality: {quality}, use best or a height such as 720p",
+    ),
+    ("download.no_log", "yt-dlp hasn't run for this download yet"),
+    ("download.no_thumbnail", "This download has no thumbnail"),
+    ("download.not_completed", "Download hasn't completed"),
+    ("download.present", "Download already present"),
+    (
+        "download.same_video",
+        "Already downloading this video from: {url}",
+    ),
+    ("download.unavailable", "Can't download: {reason}"),
+    ("download.unknown", "Unknown download"),
+    (
+        "events.unknown_category",
+        "Unknown event category: {category}",
+    ),
+    (
+        "geo_bypass.invalid_country",
+        "Invalid country: {country}, use a two letter code like US or DE",
+    ),
+    (
+        "headers.invalid_domain",
+        "Not a domain: {domain}, use a bare host name like example.com",
+    ),
+    (
+        "headers.invalid_header",
+        "Header names can't hold spaces or colons, and values have to fit on one line",
+    ),
+    ("headers.unknown", "Unknown header rule"),
+    (
+        "history.invalid_status",
+        "{status} downloads aren't finished, history holds Completed, Failed and Canceled ones",
+    ),
+    (
+        "impersonate.unknown_target",
+        "yt-dlp can't impersonate: {target}, it can do: {targets}",
+    ),
+    (
+        "impersonate.unsupported",
+        "The installed yt-dlp can't impersonate browsers, it needs a recent release with curl_cffi",
+    ),
+    ("internal", "Something went wrong: {error}"),
+    (
+        "job.failing",
+        "Job: {name} has failed {failures} times in a row, last with: {error}",
+    ),
+    ("job.running", "Job: {name} is already running"),
+    ("job.unknown", "Unknown job: {name}"),
+    (
+        "policy.invalid_target",
+        "Can't remux or transcode to: {target}",
+    ),
+    ("policy.unknown", "Unknown policy"),
+    (
+        "proxy.invalid",
+        "Invalid proxy: {proxy}, use a url like socks5://host:port with one of: {schemes}",
+    ),
+    (
+        "queue.not_queued",
+        "Only downloads waiting in the queue can be reordered",
+    ),
+    (
+        "queue.repeated_url",
+        "The new order lists a download more than once",
+    ),
+    (
+        "rate_limit.invalid",
+        "Invalid rate limit: {rate_limit}, use a number of bytes with an optional K, M or G",
+    ),
+    (
+        "request.timed_out",
+        "The server took longer than {secs} seconds to answer, try again",
+    ),
+    (
+        "restore.corrupt",
+        "The backup failed its integrity check: {error}",
+    ),
+    (
+        "restore.newer_schema",
+        "The backup is from a newer version (migration {version}), upgrade before restoring it",
+    ),
+    ("restore.not_database", "Not a database backup"),
+    (
+        "restore.staged",
+        "The backup will replace the database when the server next starts",
+    ),
+    (
+        "retention.invalid_limit",
+        "History limits have to be a positive number, not: {limit}",
+    ),
+    ("saved.unknown", "Unknown saved url"),
+    ("schedule.invalid_cron", "Invalid cron expression: {error}"),
+    (
+        "schedule.listing_failed",
+        "Couldn't list the videos to download: {error}",
+    ),
+    ("schedule.unknown", "Unknown schedule"),
+    (
+        "server.shutting_down",
+        "The server is shutting down and isn't taking new downloads",
+    ),
+    (
+        "storage.insufficient",
+        "Not enough free space, the download needs about {needed} MB and {available} MB are free",
+    ),
+    (
+        "storage.waiting",
+        "Waiting for disk space to start: {url}, it needs about {needed} MB and {available} MB are free for it",
+    ),
+    (
+        "subtitle.invalid_format",
+        "Can't convert subtitles to: {format}, use srt or vtt",
+    ),
+    ("tag.exists", "There's already a tag named: {name}"),
+    (
+        "tag.invalid_name",
+        "Invalid tag name: {name}, use lowercase letters, digits, dashes and underscores",
+    ),
+    (
+        "tag.invalid_subdirectory",
+        "Tag subdirectories need a relative path inside the download folder, not: {subdirectory}",
+    ),
+    ("tag.unknown", "Unknown tag"),
+    (
+        "tags.invalid_name",
+        "Invalid tag name: {tag}, use lowercase letters, digits and underscores",
+    ),
+    ("upgrade.scan_running", "An upgrade scan is already running"),
+    ("url.invalid", "Invalid url: {error}"),
+    (
+        "url.short_form",
+        "Shorts and clip links are turned off, submit the video's watch url instead: {url}",
+    ),
+    (
+        "websocket.too_many",
+        "Too many open websockets, the limit is {limit}, close some before opening more",
+    ),
+    (
+        "windows.invalid_domain",
+        "Download windows need a bare domain like example.com, not: {domain}",
+    ),
+    (
+        "worker.disabled",
+        "This instance doesn't take workers, set WORKER_TOKEN to let them register",
+    ),
+    ("worker.invalid_path", "Workers can't upload to: {path}"),
+    ("worker.unauthorized", "Wrong or missing worker token"),
+    ("worker.unknown", "Unknown worker: {worker}, register again"),
+    (
+        "worker.unknown_job",
+        "Unknown job: {job}, it was stopped or the server restarted",
+    ),
+    ("ytdlp.start_failed", "Failed to start yt-dlp: {error}"),
+    ("ytdlp.timed_out", "yt-dlp took too long to respond"),
+    (
+        "ytdlp_config.forbidden_option",
+        "yt-dlp config files can't hold {option}, only options the server allows by their full name",
+    ),
+    (
+        "ytdlp_config.invalid_name",
+        "Invalid yt-dlp config name: {name}, use letters, digits, - and _",
+    ),
+    ("ytdlp_config.unknown", "Unknown yt-dlp config: {name}"),
+];
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// A user facing message as a key plus parameters, so clients can render it in any locale.
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub key: &'static str,
+    pub params: BTreeMap<&'static str, String>,
+}
+
+struct Catalog {
+    locale: String,
+    locales: HashMap<String, HashMap<String, String>>,
+}
+
+impl Message {
+    pub fn new(key: &'static str) -> Message {
+        Message {
+            key,
+            params: BTreeMap::new(),
+        }
+    }
+
+    pub fn with(mut self, name: &'static str, value: impl ToString) -> Message {
+        self.params.insert(name, value.to_string());
+        self
+    }
+
+    /// Fills in the template for `locale`, falling back to English and then the bare key.
+    pub fn render(&self, locale: &str) -> String {
+        let template = catalog()
+            .template(locale, self.key)
+            .unwrap_or(self.key)
+            .to_string();
+
+        self.params.iter().fold(template, |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+    }
+}
+
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(&catalog().locale))
+    }
+}
+
+/// Serializes as `{ key, params, message }` with `message` in the instance locale.
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("key", self.key)?;
+        map.serialize_entry("params", &self.params)?;
+        map.serialize_entry("message", &self.to_string())?;
+        map.end()
+    }
+}
+
+impl Catalog {
+    fn template(&self, locale: &str, key: &str) -> Option<&str> {
+        self.locales
+            .get(locale)
+            .and_then(|templates| templates.get(key))
+            .or_else(|| self.locales.get(DEFAULT_LOCALE)?.get(key))
+            .map(String::as_str)
+    }
+}
+
+fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| load(None, DEFAULT_LOCALE))
+}
+
+fn load(dir: Option<&Path>, locale: &str) -> Catalog {
+    let mut locales = HashMap::new();
+    locales.insert(
+        DEFAULT_LOCALE.to_string(),
+        ENGLISH
+            .iter()
+            .map(|(key, template)| (key.to_string(), template.to_string()))
+            .collect::<HashMap<_, _>>(),
+    );
+
+    match dir.map(fs::read_dir) {
+        Some(Ok(entries)) => {
+            for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+                load_file(&path, &mut locales);
+            }
+        }
+        Some(Err(err)) => error!("failed to read message catalog dir: {}", err),
+        None => (),
+    }
+
+    Catalog {
+        locale: locale.to_string(),
+        locales,
+    }
+}
+
+fn load_file(path: &Path, locales: &mut HashMap<String, HashMap<String, String>>) {
+    if path.extension().is_none_or(|extension| extension != "json") {
+        return;
+    }
+    let Some(name) = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+    else {
+        return;
+    };
+
+    let templates = fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| {
+            serde_json::from_str::<HashMap<String, String>>(&text).map_err(|err| err.to_string())
+        });
+    match templates {
+        Ok(templates) => {
+            info!("loaded {} messages for locale: {}", templates.len(), name);
+            locales.entry(name).or_default().extend(templates);
+        }
+        Err(err) => error!("failed to load message catalog {:?}: {}", path, err),
+    }
+}
+
+/// Loads `<locale>.json` catalogs from `dir` over the built in English one. Call once at startup.
+pub fn init(dir: Option<&Path>, locale: &str) {
+    if CATALOG.set(load(dir, locale)).is_err() {
+        error!("message catalog was already initialized");
+    }
+}
+
+/// Every template for `locale`, with English filling any gaps.
+pub fn templates(locale: &str) -> BTreeMap<String, String> {
+    let catalog = catalog();
+    let mut templates: BTreeMap<String, String> = catalog
+        .locales
+        .get(DEFAULT_LOCALE)
+        .map(|templates| templates.clone().into_iter().collect())
+        .unwrap_or_default();
+    if let Some(overrides) = catalog.locales.get(locale) {
+        templates.extend(overrides.clone());
+    }
+    templates
+}
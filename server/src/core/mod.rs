@@ -1 +1,37 @@
-pub mod ytdlp;
\ No newline at end of file
+pub mod archive;
+pub mod backup;
+pub mod bandwidth;
+pub mod canonical;
+pub mod clock;
+pub mod cookies;
+pub mod duplicates;
+pub mod events;
+pub mod filters;
+pub mod formats;
+pub mod headers;
+pub mod history;
+pub mod impersonate;
+pub mod instance;
+pub mod jobs;
+pub mod links;
+pub mod logs;
+pub mod media;
+pub mod messages;
+pub mod pending;
+pub mod policy;
+pub mod process;
+pub mod progress;
+pub mod queue;
+pub mod recurring;
+pub mod retention;
+pub mod retry;
+pub mod storage;
+pub mod tagging;
+pub mod tags;
+pub mod transcode;
+pub mod upgrade;
+pub mod windows;
+pub mod worker_mode;
+pub mod workers;
+pub mod ytdlp;
+pub mod ytdlp_configs;
@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+use url::Url;
+
+const PENDING_CAPACITY: usize = 10_000;
+
+/// Downloads whose latest state couldn't be written, held until the db takes writes again.
+/// Only the url is kept, the retry writes whatever the manager holds at that point.
+#[derive(Clone, Default)]
+pub struct PendingWrites {
+    notify: Arc<Notify>,
+    state: Arc<Mutex<PendingState>>,
+}
+
+#[derive(Default)]
+struct PendingState {
+    dropped: usize,
+    failing: bool,
+    urls: HashSet<Url>,
+}
+
+impl PendingWrites {
+    /// Holds on to `url` for the retry task. Returns true for the first failure since the db was
+    /// last healthy, so callers warn once per outage rather than once per write.
+    pub fn push(&self, url: Url) -> bool {
+        let mut state = self.lock();
+        if state.urls.len() < PENDING_CAPACITY || state.urls.contains(&url) {
+            state.urls.insert(url);
+        } else {
+            state.dropped += 1;
+        }
+
+        let first = !state.failing;
+        state.failing = true;
+        self.notify.notify_one();
+        first
+    }
+
+    pub fn take(&self) -> Vec<Url> {
+        self.lock().urls.drain().collect()
+    }
+
+    /// Marks the db healthy again, returning how many writes were dropped while it wasn't.
+    pub fn recovered(&self) -> usize {
+        let mut state = self.lock();
+        state.failing = false;
+        std::mem::take(&mut state.dropped)
+    }
+
+    /// Waits until something has been pushed.
+    pub async fn wait(&self) {
+        self.notify.notified().await
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PendingState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::media::MediaInfo;
+use super::transcode;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// Copies the streams into the `target` container.
+    Remux,
+    /// Re-encodes the video stream to the `target` codec, keeping the audio.
+    Transcode,
+}
+
+/// A rule run against every completed download's media info. Unset conditions match anything.
+#[derive(Clone, Debug, Serialize)]
+pub struct Policy {
+    pub id: i64,
+    pub video_codec: Option<String>,
+    pub container: Option<String>,
+    pub action: PolicyAction,
+    pub target: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Policy {
+    pub fn matches(&self, media: &MediaInfo) -> bool {
+        let video_codec = self
+            .video_codec
+            .as_deref()
+            .is_none_or(|codec| media.has_video_codec(codec));
+        let container = self.container.as_deref().is_none_or(|container| {
+            media
+                .container
+                .as_deref()
+                .is_some_and(|media_container| media_container.eq_ignore_ascii_case(container))
+        });
+
+        video_codec && container && !self.already_applied(media)
+    }
+
+    /// Whether the file is already what the action would turn it into.
+    fn already_applied(&self, media: &MediaInfo) -> bool {
+        match self.action {
+            PolicyAction::Remux => media
+                .container
+                .as_deref()
+                .is_some_and(|container| container.eq_ignore_ascii_case(&self.target)),
+            PolicyAction::Transcode => media.has_video_codec(&self.target),
+        }
+    }
+}
+
+/// Whether ffmpeg can carry out `action` towards `target`.
+pub fn is_valid_target(action: PolicyAction, target: &str) -> bool {
+    match action {
+        PolicyAction::Remux => transcode::CONTAINERS.contains(&target),
+        PolicyAction::Transcode => transcode::encoder(target).is_some(),
+    }
+}
+
+/// Every policy, oldest first so earlier rules take precedence.
+pub async fn load(db: &SqlitePool) -> sqlx::Result<Vec<Policy>> {
+    sqlx::query_as!(
+        Policy,
+        r#"SELECT
+            id,
+            video_codec,
+            container,
+            action as "action: PolicyAction",
+            target,
+            created_at as "created_at: DateTime<Utc>"
+        FROM PostPolicy ORDER BY id"#
+    )
+    .fetch_all(db)
+    .await
+}
@@ -0,0 +1,101 @@
+use serde::Serialize;
+use std::fs;
+use std::time::Duration;
+
+/// Kernel clock ticks per second used by the times in `/proc/<pid>/stat`.
+const USER_HZ: f64 = 100.0;
+
+/// Cpu and memory of a yt-dlp child, including the ffmpeg processes it spawned.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub processes: usize,
+    pub cpu_percent: Option<f64>,
+    pub rss_bytes: Option<u64>,
+}
+
+struct Stat {
+    ppid: u32,
+    cpu_ticks: u64,
+}
+
+fn read_stat(pid: u32) -> Option<Stat> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces, so skip past its closing paren.
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 2..)?.split(' ').collect();
+
+    Some(Stat {
+        ppid: fields.get(1)?.parse().ok()?,
+        cpu_ticks: fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?,
+    })
+}
+
+fn read_rss_bytes(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kilobytes * 1024)
+}
+
+/// The pid and all of its descendants.
+fn process_tree(pid: u32) -> Vec<u32> {
+    let parents: Vec<(u32, u32)> = match fs::read_dir("/proc") {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter_map(|child| Some((child, read_stat(child)?.ppid)))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    let mut tree = vec![pid];
+    let mut index = 0;
+    while index < tree.len() {
+        let parent = tree[index];
+        tree.extend(
+            parents
+                .iter()
+                .filter(|(_, ppid)| *ppid == parent)
+                .map(|(child, _)| *child),
+        );
+        index += 1;
+    }
+
+    tree
+}
+
+fn total_cpu_ticks(pids: &[u32]) -> Option<u64> {
+    pids.iter()
+        .filter_map(|pid| read_stat(*pid))
+        .map(|stat| stat.cpu_ticks)
+        .reduce(|a, b| a + b)
+}
+
+/// Samples cpu usage over `window`, returns `None` if the process is gone.
+pub async fn sample(pid: u32, window: Duration) -> Option<ProcessUsage> {
+    let tree = process_tree(pid);
+    let start_ticks = total_cpu_ticks(&tree)?;
+    tokio::time::sleep(window).await;
+    let end_ticks = total_cpu_ticks(&tree);
+
+    let cpu_percent = end_ticks.map(|end_ticks| {
+        end_ticks.saturating_sub(start_ticks) as f64 / USER_HZ / window.as_secs_f64() * 100.0
+    });
+    let rss_bytes = tree
+        .iter()
+        .filter_map(|pid| read_rss_bytes(*pid))
+        .reduce(|a, b| a + b);
+
+    Some(ProcessUsage {
+        pid,
+        processes: tree.len(),
+        cpu_percent,
+        rss_bytes,
+    })
+}
@@ -0,0 +1,108 @@
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tracing::{error, trace, warn};
+use url::Url;
+
+use super::ytdlp::DownloadProgress;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+const PENDING_CAPACITY: usize = 1024;
+
+/// Funnels progress snapshots to a single task that writes only the latest one per download.
+#[derive(Clone)]
+pub struct ProgressWriter {
+    tx: Sender<DownloadProgress>,
+}
+
+impl ProgressWriter {
+    pub fn spawn(db: SqlitePool) -> ProgressWriter {
+        let (tx, rx) = mpsc::channel(PENDING_CAPACITY);
+        tokio::spawn(run(db, rx));
+        ProgressWriter { tx }
+    }
+
+    /// Queues a snapshot without waiting, dropping it if the writer is backed up.
+    pub fn record(&self, progress: DownloadProgress) {
+        match self.tx.try_send(progress) {
+            Ok(_) => (),
+            Err(TrySendError::Full(progress)) => {
+                warn!(
+                    "progress writer full, dropped update for url: {}",
+                    progress.url
+                )
+            }
+            Err(TrySendError::Closed(_)) => error!("progress writer stopped"),
+        }
+    }
+}
+
+async fn run(db: SqlitePool, mut rx: Receiver<DownloadProgress>) {
+    let mut pending: HashMap<Url, (DownloadProgress, DateTime<Utc>)> = HashMap::new();
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            progress = rx.recv() => match progress {
+                Some(progress) => {
+                    pending.insert(progress.url.clone(), (progress, Utc::now()));
+                }
+                None => break,
+            },
+            _ = interval.tick() => flush(&db, &mut pending).await,
+        }
+    }
+
+    flush(&db, &mut pending).await;
+}
+
+async fn flush(db: &SqlitePool, pending: &mut HashMap<Url, (DownloadProgress, DateTime<Utc>)>) {
+    if pending.is_empty() {
+        return;
+    }
+
+    if let Err(err) = write_batch(db, pending).await {
+        error!(
+            "failed to persist {} progress updates: {}",
+            pending.len(),
+            err
+        );
+        return;
+    }
+
+    trace!("persisted {} progress updates", pending.len());
+    pending.clear();
+}
+
+async fn write_batch(
+    db: &SqlitePool,
+    pending: &HashMap<Url, (DownloadProgress, DateTime<Utc>)>,
+) -> sqlx::Result<()> {
+    let mut transaction = db.begin().await?;
+
+    for (url, (progress, updated_at)) in pending {
+        let url = url.as_str();
+        sqlx::query!(
+            r#"INSERT INTO DownloadProgress (url, percent, size_downloaded, speed, eta, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT(url) DO UPDATE SET
+                percent = excluded.percent,
+                size_downloaded = excluded.size_downloaded,
+                speed = excluded.speed,
+                eta = excluded.eta,
+                updated_at = excluded.updated_at"#,
+            url,
+            progress.percent,
+            progress.size_downloaded,
+            progress.speed,
+            progress.eta,
+            updated_at
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await
+}
@@ -0,0 +1,330 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use url::Url;
+
+use super::clock;
+use super::retry;
+use super::windows::{self, DownloadWindow};
+
+/// Hands out a fixed number of worker slots, highest priority first then lowest rank.
+/// Domains that turned downloads away for sending too many requests sit out a cooldown and get
+/// fewer slots after it, see [`DownloadQueue::throttle`], and domains with download windows wait
+/// for one to open.
+#[derive(Clone)]
+pub struct DownloadQueue {
+    state: Arc<Mutex<QueueState>>,
+}
+
+struct QueueState {
+    max_running: usize,
+    next_ticket: u64,
+    running: usize,
+    running_by_domain: HashMap<String, usize>,
+    throttles: HashMap<String, Throttle>,
+    waiting: VecDeque<Waiter>,
+    windows: Vec<DownloadWindow>,
+}
+
+struct Throttle {
+    max_running: usize,
+    strikes: u32,
+    until: Instant,
+}
+
+/// A domain the queue is holding back, as shown to users.
+#[derive(Clone, Debug, Serialize)]
+pub struct DomainThrottle {
+    pub domain: String,
+    /// Downloads from the domain allowed to run at once.
+    pub max_running: usize,
+    /// Nothing more from the domain starts before then.
+    pub cooling_until: DateTime<Utc>,
+    /// Times in a row the domain throttled us, each one doubling the cooldown.
+    pub strikes: u32,
+}
+
+struct Waiter {
+    domain: String,
+    priority: i64,
+    rank: i64,
+    ticket: u64,
+    turn: oneshot::Sender<()>,
+    url: Url,
+}
+
+/// A held worker slot, given back to the queue when dropped.
+pub struct Slot {
+    domain: String,
+    queue: DownloadQueue,
+}
+
+/// A place in line. Dropping it before its turn comes leaves the queue.
+struct Ticket {
+    domain: String,
+    queue: DownloadQueue,
+    rx: Option<oneshot::Receiver<()>>,
+    ticket: u64,
+}
+
+impl DownloadQueue {
+    pub fn new(max_running: usize) -> DownloadQueue {
+        DownloadQueue {
+            state: Arc::new(Mutex::new(QueueState {
+                max_running: max_running.max(1),
+                next_ticket: 0,
+                running: 0,
+                running_by_domain: HashMap::new(),
+                throttles: HashMap::new(),
+                waiting: VecDeque::new(),
+                windows: Vec::new(),
+            })),
+        }
+    }
+
+    /// Waits for a free worker slot. Safe to cancel, an abandoned place is given up.
+    pub async fn acquire(&self, url: &Url, priority: i64, rank: i64) -> Slot {
+        let domain = domain(url);
+        let mut ticket = {
+            let mut state = self.lock();
+            if state.waiting.is_empty()
+                && state.running < state.max_running
+                && state.admits(&domain)
+            {
+                state.start(&domain);
+                return Slot {
+                    domain,
+                    queue: self.clone(),
+                };
+            }
+
+            let (turn, rx) = oneshot::channel();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.waiting.push_back(Waiter {
+                domain: domain.clone(),
+                priority,
+                rank,
+                ticket,
+                turn,
+                url: url.clone(),
+            });
+            // Whoever is waiting already may be held back by their domain while this one isn't.
+            state.grant();
+
+            Ticket {
+                domain: domain.clone(),
+                queue: self.clone(),
+                rx: Some(rx),
+                ticket,
+            }
+        };
+
+        if let Some(rx) = ticket.rx.as_mut() {
+            let _ = rx.await;
+        }
+        ticket.rx = None;
+
+        Slot {
+            domain,
+            queue: self.clone(),
+        }
+    }
+
+    /// Holds `url`'s domain back after it turned a download away for sending too many requests.
+    /// Nothing more from it starts until the cooldown, doubled for every strike in a row, has
+    /// passed, and from then on it gets half the slots it had.
+    pub fn throttle(&self, url: &Url, cooldown: Duration) -> DomainThrottle {
+        let domain = domain(url);
+        let mut state = self.lock();
+        let max_running = state.max_running;
+        let throttle = state.throttles.entry(domain.clone()).or_insert(Throttle {
+            max_running,
+            strikes: 0,
+            until: Instant::now(),
+        });
+        throttle.strikes += 1;
+        throttle.max_running = (throttle.max_running / 2).max(1);
+        let cooldown = retry::backoff(cooldown, throttle.strikes);
+        throttle.until = Instant::now() + cooldown;
+        let throttled = throttle.info(&domain);
+
+        let queue = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(cooldown).await;
+            queue.lock().grant();
+        });
+        throttled
+    }
+
+    /// Lifts the throttle on `url`'s domain once a download from it went through.
+    pub fn relax(&self, url: &Url) {
+        let mut state = self.lock();
+        if state.throttles.remove(&domain(url)).is_some() {
+            state.grant();
+        }
+    }
+
+    /// Replaces the download windows, starting whatever they now let through.
+    pub fn set_windows(&self, windows: Vec<DownloadWindow>) {
+        let mut state = self.lock();
+        state.windows = windows;
+        state.grant();
+    }
+
+    /// Starts whatever a download window that opened since the last slot was handed out lets
+    /// through.
+    pub fn regrant(&self) {
+        self.lock().grant();
+    }
+
+    /// The domains being held back.
+    pub fn throttles(&self) -> Vec<DomainThrottle> {
+        let state = self.lock();
+        let mut throttles: Vec<DomainThrottle> = state
+            .throttles
+            .iter()
+            .map(|(domain, throttle)| throttle.info(domain))
+            .collect();
+        throttles.sort_by(|a, b| a.domain.cmp(&b.domain));
+        throttles
+    }
+
+    /// Urls waiting for a slot, in the order they will get one.
+    pub fn waiting(&self) -> Vec<Url> {
+        let state = self.lock();
+        let mut waiting: Vec<&Waiter> = state
+            .waiting
+            .iter()
+            .filter(|waiter| !waiter.turn.is_closed())
+            .collect();
+        waiting.sort_by_key(|waiter| waiter.order());
+        waiting.iter().map(|waiter| waiter.url.clone()).collect()
+    }
+
+    /// Moves a waiting url to a new priority and rank, returns false if it isn't waiting.
+    pub fn set_place(&self, url: &Url, priority: i64, rank: i64) -> bool {
+        let mut state = self.lock();
+        match state.waiting.iter_mut().find(|waiter| &waiter.url == url) {
+            Some(waiter) => {
+                waiter.priority = priority;
+                waiter.rank = rank;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn release(&self, domain: &str) {
+        let mut state = self.lock();
+        state.finish(domain);
+        state.grant();
+    }
+}
+
+impl Throttle {
+    fn info(&self, domain: &str) -> DomainThrottle {
+        let remaining = self.until.saturating_duration_since(Instant::now());
+        DomainThrottle {
+            domain: domain.to_string(),
+            max_running: self.max_running,
+            cooling_until: Utc::now() + chrono::Duration::from_std(remaining).unwrap_or_default(),
+            strikes: self.strikes,
+        }
+    }
+}
+
+/// The host downloads are throttled by, empty for urls without one.
+fn domain(url: &Url) -> String {
+    url.host_str().unwrap_or_default().to_string()
+}
+
+impl Waiter {
+    fn order(&self) -> (std::cmp::Reverse<i64>, i64, u64) {
+        (std::cmp::Reverse(self.priority), self.rank, self.ticket)
+    }
+}
+
+impl QueueState {
+    /// Whether a download from `domain` may start, going by its windows and throttle.
+    fn admits(&self, domain: &str) -> bool {
+        if !windows::is_open(&self.windows, domain, clock::local(Utc::now()).time()) {
+            return false;
+        }
+        match self.throttles.get(domain) {
+            Some(throttle) => {
+                Instant::now() >= throttle.until
+                    && self.running_by_domain.get(domain).copied().unwrap_or(0)
+                        < throttle.max_running
+            }
+            None => true,
+        }
+    }
+
+    fn start(&mut self, domain: &str) {
+        self.running += 1;
+        *self
+            .running_by_domain
+            .entry(domain.to_string())
+            .or_insert(0) += 1;
+    }
+
+    fn finish(&mut self, domain: &str) {
+        self.running -= 1;
+        if let Some(running) = self.running_by_domain.get_mut(domain) {
+            *running -= 1;
+            if *running == 0 {
+                self.running_by_domain.remove(domain);
+            }
+        }
+    }
+
+    fn grant(&mut self) {
+        while self.running < self.max_running {
+            let next = self
+                .waiting
+                .iter()
+                .enumerate()
+                .filter(|(_, waiter)| self.admits(&waiter.domain))
+                .min_by_key(|(_, waiter)| waiter.order())
+                .map(|(index, _)| index);
+            match next.and_then(|index| self.waiting.remove(index)) {
+                Some(waiter) => {
+                    if waiter.turn.send(()).is_ok() {
+                        self.start(&waiter.domain);
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.queue.release(&self.domain);
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let Some(mut rx) = self.rx.take() else {
+            return;
+        };
+
+        let mut state = self.queue.lock();
+        state.waiting.retain(|waiter| waiter.ticket != self.ticket);
+        // The turn may have been granted just before the waiter gave up.
+        if rx.try_recv().is_ok() {
+            state.finish(&self.domain);
+            state.grant();
+        }
+    }
+}
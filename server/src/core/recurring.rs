@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use std::str::FromStr;
+
+use super::clock;
+
+/// Parses a cron expression. The usual five fields are accepted as well as the six or seven
+/// field form with seconds and years, so `0 3 * * *` and `0 0 3 * * *` both mean 03:00 daily.
+/// # Errors
+/// The parser's description of what was wrong with the expression.
+pub fn parse(expression: &str) -> Result<Schedule, String> {
+    let expression = expression.trim();
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression),
+        _ => expression.to_string(),
+    };
+
+    Schedule::from_str(&expression).map_err(|err| err.to_string())
+}
+
+/// The first time the schedule fires after `after`, read on the instance clock.
+pub fn next_run(schedule: &Schedule, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    schedule
+        .after(&clock::local(after))
+        .next()
+        .map(|next| next.with_timezone(&Utc))
+}
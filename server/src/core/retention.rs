@@ -0,0 +1,47 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How much of the history is kept, completed and canceled downloads past either limit are
+/// pruned. Unset limits keep everything. Pinned and starred downloads are always kept and don't
+/// count.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct RetentionSettings {
+    pub max_age_days: Option<i64>,
+    pub max_rows: Option<i64>,
+    /// Also removes pruned downloads' files from disk.
+    #[serde(default)]
+    pub remove_files: bool,
+}
+
+impl RetentionSettings {
+    /// The first limit that isn't a positive number, if any.
+    pub fn invalid_limit(&self) -> Option<i64> {
+        [self.max_age_days, self.max_rows]
+            .into_iter()
+            .flatten()
+            .find(|limit| *limit < 1)
+    }
+}
+
+/// Which of the `finished` downloads, given as their id and when they finished, are past the
+/// limits. The most recently finished are the ones kept under `max_rows`.
+pub fn expired(
+    mut finished: Vec<(i64, DateTime<Utc>)>,
+    settings: &RetentionSettings,
+    now: DateTime<Utc>,
+) -> Vec<i64> {
+    finished.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+    let cutoff = settings
+        .max_age_days
+        .map(|days| now - TimeDelta::days(days));
+
+    finished
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (_, finished_at))| {
+            settings.max_rows.is_some_and(|max| *index as i64 >= max)
+                || cutoff.is_some_and(|cutoff| *finished_at < cutoff)
+        })
+        .map(|(_, (id, _))| id)
+        .collect()
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Errors that mean the video itself can't be fetched, checked before the transient ones.
+const TERMINAL_PATTERNS: &[&str] = &[
+    "http error 403",
+    "http error 404",
+    "http error 410",
+    "private video",
+    "video is private",
+    "not available in your country",
+    "geo restrict",
+    "not available from your location",
+    "video unavailable",
+    "has been removed",
+    "copyright",
+    "members-only",
+    "sign in to confirm",
+    "unsupported url",
+    "requested format is not available",
+];
+
+/// Network trouble and server side hiccups that are worth another go.
+const TRANSIENT_PATTERNS: &[&str] = &[
+    "http error 429",
+    "http error 5",
+    "too many requests",
+    "timed out",
+    "connection reset",
+    "connection refused",
+    "connection aborted",
+    "remote end closed connection",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "network is unreachable",
+    "incompleteread",
+    "unable to download video data",
+    "stalled with no output",
+    "stopped answering",
+];
+
+/// The site turning us away for sending too many requests, see [`DownloadQueue::throttle`].
+///
+/// [`DownloadQueue::throttle`]: super::queue::DownloadQueue::throttle
+const THROTTLED_PATTERNS: &[&str] = &["http error 429", "too many requests"];
+
+/// What a download's last error came down to, read from yt-dlp's message so clients can explain
+/// it without parsing the text themselves.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum FailureCause {
+    Private,
+    GeoBlocked,
+    MembersOnly,
+    SignInRequired,
+    Copyright,
+    Forbidden,
+    NotFound,
+    Unsupported,
+    FormatUnavailable,
+    RateLimited,
+    Stalled,
+    TimedOut,
+    /// Cut short by the server stopping, not by the download itself.
+    Interrupted,
+    Network,
+    Other,
+}
+
+/// Checked in order, the more specific messages first. yt-dlp's own messages are matched loosely
+/// since their wording shifts between extractors and versions.
+const CAUSE_PATTERNS: &[(&str, FailureCause)] = &[
+    ("private video", FailureCause::Private),
+    ("video is private", FailureCause::Private),
+    ("not available in your country", FailureCause::GeoBlocked),
+    ("geo restrict", FailureCause::GeoBlocked),
+    ("not available from your location", FailureCause::GeoBlocked),
+    ("members-only", FailureCause::MembersOnly),
+    ("sign in to confirm", FailureCause::SignInRequired),
+    ("copyright", FailureCause::Copyright),
+    ("http error 403", FailureCause::Forbidden),
+    ("http error 404", FailureCause::NotFound),
+    ("http error 410", FailureCause::NotFound),
+    ("video unavailable", FailureCause::NotFound),
+    ("has been removed", FailureCause::NotFound),
+    ("unsupported url", FailureCause::Unsupported),
+    (
+        "requested format is not available",
+        FailureCause::FormatUnavailable,
+    ),
+    ("http error 429", FailureCause::RateLimited),
+    ("too many requests", FailureCause::RateLimited),
+    ("stalled with no output", FailureCause::Stalled),
+    ("ran past its limit", FailureCause::TimedOut),
+    ("interrupted by a server restart", FailureCause::Interrupted),
+    ("paused by a server shutdown", FailureCause::Interrupted),
+];
+
+/// Sorts a yt-dlp error into a [`FailureCause`], other network trouble reads as `Network`.
+pub fn classify(error: &str) -> FailureCause {
+    let lowered = error.to_lowercase();
+    if let Some((_, cause)) = CAUSE_PATTERNS
+        .iter()
+        .find(|(pattern, _)| lowered.contains(pattern))
+    {
+        return *cause;
+    }
+    match is_transient(error) {
+        true => FailureCause::Network,
+        false => FailureCause::Other,
+    }
+}
+
+/// Whether a yt-dlp error looks like it could pass on a later attempt.
+pub fn is_transient(error: &str) -> bool {
+    let error = error.to_lowercase();
+    if TERMINAL_PATTERNS
+        .iter()
+        .any(|pattern| error.contains(pattern))
+    {
+        return false;
+    }
+
+    TRANSIENT_PATTERNS
+        .iter()
+        .any(|pattern| error.contains(pattern))
+}
+
+/// Whether a yt-dlp error says the site is rate limiting us.
+pub fn is_throttled(error: &str) -> bool {
+    let error = error.to_lowercase();
+    THROTTLED_PATTERNS
+        .iter()
+        .any(|pattern| error.contains(pattern))
+}
+
+/// Doubles `base` for every attempt already made, capped at ten minutes.
+pub fn backoff(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
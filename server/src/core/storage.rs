@@ -0,0 +1,173 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::warn;
+use url::Url;
+
+/// How long a download waiting on space goes before looking again, for space freed by hand.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Holds each download's own working directory, under the download path so finished files are
+/// moved rather than copied out of it.
+const WORK_DIRS: &str = ".tmp";
+
+/// How far a download's estimated size is from fitting on the disk.
+#[derive(Clone, Debug, Serialize)]
+pub struct Shortfall {
+    pub needed: u64,
+    pub available: u64,
+}
+
+/// Disk space set aside for running downloads by their estimated size. Each download only has to
+/// fit in the free space when it's submitted, the reservations keep the ones that start around the
+/// same time from filling the disk together.
+#[derive(Clone, Default)]
+pub struct Reservations {
+    reserved: Arc<Mutex<HashMap<Url, u64>>>,
+    released: Arc<Notify>,
+}
+
+impl Reservations {
+    /// Sets `size` bytes aside for `url` if they fit in the free space on `path` that isn't already
+    /// set aside for another download. An unreadable disk is taken to have room.
+    pub fn reserve(&self, url: &Url, size: u64, path: &Path) -> Result<(), Shortfall> {
+        let mut reserved = self.lock();
+        let others: u64 = reserved
+            .iter()
+            .filter(|(reserved_url, _)| *reserved_url != url)
+            .map(|(_, size)| size)
+            .sum();
+        if let Ok(free) = fs4::available_space(path) {
+            let available = free.saturating_sub(others);
+            if size > available {
+                return Err(Shortfall {
+                    needed: size,
+                    available,
+                });
+            }
+        }
+        reserved.insert(url.clone(), size);
+        Ok(())
+    }
+
+    /// Reserves space for `url` as [`Reservations::reserve`] does, waiting for other downloads to
+    /// give theirs back while it doesn't fit. `on_wait` hears about the first shortfall.
+    pub async fn reserve_when_free(
+        &self,
+        url: &Url,
+        size: u64,
+        path: &Path,
+        on_wait: impl FnOnce(Shortfall),
+    ) {
+        let mut on_wait = Some(on_wait);
+        loop {
+            // Registered before the attempt so a release in between isn't missed.
+            let released = self.released.notified();
+            match self.reserve(url, size, path) {
+                Ok(()) => return,
+                Err(shortfall) => {
+                    if let Some(on_wait) = on_wait.take() {
+                        on_wait(shortfall);
+                    }
+                }
+            }
+            let _ = tokio::time::timeout(RECHECK_INTERVAL, released).await;
+        }
+    }
+
+    /// Gives the space set aside for `url` back to the downloads waiting on it.
+    pub fn release(&self, url: &Url) {
+        if self.lock().remove(url).is_some() {
+            self.released.notify_waiters();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Url, u64>> {
+        self.reserved.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Whether a download of `size` bytes fits in the free space on `path`, leaving reservations
+/// aside. An unreadable disk is taken to have room.
+pub fn check_free_space(size: u64, path: &Path) -> Result<(), Shortfall> {
+    match fs4::available_space(path) {
+        Ok(available) if size > available => Err(Shortfall {
+            needed: size,
+            available,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// `bytes` in whole megabytes, for messages.
+pub fn megabytes(bytes: u64) -> u64 {
+    bytes / 1_000_000
+}
+
+/// The working directory named `name`, where a download's fragments and intermediate files are
+/// kept apart from the other downloads'.
+pub fn work_dir(download_path: &Path, name: &str) -> PathBuf {
+    download_path.join(WORK_DIRS).join(name)
+}
+
+/// Removes the working directory named `name` with whatever was left in it.
+pub async fn remove_work_dir(download_path: &Path, name: &str) {
+    let work_dir = work_dir(download_path, name);
+    if let Err(err) = tokio::fs::remove_dir_all(&work_dir).await {
+        if err.kind() != io::ErrorKind::NotFound {
+            warn!(
+                "couldn't remove work dir: {}, err: {}",
+                work_dir.display(),
+                err
+            );
+        }
+    }
+}
+
+/// yt-dlp reports paths relative to the download folder unless the name format was absolute.
+pub fn resolve_file_path(download_path: &Path, file_path: PathBuf) -> PathBuf {
+    match file_path.is_relative() {
+        true => download_path.join(file_path),
+        false => file_path,
+    }
+}
+
+/// Subtitles, thumbnails, info json and the like written next to the main file, named after it
+/// with another extension, e.g. `clip.en.vtt` for `clip.mp4`. Another download's `clip-2.mp4`
+/// shares the start of the name but isn't one.
+pub fn sidecar_files(file_path: &Path) -> Vec<PathBuf> {
+    let (Some(parent), Some(stem)) = (file_path.parent(), file_path.file_stem()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", stem.to_string_lossy());
+
+    match fs::read_dir(parent) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path != file_path)
+            .filter(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy())
+                    .is_some_and(|name| name.starts_with(&prefix) && !name.ends_with(".part"))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Removes `files`, returning the ones that were removed. Those already gone are skipped.
+pub async fn remove_files(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut removed = Vec::new();
+    for file in files {
+        match tokio::fs::remove_file(&file).await {
+            Ok(()) => removed.push(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => warn!("couldn't remove file: {}, err: {}", file.display(), err),
+        }
+    }
+    removed
+}
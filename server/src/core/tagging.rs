@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::path::{Component, Path};
+
+/// A label downloads are filed under, e.g. `music` or `lectures`. Downloads with a tag that has a
+/// subdirectory are saved there, inside the download path.
+#[derive(Clone, Debug, Serialize)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub subdirectory: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Whether `name` can be used as a tag, lowercase words joined by dashes or underscores.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Whether `subdirectory` stays inside the download path, away from the hidden work dirs.
+pub fn is_valid_subdirectory(subdirectory: &str) -> bool {
+    let path = Path::new(subdirectory);
+    path.components().next().is_some()
+        && path.components().all(|component| match component {
+            Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+            _ => false,
+        })
+}
+
+/// Every tag, by name.
+pub async fn load(db: &SqlitePool) -> sqlx::Result<Vec<Tag>> {
+    sqlx::query_as!(
+        Tag,
+        r#"SELECT id as "id!", name, subdirectory, created_at as "created_at: DateTime<Utc>"
+        FROM Tag ORDER BY name"#
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// The tags on a download, by name.
+pub async fn for_download(db: &SqlitePool, download_id: i64) -> sqlx::Result<Vec<Tag>> {
+    sqlx::query_as!(
+        Tag,
+        r#"SELECT Tag.id as "id!", name, subdirectory, created_at as "created_at: DateTime<Utc>"
+        FROM Tag JOIN DownloadTag ON DownloadTag.tag_id = Tag.id
+        WHERE DownloadTag.download_id = $1 ORDER BY name"#,
+        download_id
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// Where a download is saved relative to the download path, the subdirectory of the first of its
+/// tags by name that has one.
+pub async fn subdirectory(db: &SqlitePool, download_id: i64) -> sqlx::Result<Option<String>> {
+    let subdirectory = sqlx::query_scalar!(
+        r#"SELECT subdirectory as "subdirectory!" FROM Tag
+        JOIN DownloadTag ON DownloadTag.tag_id = Tag.id
+        WHERE DownloadTag.download_id = $1 AND subdirectory IS NOT NULL
+        ORDER BY name LIMIT 1"#,
+        download_id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(subdirectory)
+}
+
+/// Puts `names` on a download alongside the tags it already has, creating the ones that don't
+/// exist yet.
+pub async fn add(db: &SqlitePool, download_id: i64, names: &[String]) -> sqlx::Result<()> {
+    let mut transaction = db.begin().await?;
+    link(&mut transaction, download_id, names).await?;
+    transaction.commit().await
+}
+
+/// Replaces a download's tags with `names`, creating the ones that don't exist yet.
+pub async fn set(db: &SqlitePool, download_id: i64, names: &[String]) -> sqlx::Result<()> {
+    let mut transaction = db.begin().await?;
+    sqlx::query!(
+        "DELETE FROM DownloadTag WHERE download_id = $1",
+        download_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    link(&mut transaction, download_id, names).await?;
+    transaction.commit().await
+}
+
+async fn link(
+    connection: &mut SqliteConnection,
+    download_id: i64,
+    names: &[String],
+) -> sqlx::Result<()> {
+    let now = Utc::now();
+    for name in names {
+        sqlx::query!(
+            "INSERT INTO Tag (name, created_at) VALUES ($1, $2) ON CONFLICT (name) DO NOTHING",
+            name,
+            now
+        )
+        .execute(&mut *connection)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO DownloadTag (download_id, tag_id)
+            SELECT $1, id FROM Tag WHERE name = $2
+            ON CONFLICT DO NOTHING"#,
+            download_id,
+            name
+        )
+        .execute(&mut *connection)
+        .await?;
+    }
+    Ok(())
+}
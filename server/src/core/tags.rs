@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+/// Formats yt-dlp can extract audio to.
+pub const AUDIO_FORMATS: &[&str] = &["aac", "alac", "flac", "m4a", "mp3", "opus", "vorbis", "wav"];
+
+/// The tags written into extracted audio unless a download's template says otherwise, as yt-dlp
+/// output templates. Fields a video doesn't have fall through to the next one listed.
+pub const DEFAULT_TEMPLATE: &[(&str, &str)] = &[
+    ("album", "%(album,playlist_title)s"),
+    ("artist", "%(artist,uploader)s"),
+    ("date", "%(release_year,upload_date>%Y)s"),
+    ("title", "%(track,title)s"),
+];
+
+/// Whether `name` can be used as a tag, ID3 and Vorbis comments are both fine with lowercase names.
+pub fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// The default template with `overrides` laid over it, an empty template drops the tag.
+pub fn template(overrides: Option<&BTreeMap<String, String>>) -> BTreeMap<String, String> {
+    let mut template: BTreeMap<String, String> = DEFAULT_TEMPLATE
+        .iter()
+        .map(|(tag, field)| (tag.to_string(), field.to_string()))
+        .collect();
+    if let Some(overrides) = overrides {
+        template.extend(overrides.clone());
+    }
+    template.retain(|_, field| !field.is_empty());
+    template
+}
+
+/// The `--parse-metadata` arguments that have yt-dlp fill each tag before embedding them.
+pub fn parse_metadata_args(template: &BTreeMap<String, String>) -> Vec<String> {
+    template
+        .iter()
+        .map(|(tag, field)| format!("{}:%(meta_{})s", field, tag))
+        .collect()
+}
@@ -0,0 +1,270 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+use super::policy::PolicyAction;
+
+/// Containers a file can be remuxed into.
+pub const CONTAINERS: &[&str] = &["mkv", "mov", "mp4", "webm"];
+/// Formats subtitles can be converted to.
+pub const SUBTITLE_FORMATS: &[&str] = &["srt", "vtt"];
+/// How wide each frame of a storyboard is scaled to, in pixels.
+const STORYBOARD_FRAME_WIDTH: u32 = 320;
+
+/// How hard the steps that rewrite whole files may lean on the disk, so they don't starve other
+/// readers of it like a media server streaming from the same drive.
+#[derive(Clone, Debug, Default)]
+pub struct IoLimits {
+    /// The `ionice` scheduling class ffmpeg runs in, Linux only.
+    pub io_class: Option<IoClass>,
+    /// Passed to ffmpeg's `-readrate`, how many times faster than playback inputs are read.
+    pub read_rate: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// Best effort at the lowest priority.
+    BestEffort,
+    /// Only touches the disk when nothing else wants it.
+    Idle,
+}
+
+impl IoClass {
+    fn ionice_args(self) -> &'static [&'static str] {
+        match self {
+            IoClass::BestEffort => &["-c", "2", "-n", "7"],
+            IoClass::Idle => &["-c", "3"],
+        }
+    }
+}
+
+impl IoLimits {
+    /// The yt-dlp arguments that hold its merges to the read rate. yt-dlp runs ffmpeg itself,
+    /// so the io class can't be applied to them.
+    pub fn merge_args(&self) -> Vec<String> {
+        match self.read_rate {
+            Some(read_rate) => vec![
+                String::from("--postprocessor-args"),
+                format!("Merger+ffmpeg_i:-readrate {}", read_rate),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The ffmpeg encoder used to produce `video_codec`, named as ffprobe reports it.
+pub fn encoder(video_codec: &str) -> Option<&'static str> {
+    match video_codec {
+        "av1" => Some("libsvtav1"),
+        "h264" => Some("libx264"),
+        "hevc" => Some("libx265"),
+        "vp9" => Some("libvpx-vp9"),
+        _ => None,
+    }
+}
+
+/// Runs ffmpeg over `input`, returning where the result was written. A remux leaves a file with
+/// the new extension and removes the original, a transcode replaces the original in place.
+/// # Errors
+/// Why ffmpeg couldn't be run or what it complained about.
+pub async fn run(
+    ffmpeg_path: &str,
+    limits: &IoLimits,
+    input: &Path,
+    action: PolicyAction,
+    target: &str,
+) -> Result<PathBuf, String> {
+    let extension = input
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (output, codec_args) = match action {
+        PolicyAction::Remux => (input.with_extension(target), vec!["-c", "copy"]),
+        PolicyAction::Transcode => {
+            let encoder = encoder(target).ok_or_else(|| format!("no encoder for: {}", target))?;
+            (
+                input.with_extension(format!("transcode.{}", extension)),
+                vec!["-c:v", encoder, "-c:a", "copy"],
+            )
+        }
+    };
+
+    ffmpeg(ffmpeg_path, limits, input, &codec_args, &output).await?;
+
+    match action {
+        PolicyAction::Remux => {
+            tokio::fs::remove_file(input)
+                .await
+                .map_err(|err| err.to_string())?;
+            Ok(output)
+        }
+        PolicyAction::Transcode => {
+            tokio::fs::rename(&output, input)
+                .await
+                .map_err(|err| err.to_string())?;
+            Ok(input.to_path_buf())
+        }
+    }
+}
+
+/// Whether `subtitle` is a subtitle file that can be converted to `format`. ASS only goes to SRT,
+/// as WebVTT can't carry most of its styling either.
+pub fn converts_subtitle(subtitle: &Path, format: &str) -> bool {
+    let sources: &[&str] = match format {
+        "srt" => &["ass", "vtt"],
+        "vtt" => &["srt"],
+        _ => &[],
+    };
+
+    subtitle
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| sources.contains(&extension.as_str()))
+}
+
+/// Writes a copy of `subtitle` in `format` next to it.
+/// # Errors
+/// Why ffmpeg couldn't be run or what it complained about.
+pub async fn convert_subtitle(
+    ffmpeg_path: &str,
+    subtitle: &Path,
+    format: &str,
+) -> Result<PathBuf, String> {
+    let output = subtitle.with_extension(format);
+    ffmpeg(ffmpeg_path, &IoLimits::default(), subtitle, &[], &output).await?;
+    Ok(output)
+}
+
+/// Writes `frames` evenly spaced frames of a `duration_secs` long video side by side into one
+/// image, a storyboard for sources without a usable thumbnail.
+/// # Errors
+/// Why ffmpeg couldn't be run or what it complained about.
+pub async fn storyboard(
+    ffmpeg_path: &str,
+    input: &Path,
+    duration_secs: f64,
+    frames: u32,
+    output: &Path,
+) -> Result<(), String> {
+    let filter = format!(
+        "fps={}/{:.3},scale={}:-2,tile={}x1",
+        frames, duration_secs, STORYBOARD_FRAME_WIDTH, frames
+    );
+    ffmpeg(
+        ffmpeg_path,
+        &IoLimits::default(),
+        input,
+        &["-vf", &filter, "-frames:v", "1"],
+        output,
+    )
+    .await
+}
+
+/// A single `width` by `height` grayscale frame of `input` from `at_secs` in, one byte per pixel.
+/// # Errors
+/// Why ffmpeg couldn't be run or what it complained about.
+pub async fn gray_frame(
+    ffmpeg_path: &str,
+    input: &Path,
+    at_secs: f64,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, String> {
+    let output = input.with_extension("frame.gray");
+    let at = format!("{:.3}", at_secs);
+    let filter = format!("scale={}:{},format=gray", width, height);
+    ffmpeg(
+        ffmpeg_path,
+        &IoLimits::default(),
+        input,
+        &[
+            "-ss",
+            &at,
+            "-vf",
+            &filter,
+            "-frames:v",
+            "1",
+            "-f",
+            "rawvideo",
+        ],
+        &output,
+    )
+    .await?;
+
+    let pixels = tokio::fs::read(&output)
+        .await
+        .map_err(|err| err.to_string());
+    let _ = tokio::fs::remove_file(&output).await;
+    pixels
+}
+
+/// Rewrites the metadata of `file` in place, copying the streams untouched.
+/// # Errors
+/// Why ffmpeg couldn't be run or what it complained about.
+pub async fn tag(
+    ffmpeg_path: &str,
+    limits: &IoLimits,
+    file: &Path,
+    tags: &[(&str, String)],
+) -> Result<(), String> {
+    let extension = file
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let output = file.with_extension(format!("tagging.{}", extension));
+    let metadata: Vec<String> = tags
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let mut args = vec!["-map", "0", "-c", "copy"];
+    for metadata in &metadata {
+        args.extend(["-metadata", metadata.as_str()]);
+    }
+
+    ffmpeg(ffmpeg_path, limits, file, &args, &output).await?;
+    tokio::fs::rename(&output, file)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+async fn ffmpeg(
+    ffmpeg_path: &str,
+    limits: &IoLimits,
+    input: &Path,
+    args: &[&str],
+    output: &Path,
+) -> Result<(), String> {
+    let mut command = match limits.io_class {
+        Some(io_class) => {
+            let mut command = Command::new("ionice");
+            command.args(io_class.ionice_args()).arg(ffmpeg_path);
+            command
+        }
+        None => Command::new(ffmpeg_path),
+    };
+    command
+        .arg("-nostdin")
+        .arg("-y")
+        .arg("-loglevel")
+        .arg("error");
+    if let Some(read_rate) = limits.read_rate {
+        command.arg("-readrate").arg(read_rate.to_string());
+    }
+    let result = command
+        .arg("-i")
+        .arg(input)
+        .args(args)
+        .arg(output)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if !result.status.success() {
+        let _ = tokio::fs::remove_file(output).await;
+        return Err(String::from_utf8_lossy(&result.stderr).trim().to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+use url::Url;
+
+use super::formats::UpgradeReport;
+use super::ytdlp::{DownloadOptions, YtdlpClient};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ScanSettings {
+    #[serde(default)]
+    pub auto_enqueue: bool,
+    #[serde(default)]
+    pub min_improvement_percent: f64,
+    pub max_upgrade_bytes: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScanResult {
+    pub checked: usize,
+    pub failed: Vec<Url>,
+    pub upgradeable: Vec<UpgradeReport>,
+    pub enqueued: Vec<Url>,
+}
+
+/// Walks every completed download looking for better formats, one scan at a time.
+#[derive(Clone)]
+pub struct UpgradeScanner {
+    delay: Duration,
+    running: Arc<AtomicBool>,
+    ytdlp_client: YtdlpClient,
+}
+
+impl ScanSettings {
+    /// Whether an upgrade clears the improvement and size thresholds for auto-enqueueing.
+    pub fn should_enqueue(&self, report: &UpgradeReport) -> bool {
+        if !self.auto_enqueue || !report.upgradeable {
+            return false;
+        }
+
+        if report.improvement_percent.unwrap_or(0.0) < self.min_improvement_percent {
+            return false;
+        }
+
+        match self.max_upgrade_bytes {
+            Some(max_upgrade_bytes) => report
+                .best
+                .as_ref()
+                .and_then(|best| best.estimated_size())
+                .is_some_and(|size| size <= max_upgrade_bytes),
+            None => true,
+        }
+    }
+}
+
+impl UpgradeScanner {
+    pub fn new(ytdlp_client: YtdlpClient, delay: Duration) -> UpgradeScanner {
+        UpgradeScanner {
+            delay,
+            running: Arc::new(AtomicBool::new(false)),
+            ytdlp_client,
+        }
+    }
+
+    /// Checks the whole library, waiting `delay` between probes so the origin isn't hammered.
+    /// Returns `None` if a scan is already in progress.
+    pub async fn scan(&self) -> Option<ScanResult> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+
+        let urls = self.ytdlp_client.get_completed_urls().await;
+        let mut result = ScanResult::default();
+        info!("starting upgrade scan over {} downloads", urls.len());
+
+        for (index, url) in urls.into_iter().enumerate() {
+            if index > 0 {
+                tokio::time::sleep(self.delay).await;
+            }
+
+            result.checked += 1;
+            match self.ytdlp_client.check_upgrade(&url).await {
+                Ok(report) => {
+                    if report.upgradeable {
+                        result.upgradeable.push(report);
+                    }
+                }
+                Err(err) => {
+                    error!("upgrade check failed for url: {}, err: {:?}", url, err);
+                    result.failed.push(url);
+                }
+            }
+        }
+
+        info!(
+            "upgrade scan finished, {} of {} downloads upgradeable",
+            result.upgradeable.len(),
+            result.checked
+        );
+        self.running.store(false, Ordering::SeqCst);
+
+        Some(result)
+    }
+}
+
+/// The options to re-download with so the best available resolution is picked.
+pub fn upgraded_options(options: &DownloadOptions, report: &UpgradeReport) -> DownloadOptions {
+    let mut options = options.clone();
+    if let Some(height) = report.best.as_ref().and_then(|best| best.height) {
+        options.quality = height.to_string();
+    }
+    options
+}
@@ -0,0 +1,48 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::SqlitePool;
+
+use super::clock;
+use super::headers;
+
+/// Hours of the day, on the instance clock, when downloads from `domain` and its subdomains may
+/// start. Queued ones wait for the window to open, running ones are left to finish. Windows run
+/// past midnight and cover the whole day the same way bandwidth windows do.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DownloadWindow {
+    pub domain: String,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl DownloadWindow {
+    pub fn applies_to(&self, host: &str) -> bool {
+        headers::is_on_domain(host, &self.domain)
+    }
+}
+
+/// Whether downloads from `host` may start at `time`, which they always may unless a window
+/// covers the host. With more than one, any of them being open will do.
+pub fn is_open(windows: &[DownloadWindow], host: &str, time: NaiveTime) -> bool {
+    let mut applying = windows
+        .iter()
+        .filter(|window| window.applies_to(host))
+        .peekable();
+    applying.peek().is_none()
+        || applying.any(|window| clock::within(window.start, window.end, time))
+}
+
+/// The download windows in the config.
+pub async fn load(db: &SqlitePool) -> sqlx::Result<Vec<DownloadWindow>> {
+    let windows = sqlx::query_scalar!(
+        r#"SELECT download_windows as "download_windows: Json<Vec<DownloadWindow>>"
+        FROM Config WHERE id = 1"#
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(windows
+        .flatten()
+        .map(|windows| windows.0)
+        .unwrap_or_default())
+}
@@ -0,0 +1,467 @@
+use futures_util::TryStreamExt;
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+use url::Url;
+
+use super::workers::{
+    Job, JobExit, JobOutput, OutputReply, Registered, Registration, CLAIM_WAIT, HEARTBEAT_INTERVAL,
+};
+
+/// Wait before trying the primary again after it couldn't be reached.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// How long yt-dlp's output is held before it's sent on, short of the primary's stall timeout.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+/// Lines sent in one report at most.
+const REPORT_BATCH: usize = 200;
+/// The prefixes of yt-dlp arguments that hold a path, e.g. `-P home:/downloads`.
+const PATH_ARG_PREFIXES: &[&str] = &["", "home:", "temp:", "chapter:"];
+
+/// How this instance runs when it's a worker for a primary instead of a server of its own.
+#[derive(Clone, Debug)]
+pub struct WorkerSettings {
+    /// Where jobs run, each in a folder of its own removed once its files are uploaded.
+    pub download_path: PathBuf,
+    pub max_jobs: usize,
+    /// How the primary lists this worker.
+    pub name: String,
+    pub primary_url: Url,
+    /// The primary's `WORKER_TOKEN`.
+    pub token: String,
+    pub ytdlp_path: String,
+}
+
+#[derive(Debug)]
+enum PrimaryError {
+    /// The primary doesn't know the worker or job, it restarted or the job was dropped.
+    Unknown,
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    Status(StatusCode),
+}
+
+impl std::fmt::Display for PrimaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrimaryError::Unknown => write!(f, "the primary doesn't know this worker or job"),
+            PrimaryError::Http(err) => write!(f, "{}", err),
+            PrimaryError::Io(err) => write!(f, "{}", err),
+            PrimaryError::Status(status) => write!(f, "the primary answered with {}", status),
+        }
+    }
+}
+
+/// The worker api of the primary instance.
+#[derive(Clone)]
+struct Primary {
+    http: reqwest::Client,
+    token: String,
+    url: Url,
+}
+
+impl Primary {
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .expect("the primary url can't be a base")
+            .pop_if_empty()
+            .extend(["api", "workers"])
+            .extend(segments);
+        url
+    }
+
+    async fn post<T: Serialize, R: DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        body: &T,
+    ) -> Result<Option<R>, PrimaryError> {
+        let response = self
+            .http
+            .post(self.endpoint(segments))
+            .bearer_auth(&self.token)
+            .json(body)
+            .send()
+            .await
+            .map_err(PrimaryError::Http)?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            StatusCode::NOT_FOUND => Err(PrimaryError::Unknown),
+            status if status.is_success() => {
+                response.json().await.map(Some).map_err(PrimaryError::Http)
+            }
+            status => Err(PrimaryError::Status(status)),
+        }
+    }
+
+    async fn register(&self, settings: &WorkerSettings) -> String {
+        let registration = Registration {
+            name: settings.name.clone(),
+            max_jobs: settings.max_jobs,
+        };
+        loop {
+            match self
+                .post::<_, Registered>(&["register"], &registration)
+                .await
+            {
+                Ok(Some(registered)) => return registered.id,
+                Ok(None) => warn!("the primary didn't answer the registration"),
+                Err(err) => warn!("couldn't register with the primary: {}", err),
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+}
+
+/// Registers with the primary and runs the jobs it hands out, for as long as the process runs.
+pub async fn run(settings: WorkerSettings) {
+    let primary = Primary {
+        // Claims wait on the primary for a while before answering.
+        http: reqwest::Client::builder()
+            .connect_timeout(RETRY_DELAY)
+            .build()
+            .expect("couldn't build the http client"),
+        token: settings.token.clone(),
+        url: settings.primary_url.clone(),
+    };
+    let settings = Arc::new(settings);
+    let slots = Arc::new(Semaphore::new(settings.max_jobs.max(1)));
+
+    loop {
+        let worker_id = primary.register(&settings).await;
+        info!(
+            "registered with {} as worker {}, running up to {} jobs",
+            primary.url, settings.name, settings.max_jobs
+        );
+        loop {
+            let slot = slots
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("the job slots are never closed");
+            let claimed = tokio::time::timeout(
+                CLAIM_WAIT + RETRY_DELAY,
+                primary.post::<_, Job>(&[&worker_id, "claim"], &()),
+            )
+            .await;
+            match claimed {
+                Ok(Ok(Some(job))) => {
+                    info!("running job {} for url: {}", job.id, job.url);
+                    tokio::spawn(run_job(
+                        primary.clone(),
+                        settings.clone(),
+                        worker_id.clone(),
+                        job,
+                        slot,
+                    ));
+                }
+                Ok(Ok(None)) | Err(_) => {}
+                Ok(Err(PrimaryError::Unknown)) => {
+                    warn!("the primary forgot this worker, registering again");
+                    break;
+                }
+                Ok(Err(err)) => {
+                    warn!("couldn't claim a job: {}", err);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+async fn run_job(
+    primary: Primary,
+    settings: Arc<WorkerSettings>,
+    worker_id: String,
+    job: Job,
+    _slot: OwnedSemaphorePermit,
+) {
+    let scratch = std::path::absolute(settings.download_path.join(&job.id))
+        .unwrap_or_else(|_| settings.download_path.join(&job.id));
+    let mut runner = JobRunner {
+        primary,
+        worker_id,
+        job,
+        scratch,
+        output: JobOutput::default(),
+    };
+    let exit = runner.run(&settings).await;
+
+    if let Err(err) = runner
+        .primary
+        .post::<_, ()>(&[&runner.worker_id, "jobs", &runner.job.id, "exit"], &exit)
+        .await
+    {
+        warn!("couldn't report the end of job {}: {}", runner.job.id, err);
+    }
+    if let Err(err) = tokio::fs::remove_dir_all(&runner.scratch).await {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            warn!(
+                "couldn't remove job dir: {}, err: {}",
+                runner.scratch.display(),
+                err
+            );
+        }
+    }
+}
+
+struct JobRunner {
+    primary: Primary,
+    worker_id: String,
+    job: Job,
+    /// Stands in for the primary's download path.
+    scratch: PathBuf,
+    /// Output not yet sent.
+    output: JobOutput,
+}
+
+impl JobRunner {
+    async fn run(&mut self, settings: &WorkerSettings) -> JobExit {
+        let args: Vec<String> = self
+            .job
+            .args
+            .iter()
+            .map(|arg| swap_root(arg, &self.job.root, &self.scratch))
+            .collect();
+        let mut child = match Command::new(&settings.ytdlp_path)
+            .args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) => {
+                return JobExit {
+                    success: false,
+                    code: None,
+                    error: Some(format!(
+                        "failed to start yt-dlp on worker {}: {}",
+                        settings.name, err
+                    )),
+                }
+            }
+        };
+
+        let (lines_tx, mut lines) = mpsc::unbounded_channel();
+        if let Some(stdout) = child.stdout.take() {
+            let lines_tx = lines_tx.clone();
+            tokio::spawn(async move {
+                let mut stdout = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = stdout.next_line().await {
+                    let _ = lines_tx.send((false, line));
+                }
+            });
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(async move {
+                let mut stderr = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = stderr.next_line().await {
+                    let _ = lines_tx.send((true, line));
+                }
+            });
+        }
+
+        let mut stopped = false;
+        let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+        let mut last_report = Instant::now();
+        loop {
+            tokio::select! {
+                line = lines.recv() => {
+                    let Some((is_stderr, line)) = line else {
+                        break;
+                    };
+                    let line = self.swap_back(&line);
+                    match is_stderr {
+                        true => self.output.stderr.push(line),
+                        false => self.output.stdout.push(line),
+                    }
+                    if self.output.stdout.len() + self.output.stderr.len() < REPORT_BATCH {
+                        continue;
+                    }
+                }
+                _ = flush.tick() => {
+                    let quiet = self.output.stdout.is_empty() && self.output.stderr.is_empty();
+                    if quiet && last_report.elapsed() < HEARTBEAT_INTERVAL {
+                        continue;
+                    }
+                }
+            }
+            last_report = Instant::now();
+            if !stopped && self.report().await {
+                info!("stopping job {} as the primary asked", self.job.id);
+                stopped = true;
+                let _ = child.start_kill();
+            }
+        }
+        self.report().await;
+
+        let status = match child.wait().await {
+            Ok(status) => status,
+            Err(err) => {
+                return JobExit {
+                    success: false,
+                    code: None,
+                    error: Some(format!("lost yt-dlp on worker {}: {}", settings.name, err)),
+                }
+            }
+        };
+        let error = match status.success() && !stopped {
+            true => self.upload_files().await.err(),
+            false => None,
+        };
+        JobExit {
+            success: status.success() && !stopped,
+            code: status.code(),
+            error,
+        }
+    }
+
+    /// Sends the output held so far, answering whether the primary wants the job stopped.
+    async fn report(&mut self) -> bool {
+        let output = std::mem::take(&mut self.output);
+        let reply = self
+            .primary
+            .post::<_, OutputReply>(&[&self.worker_id, "jobs", &self.job.id, "output"], &output)
+            .await;
+        match reply {
+            Ok(reply) => reply.is_some_and(|reply| reply.stop),
+            Err(PrimaryError::Unknown) => true,
+            Err(err) => {
+                warn!("couldn't report on job {}: {}", self.job.id, err);
+                // Kept for the next report, ahead of whatever comes after it.
+                let mut later = std::mem::replace(&mut self.output, output);
+                self.output.stdout.append(&mut later.stdout);
+                self.output.stderr.append(&mut later.stderr);
+                false
+            }
+        }
+    }
+
+    /// Sends everything yt-dlp left in the download path to the primary. The work dirs and
+    /// anything else hidden are skipped, they're only the worker's.
+    async fn upload_files(&mut self) -> Result<(), String> {
+        let mut files = Vec::new();
+        let mut dirs = vec![self.scratch.clone()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(format!("couldn't list {}: {}", dir.display(), err)),
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                if dir == self.scratch && entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                match entry.file_type().await {
+                    Ok(file_type) if file_type.is_dir() => dirs.push(path),
+                    Ok(file_type) if file_type.is_file() => files.push(path),
+                    _ => {}
+                }
+            }
+        }
+
+        for file in files {
+            let relative = file
+                .strip_prefix(&self.scratch)
+                .unwrap_or(&file)
+                .to_path_buf();
+            self.upload_file(&file, &relative).await.map_err(|err| {
+                format!(
+                    "failed to upload {} to the primary: {}",
+                    relative.display(),
+                    err
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Streams one file up, reporting how far it got so the primary doesn't take a long upload
+    /// for a stalled download.
+    async fn upload_file(&mut self, file: &Path, relative: &Path) -> Result<(), PrimaryError> {
+        let size = tokio::fs::metadata(file)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        let sent = Arc::new(AtomicU64::new(0));
+        let counter = sent.clone();
+        let stream = ReaderStream::new(
+            tokio::fs::File::open(file)
+                .await
+                .map_err(PrimaryError::Io)?,
+        )
+        .inspect_ok(move |chunk| {
+            counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        });
+
+        let mut url = self
+            .primary
+            .endpoint(&[&self.worker_id, "jobs", &self.job.id, "files"]);
+        url.path_segments_mut()
+            .expect("the primary url can't be a base")
+            .extend(
+                relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy()),
+            );
+        let upload = self
+            .primary
+            .http
+            .put(url)
+            .bearer_auth(&self.primary.token)
+            .body(reqwest::Body::wrap_stream(stream))
+            .send();
+        tokio::pin!(upload);
+
+        let mut progress = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let response = loop {
+            tokio::select! {
+                response = &mut upload => break response.map_err(PrimaryError::Http)?,
+                _ = progress.tick() => {
+                    self.output.stdout.push(format!(
+                        "[worker] Uploading {}: {} of {} bytes",
+                        relative.display(),
+                        sent.load(Ordering::Relaxed),
+                        size
+                    ));
+                    self.report().await;
+                }
+            }
+        };
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::NOT_FOUND => Err(PrimaryError::Unknown),
+            status => Err(PrimaryError::Status(status)),
+        }
+    }
+
+    /// Puts the primary's paths back in a line yt-dlp wrote.
+    fn swap_back(&self, line: &str) -> String {
+        let scratch = self.scratch.to_string_lossy();
+        let root = self.job.root.to_string_lossy();
+        line.replace(scratch.as_ref(), root.trim_end_matches('/'))
+    }
+}
+
+/// Points an argument holding a path under the primary's download path at `scratch` instead.
+fn swap_root(arg: &str, root: &Path, scratch: &Path) -> String {
+    for prefix in PATH_ARG_PREFIXES {
+        let Some(path) = arg.strip_prefix(prefix) else {
+            continue;
+        };
+        if let Ok(relative) = Path::new(path).strip_prefix(root) {
+            return format!("{}{}", prefix, scratch.join(relative).display());
+        }
+    }
+    arg.to_string()
+}
@@ -0,0 +1,411 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Notify};
+use tracing::{info, warn};
+use url::Url;
+
+/// How long a worker can go without claiming or reporting before its jobs are given up on.
+pub const WORKER_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a claim waits for a job before answering with none, so idle workers aren't polling.
+pub const CLAIM_WAIT: Duration = Duration::from_secs(20);
+/// How often a worker reports on a job that's quiet, so the primary knows it's still there.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+/// How long a stopped job has to report its exit before it's taken as lost.
+const EXIT_WAIT: Duration = Duration::from_secs(30);
+
+/// A worker asking to be handed jobs.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Registration {
+    pub name: String,
+    /// Jobs the worker runs at once.
+    pub max_jobs: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Registered {
+    pub id: String,
+}
+
+/// A registered worker, as shown to users.
+#[derive(Clone, Debug, Serialize)]
+pub struct WorkerInfo {
+    pub id: String,
+    pub name: String,
+    pub max_jobs: usize,
+    /// Jobs claimed or waiting to be.
+    pub jobs: usize,
+    pub registered_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// One yt-dlp run handed to a worker. Paths in `args` are under `root`, the primary's download
+/// path, which the worker swaps for a folder of its own and swaps back in what it reports.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub url: Url,
+    /// Everything yt-dlp is run with, the url last.
+    pub args: Vec<String>,
+    pub root: PathBuf,
+}
+
+/// What yt-dlp wrote since the worker last reported.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct JobOutput {
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OutputReply {
+    /// The download was paused or canceled, the worker should kill yt-dlp and report its exit.
+    pub stop: bool,
+}
+
+/// How a job's yt-dlp run ended, reported once its files are uploaded.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JobExit {
+    pub success: bool,
+    pub code: Option<i32>,
+    /// Why the worker couldn't run yt-dlp or upload what it wrote.
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum WorkerError {
+    UnknownWorker,
+    UnknownJob,
+}
+
+/// The workers registered with this instance and the jobs handed to them. Only in memory, after
+/// a restart workers register again and interrupted downloads resume as they would locally.
+#[derive(Clone, Default)]
+pub struct WorkerPool {
+    inner: Arc<PoolState>,
+}
+
+#[derive(Default)]
+struct PoolState {
+    jobs: DashMap<String, JobSlot>,
+    /// Woken when a job is handed out, for the claims waiting on one.
+    handed_out: Notify,
+    workers: DashMap<String, Worker>,
+}
+
+struct Worker {
+    name: String,
+    max_jobs: usize,
+    inbox: Vec<Job>,
+    registered_at: DateTime<Utc>,
+    last_seen: Instant,
+    last_seen_at: DateTime<Utc>,
+}
+
+struct JobSlot {
+    worker_id: String,
+    root: PathBuf,
+    stdout: mpsc::UnboundedSender<String>,
+    stderr: mpsc::UnboundedSender<String>,
+    exit: Option<oneshot::Sender<JobExit>>,
+    stop: bool,
+}
+
+/// The primary's end of a job, read like a local yt-dlp process.
+pub struct RemoteJob {
+    id: String,
+    pool: WorkerPool,
+    stdout: mpsc::UnboundedReceiver<String>,
+    stderr: Option<mpsc::UnboundedReceiver<String>>,
+    exit: oneshot::Receiver<JobExit>,
+    /// The name of the worker running it.
+    pub worker: String,
+}
+
+impl Worker {
+    fn is_alive(&self) -> bool {
+        self.last_seen.elapsed() < WORKER_TIMEOUT
+    }
+
+    fn touch(&mut self) {
+        self.last_seen = Instant::now();
+        self.last_seen_at = Utc::now();
+    }
+}
+
+impl WorkerPool {
+    pub fn register(&self, registration: Registration) -> Registered {
+        let id = uuid::Uuid::new_v4().to_string();
+        info!(
+            "worker {} registered to run {} jobs at once",
+            registration.name, registration.max_jobs
+        );
+        self.inner.workers.insert(
+            id.clone(),
+            Worker {
+                name: registration.name,
+                max_jobs: registration.max_jobs.max(1),
+                inbox: Vec::new(),
+                registered_at: Utc::now(),
+                last_seen: Instant::now(),
+                last_seen_at: Utc::now(),
+            },
+        );
+        Registered { id }
+    }
+
+    pub fn list(&self) -> Vec<WorkerInfo> {
+        let mut workers: Vec<WorkerInfo> = self
+            .inner
+            .workers
+            .iter()
+            .filter(|worker| worker.is_alive())
+            .map(|worker| WorkerInfo {
+                id: worker.key().clone(),
+                name: worker.name.clone(),
+                max_jobs: worker.max_jobs,
+                jobs: self.jobs_of(worker.key()),
+                registered_at: worker.registered_at,
+                last_seen_at: worker.last_seen_at,
+            })
+            .collect();
+        workers.sort_by(|a, b| a.name.cmp(&b.name));
+        workers
+    }
+
+    /// Hands a yt-dlp run to the least busy worker with room for it, `None` when there's none.
+    pub fn assign(&self, url: &Url, args: Vec<String>, root: &Path) -> Option<RemoteJob> {
+        let worker_id = self
+            .inner
+            .workers
+            .iter()
+            .filter(|worker| worker.is_alive())
+            .map(|worker| (worker.key().clone(), worker.max_jobs))
+            .map(|(worker_id, max_jobs)| {
+                let jobs = self.jobs_of(&worker_id);
+                (worker_id, max_jobs, jobs)
+            })
+            .filter(|(_, max_jobs, jobs)| jobs < max_jobs)
+            .min_by_key(|(_, _, jobs)| *jobs)
+            .map(|(worker_id, _, _)| worker_id)?;
+        let mut worker = self.inner.workers.get_mut(&worker_id)?;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let (stdout_tx, stdout) = mpsc::unbounded_channel();
+        let (stderr_tx, stderr) = mpsc::unbounded_channel();
+        let (exit_tx, exit) = oneshot::channel();
+        self.inner.jobs.insert(
+            id.clone(),
+            JobSlot {
+                worker_id,
+                root: root.to_path_buf(),
+                stdout: stdout_tx,
+                stderr: stderr_tx,
+                exit: Some(exit_tx),
+                stop: false,
+            },
+        );
+        worker.inbox.push(Job {
+            id: id.clone(),
+            url: url.clone(),
+            args,
+            root: root.to_path_buf(),
+        });
+        let name = worker.name.clone();
+        drop(worker);
+        self.inner.handed_out.notify_waiters();
+
+        Some(RemoteJob {
+            id,
+            pool: self.clone(),
+            stdout,
+            stderr: Some(stderr),
+            exit,
+            worker: name,
+        })
+    }
+
+    /// The next job for a worker, waiting up to `wait` for one to be handed out.
+    pub async fn claim(&self, worker_id: &str, wait: Duration) -> Result<Option<Job>, WorkerError> {
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            let handed_out = self.inner.handed_out.notified();
+            {
+                let mut worker = self
+                    .inner
+                    .workers
+                    .get_mut(worker_id)
+                    .ok_or(WorkerError::UnknownWorker)?;
+                worker.touch();
+                if !worker.inbox.is_empty() {
+                    return Ok(Some(worker.inbox.remove(0)));
+                }
+            }
+            if tokio::time::timeout_at(deadline, handed_out).await.is_err() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Passes on what a job's yt-dlp wrote, answering whether it should be stopped.
+    pub fn report(
+        &self,
+        worker_id: &str,
+        job_id: &str,
+        output: JobOutput,
+    ) -> Result<OutputReply, WorkerError> {
+        self.touch(worker_id)?;
+        let job = self
+            .inner
+            .jobs
+            .get(job_id)
+            .filter(|job| job.worker_id == worker_id)
+            .ok_or(WorkerError::UnknownJob)?;
+        for line in output.stdout {
+            let _ = job.stdout.send(line);
+        }
+        for line in output.stderr {
+            let _ = job.stderr.send(line);
+        }
+        Ok(OutputReply { stop: job.stop })
+    }
+
+    /// Where an uploaded file of a job goes. Only files under the download path are taken, never
+    /// the work dirs or logs.
+    pub fn upload_path(
+        &self,
+        worker_id: &str,
+        job_id: &str,
+        path: &str,
+    ) -> Result<Option<PathBuf>, WorkerError> {
+        self.touch(worker_id)?;
+        let job = self
+            .inner
+            .jobs
+            .get(job_id)
+            .filter(|job| job.worker_id == worker_id)
+            .ok_or(WorkerError::UnknownJob)?;
+        let path = Path::new(path);
+        let is_plain = path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        let is_hidden = path
+            .components()
+            .next()
+            .is_some_and(|first| first.as_os_str().to_string_lossy().starts_with('.'));
+        Ok((is_plain && !is_hidden).then(|| job.root.join(path)))
+    }
+
+    /// Ends a job with how its yt-dlp run went.
+    pub fn finish(&self, worker_id: &str, job_id: &str, exit: JobExit) -> Result<(), WorkerError> {
+        self.touch(worker_id)?;
+        let (_, mut job) = self
+            .inner
+            .jobs
+            .remove_if(job_id, |_, job| job.worker_id == worker_id)
+            .ok_or(WorkerError::UnknownJob)?;
+        if let Some(exit_tx) = job.exit.take() {
+            let _ = exit_tx.send(exit);
+        }
+        Ok(())
+    }
+
+    fn touch(&self, worker_id: &str) -> Result<(), WorkerError> {
+        self.inner
+            .workers
+            .get_mut(worker_id)
+            .map(|mut worker| worker.touch())
+            .ok_or(WorkerError::UnknownWorker)
+    }
+
+    fn jobs_of(&self, worker_id: &str) -> usize {
+        self.inner
+            .jobs
+            .iter()
+            .filter(|job| job.worker_id == worker_id)
+            .count()
+    }
+
+    /// Whether the worker running a job has stopped claiming or reporting.
+    fn is_lost(&self, job_id: &str) -> bool {
+        let Some(worker_id) = self.inner.jobs.get(job_id).map(|job| job.worker_id.clone()) else {
+            return false;
+        };
+        !self
+            .inner
+            .workers
+            .get(&worker_id)
+            .is_some_and(|worker| worker.is_alive())
+    }
+
+    fn drop_job(&self, job_id: &str) {
+        if let Some((_, job)) = self.inner.jobs.remove(job_id) {
+            if let Some(mut worker) = self.inner.workers.get_mut(&job.worker_id) {
+                worker.inbox.retain(|queued| queued.id != job_id);
+            }
+        }
+    }
+}
+
+impl RemoteJob {
+    /// The next line yt-dlp wrote to stdout, `None` once it has exited or the worker is lost.
+    pub async fn next_line(&mut self) -> Option<String> {
+        loop {
+            tokio::select! {
+                line = self.stdout.recv() => return line,
+                _ = tokio::time::sleep(HEARTBEAT_INTERVAL) => {
+                    if self.pool.is_lost(&self.id) {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// What yt-dlp writes to stderr, taken once.
+    pub fn take_stderr(&mut self) -> Option<mpsc::UnboundedReceiver<String>> {
+        self.stderr.take()
+    }
+
+    /// Asks the worker to kill yt-dlp, it's told when it next reports.
+    pub fn stop(&self) {
+        if let Some(mut job) = self.pool.inner.jobs.get_mut(&self.id) {
+            job.stop = true;
+        }
+    }
+
+    /// Waits for the worker to report the exit, failing the job if the worker is lost or
+    /// doesn't answer a stop in time.
+    pub async fn wait(mut self) -> JobExit {
+        let stopped = self
+            .pool
+            .inner
+            .jobs
+            .get(&self.id)
+            .is_some_and(|job| job.stop);
+        let deadline = stopped.then(|| tokio::time::Instant::now() + EXIT_WAIT);
+        let exit = loop {
+            tokio::select! {
+                exit = &mut self.exit => break exit.ok(),
+                _ = tokio::time::sleep(HEARTBEAT_INTERVAL) => {
+                    let timed_out = deadline.is_some_and(|deadline| tokio::time::Instant::now() >= deadline);
+                    if timed_out || self.pool.is_lost(&self.id) {
+                        break None;
+                    }
+                }
+            }
+        };
+        self.pool.drop_job(&self.id);
+        exit.unwrap_or_else(|| {
+            warn!("lost track of job {} on worker {}", self.id, self.worker);
+            JobExit {
+                success: false,
+                code: None,
+                error: Some(format!("worker {} stopped answering", self.worker)),
+            }
+        })
+    }
+}
@@ -1,17 +1,114 @@
-use dashmap::DashMap;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use dashmap::{mapref::entry::Entry, DashMap};
+use futures_util::{future, stream, StreamExt};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, SqlitePool};
+use sqlx::types::Json;
+use sqlx::{FromRow, SqliteExecutor, SqlitePool};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs;
-use std::path::PathBuf;
-use std::process::Stdio;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-use tokio::sync::mpsc::{self, error::TryRecvError, Sender};
-use tracing::{debug, error, info, trace};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::sync::mpsc::{self, error::TryRecvError, Receiver, Sender};
+use tokio::sync::watch;
+use tracing::{debug, error, info, trace, warn};
 use url::Url;
+use uuid::Uuid;
 
+use super::bandwidth::{self, BandwidthWindow};
+use super::canonical::ShortFormPolicy;
+use super::clock;
+use super::cookies;
+use super::duplicates::{self, DuplicatePolicy, DuplicateSettings};
+use super::events::{Event, EventBus};
+use super::filters::{self, DownloadFilter};
+use super::formats::{
+    self, CheckedVideo, DumpedVideo, FormatListing, PlaylistListing, UpgradeReport, VideoDetails,
+    VideoMetadata,
+};
+use super::headers;
+use super::history::{self, HistoryPage, HistorySort};
+use super::impersonate;
+use super::instance::DownloadCounts;
+use super::links::{self, LibraryLink};
+use super::logs::{self, DownloadLog, LogLimits};
+use super::media::{self, MediaInfo};
+use super::messages::Message;
+use super::pending::PendingWrites;
+use super::policy;
+use super::process::{self, ProcessUsage};
+use super::progress::ProgressWriter;
+use super::queue::{DomainThrottle, DownloadQueue, Slot};
+use super::retention::{self, RetentionSettings};
+use super::retry::{self, FailureCause};
+use super::storage::{self, Reservations, Shortfall};
+use super::tagging::{self, Tag};
+use super::tags;
+use super::transcode::{self, IoLimits};
+use super::windows;
+use super::workers::{RemoteJob, WorkerPool};
+use super::ytdlp_configs;
+
+/// Synthetic downloads from the debug endpoints all live under this host.
+pub const SYNTHETIC_HOST: &str = "synthetic.invalid";
+/// How many yt-dlp probes a batch runs at once.
+pub const BATCH_PROBE_CONCURRENCY: usize = 4;
+/// How often running downloads are checked against the bandwidth windows.
+const BANDWIDTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How often queued downloads held back by a download window are checked for it opening.
+const WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const INTERRUPTED_ERROR: &str = "interrupted by a server restart";
+const IMAGE_EXTENSIONS: &[&str] = &["jpeg", "jpg", "png", "webp"];
+/// Replaces the video's extension for its generated storyboard.
+const PREVIEW_EXTENSION: &str = "preview.jpg";
+const PROCESS_SAMPLE_WINDOW: Duration = Duration::from_millis(500);
+/// How long a shutdown waits for paused yt-dlp processes to exit, inside Docker's default grace.
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(8);
+const SHUTDOWN_ERROR: &str = "paused by a server shutdown";
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const STALL_ERROR: &str = "stalled with no output from yt-dlp";
+const WRITE_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const WRITE_RETRY_MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// What yt-dlp's `--merge-output-format` takes.
+pub const CONTAINERS: &[&str] = &["avi", "flv", "mkv", "mov", "mp4", "webm"];
+/// The yt-dlp flags a download can add through [`DownloadOptions::extra_args`]. All of them
+/// only change what's written alongside or into the file, never where it goes or what runs.
+pub const EXTRA_ARGS: &[&str] = &[
+    "--embed-chapters",
+    "--embed-metadata",
+    "--embed-subs",
+    "--embed-thumbnail",
+    "--live-from-start",
+    "--mark-watched",
+    "--no-mtime",
+    "--no-playlist",
+    "--prefer-free-formats",
+    "--restrict-filenames",
+    "--write-auto-subs",
+    "--write-description",
+    "--write-info-json",
+    "--write-subs",
+    "--write-thumbnail",
+    "--xattrs",
+];
+const GEO_BYPASS_COUNTRY_REGEX: &str = r"^[A-Z]{2}$";
+/// The proxy schemes yt-dlp's `--proxy` takes.
+pub const PROXY_SCHEMES: &[&str] = &["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
+const RATE_LIMIT_REGEX: &str = r"^\d+(?:\.\d+)?[KMGkmg]?$";
+const YTDLP_DESTINATION_REGEX: &str =
+    r#"^\[(?:download|ExtractAudio|Merger)\] (?:Destination: |Merging formats into ")(.+?)"?$"#;
+const YTDLP_CHAPTER_REGEX: &str = r"^\[SplitChapters\] Chapter \d+; Destination: (.+)$";
+const YTDLP_MOVE_REGEX: &str = r#"^\[MoveFiles\] Moving file "(.+?)" to "(.+)"$"#;
+/// Where `--split-chapters` writes the tracks, a folder named after the video in the download path.
+const YTDLP_CHAPTER_TEMPLATE: &str = "%(title)s/%(section_number)02d - %(section_title)s.%(ext)s";
+const YTDLP_FORMAT_SELECTION_REGEX: &str = r"\[info\] [^:]+: Downloading \d+ format\(s\): (\S+)";
 const YTDLP_DOWNLOAD_UPDATE_REGEX: &str = r"\[download\]\s+(\d+(?:\.\d+)?)%\s+of\s+~?\s+?(\d+(?:\.\d+)?[GMK]iB)\s+at\s+(\d+\.\d+(?:[GMK]i)?B\/s)\s+ETA\s+((\d+:\d+)|(?:Unknown))";
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -19,25 +116,191 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     DownloadAlreadyPresent,
+    DownloadNotPresent,
     FailedCheck,
-    FailedToComplete,
     FailedToHalt,
+    /// The download's yt-dlp config sets an option config files can't, see
+    /// [`ytdlp_configs::forbidden_option`].
+    ForbiddenConfig {
+        option: String,
+    },
+    NotCompleted,
     NotDownloading,
-    General { err: std::io::Error },
+    NotQueued,
+    ProbeTimedOut,
+    /// Another url already led to the same video and the duplicate policy skips repeats.
+    SameVideo {
+        id: i64,
+        url: Url,
+    },
+    ShuttingDown,
+    UnexpectedOutput,
+    General {
+        err: std::io::Error,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::DownloadAlreadyPresent => write!(f, "download already present"),
+            Error::DownloadNotPresent => write!(f, "download not present"),
+            Error::FailedCheck => write!(f, "yt-dlp can't download this url"),
+            Error::FailedToHalt => write!(f, "failed to halt download"),
+            Error::ForbiddenConfig { option } => {
+                write!(
+                    f,
+                    "the yt-dlp config sets {}, which config files can't",
+                    option
+                )
+            }
+            Error::NotCompleted => write!(f, "download hasn't completed"),
+            Error::NotDownloading => write!(f, "not downloading"),
+            Error::NotQueued => write!(f, "download isn't waiting in the queue"),
+            Error::ProbeTimedOut => write!(f, "yt-dlp took too long to respond"),
+            Error::SameVideo { url, .. } => {
+                write!(f, "already downloading this video from {}", url)
+            }
+            Error::ShuttingDown => write!(f, "the server is shutting down"),
+            Error::UnexpectedOutput => write!(f, "unexpected output from yt-dlp"),
+            Error::General { err } => write!(f, "{}", err),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct YtdlpClient {
-    download_path: PathBuf,
+    db: SqlitePool,
     pub downloads: Arc<DashMap<Url, Download>>,
-    ytdlp_path: String,
+    events: EventBus,
+    /// What the installed yt-dlp can pass to `--impersonate`, found at startup.
+    impersonate_targets: Arc<Vec<String>>,
+    next_id: Arc<AtomicI64>,
+    pending_writes: PendingWrites,
+    progress_writer: ProgressWriter,
+    queue: DownloadQueue,
+    /// Space set aside on the download disk for the running downloads.
+    reservations: Reservations,
+    settings: ClientSettings,
+    shutdown: Arc<watch::Sender<bool>>,
+    /// The url each video was first downloaded from, by extractor and video id. Claims go stale
+    /// when their download is removed and are checked against [`Self::downloads`] before use.
+    videos: Arc<DashMap<String, Url>>,
+    /// Remote workers downloads can be handed to, see [`super::workers`].
+    workers: WorkerPool,
+}
+
+/// Operator settings for how yt-dlp is run.
+#[derive(Clone, Debug)]
+pub struct ClientSettings {
+    pub checkpoint_interval: Duration,
+    /// Where the cookies files downloads can pick with [`DownloadOptions::cookies`] live.
+    pub cookies_dir: PathBuf,
+    /// The archive yt-dlp records finished videos in, see [`DownloadOptions::download_archive`].
+    pub download_archive_path: PathBuf,
+    pub download_path: PathBuf,
+    /// Used by post-download policies to remux and transcode.
+    pub ffmpeg_path: String,
+    /// Used to read codecs and resolution from completed files.
+    pub ffprobe_path: String,
+    pub log_limits: LogLimits,
+    pub max_attempts: u32,
+    pub max_concurrent_downloads: usize,
+    /// The video options are dry run against when they're validated without a url of their own.
+    pub options_check_url: Url,
+    /// Held to by merges, transcodes and retagging, see [`IoLimits`].
+    pub postprocess_limits: IoLimits,
+    /// Frames in the storyboard made for videos that came without a thumbnail, 0 turns it off.
+    pub preview_frames: u32,
+    pub probe_timeout: Duration,
+    /// Wait before the first retry of a transient failure, doubled for each one after.
+    pub retry_backoff: Duration,
+    /// Kills a download that stays stalled for a second stall timeout, so it's retried.
+    pub stall_retry: bool,
+    /// How long yt-dlp may go without output before its download is flagged as stalled.
+    pub stall_timeout: Option<Duration>,
+    /// How long a domain that answered with too many requests sits out, doubled for each time
+    /// in a row it does.
+    pub throttle_cooldown: Duration,
+    /// What workers send to register and report, unset turns the worker endpoints off.
+    pub worker_token: Option<String>,
+    /// Where the config files downloads can pick with [`DownloadOptions::ytdlp_config`] live.
+    pub ytdlp_config_dir: PathBuf,
+    pub ytdlp_path: String,
 }
 
 #[derive(Clone, Debug)]
 pub struct Download {
+    /// The `--rate-limit` the current yt-dlp process was started with.
+    applied_rate_limit: Option<String>,
+    attempts: u32,
+    /// Whether yt-dlp should pick up the partial files of an earlier paused or interrupted run.
+    continue_partial: bool,
+    /// A hash of a frame from the middle of the video, see [`duplicates::dhash`].
+    content_hash: Option<i64>,
+    created_at: DateTime<Utc>,
+    details: VideoDetails,
+    /// The earlier download holding the same video, under another url or a close content hash.
+    duplicate_of: Option<i64>,
+    /// When the download gave up for good, kept apart from `finished_at` for the failure history.
+    failed_at: Option<DateTime<Utc>>,
+    /// Sorted from `last_error`, kept alongside it.
+    failure_cause: Option<FailureCause>,
+    file_path: Option<PathBuf>,
+    /// The size of the finished file in bytes, taken as the download completes.
+    file_size: Option<u64>,
+    finished_at: Option<DateTime<Utc>>,
+    format_id: Option<String>,
+    id: i64,
+    last_error: Option<String>,
+    log_tail: VecDeque<String>,
+    media: Option<MediaInfo>,
+    next_retry_at: Option<DateTime<Utc>>,
     options: DownloadOptions,
+    pid: Option<u32>,
+    pinned: bool,
+    progress: Option<DownloadProgress>,
+    /// Order among queued downloads of the same priority, the id unless the queue was reordered.
+    queue_rank: i64,
+    /// The file an upgrade is fetching a better copy of, removed once the new one is in.
+    replaces: Option<PathBuf>,
+    restart_requested: bool,
+    retries_exhausted: bool,
+    /// Marked as a favorite, kept through history pruning like a pinned download.
+    starred: bool,
+    start_at: Option<DateTime<Utc>>,
+    started_at: Option<DateTime<Utc>>,
     status: Status,
+    /// The per-chapter files written by the last attempt, in chapter order.
+    tracks: Vec<PathBuf>,
     tx: Option<Sender<Signal>>, // TODO - Rename this field.
+    upgradeable: bool,
+    /// The extractor and video id yt-dlp resolved the url to, e.g. `Youtube:dQw4w9WgXcQ`.
+    video_id: Option<String>,
+    /// Names the directory yt-dlp keeps this download's fragments and intermediate files in, so
+    /// two downloads writing the same file name can't trip over each other. Kept across attempts
+    /// and removed once the download is done with.
+    work_dir: Option<String>,
+    /// The remote worker that ran its last attempt, unset when it ran here.
+    worker: Option<String>,
+}
+
+/// A download to start, as submitted or re-armed.
+#[derive(Deserialize, Serialize)]
+pub struct DownloadRequest {
+    pub url: Url,
+    pub options: DownloadOptions,
+    #[serde(default)]
+    pub pinned: bool,
+    /// Holds the download back until this time, or starts it straight away if unset or past.
+    #[serde(default)]
+    pub start_at: Option<DateTime<Utc>>,
+    /// Names of the tags to file the download under, created if they don't exist yet.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Filled in by the availability check, see [`YtdlpClient::check_url_availability`].
+    #[serde(skip)]
+    pub video: Option<CheckedVideo>,
 }
 
 #[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
@@ -45,27 +308,390 @@ pub struct DownloadOptions {
     pub container: String,
     pub name_format: String,
     pub quality: String,
+    /// Higher runs sooner when downloads are queued.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub priority: i64,
+    /// Passed to yt-dlp's `--rate-limit`, e.g. `500K` or `4.2M`. Unset uses the configured default.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub rate_limit: Option<String>,
+    /// Converts downloaded subtitles to `srt` or `vtt` next to the originals. Setting it also
+    /// asks yt-dlp for the subtitles.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub subtitle_format: Option<String>,
+    /// Extracts the audio and splits it into one tagged track per chapter, e.g. for full-album
+    /// uploads. The tracks land in a folder named after the video.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub split_chapters: bool,
+    /// Extracts just the audio in this format, e.g. `mp3` or `flac`, with tags and cover art
+    /// embedded from the video's metadata.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub audio_format: Option<String>,
+    /// Tag names to yt-dlp output templates for extracted audio, laid over
+    /// [`tags::DEFAULT_TEMPLATE`]. An empty template leaves the tag out.
+    #[serde(default)]
+    #[sqlx(default, json(nullable))]
+    pub tag_template: Option<BTreeMap<String, String>>,
+    /// Records finished videos in the download archive and skips those already in it, so
+    /// re-running a channel or playlist only fetches what's new. Unset uses the configured default.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub download_archive: Option<bool>,
+    /// Stops the download once it has been running this long, leaving it TimedOut with its
+    /// partial files kept so it can be resumed. Useful for unattended batches.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub max_duration_secs: Option<i64>,
+    /// Other library folders the finished file is hard or symbolic linked into. The links are
+    /// tracked with the download and removed along with its files.
+    #[serde(default)]
+    #[sqlx(default, json(nullable))]
+    pub library_links: Option<Vec<LibraryLink>>,
+    /// A stored config file yt-dlp reads before the server's own options, by name, for settings
+    /// the options don't cover. See [`ytdlp_configs`].
+    #[serde(default)]
+    #[sqlx(default)]
+    pub ytdlp_config: Option<String>,
+    /// Flags from [`EXTRA_ARGS`] passed to yt-dlp on top of those the options make.
+    #[serde(default)]
+    #[sqlx(default, json)]
+    pub extra_args: Vec<String>,
+    /// A stored cookies.txt file yt-dlp sends, by name, for age restricted or members only
+    /// videos. See [`cookies`].
+    #[serde(default)]
+    #[sqlx(default)]
+    pub cookies: Option<String>,
+    /// Passed to yt-dlp's `--proxy` instead of the configured one, e.g. `socks5://10.0.0.2:1080`
+    /// for a VPN gateway. An empty proxy connects directly.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub proxy: Option<String>,
+    /// The two letter country code yt-dlp's `--xff` fakes for region locked videos, instead of
+    /// the configured one. An empty country turns the bypass off.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub geo_bypass_country: Option<String>,
+}
+
+impl DownloadOptions {
+    /// Whether yt-dlp should keep only the audio.
+    pub fn extracts_audio(&self) -> bool {
+        self.split_chapters || self.audio_format.is_some()
+    }
+
+    /// The tallest video `quality` allows, e.g. `720` for `720p`, or `None` for the best there is.
+    pub fn max_height(&self) -> Option<u64> {
+        parse_quality(&self.quality).flatten()
+    }
+
+    /// What yt-dlp's `-f` picks for these options.
+    pub fn format_selector(&self) -> String {
+        if self.extracts_audio() {
+            return String::from("bestaudio/best");
+        }
+        match self.max_height() {
+            Some(height) => {
+                format!("bestvideo[height<={height}]+bestaudio/best[height<={height}]/best",)
+            }
+            None => String::from("bestvideo+bestaudio/best"),
+        }
+    }
+}
+
+/// Reads a quality such as `best`, `1080p` or `720`, `Some(None)` being the best available.
+/// `None` when it's neither.
+pub fn parse_quality(quality: &str) -> Option<Option<u64>> {
+    let quality = quality.trim().to_lowercase();
+    if quality == "best" {
+        return Some(None);
+    }
+    let height = quality.strip_suffix('p').unwrap_or(&quality);
+    height
+        .parse::<u64>()
+        .ok()
+        .filter(|height| *height > 0)
+        .map(Some)
+}
+
+/// Whether yt-dlp can merge into `container` with `--merge-output-format`, in any case.
+pub fn is_valid_container(container: &str) -> bool {
+    CONTAINERS.contains(&container.to_lowercase().as_str())
+}
+
+/// The first of `extra_args` that isn't in [`EXTRA_ARGS`], if any.
+pub fn disallowed_extra_arg(extra_args: &[String]) -> Option<&str> {
+    extra_args
+        .iter()
+        .map(String::as_str)
+        .find(|arg| !EXTRA_ARGS.contains(arg))
+}
+
+/// Whether `name_format` names a file under the download path. yt-dlp's fields such as
+/// `%(title)s` are left to it, but absolute paths and `..` would write outside the library.
+pub fn is_valid_name_format(name_format: &str) -> bool {
+    let path = Path::new(name_format);
+    !name_format.trim().is_empty()
+        && !name_format.contains(['\0', '\n'])
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DownloadInfo {
+    pub id: i64,
+    pub url: Url,
+    pub format_id: Option<String>,
+    pub options: DownloadOptions,
+    pub pid: Option<u32>,
+    /// The name of the remote worker that ran its last attempt.
+    pub worker: Option<String>,
+    pub pinned: bool,
+    pub starred: bool,
+    pub status: Status,
+    pub upgradeable: bool,
+    pub created_at: DateTime<Utc>,
+    pub start_at: Option<DateTime<Utc>>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub failed_at: Option<DateTime<Utc>>,
+    pub elapsed_secs: Option<f64>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub failure_cause: Option<FailureCause>,
+    pub retries_exhausted: bool,
+    pub next_retry_at: Option<DateTime<Utc>>,
+    pub queue_position: Option<usize>,
+    pub media: Option<MediaInfo>,
+    pub video_id: Option<String>,
+    #[serde(flatten)]
+    pub details: VideoDetails,
+    pub duplicate_of: Option<i64>,
+    pub local: LocalTimes,
+}
+
+/// The timestamps of a `DownloadInfo` on the instance's wall clock.
+#[derive(Clone, Debug, Serialize)]
+pub struct LocalTimes {
+    pub created_at: DateTime<Tz>,
+    pub start_at: Option<DateTime<Tz>>,
+    pub started_at: Option<DateTime<Tz>>,
+    pub finished_at: Option<DateTime<Tz>>,
+    pub failed_at: Option<DateTime<Tz>>,
+    pub next_retry_at: Option<DateTime<Tz>>,
+}
+
+/// Everything known about one download, for the details drawer.
+#[derive(Clone, Debug, Serialize)]
+pub struct DownloadDetail {
+    #[serde(flatten)]
+    pub info: DownloadInfo,
+    pub progress: Option<DownloadProgress>,
+    pub file_path: Option<PathBuf>,
+    pub file_size: Option<u64>,
+    pub sidecars: Vec<PathBuf>,
+    pub log_tail: Vec<String>,
+    pub attempt_history: Vec<AttemptRecord>,
+    pub tags: Vec<String>,
+    /// Where the file has been linked into other library folders.
+    pub library_links: Vec<PathBuf>,
+}
+
+/// One past run of yt-dlp for a download.
+#[derive(Clone, Debug, Serialize)]
+pub struct AttemptRecord {
+    pub attempt: i64,
+    pub status: Status,
+    pub error: Option<String>,
+    pub transient: bool,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// A download's yt-dlp process, run here or handed to a remote worker.
+enum YtdlpProcess {
+    Local {
+        child: Box<Child>,
+        stdout: Lines<BufReader<ChildStdout>>,
+    },
+    Remote(RemoteJob),
+}
+
+/// How a yt-dlp process exited, `description` says why when it didn't succeed.
+struct ProcessExit {
+    success: bool,
+    description: String,
+}
+
+impl YtdlpProcess {
+    fn pid(&self) -> Option<u32> {
+        match self {
+            YtdlpProcess::Local { child, .. } => child.id(),
+            YtdlpProcess::Remote(_) => None,
+        }
+    }
+
+    fn worker(&self) -> Option<String> {
+        match self {
+            YtdlpProcess::Local { .. } => None,
+            YtdlpProcess::Remote(job) => Some(job.worker.clone()),
+        }
+    }
+
+    async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        match self {
+            YtdlpProcess::Local { stdout, .. } => stdout.next_line().await,
+            YtdlpProcess::Remote(job) => Ok(job.next_line().await),
+        }
+    }
+
+    async fn kill(&mut self, url: &Url) {
+        match self {
+            YtdlpProcess::Local { child, .. } => kill_child(url, child).await,
+            YtdlpProcess::Remote(job) => job.stop(),
+        }
+    }
+
+    async fn wait(self) -> std::io::Result<ProcessExit> {
+        match self {
+            YtdlpProcess::Local { mut child, .. } => child.wait().await.map(|status| ProcessExit {
+                success: status.success(),
+                description: format!("yt-dlp exited with {}", status),
+            }),
+            YtdlpProcess::Remote(job) => {
+                let worker = job.worker.clone();
+                let exit = job.wait().await;
+                Ok(ProcessExit {
+                    success: exit.success && exit.error.is_none(),
+                    description: exit.error.unwrap_or_else(|| match exit.code {
+                        Some(code) => format!("yt-dlp exited with {} on worker {}", code, worker),
+                        None => format!("yt-dlp was killed on worker {}", worker),
+                    }),
+                })
+            }
+        }
+    }
+}
+
+/// How a single run of yt-dlp ended.
+struct AttemptOutcome {
+    error: Option<String>,
+    /// Killed to pick up a new rate limit, to be run again rather than counted.
+    restarted: bool,
+    started_at: DateTime<Utc>,
+    status: Status,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UrlSupport {
+    pub supported: bool,
+    pub extractor: Option<String>,
+    pub title: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UrlCheck {
+    pub url: Url,
+    pub available: bool,
+    pub title: Option<String>,
+    pub estimated_size: Option<f64>,
+    pub reason: Option<String>,
+}
+
+/// What yt-dlp made of a set of options in [`YtdlpClient::simulate`].
+#[derive(Clone, Debug, Serialize)]
+pub struct Simulation {
+    pub url: Url,
+    pub valid: bool,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Simulation {
+    fn from_stderr(url: &Url, succeeded: bool, stderr: &str) -> Simulation {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        for line in stderr.lines().map(str::trim) {
+            if let Some(error) = line.strip_prefix("ERROR: ") {
+                errors.push(error.to_string());
+            } else if let Some(warning) = line.strip_prefix("WARNING: ") {
+                warnings.push(warning.to_string());
+            } else if let Some((_, error)) = line.split_once(": error: ") {
+                // How yt-dlp's argument parser reports unknown or malformed flags.
+                errors.push(error.to_string());
+            }
+        }
+        if !succeeded && errors.is_empty() {
+            let last = stderr.lines().rfind(|line| !line.trim().is_empty());
+            errors.push(last.unwrap_or("yt-dlp exited with an error").to_string());
+        }
+
+        Simulation {
+            url: url.clone(),
+            valid: succeeded && errors.is_empty(),
+            errors,
+            warnings,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DownloadUsage {
+    pub url: Url,
+    #[serde(flatten)]
+    pub usage: ProcessUsage,
+}
+
+/// What the manager is up to, with nothing that says what it's downloading, for a public page.
+#[derive(Clone, Debug, Serialize)]
+pub struct PublicStatus {
+    pub running: usize,
+    /// Queued and scheduled downloads waiting on their turn.
+    pub queued: usize,
+    pub completed: usize,
+    pub failed: usize,
+    /// How far along each running download is, oldest first.
+    pub progress: Vec<PublicProgress>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PublicProgress {
+    pub percent: String,
+    pub speed: String,
+    pub eta: String,
 }
 
-#[derive(Serialize)]
-struct DownloadProgress {
-    url: Url,
-    percent: String,
-    size_downloaded: String,
-    speed: String,
-    eta: String,
+#[derive(Clone, Debug, Serialize)]
+pub struct DownloadProgress {
+    pub url: Url,
+    pub percent: String,
+    pub size_downloaded: String,
+    pub speed: String,
+    pub eta: String,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, sqlx::Type)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, sqlx::Type)]
 #[sqlx(type_name = "status")]
 pub enum Status {
     Canceled,
     Checking,
     Completed,
     Failed,
+    Interrupted,
     None,
     Paused,
+    Queued,
     Running,
+    Scheduled,
+    /// Running, but yt-dlp hasn't written anything for the stall timeout.
+    Stalled,
+    /// Stopped after running past its `max_duration_secs`.
+    TimedOut,
 }
 
 #[derive(Clone)]
@@ -74,450 +700,3391 @@ pub enum Signal {
     Pause,
 }
 
+impl Download {
+    /// Whether `add_download` may take this entry over. A paused download only qualifies once
+    /// its task has let go of it.
+    fn is_resumable(&self) -> bool {
+        match self.status {
+            Status::Interrupted | Status::TimedOut => true,
+            Status::Paused => self.finished_at.is_some(),
+            Status::Scheduled => self.tx.is_none(),
+            Status::Completed => self.replaces.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Whether a yt-dlp process is, or will be, working on this download.
+    fn is_active(&self) -> bool {
+        matches!(
+            self.status,
+            Status::Checking
+                | Status::Queued
+                | Status::Running
+                | Status::Scheduled
+                | Status::Stalled
+        )
+    }
+
+    fn info(&self, url: &Url) -> DownloadInfo {
+        let elapsed_secs = self.started_at.map(|started_at| {
+            let finished_at = self.finished_at.unwrap_or_else(Utc::now);
+            (finished_at - started_at).num_milliseconds() as f64 / 1000.0
+        });
+
+        DownloadInfo {
+            id: self.id,
+            url: url.clone(),
+            format_id: self.format_id.clone(),
+            options: self.options.clone(),
+            pid: self.pid,
+            worker: self.worker.clone(),
+            pinned: self.pinned,
+            starred: self.starred,
+            status: self.status.clone(),
+            upgradeable: self.upgradeable,
+            created_at: self.created_at,
+            start_at: self.start_at,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            failed_at: self.failed_at,
+            elapsed_secs,
+            attempts: self.attempts,
+            last_error: self.last_error.clone(),
+            failure_cause: self.failure_cause,
+            retries_exhausted: self.retries_exhausted,
+            next_retry_at: self.next_retry_at,
+            queue_position: None,
+            media: self.media.clone(),
+            video_id: self.video_id.clone(),
+            details: self.details.clone(),
+            duplicate_of: self.duplicate_of,
+            local: LocalTimes {
+                created_at: clock::local(self.created_at),
+                start_at: self.start_at.map(clock::local),
+                started_at: self.started_at.map(clock::local),
+                finished_at: self.finished_at.map(clock::local),
+                failed_at: self.failed_at.map(clock::local),
+                next_retry_at: self.next_retry_at.map(clock::local),
+            },
+        }
+    }
+}
+
 impl From<String> for Status {
     fn from(value: String) -> Self {
         match value.as_str() {
             "Canceled" => Status::Canceled,
+            "Checking" => Status::Checking,
             "Completed" => Status::Completed,
+            "Failed" => Status::Failed,
+            "Interrupted" => Status::Interrupted,
             "None" => Status::None,
             "Paused" => Status::Paused,
+            "Queued" => Status::Queued,
             "Running" => Status::Running,
+            "Stalled" => Status::Stalled,
+            "TimedOut" => Status::TimedOut,
+            "Scheduled" => Status::Scheduled,
             _ => panic!("Wrong value in db."),
         }
     }
 }
 
-async fn init_from_db(db: SqlitePool) -> Arc<DashMap<Url, Download>> {
-    // let rows = sqlx::query!("SELECT * FROM Download").fetch_all(&db).await;
-    // let downloads = match rows {
-    //     Ok(rows) => {
-    //         let downloads: Vec<(Url, Status, DownloadOptions)> = rows
-    //             .into_iter()
-    //             .map(|row| {
-    //                 let url = Url::parse(&row.url).expect("Failed to parse URL");
-    //                 let status = Status::from(row.status);
-    //                 (
-    //                     url,
-    //                     status,
-    //                     DownloadOptions {
-    //                         container: row.container,
-    //                         name_format: row.name_format,
-    //                         quality: row.quality,
-    //                     },
-    //                 )
-    //             })
-    //             .collect();
-
-    //         downloads
-    //     }
-    //     Err(_) => todo!(),
-    // };
-
-    // let download_map = downloads
-    //     .into_iter()
-    //     .map(|x| (x.0, (x.1, x.2, None)))
-    //     .collect::<HashMap<_, (_, _, _)>>();
-    // Arc::new(Mutex::new(download_map))
-    Arc::new(DashMap::new())
-}
-
-impl YtdlpClient {
-    pub async fn new(db: SqlitePool, ytdlp_path: String, download_path: PathBuf) -> YtdlpClient {
-        YtdlpClient {
-            download_path,
-            downloads: init_from_db(db).await,
-            ytdlp_path,
+/// Asks yt-dlp which browsers it can impersonate, none if it's too old or lacks curl_cffi.
+async fn detect_impersonate_targets(settings: &ClientSettings) -> Vec<String> {
+    let output = Command::new(&settings.ytdlp_path)
+        .arg("--list-impersonate-targets")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let targets = match tokio::time::timeout(settings.probe_timeout, output).await {
+        Ok(Ok(output)) if output.status.success() => {
+            impersonate::parse_targets(&String::from_utf8_lossy(&output.stdout))
         }
-    }
+        Ok(Ok(_)) | Err(_) => Vec::new(),
+        Ok(Err(err)) => {
+            error!("failed to list yt-dlp impersonate targets: {}", err);
+            Vec::new()
+        }
+    };
 
-    pub async fn add_download(
-        &self,
-        url: &Url,
-        options: &DownloadOptions,
-        tx: Option<Sender<Signal>>,
-    ) -> Result<()> {
-        match self.downloads.contains_key(&url) {
-            true => Err(Error::DownloadAlreadyPresent),
-            false => {
-                self.downloads.insert(
-                    url.clone(),
-                    Download {
-                        options: options.clone(),
-                        status: Status::Running,
-                        tx,
-                    },
-                );
+    match targets.is_empty() {
+        true => info!("yt-dlp can't impersonate browsers here"),
+        false => info!("yt-dlp can impersonate: {}", targets.join(", ")),
+    }
+    targets
+}
 
-                Ok(())
+/// Kills a yt-dlp process and reaps it.
+async fn kill_child(url: &Url, child: &mut Child) {
+    let pid = child
+        .id()
+        .map_or("unknown".to_string(), |code| code.to_string());
+    debug!("killing child for url: {}, pid: {}", url, pid);
+    match child.kill().await {
+        Ok(_) => {
+            info!("successfully killed child for url: {}, pid: {}", url, pid);
+            match child.wait().await {
+                Ok(exit_status) => {
+                    debug!(
+                        "killed zombie child for url: {}, pid: {}, exit code: {}",
+                        url, pid, exit_status
+                    );
+                }
+                Err(err) => {
+                    error!(
+                        "failed to kill zombie child for url: {}, pid: {}, err: {}",
+                        url, pid, err
+                    );
+                }
             }
         }
+        Err(err) => error!(
+            "failed to kill child for url: {}, pid: {} err: {}",
+            url, pid, err
+        ),
     }
+}
 
-    pub async fn cancel_download(&self, url: Url) -> Result<Status> {
-        match self.downloads.remove(&url) {
-            Some((_, download)) => match download {
-                Download {
-                    status: Status::Running,
-                    options,
-                    tx: Some(tx),
-                } => match tx.send(Signal::Cancel).await {
-                    Ok(_) => {
-                        self.downloads.insert(
-                            url,
-                            Download {
-                                status: Status::Canceled,
-                                options,
-                                tx: None,
-                            },
-                        );
-                        Ok(Status::Canceled)
-                    }
-                    Err(_) => Err(Error::FailedToHalt),
-                },
-                _ => Err(Error::NotDownloading),
-            },
-            None => Err(Error::NotDownloading),
-        }
-    }
+/// Whether yt-dlp will take `country` as an `--xff`, an upper case two letter country code, or
+/// empty to turn the bypass off.
+pub fn is_valid_geo_bypass_country(country: &str) -> bool {
+    country.is_empty()
+        || Regex::new(GEO_BYPASS_COUNTRY_REGEX)
+            .expect("couldn't compile geo bypass country regex")
+            .is_match(country)
+}
 
-    /// Checks if yt-dlp is able to download the video(s) of the url with the given options.
-    /// # Errors
-    /// Possible error variants are: FailedCheck, General
-    pub async fn check_url_availability(&self, url: &Url, options: &DownloadOptions) -> Result<()> {
-        match Command::new(&self.ytdlp_path)
-            .arg("--simulate")
-            .arg("-o")
-            .arg(&options.name_format)
-            .arg("-f")
-            .arg(format!(
-                "bestvideo[height={}][ext={}]+bestaudio/best",
-                options.quality, options.container
-            ))
-            .arg(url.as_str())
-            .stderr(Stdio::null())
-            .stdout(Stdio::null())
-            .status()
-            .await
-        {
-            Ok(exit_status) => match exit_status.success() {
-                true => Ok(()),
-                false => Err(Error::FailedCheck),
-            },
-            Err(err) => Err(Error::General { err }),
-        }
-    }
+/// Whether yt-dlp will take `proxy` as a `--proxy`, a url with one of [`PROXY_SCHEMES`] and a host,
+/// or empty for a direct connection.
+pub fn is_valid_proxy(proxy: &str) -> bool {
+    proxy.is_empty()
+        || Url::parse(proxy).is_ok_and(|url| {
+            PROXY_SCHEMES.contains(&url.scheme()) && url.host_str().is_some_and(|h| !h.is_empty())
+        })
+}
 
-    pub async fn download_from_options(
-        &self,
-        url: &Url,
-        options: &DownloadOptions,
-        download_update_tx: Option<Sender<String>>,
-    ) -> Result<Status> {
-        let mut received_signal = None;
-        let download_path = self.download_path.clone().join(&options.name_format);
-        let (download_kill_tx, mut download_kill_rx) = mpsc::channel(100);
+/// Whether yt-dlp will take `rate_limit` as a `--rate-limit`, bytes per second with an optional K, M or G.
+pub fn is_valid_rate_limit(rate_limit: &str) -> bool {
+    Regex::new(RATE_LIMIT_REGEX)
+        .expect("couldn't compile rate limit regex")
+        .is_match(rate_limit)
+}
 
-        if let Err(err) = self
-            .add_download(url, options, Some(download_kill_tx))
-            .await
-        {
-            return Err(err);
-        }
+async fn send_event(download_update_tx: &Option<Sender<Event>>, event: Event) {
+    if let Some(download_update_tx) = download_update_tx {
+        crate::handle_send(download_update_tx.send(event).await);
+    }
+}
 
-        debug!("downloading from url");
-        let mut child = Command::new(&self.ytdlp_path)
-            .arg("--newline")
-            .arg("-f")
-            .arg(self.get_format(options))
-            .arg("--merge-output-format")
-            .arg(&options.container)
-            // .arg("--rate-limit")
-            // .arg("100K")
-            .arg("-o")
-            .arg(download_path)
-            .arg(url.as_str())
-            .stderr(Stdio::null())
-            .stdout(Stdio::piped())
-            .spawn()
-            .unwrap();
+/// The tags for track `number` of `total`, named `NN - title.ext` inside the album's folder.
+fn track_tags(track: &Path, number: usize, total: usize) -> Vec<(&'static str, String)> {
+    let mut tags = vec![("track", format!("{}/{}", number, total))];
+    if let Some(album) = track.parent().and_then(Path::file_name) {
+        tags.push(("album", album.to_string_lossy().into_owned()));
+    }
+    if let Some(stem) = track.file_stem().map(|stem| stem.to_string_lossy()) {
+        let title = stem.split_once(" - ").map_or(&*stem, |(_, title)| title);
+        tags.push(("title", title.to_string()));
+    }
+    tags
+}
 
-        debug!(
-            "spawned ytdlp download from url: {}, with pid: {}",
-            url,
-            child
-                .id()
-                .map_or("unknown".to_string(), |code| code.to_string())
-        );
+/// The thumbnails yt-dlp wrote next to the main file, leaving out generated previews.
+fn thumbnails(file_path: &Path) -> Vec<PathBuf> {
+    storage::sidecar_files(file_path)
+        .into_iter()
+        .filter(|sidecar| {
+            sidecar
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.as_str()))
+        })
+        .filter(|sidecar| {
+            !sidecar
+                .to_string_lossy()
+                .ends_with(&format!(".{}", PREVIEW_EXTENSION))
+        })
+        .collect()
+}
 
-        let stderr = child.stdout.take().unwrap();
-        let mut reader = BufReader::new(stderr).lines();
-        let regex = Regex::new(YTDLP_DOWNLOAD_UPDATE_REGEX).expect("couldn't compile yt-dlp regex");
+async fn init_from_db(db: &SqlitePool, download_path: &Path) -> Arc<DashMap<Url, Download>> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            id,
+            url,
+            status,
+            container,
+            name_format,
+            quality,
+            pinned,
+            created_at as "created_at: DateTime<Utc>",
+            started_at as "started_at: DateTime<Utc>",
+            finished_at as "finished_at: DateTime<Utc>",
+            attempts,
+            last_error,
+            file_path,
+            priority,
+            start_at as "start_at: DateTime<Utc>",
+            rate_limit,
+            queue_rank,
+            subtitle_format,
+            split_chapters,
+            audio_format,
+            tag_template as "tag_template: Json<BTreeMap<String, String>>",
+            download_archive,
+            max_duration_secs,
+            library_links as "library_links: Json<Vec<LibraryLink>>",
+            ytdlp_config,
+            extra_args as "extra_args: Json<Vec<String>>",
+            cookies,
+            proxy,
+            geo_bypass_country,
+            video_id,
+            content_hash,
+            duplicate_of,
+            work_dir,
+            failed_at as "failed_at: DateTime<Utc>",
+            title,
+            uploader,
+            duration_secs,
+            upload_date as "upload_date: NaiveDate",
+            thumbnail_url,
+            estimated_size,
+            failure_cause as "failure_cause: FailureCause",
+            starred,
+            file_size,
+            reversed_host,
+            upgradeable
+        FROM Download"#
+    )
+    .fetch_all(db)
+    .await;
 
-        while let Ok(Some(line)) = reader.next_line().await {
-            trace!("ytdlp output: {}", line);
-            match download_kill_rx.try_recv() {
-                Ok(signal) => {
-                    received_signal = Some(signal.clone());
-                    let pid = child
-                        .id()
-                        .map_or("unknown".to_string(), |code| code.to_string());
-                    debug!("received kill signal for url: {}, pid: {}", url, pid);
-                    match child.kill().await {
-                        Ok(_) => {
-                            info!("successfully killed child for url: {}, pid: {}", url, pid);
-                            match child.wait().await {
-                                Ok(exit_status) => {
-                                    debug!(
-                                        "killed zombie child for url: {}, pid: {}, exit code: {}",
-                                        url, pid, exit_status
-                                    );
-                                }
-                                Err(err) => {
-                                    error!(
-                                        "failed to kill zombie child for url: {}, pid: {}, err: {}",
-                                        url, pid, err
-                                    );
-                                }
-                            }
-                        }
-                        Err(err) => error!(
-                            "failed to kill child for url: {}, pid: {} err: {}",
-                            url, pid, err
-                        ),
-                    }
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!("failed to load downloads from db: {}", err);
+            return Arc::new(DashMap::new());
+        }
+    };
 
-                    match signal {
-                        Signal::Cancel => {
-                            self.remove_partial_files(&url, &options).await;
-                        }
-                        Signal::Pause => {} // Nothing should done, partially completed files should remain
-                    }
-                    break;
-                }
-                Err(TryRecvError::Disconnected) => {
-                    break;
-                }
-                Err(TryRecvError::Empty) => {}
+    let mut media = load_media(db).await;
+    let downloads = DashMap::new();
+    for row in rows {
+        let url = match Url::parse(&row.url) {
+            Ok(url) => url,
+            Err(err) => {
+                error!(
+                    "skipping stored download with bad url: {}, err: {}",
+                    row.url, err
+                );
+                continue;
             }
-            if regex.is_match(&line) {
-                if let Some(captures) = regex.captures(&line) {
-                    let url = url.clone();
-                    let percent = String::from(&captures[1]);
-                    let size_downloaded = String::from(&captures[2]);
-                    let speed = String::from(&captures[3]);
-                    let eta = String::from(&captures[4]);
+        };
 
-                    let download_update = DownloadProgress {
-                        url,
-                        percent,
-                        size_downloaded,
-                        speed,
-                        eta,
-                    };
+        let mut download = Download {
+            applied_rate_limit: None,
+            attempts: row.attempts as u32,
+            continue_partial: false,
+            content_hash: row.content_hash,
+            created_at: row.created_at.unwrap_or_else(Utc::now),
+            details: VideoDetails {
+                title: row.title,
+                uploader: row.uploader,
+                duration_secs: row.duration_secs,
+                upload_date: row.upload_date,
+                thumbnail_url: row.thumbnail_url,
+                estimated_size: row.estimated_size,
+            },
+            duplicate_of: row.duplicate_of,
+            failed_at: row.failed_at,
+            failure_cause: row.failure_cause,
+            file_path: row.file_path.map(PathBuf::from),
+            file_size: row.file_size.map(|file_size| file_size as u64),
+            finished_at: row.finished_at,
+            format_id: None,
+            id: row.id,
+            last_error: row.last_error,
+            log_tail: VecDeque::new(),
+            media: media.remove(&row.id),
+            next_retry_at: None,
+            options: DownloadOptions {
+                container: row.container,
+                name_format: row.name_format,
+                quality: row.quality,
+                priority: row.priority,
+                rate_limit: row.rate_limit,
+                subtitle_format: row.subtitle_format,
+                split_chapters: row.split_chapters,
+                audio_format: row.audio_format,
+                tag_template: row.tag_template.map(|tag_template| tag_template.0),
+                download_archive: row.download_archive,
+                max_duration_secs: row.max_duration_secs,
+                library_links: row.library_links.map(|library_links| library_links.0),
+                ytdlp_config: row.ytdlp_config,
+                extra_args: row.extra_args.0,
+                cookies: row.cookies,
+                proxy: row.proxy,
+                geo_bypass_country: row.geo_bypass_country,
+            },
+            pid: None,
+            pinned: row.pinned,
+            progress: None,
+            queue_rank: row.queue_rank.unwrap_or(row.id),
+            replaces: None,
+            restart_requested: false,
+            retries_exhausted: false,
+            starred: row.starred,
+            start_at: row.start_at,
+            started_at: row.started_at,
+            status: Status::from(row.status),
+            tracks: Vec::new(),
+            tx: None,
+            upgradeable: row.upgradeable,
+            video_id: row.video_id,
+            work_dir: row.work_dir,
+            worker: None,
+        };
 
-                    if let Some(ref download_update_tx) = download_update_tx {
-                        let send_result = download_update_tx
-                            .send(serde_json::to_string(&download_update).unwrap())
-                            .await;
+        // Rows from before the downloads list could filter on them get their host and file size.
+        let mut outdated = row.reversed_host.is_none();
+        if download.file_size.is_none() && matches!(download.status, Status::Completed) {
+            download.file_size = download
+                .file_path
+                .as_ref()
+                .and_then(|file_path| fs::metadata(download_path.join(file_path)).ok())
+                .map(|metadata| metadata.len());
+            outdated |= download.file_size.is_some();
+        }
 
-                        server::handle_send(send_result);
-                    }
-                }
+        // Whatever was in flight died with the previous process, scheduled ones are re-armed.
+        if download.is_active() && !matches!(download.status, Status::Scheduled) {
+            info!("marking download as interrupted: {}", url);
+            download.last_error = Some(String::from(INTERRUPTED_ERROR));
+            download.failure_cause = Some(FailureCause::Interrupted);
+            download.status = Status::Interrupted;
+            outdated = true;
+        }
+        if outdated {
+            if let Err(err) = upsert_download(db, &url, &download).await {
+                error!("failed to persist download: {}, err: {}", url, err);
             }
         }
 
-        let status: Status = match child.wait().await {
-            Ok(status) => match status.success() {
-                true => Status::Completed,
-                false => match received_signal {
-                    Some(signal) => match signal {
-                        Signal::Cancel => Status::Canceled,
-                        Signal::Pause => Status::Paused,
-                    },
-                    None => Status::Failed,
-                },
-            },
-            Err(_) => Status::Failed,
-        };
+        downloads.insert(url, download);
     }
 
-    // async fn add_download_handler(
-    //     &self,
-    //     url: &Url,
-    //     options: &DownloadOptions,
-    //     tx: Sender<Signal>,
-    // ) -> Result<()> {
-    //     if self.downloads.lock().await.contains_key(url) {
-    //         return Err(Error::DownloadAlreadyPresent);
-    //     }
+    info!("restored {} downloads from db", downloads.len());
+    Arc::new(downloads)
+}
+
+async fn load_media(db: &SqlitePool) -> HashMap<i64, MediaInfo> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            download_id,
+            container,
+            video_codec,
+            audio_codec,
+            width,
+            height,
+            duration_secs,
+            bitrate
+        FROM DownloadMedia"#
+    )
+    .fetch_all(db)
+    .await;
 
-    //     self.downloads
-    //         .lock()
-    //         .await
-    //         .insert(url.clone(), (Status::Running, options.clone(), Some(tx)));
+    match rows {
+        Ok(rows) => rows
+            .into_iter()
+            .map(|row| {
+                let media = MediaInfo {
+                    container: row.container,
+                    video_codec: row.video_codec,
+                    audio_codec: row.audio_codec,
+                    width: row.width,
+                    height: row.height,
+                    duration_secs: row.duration_secs,
+                    bitrate: row.bitrate,
+                };
+                (row.download_id, media)
+            })
+            .collect(),
+        Err(err) => {
+            error!("failed to load media info from db: {}", err);
+            HashMap::new()
+        }
+    }
+}
 
-    //     match self.insert_download_db(url, Status::Running, options).await {
-    //         Ok(_) => info!("download with url successfully added to database: {}", url),
-    //         Err(err) => return Err(err),
-    //     }
+async fn upsert_download<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    url: &Url,
+    download: &Download,
+) -> sqlx::Result<()> {
+    let stored_url = url.as_str();
+    let attempts = download.attempts as i64;
+    let file_path = download
+        .file_path
+        .as_ref()
+        .map(|file_path| file_path.to_string_lossy().into_owned());
+    let tag_template = download.options.tag_template.as_ref().map(Json);
+    let library_links = download.options.library_links.as_ref().map(Json);
+    let extra_args = Json(&download.options.extra_args);
+    let file_size = download.file_size.map(|file_size| file_size as i64);
+    let reversed_host = filters::reversed_host(url);
 
-    //     Ok(())
-    // }
+    sqlx::query!(
+        r#"INSERT INTO Download (
+            id,
+            url,
+            status,
+            container,
+            name_format,
+            quality,
+            pinned,
+            created_at,
+            started_at,
+            finished_at,
+            attempts,
+            last_error,
+            file_path,
+            priority,
+            start_at,
+            rate_limit,
+            queue_rank,
+            subtitle_format,
+            split_chapters,
+            audio_format,
+            tag_template,
+            video_id,
+            content_hash,
+            duplicate_of,
+            download_archive,
+            failed_at,
+            max_duration_secs,
+            title,
+            uploader,
+            duration_secs,
+            upload_date,
+            thumbnail_url,
+            work_dir,
+            estimated_size,
+            library_links,
+            failure_cause,
+            starred,
+            ytdlp_config,
+            extra_args,
+            cookies,
+            proxy,
+            geo_bypass_country,
+            file_size,
+            reversed_host,
+            upgradeable
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19,
+            $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33, $34, $35, $36,
+            $37, $38, $39, $40, $41, $42, $43, $44, $45
+        )
+        ON CONFLICT(url) DO UPDATE SET
+            status = excluded.status,
+            container = excluded.container,
+            name_format = excluded.name_format,
+            quality = excluded.quality,
+            pinned = excluded.pinned,
+            created_at = excluded.created_at,
+            started_at = excluded.started_at,
+            finished_at = excluded.finished_at,
+            attempts = excluded.attempts,
+            last_error = excluded.last_error,
+            file_path = excluded.file_path,
+            priority = excluded.priority,
+            start_at = excluded.start_at,
+            rate_limit = excluded.rate_limit,
+            queue_rank = excluded.queue_rank,
+            subtitle_format = excluded.subtitle_format,
+            split_chapters = excluded.split_chapters,
+            audio_format = excluded.audio_format,
+            tag_template = excluded.tag_template,
+            video_id = excluded.video_id,
+            content_hash = excluded.content_hash,
+            duplicate_of = excluded.duplicate_of,
+            download_archive = excluded.download_archive,
+            failed_at = excluded.failed_at,
+            max_duration_secs = excluded.max_duration_secs,
+            title = excluded.title,
+            uploader = excluded.uploader,
+            duration_secs = excluded.duration_secs,
+            upload_date = excluded.upload_date,
+            thumbnail_url = excluded.thumbnail_url,
+            work_dir = excluded.work_dir,
+            estimated_size = excluded.estimated_size,
+            library_links = excluded.library_links,
+            failure_cause = excluded.failure_cause,
+            starred = excluded.starred,
+            ytdlp_config = excluded.ytdlp_config,
+            extra_args = excluded.extra_args,
+            cookies = excluded.cookies,
+            proxy = excluded.proxy,
+            geo_bypass_country = excluded.geo_bypass_country,
+            file_size = excluded.file_size,
+            reversed_host = excluded.reversed_host,
+            upgradeable = excluded.upgradeable"#,
+        download.id,
+        stored_url,
+        download.status,
+        download.options.container,
+        download.options.name_format,
+        download.options.quality,
+        download.pinned,
+        download.created_at,
+        download.started_at,
+        download.finished_at,
+        attempts,
+        download.last_error,
+        file_path,
+        download.options.priority,
+        download.start_at,
+        download.options.rate_limit,
+        download.queue_rank,
+        download.options.subtitle_format,
+        download.options.split_chapters,
+        download.options.audio_format,
+        tag_template,
+        download.video_id,
+        download.content_hash,
+        download.duplicate_of,
+        download.options.download_archive,
+        download.failed_at,
+        download.options.max_duration_secs,
+        download.details.title,
+        download.details.uploader,
+        download.details.duration_secs,
+        download.details.upload_date,
+        download.details.thumbnail_url,
+        download.work_dir,
+        download.details.estimated_size,
+        library_links,
+        download.failure_cause,
+        download.starred,
+        download.options.ytdlp_config,
+        extra_args,
+        download.options.cookies,
+        download.options.proxy,
+        download.options.geo_bypass_country,
+        file_size,
+        reversed_host,
+        download.upgradeable
+    )
+    .execute(executor)
+    .await
+    .map(|_| ())
+}
 
-    async fn get_filename(&self, url: &Url, options: &DownloadOptions) -> Option<String> {
-        let child = Command::new(&self.ytdlp_path)
-            .arg("-o")
-            .arg(&options.name_format)
-            .arg("--get-filename")
-            .arg(url.as_str())
-            .stderr(Stdio::null())
-            .stdout(Stdio::piped())
-            .output()
-            .await;
+impl YtdlpClient {
+    pub async fn new(db: SqlitePool, settings: ClientSettings, events: EventBus) -> YtdlpClient {
+        let downloads = init_from_db(&db, &settings.download_path).await;
+        let next_id = downloads.iter().map(|entry| entry.id).max().unwrap_or(0) + 1;
+        let impersonate_targets = detect_impersonate_targets(&settings).await;
+        let ytdlp_client = YtdlpClient {
+            downloads,
+            events,
+            impersonate_targets: Arc::new(impersonate_targets),
+            next_id: Arc::new(AtomicI64::new(next_id)),
+            pending_writes: PendingWrites::default(),
+            progress_writer: ProgressWriter::spawn(db.clone()),
+            queue: DownloadQueue::new(settings.max_concurrent_downloads),
+            reservations: Reservations::default(),
+            db,
+            settings,
+            shutdown: Arc::new(watch::Sender::new(false)),
+            videos: Arc::default(),
+            workers: WorkerPool::default(),
+        };
 
-        if let Ok(output) = child {
-            if output.status.success() {
-                let mut last_line = String::new();
-                let mut lines = output.stdout.lines();
-                while let Ok(Some(line)) = lines.next_line().await {
-                    last_line = line;
-                }
-                return Some(last_line);
+        let checkpoint_client = ytdlp_client.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(checkpoint_client.settings.checkpoint_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                checkpoint_client.checkpoint().await;
             }
-        };
+        });
 
-        None
-    }
+        let bandwidth_client = ytdlp_client.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(BANDWIDTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                bandwidth_client.apply_rate_limit().await;
+            }
+        });
 
-    fn get_format(&self, options: &DownloadOptions) -> String {
-        format!("bestvideo[height={}]+bestaudio/best", &options.quality)
+        let window_client = ytdlp_client.clone();
+        tokio::spawn(async move {
+            window_client.reload_windows().await;
+            let mut interval = tokio::time::interval(WINDOW_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                window_client.queue.regrant();
+            }
+        });
+
+        let retry_client = ytdlp_client.clone();
+        tokio::spawn(async move {
+            loop {
+                retry_client.pending_writes.wait().await;
+                let mut attempt = 1;
+                loop {
+                    tokio::time::sleep(
+                        retry::backoff(WRITE_RETRY_BACKOFF, attempt).min(WRITE_RETRY_MAX_BACKOFF),
+                    )
+                    .await;
+                    if retry_client.flush_pending_writes().await {
+                        break;
+                    }
+                    attempt += 1;
+                }
+            }
+        });
+
+        ytdlp_client
     }
 
-    pub async fn get_urls(&self) -> Result<Vec<Url>> {
-        Ok(self
+    /// Writes every in-flight download to the db, terminal transitions are written as they happen.
+    pub async fn checkpoint(&self) {
+        let active: Vec<(Url, Download)> = self
             .downloads
             .iter()
-            .map(|entry| entry.key().clone())
-            .collect())
-    }
+            .filter(|entry| entry.is_active())
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
 
-    pub async fn pause_download(&self, url: Url) -> Result<Status> {
-        match self.downloads.remove(&url) {
-            Some((_, download)) => match download {
-                Download {
-                    status: Status::Running,
-                    options,
-                    tx: Some(tx),
-                } => match tx.send(Signal::Pause).await {
-                    Ok(_) => {
-                        self.downloads.insert(
-                            url,
-                            Download {
-                                status: Status::Paused,
-                                options,
-                                tx: None,
-                            },
-                        );
-                        Ok(Status::Paused)
-                    }
-                    Err(_) => Err(Error::FailedToHalt),
-                },
-                _ => Err(Error::NotDownloading),
-            },
-            None => Err(Error::NotDownloading),
+        if active.is_empty() {
+            return;
         }
-    }
-
-    pub async fn modify_download(
-        &self,
-        url: &Url,
-        options: &DownloadOptions,
-        tx: Option<Sender<Signal>>,
-    ) -> Result<()> {
-        match self.downloads.contains_key(&url) {
-            true => Err(Error::DownloadAlreadyPresent),
-            false => {
-                self.downloads.insert(
-                    url.clone(),
-                    Download {
-                        options: options.clone(),
-                        status: Status::Running,
-                        tx,
-                    },
-                );
 
-                Ok(())
+        let result = async {
+            let mut transaction = self.db.begin().await?;
+            for (url, download) in &active {
+                upsert_download(&mut *transaction, url, download).await?;
             }
+            transaction.commit().await
         }
-    }
+        .await;
 
-    async fn remove_partial_files(&self, url: &Url, options: &DownloadOptions) {
-        let download_file_name = self.get_filename(url, options).await;
-        let download_dir_files = std::fs::read_dir(&self.download_path);
-        if let Some(download_file_name) = download_file_name {
-            for dir in download_dir_files {
-                for file in dir {
-                    match file {
-                        Ok(file) => match file.file_name().into_string() {
-                            Ok(file_name) => {
-                                if file_name.contains(&download_file_name) {
-                                    info!(
-                                        "removing file: {}",
-                                        file.file_name()
-                                            .into_string()
-                                            .unwrap_or("unknown".to_string())
-                                    );
-                                    let _ = fs::remove_file(file.path());
-                                }
-                            }
-                            Err(_) => todo!(),
-                        },
-                        Err(_) => todo!(),
-                    }
+        match result {
+            Ok(_) => trace!("checkpointed {} downloads", active.len()),
+            Err(err) => {
+                for (url, _) in active {
+                    self.defer_write(url, &err);
                 }
             }
         }
     }
 
-    // async fn insert_download_db(
-    //     &self,
-    //     url: &Url,
-    //     status: Status,
-    //     options: &DownloadOptions,
-    // ) -> Result<()> {
-    //     match sqlx::query(
-    //         r#"INSERT INTO Download (
-    //         url,
-    //         status,
-    //         container,
-    //         name_format,
-    //         quality
-    //     )
-    //     VALUES (
-    //         $1,
-    //         $2,
-    //         $3,
-    //         $4,
-    //         $5
-    //     )
-    //     ON CONFLICT(url) DO NOTHING"#,
-    //     )
-    //     .bind(url.as_str())
-    //     .bind(status)
-    //     .bind(options.container.clone())
-    //     .bind(options.name_format.clone())
-    //     .bind(options.quality.clone())
-    //     .execute(&self.db)
-    //     .await
-    //     {
-    //         Ok(query) => match query.rows_affected() {
-    //             1 => Ok(()),
-    //             0 => Err(Error::DownloadAlreadyPresent),
-    //             _ => panic!("tried to edit/insert multiple downloads"),
-    //         },
-    //         Err(err) => {
-    //             panic!("failed to create default config: {}", err);
-    //         }
-    //     }
-    // }
+    async fn persist_download(&self, url: &Url) {
+        if let Err(err) = self.write_download(url).await {
+            self.defer_write(url.clone(), &err);
+        }
+    }
+
+    /// Writes what the manager holds for `url` to the db, deleting its rows if it's no longer tracked.
+    async fn write_download(&self, url: &Url) -> sqlx::Result<()> {
+        let download = self.downloads.get(url).map(|download| download.clone());
+        match download {
+            Some(download) => upsert_download(&self.db, url, &download).await,
+            None => {
+                let stored_url = url.as_str();
+                let mut transaction = self.db.begin().await?;
+                sqlx::query!(
+                    "DELETE FROM DownloadAttempt WHERE download_id IN (SELECT id FROM Download WHERE url = $1)",
+                    stored_url
+                )
+                .execute(&mut *transaction)
+                .await?;
+                sqlx::query!(
+                    "DELETE FROM DownloadMedia WHERE download_id IN (SELECT id FROM Download WHERE url = $1)",
+                    stored_url
+                )
+                .execute(&mut *transaction)
+                .await?;
+                sqlx::query!(
+                    "DELETE FROM DownloadLink WHERE download_id IN (SELECT id FROM Download WHERE url = $1)",
+                    stored_url
+                )
+                .execute(&mut *transaction)
+                .await?;
+                sqlx::query!(
+                    "DELETE FROM DownloadTag WHERE download_id IN (SELECT id FROM Download WHERE url = $1)",
+                    stored_url
+                )
+                .execute(&mut *transaction)
+                .await?;
+                sqlx::query!("DELETE FROM Download WHERE url = $1", stored_url)
+                    .execute(&mut *transaction)
+                    .await?;
+                transaction.commit().await
+            }
+        }
+    }
+
+    /// Keeps a failed write for the retry task, warning subscribers the first time the db refuses one.
+    fn defer_write(&self, url: Url, err: &sqlx::Error) {
+        error!("failed to persist download: {}, err: {}", url, err);
+        if self.pending_writes.push(url) {
+            warn!("db is refusing writes, holding them in memory until it recovers");
+            self.events.publish(Event::SystemWarning {
+                message: Message::new("db.write_failed").with("error", err),
+            });
+        }
+    }
+
+    /// Retries every held write, returning whether they all went through.
+    async fn flush_pending_writes(&self) -> bool {
+        let urls = self.pending_writes.take();
+        if urls.is_empty() {
+            return true;
+        }
+
+        let mut failed = None;
+        for url in &urls {
+            if let Err(err) = self.write_download(url).await {
+                failed = Some(err);
+                break;
+            }
+        }
+
+        match failed {
+            Some(err) => {
+                debug!("db still refusing writes: {}", err);
+                for url in urls {
+                    self.pending_writes.push(url);
+                }
+                false
+            }
+            None => {
+                let dropped = self.pending_writes.recovered();
+                info!(
+                    "db accepting writes again, wrote {} held downloads, {} were dropped",
+                    urls.len(),
+                    dropped
+                );
+                true
+            }
+        }
+    }
+
+    /// Starts tracking a download, picking an interrupted or restored scheduled one back up in place.
+    /// Another url leading to the same video is turned away under [`DuplicatePolicy::Skip`], the
+    /// video stays claimed until the download is in so two urls for it can't both get past.
+    /// # Errors
+    /// Possible error variants are: DownloadAlreadyPresent, SameVideo, ShuttingDown
+    pub async fn add_download(
+        &self,
+        url: &Url,
+        options: &DownloadOptions,
+        pinned: bool,
+        start_at: Option<DateTime<Utc>>,
+        video: Option<CheckedVideo>,
+        tx: Option<Sender<Signal>>,
+    ) -> Result<()> {
+        if self.is_shutting_down() {
+            return Err(Error::ShuttingDown);
+        }
+        let (video_id, details) = match video {
+            Some(video) => (Some(video.video_id), Some(video.details)),
+            None => (None, None),
+        };
+        let policy = match video_id {
+            Some(_) => self.duplicate_settings().await.policy,
+            None => DuplicatePolicy::Allow,
+        };
+        let status = match start_at {
+            Some(_) => Status::Scheduled,
+            None => Status::Queued,
+        };
+
+        {
+            let mut claim = video_id.as_ref().map(|video_id| {
+                self.videos
+                    .entry(video_id.clone())
+                    .or_insert_with(|| url.clone())
+            });
+            let existing = claim.as_ref().and_then(|claim| {
+                let video_id = video_id.as_deref();
+                let still_claimed = |existing: &Url| {
+                    self.downloads
+                        .get(existing)
+                        .filter(|download| download.video_id.as_deref() == video_id)
+                        .map(|download| (existing.clone(), download.id))
+                };
+                Some(claim.value())
+                    .filter(|claimed| *claimed != url)
+                    .and_then(still_claimed)
+                    .or_else(|| {
+                        let existing = self
+                            .downloads
+                            .iter()
+                            .find(|entry| {
+                                entry.key() != url && entry.video_id.as_deref() == video_id
+                            })
+                            .map(|entry| entry.key().clone())?;
+                        still_claimed(&existing)
+                    })
+            });
+            let duplicate_of = match existing {
+                Some((existing, id)) => match policy {
+                    DuplicatePolicy::Skip => return Err(Error::SameVideo { id, url: existing }),
+                    DuplicatePolicy::Allow => {
+                        info!("allowing url: {} as a duplicate of: {}", url, existing);
+                        Some(id)
+                    }
+                },
+                None => None,
+            };
+
+            match self.downloads.entry(url.clone()) {
+                Entry::Occupied(mut entry) if entry.get().is_resumable() => {
+                    let download = entry.get_mut();
+                    download.continue_partial = matches!(
+                        download.status,
+                        Status::Interrupted | Status::Paused | Status::TimedOut
+                    );
+                    download.finished_at = None;
+                    download.media = None;
+                    download.options = options.clone();
+                    download.pinned = pinned;
+                    download.start_at = start_at;
+                    download.status = status;
+                    download.tx = tx;
+                    if video_id.is_some() {
+                        download.video_id = video_id;
+                        download.duplicate_of = duplicate_of;
+                    }
+                    if let Some(details) = details {
+                        download.details = details;
+                    }
+                }
+                Entry::Occupied(_) => return Err(Error::DownloadAlreadyPresent),
+                Entry::Vacant(entry) => {
+                    let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+                    entry.insert(Download {
+                        applied_rate_limit: None,
+                        attempts: 0,
+                        continue_partial: false,
+                        content_hash: None,
+                        created_at: Utc::now(),
+                        details: details.unwrap_or_default(),
+                        duplicate_of,
+                        failed_at: None,
+                        failure_cause: None,
+                        file_path: None,
+                        file_size: None,
+                        finished_at: None,
+                        format_id: None,
+                        id,
+                        last_error: None,
+                        log_tail: VecDeque::new(),
+                        media: None,
+                        next_retry_at: None,
+                        options: options.clone(),
+                        pid: None,
+                        pinned,
+                        progress: None,
+                        queue_rank: id,
+                        replaces: None,
+                        restart_requested: false,
+                        retries_exhausted: false,
+                        starred: false,
+                        start_at,
+                        started_at: None,
+                        status,
+                        tracks: Vec::new(),
+                        tx,
+                        upgradeable: false,
+                        video_id,
+                        work_dir: None,
+                        worker: None,
+                    });
+                }
+            }
+            if let Some(claim) = claim.as_mut().filter(|_| duplicate_of.is_none()) {
+                **claim = url.clone();
+            }
+        }
+        self.persist_download(url).await;
+
+        Ok(())
+    }
+
+    pub async fn cancel_download(&self, url: Url) -> Result<Status> {
+        self.halt_download(&url, Signal::Cancel).await
+    }
+
+    /// Checks if yt-dlp is able to download the video(s) of the url with the given options.
+    /// Returns the video's id and details, or `None` when the url holds more than one.
+    /// # Errors
+    /// Possible error variants are: FailedCheck, ForbiddenConfig, General, ProbeTimedOut
+    pub async fn check_url_availability(
+        &self,
+        url: &Url,
+        options: &DownloadOptions,
+    ) -> Result<Option<CheckedVideo>> {
+        let mut command = self.ytdlp_command(url).await;
+        self.add_file_args(&mut command, options)?;
+        let output = self
+            .run_probe(
+                command
+                    .arg("--dump-json")
+                    .arg("-o")
+                    .arg(&options.name_format)
+                    .arg("-f")
+                    .arg(options.format_selector())
+                    .arg(url.as_str())
+                    .stdout(Stdio::piped()),
+            )
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::FailedCheck);
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut videos = stdout.lines().filter(|line| !line.trim().is_empty());
+        let (Some(video), None) = (videos.next(), videos.next()) else {
+            return Ok(None);
+        };
+        match serde_json::from_str::<DumpedVideo>(video) {
+            Ok(video) => Ok(video.into_checked()),
+            Err(err) => {
+                warn!(
+                    "failed to parse yt-dlp details for url: {}, err: {}",
+                    url, err
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reports whether yt-dlp supports the url and which extractor handles it.
+    /// # Errors
+    /// Possible error variants are: General
+    pub async fn check_url_support(&self, url: &Url) -> Result<UrlSupport> {
+        match self.fetch_metadata(url).await {
+            Ok(metadata) => Ok(UrlSupport {
+                supported: true,
+                extractor: metadata.extractor,
+                title: metadata.title,
+                reason: None,
+            }),
+            Err(Error::General { err }) => Err(Error::General { err }),
+            Err(err) => Ok(UrlSupport {
+                supported: false,
+                extractor: None,
+                title: None,
+                reason: Some(err.to_string()),
+            }),
+        }
+    }
+
+    /// Probes many urls, a few at a time, returning the results in the order given.
+    pub async fn check_urls(&self, urls: Vec<Url>) -> Vec<UrlCheck> {
+        stream::iter(urls)
+            .map(|url| async move {
+                match self.fetch_metadata(&url).await {
+                    Ok(metadata) => UrlCheck {
+                        available: true,
+                        estimated_size: metadata.estimated_size(),
+                        reason: None,
+                        title: metadata.title,
+                        url,
+                    },
+                    Err(err) => UrlCheck {
+                        available: false,
+                        estimated_size: None,
+                        reason: Some(err.to_string()),
+                        title: None,
+                        url,
+                    },
+                }
+            })
+            .buffered(BATCH_PROBE_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// Runs a short-lived yt-dlp invocation, killing it if it outlives the probe timeout.
+    /// # Errors
+    /// Possible error variants are: General, ProbeTimedOut
+    async fn run_probe(&self, command: &mut Command) -> Result<Output> {
+        let output = command.stderr(Stdio::null()).kill_on_drop(true).output();
+
+        match tokio::time::timeout(self.settings.probe_timeout, output).await {
+            Ok(result) => result.map_err(|err| Error::General { err }),
+            Err(_) => {
+                error!(
+                    "yt-dlp probe timed out after {:?}",
+                    self.settings.probe_timeout
+                );
+                Err(Error::ProbeTimedOut)
+            }
+        }
+    }
+
+    /// Adds and tags the download, handing back what halts it for [`Self::download_from_options`].
+    /// A start time already passed is dropped from the request.
+    /// # Errors
+    /// Possible error variants are: DownloadAlreadyPresent, SameVideo, ShuttingDown
+    pub async fn track_download(&self, download: &mut DownloadRequest) -> Result<Receiver<Signal>> {
+        let (download_kill_tx, download_kill_rx) = mpsc::channel(100);
+        download.start_at = download.start_at.filter(|start_at| *start_at > Utc::now());
+
+        self.add_download(
+            &download.url,
+            &download.options,
+            download.pinned,
+            download.start_at,
+            download.video.take(),
+            Some(download_kill_tx),
+        )
+        .await?;
+        if !download.tags.is_empty() {
+            let id = self
+                .downloads
+                .get(&download.url)
+                .map(|download| download.id);
+            if let Some(id) = id {
+                if let Err(err) = tagging::add(&self.db, id, &download.tags).await {
+                    error!("failed to tag download: {}, err: {}", download.url, err);
+                }
+            }
+        }
+        Ok(download_kill_rx)
+    }
+
+    /// Waits out the start time and a queue slot, then runs a download [`Self::track_download`]
+    /// added.
+    pub async fn download_from_options(
+        &self,
+        download: DownloadRequest,
+        mut download_kill_rx: Receiver<Signal>,
+        download_update_tx: Option<Sender<Event>>,
+    ) -> Result<Status> {
+        let DownloadRequest {
+            url,
+            options,
+            start_at,
+            ..
+        } = download;
+        let (url, options) = (&url, &options);
+
+        match start_at {
+            Some(start_at) => {
+                send_event(
+                    &download_update_tx,
+                    Event::Status {
+                        url: url.clone(),
+                        status: Status::Scheduled,
+                    },
+                )
+                .await;
+                info!("download scheduled for {}: {}", clock::local(start_at), url);
+
+                let wait = (start_at - Utc::now()).to_std().unwrap_or_default();
+                if let Err(status) = self
+                    .unless_halted(
+                        url,
+                        &mut download_kill_rx,
+                        &download_update_tx,
+                        tokio::time::sleep(wait),
+                    )
+                    .await
+                {
+                    return Ok(status);
+                }
+                self.set_status(url, Status::Queued, &download_update_tx)
+                    .await;
+                self.persist_download(url).await;
+            }
+            None => {
+                send_event(
+                    &download_update_tx,
+                    Event::Status {
+                        url: url.clone(),
+                        status: Status::Queued,
+                    },
+                )
+                .await;
+            }
+        }
+
+        let mut slot = match self
+            .unless_halted(
+                url,
+                &mut download_kill_rx,
+                &download_update_tx,
+                self.queue
+                    .acquire(url, options.priority, self.queue_rank(url)),
+            )
+            .await
+        {
+            Ok(slot) => slot,
+            Err(status) => return Ok(status),
+        };
+        let estimated_size = self
+            .downloads
+            .get(url)
+            .and_then(|download| download.details.estimated_size);
+        if let Some(estimated_size) = estimated_size {
+            let reserved = self.reservations.reserve_when_free(
+                url,
+                estimated_size as u64,
+                &self.settings.download_path,
+                |shortfall| {
+                    info!(
+                        "download waiting for {} bytes of disk space, {} free: {}",
+                        shortfall.needed, shortfall.available, url
+                    );
+                    self.events.publish(Event::SystemWarning {
+                        message: Message::new("storage.waiting")
+                            .with("url", url)
+                            .with("needed", storage::megabytes(shortfall.needed))
+                            .with("available", storage::megabytes(shortfall.available)),
+                    });
+                },
+            );
+            if let Err(status) = self
+                .unless_halted(url, &mut download_kill_rx, &download_update_tx, reserved)
+                .await
+            {
+                return Ok(status);
+            }
+        }
+        self.set_status(url, Status::Running, &download_update_tx)
+            .await;
+        // Counted from the first attempt, so retries don't buy a download more time.
+        let max_deadline = options.max_duration_secs.map(|max_duration_secs| {
+            Instant::now() + Duration::from_secs(max_duration_secs as u64)
+        });
+
+        let status = loop {
+            let attempt = match self.downloads.get_mut(url) {
+                Some(mut download) => {
+                    download.attempts += 1;
+                    download.attempts
+                }
+                None => 1,
+            };
+
+            let outcome = match self
+                .run_attempt(
+                    url,
+                    options,
+                    max_deadline,
+                    &mut download_kill_rx,
+                    &download_update_tx,
+                )
+                .await
+            {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    error!("failed to spawn yt-dlp for url: {}, err: {}", url, err);
+                    send_event(
+                        &download_update_tx,
+                        Event::SystemWarning {
+                            message: Message::new("ytdlp.start_failed").with("error", &err),
+                        },
+                    )
+                    .await;
+                    self.record_error(url, format!("failed to start yt-dlp: {}", err));
+                    self.finish_download(url, Status::Failed, &download_update_tx)
+                        .await;
+                    return Err(err);
+                }
+            };
+
+            if outcome.restarted {
+                if let Some(mut download) = self.downloads.get_mut(url) {
+                    download.attempts -= 1;
+                }
+                continue;
+            }
+
+            let transient = outcome.error.as_deref().is_some_and(retry::is_transient);
+            self.record_attempt(url, attempt, &outcome, transient).await;
+            if outcome.error.as_deref().is_some_and(retry::is_throttled) {
+                let throttled = self.queue.throttle(url, self.settings.throttle_cooldown);
+                warn!(
+                    "{} is throttling downloads, holding it back until {} with {} at a time",
+                    throttled.domain,
+                    clock::local(throttled.cooling_until),
+                    throttled.max_running
+                );
+                self.events.publish(Event::ThrottledByOrigin(throttled));
+            }
+
+            match outcome.status {
+                Status::Failed if transient && attempt < self.settings.max_attempts => {
+                    let delay = retry::backoff(self.settings.retry_backoff, attempt);
+                    info!(
+                        "attempt {} of {} failed for url: {}, retrying in {:?}",
+                        attempt, self.settings.max_attempts, url, delay
+                    );
+                    // Give the slot up while waiting so other downloads can use it.
+                    drop(slot);
+                    slot = match self
+                        .wait_for_retry(
+                            url,
+                            options,
+                            delay,
+                            &mut download_kill_rx,
+                            &download_update_tx,
+                        )
+                        .await
+                    {
+                        Ok(slot) => slot,
+                        Err(status) => return Ok(status),
+                    };
+                }
+                Status::Failed if transient => {
+                    info!(
+                        "exhausted retries for url: {} after {} attempts",
+                        url, attempt
+                    );
+                    if let Some(mut download) = self.downloads.get_mut(url) {
+                        download.retries_exhausted = true;
+                    }
+                    break Status::Failed;
+                }
+                status => break status,
+            }
+        };
+
+        drop(slot);
+        if matches!(status, Status::Completed) {
+            self.queue.relax(url);
+            self.probe_media(url).await;
+            self.apply_policies(url).await;
+            self.hash_content(url).await;
+            self.convert_subtitles(url, options).await;
+            self.tag_tracks(url).await;
+            self.make_preview(url).await;
+            self.link_into_libraries(url, options).await;
+        }
+        self.finish_download(url, status.clone(), &download_update_tx)
+            .await;
+
+        Ok(status)
+    }
+
+    /// Links a completed download's file into the library folders its options name.
+    async fn link_into_libraries(&self, url: &Url, options: &DownloadOptions) {
+        let Some(library_links) = options
+            .library_links
+            .as_ref()
+            .filter(|library_links| !library_links.is_empty())
+        else {
+            return;
+        };
+        let Some((id, file_path)) = self.downloads.get(url).and_then(|download| {
+            let file_path = self.resolve_file_path(download.file_path.clone()?);
+            Some((download.id, file_path))
+        }) else {
+            return;
+        };
+
+        let created = links::create(&self.db, id, &file_path, library_links).await;
+        debug!(
+            "linked {} of {} library folders for url: {}",
+            created.len(),
+            library_links.len(),
+            url
+        );
+    }
+
+    /// Reads the codecs and resolution of a completed download's file and stores them.
+    async fn probe_media(&self, url: &Url) {
+        let Some((id, file_path)) = self.downloads.get(url).and_then(|download| {
+            let file_path = self.resolve_file_path(download.file_path.clone()?);
+            Some((download.id, file_path))
+        }) else {
+            return;
+        };
+
+        let media = match media::probe(
+            &self.settings.ffprobe_path,
+            &file_path,
+            self.settings.probe_timeout,
+        )
+        .await
+        {
+            Ok(media) => media,
+            Err(err) => {
+                warn!("couldn't probe media for url: {}, err: {}", url, err);
+                return;
+            }
+        };
+
+        let probed_at = Utc::now();
+        let result = sqlx::query!(
+            r#"INSERT INTO DownloadMedia (
+                download_id,
+                container,
+                video_codec,
+                audio_codec,
+                width,
+                height,
+                duration_secs,
+                bitrate,
+                probed_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT(download_id) DO UPDATE SET
+                container = excluded.container,
+                video_codec = excluded.video_codec,
+                audio_codec = excluded.audio_codec,
+                width = excluded.width,
+                height = excluded.height,
+                duration_secs = excluded.duration_secs,
+                bitrate = excluded.bitrate,
+                probed_at = excluded.probed_at"#,
+            id,
+            media.container,
+            media.video_codec,
+            media.audio_codec,
+            media.width,
+            media.height,
+            media.duration_secs,
+            media.bitrate,
+            probed_at
+        )
+        .execute(&self.db)
+        .await;
+        if let Err(err) = result {
+            error!("failed to store media info for url: {}, err: {}", url, err);
+        }
+
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.media = Some(media);
+        }
+    }
+
+    /// Runs the first policy matching a completed download's media info, then probes the result.
+    /// Only one policy runs per download so rules can't undo each other in a loop.
+    async fn apply_policies(&self, url: &Url) {
+        let Some((media, file_path)) = self.downloads.get(url).and_then(|download| {
+            let file_path = self.resolve_file_path(download.file_path.clone()?);
+            Some((download.media.clone()?, file_path))
+        }) else {
+            return;
+        };
+
+        let policies = match policy::load(&self.db).await {
+            Ok(policies) => policies,
+            Err(err) => {
+                error!("failed to load post-download policies: {}", err);
+                return;
+            }
+        };
+        let Some(policy) = policies.iter().find(|policy| policy.matches(&media)) else {
+            return;
+        };
+
+        info!(
+            "applying policy {} ({:?} to {}) to url: {}",
+            policy.id, policy.action, policy.target, url
+        );
+        match transcode::run(
+            &self.settings.ffmpeg_path,
+            &self.settings.postprocess_limits,
+            &file_path,
+            policy.action,
+            &policy.target,
+        )
+        .await
+        {
+            Ok(output) => {
+                if let Some(mut download) = self.downloads.get_mut(url) {
+                    download.file_path = Some(output);
+                }
+                self.probe_media(url).await;
+            }
+            Err(err) => {
+                warn!("policy {} failed for url: {}, err: {}", policy.id, url, err);
+                self.record_error(url, format!("policy {} failed: {}", policy.id, err));
+            }
+        }
+    }
+
+    /// Converts a completed download's subtitle sidecars to its `subtitle_format`, leaving the
+    /// originals in place so both show up as sidecars.
+    async fn convert_subtitles(&self, url: &Url, options: &DownloadOptions) {
+        let Some(format) = &options.subtitle_format else {
+            return;
+        };
+        let Some(file_path) = self
+            .downloads
+            .get(url)
+            .and_then(|download| download.file_path.clone())
+            .map(|file_path| self.resolve_file_path(file_path))
+        else {
+            return;
+        };
+
+        for subtitle in storage::sidecar_files(&file_path) {
+            if !transcode::converts_subtitle(&subtitle, format)
+                || subtitle.with_extension(format).exists()
+            {
+                continue;
+            }
+            match transcode::convert_subtitle(&self.settings.ffmpeg_path, &subtitle, format).await {
+                Ok(output) => debug!("converted subtitle to: {}", output.display()),
+                Err(err) => warn!(
+                    "couldn't convert subtitle: {}, err: {}",
+                    subtitle.display(),
+                    err
+                ),
+            }
+        }
+    }
+
+    /// Tags the tracks split from a completed download's chapters with their position and the
+    /// album, which is the folder yt-dlp named after the video.
+    async fn tag_tracks(&self, url: &Url) {
+        let tracks = match self.downloads.get(url) {
+            Some(download) if !download.tracks.is_empty() => download.tracks.clone(),
+            _ => return,
+        };
+
+        let total = tracks.len();
+        for (index, track) in tracks.iter().enumerate() {
+            let track = self.resolve_file_path(track.clone());
+            let tags = track_tags(&track, index + 1, total);
+            if let Err(err) = transcode::tag(
+                &self.settings.ffmpeg_path,
+                &self.settings.postprocess_limits,
+                &track,
+                &tags,
+            )
+            .await
+            {
+                warn!("couldn't tag track: {}, err: {}", track.display(), err);
+            }
+        }
+        info!("tagged {} tracks for url: {}", total, url);
+    }
+
+    /// Hashes a frame from the middle of a completed video when perceptual hashing is on, marking
+    /// it as a duplicate of the earliest download whose hash is close enough.
+    async fn hash_content(&self, url: &Url) {
+        if !self.duplicate_settings().await.perceptual_hash {
+            return;
+        }
+        let Some((id, file_path, duration_secs)) = self.downloads.get(url).and_then(|download| {
+            let media = download.media.as_ref()?;
+            media.video_codec.as_ref()?;
+            Some((
+                download.id,
+                self.resolve_file_path(download.file_path.clone()?),
+                media.duration_secs.unwrap_or_default(),
+            ))
+        }) else {
+            return;
+        };
+
+        let pixels = match transcode::gray_frame(
+            &self.settings.ffmpeg_path,
+            &file_path,
+            duration_secs / 2.0,
+            duplicates::FRAME_WIDTH,
+            duplicates::FRAME_HEIGHT,
+        )
+        .await
+        {
+            Ok(pixels) => pixels,
+            Err(err) => {
+                warn!("couldn't hash the content of url: {}, err: {}", url, err);
+                return;
+            }
+        };
+        let Some(content_hash) = duplicates::dhash(&pixels) else {
+            warn!("unexpected frame size from ffmpeg for url: {}", url);
+            return;
+        };
+
+        let duplicate_of = self
+            .downloads
+            .iter()
+            .filter(|entry| entry.id != id)
+            .filter(|entry| {
+                entry
+                    .content_hash
+                    .is_some_and(|other| duplicates::is_match(content_hash, other))
+            })
+            .map(|entry| entry.id)
+            .min();
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.content_hash = Some(content_hash);
+            download.duplicate_of = download.duplicate_of.or(duplicate_of);
+        }
+        if let Some(duplicate_of) = duplicate_of {
+            info!(
+                "url: {} looks like a copy of download {}",
+                url, duplicate_of
+            );
+        }
+    }
+
+    /// Makes a storyboard for a completed video that has no thumbnail of its own.
+    async fn make_preview(&self, url: &Url) {
+        if self.settings.preview_frames == 0 {
+            return;
+        }
+        let Some((file_path, duration_secs)) = self.downloads.get(url).and_then(|download| {
+            let media = download.media.as_ref()?;
+            media.video_codec.as_ref()?;
+            let duration_secs = media.duration_secs.filter(|duration| *duration > 0.0)?;
+            Some((
+                self.resolve_file_path(download.file_path.clone()?),
+                duration_secs,
+            ))
+        }) else {
+            return;
+        };
+        if !thumbnails(&file_path).is_empty() {
+            return;
+        }
+
+        let output = file_path.with_extension(PREVIEW_EXTENSION);
+        match transcode::storyboard(
+            &self.settings.ffmpeg_path,
+            &file_path,
+            duration_secs,
+            self.settings.preview_frames,
+            &output,
+        )
+        .await
+        {
+            Ok(_) => debug!("made preview: {}", output.display()),
+            Err(err) => warn!("couldn't make a preview for url: {}, err: {}", url, err),
+        }
+    }
+
+    /// Puts a download back in the queue until its backoff is over and a slot frees up.
+    async fn wait_for_retry(
+        &self,
+        url: &Url,
+        options: &DownloadOptions,
+        delay: Duration,
+        download_kill_rx: &mut Receiver<Signal>,
+        download_update_tx: &Option<Sender<Event>>,
+    ) -> std::result::Result<Slot, Status> {
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.next_retry_at = chrono::Duration::from_std(delay)
+                .ok()
+                .map(|delay| Utc::now() + delay);
+        }
+        self.set_status(url, Status::Queued, download_update_tx)
+            .await;
+        self.persist_download(url).await;
+
+        let retry = async {
+            tokio::time::sleep(delay).await;
+            // The priority may have been changed while waiting.
+            let priority = self
+                .downloads
+                .get(url)
+                .map_or(options.priority, |download| download.options.priority);
+            self.queue
+                .acquire(url, priority, self.queue_rank(url))
+                .await
+        };
+        let slot = self
+            .unless_halted(url, download_kill_rx, download_update_tx, retry)
+            .await;
+
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.next_retry_at = None;
+        }
+        if slot.is_ok() {
+            self.set_status(url, Status::Running, download_update_tx)
+                .await;
+        }
+        slot
+    }
+
+    /// Waits on `future` unless the download is paused or canceled first, then finishes it with that status.
+    async fn unless_halted<T>(
+        &self,
+        url: &Url,
+        download_kill_rx: &mut Receiver<Signal>,
+        download_update_tx: &Option<Sender<Event>>,
+        future: impl Future<Output = T>,
+    ) -> std::result::Result<T, Status> {
+        tokio::select! {
+            output = future => Ok(output),
+            signal = download_kill_rx.recv() => {
+                let status = match signal {
+                    Some(Signal::Pause) => Status::Paused,
+                    Some(Signal::Cancel) | None => Status::Canceled,
+                };
+                info!("download left the queue while waiting: {}", url);
+                self.finish_download(url, status.clone(), download_update_tx)
+                    .await;
+                Err(status)
+            }
+        }
+    }
+
+    async fn set_status(
+        &self,
+        url: &Url,
+        status: Status,
+        download_update_tx: &Option<Sender<Event>>,
+    ) {
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.status = status.clone();
+        }
+        send_event(
+            download_update_tx,
+            Event::Status {
+                url: url.clone(),
+                status,
+            },
+        )
+        .await;
+    }
+
+    /// Keeps a row per attempt so failures can be looked into after the fact.
+    async fn record_attempt(
+        &self,
+        url: &Url,
+        attempt: u32,
+        outcome: &AttemptOutcome,
+        transient: bool,
+    ) {
+        let Some(download_id) = self.downloads.get(url).map(|download| download.id) else {
+            return;
+        };
+        let attempt = attempt as i64;
+        let status = format!("{:?}", outcome.status);
+        let finished_at = Utc::now();
+        if let Err(err) = sqlx::query!(
+            "INSERT INTO DownloadAttempt (download_id, attempt, status, error, transient, started_at, finished_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            download_id,
+            attempt,
+            status,
+            outcome.error,
+            transient,
+            outcome.started_at,
+            finished_at,
+        )
+        .execute(&self.db)
+        .await
+        {
+            error!("failed to record attempt for url: {}, err: {}", url, err);
+        }
+    }
+
+    /// Runs yt-dlp once, forwarding its progress until it exits, is told to halt or reaches
+    /// `max_deadline`.
+    /// # Errors
+    /// Possible error variants are: ForbiddenConfig, General
+    async fn run_attempt(
+        &self,
+        url: &Url,
+        options: &DownloadOptions,
+        max_deadline: Option<Instant>,
+        download_kill_rx: &mut Receiver<Signal>,
+        download_update_tx: &Option<Sender<Event>>,
+    ) -> Result<AttemptOutcome> {
+        let mut received_signal = None;
+        let mut restarted = false;
+        let home = self.home_dir(url).await;
+        let work_dir = self.work_dir(url);
+
+        let rate_limit = match &options.rate_limit {
+            Some(rate_limit) => Some(rate_limit.clone()),
+            None => self.global_rate_limit().await,
+        };
+        let replacing = self
+            .downloads
+            .get(url)
+            .is_some_and(|download| download.replaces.is_some());
+        // The archive already has the video an upgrade is fetching again.
+        let download_archive = !replacing
+            && match options.download_archive {
+                Some(download_archive) => download_archive,
+                None => self.global_download_archive().await,
+            };
+
+        debug!("downloading from url");
+        let mut command = self.ytdlp_command(url).await;
+        command.arg("--newline");
+        self.add_option_args(&mut command, options, &home)?;
+        if let Some(rate_limit) = &rate_limit {
+            command.arg("--rate-limit").arg(rate_limit);
+        }
+        command.args(self.settings.postprocess_limits.merge_args());
+        if download_archive {
+            command
+                .arg("--download-archive")
+                .arg(&self.settings.download_archive_path);
+        }
+        if self
+            .downloads
+            .get(url)
+            .is_some_and(|download| download.continue_partial)
+        {
+            command.arg("--continue");
+        }
+        if replacing {
+            command.arg("--force-overwrites");
+        }
+        command
+            .arg("-P")
+            .arg(format!("home:{}", home.display()))
+            .arg("-P")
+            .arg(format!("temp:{}", work_dir.display()))
+            .arg("-o")
+            .arg(&options.name_format)
+            .arg(url.as_str());
+
+        let remote = match self.runs_remotely(url, options, &home, download_archive) {
+            true => {
+                let args = command
+                    .as_std()
+                    .get_args()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect();
+                self.workers.assign(url, args, &self.settings.download_path)
+            }
+            false => None,
+        };
+        let (mut process, mut stderr) = match remote {
+            Some(mut job) => {
+                info!("handing url: {} to worker: {}", url, job.worker);
+                let stderr = job.take_stderr();
+                (YtdlpProcess::Remote(job), stderr)
+            }
+            None => {
+                let mut child = command
+                    .stderr(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .map_err(|err| Error::General { err })?;
+                debug!(
+                    "spawned ytdlp download from url: {}, with pid: {}",
+                    url,
+                    child
+                        .id()
+                        .map_or("unknown".to_string(), |code| code.to_string())
+                );
+
+                let (stderr_tx, stderr) = mpsc::unbounded_channel();
+                if let Some(child_stderr) = child.stderr.take() {
+                    tokio::spawn(async move {
+                        let mut lines = BufReader::new(child_stderr).lines();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            let _ = stderr_tx.send(line);
+                        }
+                    });
+                }
+                let stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+                let child = Box::new(child);
+                (YtdlpProcess::Local { child, stdout }, Some(stderr))
+            }
+        };
+
+        let (id, attempt) = self
+            .downloads
+            .get(url)
+            .map_or((0, 0), |download| (download.id, download.attempts));
+        let log = DownloadLog::open(
+            &logs::dir(&self.settings.download_path),
+            id,
+            attempt,
+            &self.settings.log_limits,
+        )
+        .await;
+
+        let started_at = Utc::now();
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.applied_rate_limit = rate_limit;
+            download.pid = process.pid();
+            download.worker = process.worker();
+            download.restart_requested = false;
+            download.started_at.get_or_insert(started_at);
+            download.tracks.clear();
+        }
+        self.persist_download(url).await;
+
+        // yt-dlp reports why it gave up on stderr, keep the last reason for classifying the failure.
+        let stderr_log = log.clone();
+        let reported_error = tokio::spawn(async move {
+            let mut reported_error = None;
+            if let Some(stderr) = &mut stderr {
+                while let Some(line) = stderr.recv().await {
+                    trace!("ytdlp error output: {}", line);
+                    stderr_log.write(&line).await;
+                    if let Some(err) = line.strip_prefix("ERROR: ") {
+                        reported_error = Some(err.to_string());
+                    }
+                }
+            }
+            reported_error
+        });
+        let regex = Regex::new(YTDLP_DOWNLOAD_UPDATE_REGEX).expect("couldn't compile yt-dlp regex");
+        let format_regex =
+            Regex::new(YTDLP_FORMAT_SELECTION_REGEX).expect("couldn't compile yt-dlp regex");
+        let destination_regex =
+            Regex::new(YTDLP_DESTINATION_REGEX).expect("couldn't compile yt-dlp regex");
+        let chapter_regex = Regex::new(YTDLP_CHAPTER_REGEX).expect("couldn't compile yt-dlp regex");
+        let move_regex = Regex::new(YTDLP_MOVE_REGEX).expect("couldn't compile yt-dlp regex");
+
+        let mut last_output = Instant::now();
+        let mut stalled = false;
+        let mut stalled_out = false;
+        let mut timed_out = false;
+        loop {
+            let stall_deadline = self
+                .settings
+                .stall_timeout
+                .map(|stall_timeout| last_output + stall_timeout);
+            let next_line = match stall_deadline.into_iter().chain(max_deadline).min() {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    tokio::time::timeout(remaining, process.next_line()).await
+                }
+                None => Ok(process.next_line().await),
+            };
+            // `None` when a deadline passed without output.
+            let line = match next_line {
+                Ok(Ok(Some(line))) => Some(line),
+                Ok(_) => break,
+                Err(_) => None,
+            };
+            match download_kill_rx.try_recv() {
+                Ok(signal) => {
+                    received_signal = Some(signal);
+                    process.kill(url).await;
+                    // Partial files are in the work dir, removed as the download finishes canceled.
+                    break;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    break;
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+            if self
+                .downloads
+                .get(url)
+                .is_some_and(|download| download.restart_requested)
+            {
+                info!("restarting yt-dlp with a new rate limit for url: {}", url);
+                process.kill(url).await;
+                restarted = true;
+                break;
+            }
+
+            let Some(line) = line else {
+                if max_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    warn!("download ran past its time limit, stopping url: {}", url);
+                    process.kill(url).await;
+                    timed_out = true;
+                    break;
+                }
+                last_output = Instant::now();
+                if !stalled {
+                    warn!(
+                        "no output from yt-dlp for url: {}, flagging it stalled",
+                        url
+                    );
+                    stalled = true;
+                    self.set_status(url, Status::Stalled, download_update_tx)
+                        .await;
+                } else if self.settings.stall_retry {
+                    warn!("killing stalled yt-dlp for url: {}", url);
+                    process.kill(url).await;
+                    stalled_out = true;
+                    break;
+                }
+                continue;
+            };
+            trace!("ytdlp output: {}", line);
+            log.write(&line).await;
+            last_output = Instant::now();
+            if stalled {
+                info!("yt-dlp for url: {} is writing output again", url);
+                stalled = false;
+                self.set_status(url, Status::Running, download_update_tx)
+                    .await;
+            }
+            if let Some(mut download) = self.downloads.get_mut(url) {
+                let tail_lines = self.settings.log_limits.tail_lines;
+                while download.log_tail.len() >= tail_lines.max(1) {
+                    download.log_tail.pop_front();
+                }
+                if tail_lines > 0 {
+                    download.log_tail.push_back(line.clone());
+                }
+                if let Some(captures) = format_regex.captures(&line) {
+                    download.format_id = Some(String::from(&captures[1]));
+                }
+                if let Some(captures) = chapter_regex.captures(&line) {
+                    download.tracks.push(PathBuf::from(&captures[1]));
+                } else if let Some(captures) = destination_regex.captures(&line) {
+                    download.file_path = Some(PathBuf::from(&captures[1]));
+                } else if let Some(captures) = move_regex.captures(&line) {
+                    // Sidecars are moved out of the work dir too, only the main file is tracked.
+                    if download.file_path.as_deref() == Some(Path::new(&captures[1])) {
+                        download.file_path = Some(PathBuf::from(&captures[2]));
+                    }
+                }
+            }
+            if regex.is_match(&line) {
+                if let Some(captures) = regex.captures(&line) {
+                    let url = url.clone();
+                    let percent = String::from(&captures[1]);
+                    let size_downloaded = String::from(&captures[2]);
+                    let speed = String::from(&captures[3]);
+                    let eta = String::from(&captures[4]);
+
+                    let download_update = DownloadProgress {
+                        url,
+                        percent,
+                        size_downloaded,
+                        speed,
+                        eta,
+                    };
+
+                    self.record_progress(download_update, download_update_tx)
+                        .await;
+                }
+            }
+        }
+
+        let exit_status = process.wait().await;
+        let reported_error = reported_error.await.ok().flatten();
+        let (status, error) = match exit_status {
+            Ok(_) if restarted => (Status::Running, None),
+            Ok(_) if timed_out => (
+                Status::TimedOut,
+                Some(format!(
+                    "ran past its limit of {}s",
+                    options.max_duration_secs.unwrap_or_default()
+                )),
+            ),
+            Ok(_) if stalled_out => (
+                Status::Failed,
+                Some(format!(
+                    "{} for {:?}",
+                    STALL_ERROR,
+                    self.settings.stall_timeout.unwrap_or_default() * 2
+                )),
+            ),
+            Ok(exit) => match exit.success {
+                true => (Status::Completed, None),
+                false => match received_signal {
+                    Some(signal) => match signal {
+                        Signal::Cancel => (Status::Canceled, None),
+                        Signal::Pause => (Status::Paused, None),
+                    },
+                    None => (
+                        Status::Failed,
+                        Some(reported_error.unwrap_or(exit.description)),
+                    ),
+                },
+            },
+            Err(err) => (Status::Failed, Some(err.to_string())),
+        };
+
+        if let Some(err) = &error {
+            self.record_error(url, err.clone());
+        }
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.pid = None;
+        }
+
+        Ok(AttemptOutcome {
+            error,
+            restarted,
+            started_at,
+            status,
+        })
+    }
+
+    async fn record_progress(
+        &self,
+        progress: DownloadProgress,
+        download_update_tx: &Option<Sender<Event>>,
+    ) {
+        if let Some(mut download) = self.downloads.get_mut(&progress.url) {
+            download.progress = Some(progress.clone());
+        }
+        self.progress_writer.record(progress.clone());
+        send_event(download_update_tx, Event::Progress(progress)).await;
+    }
+
+    /// Walks a made up download through the queue, progress updates and persistence without
+    /// starting yt-dlp. Only reachable through the debug endpoints, for load testing.
+    /// # Errors
+    /// Possible error variants are: DownloadAlreadyPresent
+    pub async fn run_synthetic(
+        &self,
+        url: &Url,
+        steps: u32,
+        step_interval: Duration,
+        download_update_tx: Option<Sender<Event>>,
+    ) -> Result<Status> {
+        let (download_kill_tx, mut download_kill_rx) = mpsc::channel(100);
+        let options = DownloadOptions {
+            container: String::from("mp4"),
+            name_format: String::from("synthetic"),
+            quality: String::from("0"),
+            priority: 0,
+            rate_limit: None,
+            subtitle_format: None,
+            split_chapters: false,
+            audio_format: None,
+            tag_template: None,
+            download_archive: None,
+            max_duration_secs: None,
+            library_links: None,
+            ytdlp_config: None,
+            extra_args: Vec::new(),
+            cookies: None,
+            proxy: None,
+            geo_bypass_country: None,
+        };
+
+        self.add_download(url, &options, false, None, None, Some(download_kill_tx))
+            .await?;
+        self.set_status(url, Status::Queued, &download_update_tx)
+            .await;
+
+        let slot = tokio::select! {
+            slot = self.queue.acquire(url, options.priority, self.queue_rank(url)) => Some(slot),
+            _ = download_kill_rx.recv() => None,
+        };
+        let mut status = Status::Canceled;
+        if slot.is_some() {
+            if let Some(mut download) = self.downloads.get_mut(url) {
+                download.attempts = 1;
+                download.started_at = Some(Utc::now());
+            }
+            self.set_status(url, Status::Running, &download_update_tx)
+                .await;
+
+            status = Status::Completed;
+            let steps = steps.max(1);
+            let mut interval = tokio::time::interval(step_interval);
+            for step in 1..=steps {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    signal = download_kill_rx.recv() => {
+                        status = match signal {
+                            Some(Signal::Pause) => Status::Paused,
+                            Some(Signal::Cancel) | None => Status::Canceled,
+                        };
+                        break;
+                    }
+                }
+
+                let progress = DownloadProgress {
+                    url: url.clone(),
+                    percent: format!("{:.1}", f64::from(step) * 100.0 / f64::from(steps)),
+                    size_downloaded: String::from("10.00MiB"),
+                    speed: String::from("1.00MiB/s"),
+                    eta: format!("00:{:02}", steps - step),
+                };
+                self.record_progress(progress, &download_update_tx).await;
+            }
+        }
+
+        drop(slot);
+        self.finish_download(url, status.clone(), &download_update_tx)
+            .await;
+
+        Ok(status)
+    }
+
+    fn record_error(&self, url: &Url, err: String) {
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.failure_cause = Some(retry::classify(&err));
+            download.last_error = Some(err);
+        }
+    }
+
+    /// Records the final status of a download and lets subscribers know.
+    async fn finish_download(
+        &self,
+        url: &Url,
+        status: Status,
+        download_update_tx: &Option<Sender<Event>>,
+    ) {
+        self.reservations.release(url);
+        // Paused, interrupted and timed out downloads pick their partial files back up.
+        let work_dir = match status {
+            Status::Canceled | Status::Completed | Status::Failed => self
+                .downloads
+                .get_mut(url)
+                .and_then(|mut download| download.work_dir.take()),
+            _ => None,
+        };
+        if let Some(work_dir) = work_dir {
+            storage::remove_work_dir(&self.settings.download_path, &work_dir).await;
+        }
+
+        // An upgrade that didn't complete leaves the old file where it was.
+        let replaced = match status {
+            Status::Canceled | Status::Completed | Status::Failed => {
+                self.downloads.get_mut(url).and_then(|mut download| {
+                    let replaces = download.replaces.take()?;
+                    if !matches!(status, Status::Completed) {
+                        return None;
+                    }
+                    download.upgradeable = false;
+                    Some((replaces, download.file_path.clone()))
+                })
+            }
+            _ => None,
+        };
+        let replaced = replaced.and_then(|(replaces, file_path)| {
+            let replaces = self.resolve_file_path(replaces);
+            // yt-dlp writes over it itself when the better copy keeps the name.
+            (file_path.map(|file_path| self.resolve_file_path(file_path)) != Some(replaces.clone()))
+                .then_some(replaces)
+        });
+        if let Some(replaced) = replaced {
+            storage::remove_files(vec![replaced]).await;
+        }
+
+        let file_size = match status {
+            Status::Completed => self
+                .downloads
+                .get(url)
+                .and_then(|download| download.file_path.clone())
+                .and_then(|file_path| fs::metadata(self.resolve_file_path(file_path)).ok())
+                .map(|metadata| metadata.len()),
+            _ => None,
+        };
+
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            let now = Utc::now();
+            download.failed_at = matches!(status, Status::Failed).then_some(now);
+            if file_size.is_some() {
+                download.file_size = file_size;
+            }
+            download.finished_at = Some(now);
+            download.pid = None;
+            download.status = status.clone();
+            download.tx = None;
+        }
+        self.persist_download(url).await;
+
+        send_event(
+            download_update_tx,
+            Event::Status {
+                url: url.clone(),
+                status,
+            },
+        )
+        .await;
+    }
+
+    /// Runs `yt-dlp -J` for a single video and parses the parts of the output we use.
+    /// # Errors
+    /// Possible error variants are: FailedCheck, General, ProbeTimedOut, UnexpectedOutput
+    pub async fn fetch_metadata(&self, url: &Url) -> Result<VideoMetadata> {
+        let output = self
+            .run_probe(
+                self.ytdlp_command(url)
+                    .await
+                    .arg("-J")
+                    .arg("--no-playlist")
+                    .arg("--flat-playlist")
+                    .arg(url.as_str())
+                    .stdout(Stdio::piped()),
+            )
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::FailedCheck);
+        }
+
+        serde_json::from_slice(&output.stdout).map_err(|err| {
+            error!(
+                "failed to parse yt-dlp metadata for url: {}, err: {}",
+                url, err
+            );
+            Error::UnexpectedOutput
+        })
+    }
+
+    /// The formats the video behind a url can be downloaded in.
+    /// # Errors
+    /// Possible error variants are: FailedCheck, General, ProbeTimedOut, UnexpectedOutput
+    pub async fn list_formats(&self, url: &Url) -> Result<FormatListing> {
+        let metadata = self.fetch_metadata(url).await?;
+        Ok(metadata.into_listing(url.clone()))
+    }
+
+    /// Dry runs yt-dlp with `options` against `url` without downloading, so flags it rejects or
+    /// can't combine show up before a download or schedule relies on them.
+    /// # Errors
+    /// Possible error variants are: ForbiddenConfig, General, ProbeTimedOut
+    pub async fn simulate(&self, url: &Url, options: &DownloadOptions) -> Result<Simulation> {
+        let mut command = self.ytdlp_command(url).await;
+        self.add_option_args(&mut command, options, &self.settings.download_path)?;
+        let output = command
+            .arg("--simulate")
+            .arg("-o")
+            .arg(&options.name_format)
+            .arg(url.as_str())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+
+        let output = match tokio::time::timeout(self.settings.probe_timeout, output).await {
+            Ok(result) => result.map_err(|err| Error::General { err })?,
+            Err(_) => return Err(Error::ProbeTimedOut),
+        };
+        Ok(Simulation::from_stderr(
+            url,
+            output.status.success(),
+            &String::from_utf8_lossy(&output.stderr),
+        ))
+    }
+
+    /// Lists the videos behind a url, which is just the url itself unless it's a playlist or channel.
+    /// # Errors
+    /// Possible error variants are: FailedCheck, General, ProbeTimedOut, UnexpectedOutput
+    pub async fn list_entries(&self, url: &Url) -> Result<Vec<Url>> {
+        let output = self
+            .run_probe(
+                self.ytdlp_command(url)
+                    .await
+                    .arg("-J")
+                    .arg("--flat-playlist")
+                    .arg(url.as_str())
+                    .stdout(Stdio::piped()),
+            )
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::FailedCheck);
+        }
+
+        let listing: PlaylistListing = serde_json::from_slice(&output.stdout).map_err(|err| {
+            error!(
+                "failed to parse yt-dlp playlist for url: {}, err: {}",
+                url, err
+            );
+            Error::UnexpectedOutput
+        })?;
+
+        Ok(match listing.entries {
+            Some(entries) => entries
+                .into_iter()
+                .filter_map(|entry| entry.webpage_url.or(entry.url))
+                .filter_map(|entry| Url::parse(&entry).ok())
+                .collect(),
+            None => vec![url.clone()],
+        })
+    }
+
+    /// Compares the format a completed download was fetched in with what is available now.
+    /// # Errors
+    /// Possible error variants are: DownloadNotPresent, NotCompleted, FailedCheck, General,
+    /// ProbeTimedOut, UnexpectedOutput
+    pub async fn check_upgrade(&self, url: &Url) -> Result<UpgradeReport> {
+        let (format_id, quality) = match self.downloads.get(url) {
+            Some(download) => match download.status {
+                Status::Completed => (download.format_id.clone(), download.options.max_height()),
+                _ => return Err(Error::NotCompleted),
+            },
+            None => return Err(Error::DownloadNotPresent),
+        };
+
+        let metadata = self.fetch_metadata(url).await?;
+        let report = formats::compare(url.clone(), &metadata, format_id.as_deref(), quality);
+
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            download.upgradeable = report.upgradeable;
+        }
+        self.persist_download(url).await;
+
+        Ok(report)
+    }
+
+    /// Lets a completed download be added again to fetch a better copy into the same record,
+    /// keeping its tags, history and attempts. The file it has is written over or removed once
+    /// the new one is in.
+    /// # Errors
+    /// Possible error variants are: DownloadNotPresent, NotCompleted
+    pub fn start_upgrade(&self, url: &Url) -> Result<()> {
+        let mut download = self
+            .downloads
+            .get_mut(url)
+            .ok_or(Error::DownloadNotPresent)?;
+        match (&download.status, download.file_path.clone()) {
+            (Status::Completed, Some(file_path)) => {
+                download.replaces = Some(file_path);
+                Ok(())
+            }
+            _ => Err(Error::NotCompleted),
+        }
+    }
+
+    /// Undoes [`Self::start_upgrade`] for an upgrade that couldn't be added.
+    pub fn cancel_upgrade(&self, url: &Url) {
+        if let Some(mut download) = self.downloads.get_mut(url) {
+            if matches!(download.status, Status::Completed) {
+                download.replaces = None;
+            }
+        }
+    }
+
+    /// Downloads that were running when the server last stopped.
+    pub async fn get_interrupted(&self) -> Vec<DownloadInfo> {
+        self.downloads
+            .iter()
+            .filter(|entry| matches!(entry.status, Status::Interrupted))
+            .map(|entry| entry.info(entry.key()))
+            .collect()
+    }
+
+    pub async fn get_paused(&self) -> Vec<DownloadInfo> {
+        self.downloads
+            .iter()
+            .filter(|entry| matches!(entry.status, Status::Paused) && entry.is_resumable())
+            .map(|entry| entry.info(entry.key()))
+            .collect()
+    }
+
+    pub async fn get_scheduled(&self) -> Vec<DownloadInfo> {
+        self.downloads
+            .iter()
+            .filter(|entry| matches!(entry.status, Status::Scheduled) && entry.tx.is_none())
+            .map(|entry| entry.info(entry.key()))
+            .collect()
+    }
+
+    /// Urls of every completed download, i.e. the library an upgrade scan walks over.
+    pub async fn get_completed_urls(&self) -> Vec<Url> {
+        self.downloads
+            .iter()
+            .filter(|entry| matches!(entry.status, Status::Completed))
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Stops tracking a download that isn't running so it can be submitted again.
+    /// # Errors
+    /// Possible error variants are: DownloadNotPresent, DownloadAlreadyPresent
+    pub async fn remove_download(&self, url: &Url) -> Result<DownloadInfo> {
+        match self
+            .downloads
+            .remove_if(url, |_, download| !download.is_active())
+        {
+            Some((url, download)) => {
+                self.persist_download(&url).await;
+                self.events.publish(Event::Removed {
+                    id: download.id,
+                    url: url.clone(),
+                });
+                Ok(download.info(&url))
+            }
+            None => match self.downloads.contains_key(url) {
+                true => Err(Error::DownloadAlreadyPresent),
+                false => Err(Error::DownloadNotPresent),
+            },
+        }
+    }
+
+    /// Forgets the download with this id, along with its partial files. With `remove_files`, the
+    /// file it saved and everything yt-dlp wrote next to it go too.
+    /// Returns the files that were removed.
+    /// # Errors
+    /// Possible error variants are: DownloadNotPresent, DownloadAlreadyPresent
+    pub async fn delete_download(&self, id: i64, remove_files: bool) -> Result<Vec<PathBuf>> {
+        let (url, download) = self
+            .downloads
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .ok_or(Error::DownloadNotPresent)?;
+        // The links are forgotten with the download, read them while they're still there.
+        let library_links = self.get_link_paths(id).await;
+        self.remove_download(&url).await?;
+
+        if let Some(work_dir) = download.work_dir {
+            storage::remove_work_dir(&self.settings.download_path, &work_dir).await;
+        }
+        let _ =
+            tokio::fs::remove_file(logs::path(&logs::dir(&self.settings.download_path), id)).await;
+        if !remove_files {
+            return Ok(Vec::new());
+        }
+
+        let mut files = download.tracks;
+        files.extend(library_links);
+        if let Some(file_path) = download.file_path {
+            let file_path = self.resolve_file_path(file_path);
+            files.extend(storage::sidecar_files(&file_path));
+            files.push(file_path);
+        }
+        let removed = storage::remove_files(files).await;
+        info!("deleted download: {}, removed {} files", url, removed.len());
+
+        Ok(removed)
+    }
+
+    /// Deletes the completed and canceled downloads past the history limits, see
+    /// [`RetentionSettings`]. Returns how many were pruned.
+    pub async fn prune_history(&self) -> usize {
+        let settings = self.retention_settings().await;
+        let finished = self
+            .downloads
+            .iter()
+            .filter(|entry| matches!(entry.status, Status::Completed | Status::Canceled))
+            .filter(|entry| !entry.pinned && !entry.starred)
+            .map(|entry| (entry.id, entry.finished_at.unwrap_or(entry.created_at)))
+            .collect();
+
+        let mut pruned = 0;
+        for id in retention::expired(finished, &settings, Utc::now()) {
+            match self.delete_download(id, settings.remove_files).await {
+                Ok(_) => pruned += 1,
+                Err(err) => warn!("failed to prune download: {}, err: {}", id, err),
+            }
+        }
+        if pruned > 0 {
+            info!("pruned {} downloads from the history", pruned);
+        }
+        pruned
+    }
+
+    /// Trashes the logs past the [`LogLimits`] and those whose download is gone. Logs of
+    /// downloads that may still write to them are left alone.
+    pub async fn prune_logs(&self) -> std::io::Result<usize> {
+        let mut known = HashSet::new();
+        let mut active = HashSet::new();
+        for entry in self.downloads.iter() {
+            known.insert(entry.id);
+            if !matches!(
+                entry.status,
+                Status::Canceled | Status::Completed | Status::Failed
+            ) {
+                active.insert(entry.id);
+            }
+        }
+
+        let trashed = logs::prune(
+            &logs::dir(&self.settings.download_path),
+            &known,
+            &active,
+            &self.settings.log_limits,
+        )
+        .await?;
+        if trashed > 0 {
+            info!("trashed {} download logs", trashed);
+        }
+        Ok(trashed)
+    }
+
+    /// Drops the stored progress of downloads that are no longer running.
+    pub async fn prune_progress(&self) -> sqlx::Result<u64> {
+        let running: Vec<String> = self
+            .downloads
+            .iter()
+            .filter(|entry| entry.is_active() || entry.status == Status::Paused)
+            .map(|entry| entry.key().to_string())
+            .collect();
+        let running = Json(running);
+        let result = sqlx::query!(
+            "DELETE FROM DownloadProgress WHERE url NOT IN (SELECT value FROM json_each($1))",
+            running
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn retention_settings(&self) -> RetentionSettings {
+        let config = sqlx::query_as!(
+            RetentionSettings,
+            r#"SELECT
+                history_max_age_days as max_age_days,
+                history_max_rows as max_rows,
+                history_prune_files as remove_files
+            FROM Config WHERE id = 1"#
+        )
+        .fetch_optional(&self.db)
+        .await;
+
+        match config {
+            Ok(config) => config.unwrap_or_default(),
+            Err(err) => {
+                error!("failed to read the history retention config: {}", err);
+                RetentionSettings::default()
+            }
+        }
+    }
+
+    /// How repeated videos are handled, the defaults if the config can't be read.
+    pub async fn duplicate_settings(&self) -> DuplicateSettings {
+        let config = sqlx::query_as!(
+            DuplicateSettings,
+            r#"SELECT
+                duplicate_policy as "policy: DuplicatePolicy",
+                perceptual_hash
+            FROM Config WHERE id = 1"#
+        )
+        .fetch_optional(&self.db)
+        .await;
+
+        match config {
+            Ok(config) => config.unwrap_or_default(),
+            Err(err) => {
+                error!("failed to read the duplicate config: {}", err);
+                DuplicateSettings::default()
+            }
+        }
+    }
+
+    /// A yt-dlp command for `url`, sending the user agent and headers of the rules matching it,
+    /// impersonating the configured browser, sending the cookies of the one configured for
+    /// that, going through the configured proxy and faking the configured country. yt-dlp adds a
+    /// download's own cookies file to those, and its own proxy and country replace the configured
+    /// ones.
+    async fn ytdlp_command(&self, url: &Url) -> Command {
+        let mut command = Command::new(&self.settings.ytdlp_path);
+        match headers::load(&self.db).await {
+            Ok(rules) => {
+                command.args(headers::ytdlp_args(&rules, url));
+            }
+            Err(err) => error!("failed to load header rules: {}", err),
+        }
+        match sqlx::query!(
+            "SELECT impersonate, cookies_from_browser, proxy_url, geo_bypass_country FROM Config WHERE id = 1"
+        )
+        .fetch_optional(&self.db)
+        .await
+        {
+            Ok(Some(config)) => {
+                if let Some(target) = config.impersonate {
+                    command.arg("--impersonate").arg(target);
+                }
+                if let Some(browser) = config.cookies_from_browser {
+                    command.arg("--cookies-from-browser").arg(browser);
+                }
+                if let Some(proxy) = config.proxy_url {
+                    command.arg("--proxy").arg(proxy);
+                }
+                if let Some(country) = config.geo_bypass_country {
+                    command.arg("--xff").arg(country);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => error!(
+                "failed to read the impersonate, cookies, proxy and geo bypass config: {}",
+                err
+            ),
+        }
+        command
+    }
+
+    /// The targets found by [`detect_impersonate_targets`], empty when impersonation is unsupported.
+    pub fn impersonate_targets(&self) -> &[String] {
+        &self.impersonate_targets
+    }
+
+    /// Where yt-dlp records finished videos for downloads using the archive.
+    pub fn download_archive_path(&self) -> &Path {
+        &self.settings.download_archive_path
+    }
+
+    pub fn download_path(&self) -> &Path {
+        &self.settings.download_path
+    }
+
+    /// Where the cookies files downloads can pick are kept.
+    pub fn cookies_dir(&self) -> &Path {
+        &self.settings.cookies_dir
+    }
+
+    /// The url options are validated against by default.
+    pub fn options_check_url(&self) -> &Url {
+        &self.settings.options_check_url
+    }
+
+    /// Where the config files downloads can pick are kept.
+    pub fn ytdlp_config_dir(&self) -> &Path {
+        &self.settings.ytdlp_config_dir
+    }
+
+    /// The config file `options` pick, which yt-dlp refuses to run without if it's been deleted.
+    fn ytdlp_config_path(&self, options: &DownloadOptions) -> Option<PathBuf> {
+        options
+            .ytdlp_config
+            .as_deref()
+            .map(|name| ytdlp_configs::path(&self.settings.ytdlp_config_dir, name))
+    }
+
+    /// Whether a download can be handed to a remote worker. Workers only get the download path,
+    /// so downloads using the server's config, cookies or archive files, writing outside the
+    /// download path or picking up partial files left here run here.
+    fn runs_remotely(
+        &self,
+        url: &Url,
+        options: &DownloadOptions,
+        home: &Path,
+        download_archive: bool,
+    ) -> bool {
+        options.ytdlp_config.is_none()
+            && options.cookies.is_none()
+            && !download_archive
+            && home.starts_with(&self.settings.download_path)
+            && Path::new(&options.name_format).is_relative()
+            && !self
+                .downloads
+                .get(url)
+                .is_some_and(|download| download.continue_partial || download.replaces.is_some())
+    }
+
+    /// The remote workers downloads can be handed to.
+    pub fn workers(&self) -> &WorkerPool {
+        &self.workers
+    }
+
+    /// What workers have to send, `None` when they aren't taken.
+    pub fn worker_token(&self) -> Option<&str> {
+        self.settings.worker_token.as_deref()
+    }
+
+    /// Adds the config and cookies files, the proxy and the geo bypass country `options` pick,
+    /// which matter to every run of yt-dlp for the download, probes included. The config is
+    /// checked again each time, in case it was written some other way than through the api.
+    /// # Errors
+    /// Possible error variants are: ForbiddenConfig, General
+    fn add_file_args(&self, command: &mut Command, options: &DownloadOptions) -> Result<()> {
+        if let Some(config) = self.ytdlp_config_path(options) {
+            match fs::read_to_string(&config) {
+                Ok(contents) => {
+                    if let Some(option) = ytdlp_configs::forbidden_option(&contents) {
+                        return Err(Error::ForbiddenConfig { option });
+                    }
+                }
+                // yt-dlp refuses to run without it and says so.
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(Error::General { err }),
+            }
+            command.arg("--config-locations").arg(config);
+        }
+        if let Some(name) = &options.cookies {
+            let path = cookies::path(&self.settings.cookies_dir, name);
+            // yt-dlp goes on without cookies when the file is missing, rather than failing.
+            if !path.is_file() {
+                warn!("cookies file: {} is missing, running without it", name);
+            }
+            command.arg("--cookies").arg(path);
+        }
+        // yt-dlp uses the last --proxy, so this replaces the configured one.
+        if let Some(proxy) = &options.proxy {
+            command.arg("--proxy").arg(proxy);
+        }
+        match options.geo_bypass_country.as_deref() {
+            Some("") => {
+                command.arg("--xff").arg("never");
+            }
+            Some(country) => {
+                command.arg("--xff").arg(country);
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Adds what `options` ask of yt-dlp to `command`, with chapters split into `home`.
+    /// # Errors
+    /// Possible error variants are: ForbiddenConfig, General
+    fn add_option_args(
+        &self,
+        command: &mut Command,
+        options: &DownloadOptions,
+        home: &Path,
+    ) -> Result<()> {
+        self.add_file_args(command, options)?;
+        command.arg("-f").arg(options.format_selector());
+        if options.extracts_audio() {
+            command
+                .arg("-x")
+                .arg("--embed-metadata")
+                .arg("--embed-thumbnail");
+            if let Some(audio_format) = &options.audio_format {
+                command.arg("--audio-format").arg(audio_format);
+            }
+            let template = tags::template(options.tag_template.as_ref());
+            for parse_metadata in tags::parse_metadata_args(&template) {
+                command.arg("--parse-metadata").arg(parse_metadata);
+            }
+        }
+        if options.split_chapters {
+            command.arg("--split-chapters").arg("-o").arg(format!(
+                "chapter:{}",
+                home.join(YTDLP_CHAPTER_TEMPLATE).display()
+            ));
+        } else if !options.extracts_audio() {
+            command
+                .arg("--merge-output-format")
+                .arg(options.container.to_lowercase());
+        }
+        if options.subtitle_format.is_some() {
+            command.arg("--write-subs");
+        }
+        command.args(&options.extra_args);
+        Ok(())
+    }
+
+    /// Whether downloads without their own preference use the archive.
+    async fn global_download_archive(&self) -> bool {
+        let config = sqlx::query_scalar!("SELECT download_archive FROM Config WHERE id = 1")
+            .fetch_optional(&self.db)
+            .await;
+
+        match config {
+            Ok(download_archive) => download_archive.unwrap_or_default(),
+            Err(err) => {
+                error!("failed to read the download archive config: {}", err);
+                false
+            }
+        }
+    }
+
+    /// How Shorts and clip links are handled, the default if the config can't be read.
+    pub async fn short_form_policy(&self) -> ShortFormPolicy {
+        let config = sqlx::query_scalar!(
+            r#"SELECT short_form_policy as "short_form_policy: ShortFormPolicy"
+            FROM Config WHERE id = 1"#
+        )
+        .fetch_optional(&self.db)
+        .await;
+
+        match config {
+            Ok(policy) => policy.unwrap_or_default(),
+            Err(err) => {
+                error!("failed to read the short form policy: {}", err);
+                ShortFormPolicy::default()
+            }
+        }
+    }
+
+    /// The rate limit for downloads without their own, from the Config default and bandwidth windows.
+    async fn global_rate_limit(&self) -> Option<String> {
+        let config = sqlx::query!(
+            r#"SELECT
+                rate_limit,
+                bandwidth_windows as "bandwidth_windows: Json<Vec<BandwidthWindow>>"
+            FROM Config WHERE id = 1"#
+        )
+        .fetch_optional(&self.db)
+        .await;
+
+        match config {
+            Ok(Some(config)) => bandwidth::rate_limit_at(
+                &config
+                    .bandwidth_windows
+                    .map(|windows| windows.0)
+                    .unwrap_or_default(),
+                config.rate_limit,
+                Utc::now(),
+            ),
+            Ok(None) => None,
+            Err(err) => {
+                error!("failed to read the rate limit config: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Restarts running downloads whose yt-dlp was started under a rate limit no longer in force.
+    /// yt-dlp can't change its limit while running, so it picks up from its partial files instead.
+    pub async fn apply_rate_limit(&self) {
+        let rate_limit = self.global_rate_limit().await;
+        for mut download in self.downloads.iter_mut() {
+            if matches!(download.status, Status::Running | Status::Stalled)
+                && download.pid.is_some()
+                && download.options.rate_limit.is_none()
+                && download.applied_rate_limit != rate_limit
+            {
+                download.restart_requested = true;
+            }
+        }
+    }
+
+    pub async fn get_download(&self, url: &Url) -> Option<DownloadInfo> {
+        let mut info = self
+            .downloads
+            .get(url)
+            .map(|entry| entry.info(entry.key()))?;
+        info.queue_position = self
+            .queue
+            .waiting()
+            .iter()
+            .position(|waiting| waiting == url);
+        Some(info)
+    }
+
+    pub async fn get_download_detail(&self, id: i64) -> Option<DownloadDetail> {
+        let (url, download) = self
+            .downloads
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| (entry.key().clone(), entry.value().clone()))?;
+
+        let file_path = download
+            .file_path
+            .clone()
+            .map(|file_path| self.resolve_file_path(file_path));
+        let file_size = file_path
+            .as_ref()
+            .and_then(|file_path| fs::metadata(file_path).ok())
+            .map(|metadata| metadata.len());
+        let sidecars = file_path
+            .as_ref()
+            .map(|file_path| storage::sidecar_files(file_path))
+            .unwrap_or_default();
+
+        Some(DownloadDetail {
+            info: self.get_download(&url).await?,
+            progress: download.progress,
+            file_path,
+            file_size,
+            sidecars,
+            log_tail: download.log_tail.into(),
+            attempt_history: self.get_attempt_history(id).await,
+            tags: self.get_tag_names(id).await,
+            library_links: self.get_link_paths(id).await,
+        })
+    }
+
+    /// The image to show for a download, the thumbnail yt-dlp wrote or else a generated preview.
+    pub fn get_thumbnail(&self, id: i64) -> Option<PathBuf> {
+        let file_path = self
+            .downloads
+            .iter()
+            .find(|entry| entry.id == id)
+            .and_then(|entry| entry.file_path.clone())
+            .map(|file_path| self.resolve_file_path(file_path))?;
+
+        let mut images = thumbnails(&file_path);
+        images.extend(
+            Some(file_path.with_extension(PREVIEW_EXTENSION)).filter(|preview| preview.exists()),
+        );
+        images.into_iter().next()
+    }
+
+    /// Where the download's files end up, the download path or the subdirectory its tags give it.
+    async fn home_dir(&self, url: &Url) -> PathBuf {
+        let Some(id) = self.downloads.get(url).map(|download| download.id) else {
+            return self.settings.download_path.clone();
+        };
+        match tagging::subdirectory(&self.db, id).await {
+            Ok(Some(subdirectory)) => self.settings.download_path.join(subdirectory),
+            Ok(None) => self.settings.download_path.clone(),
+            Err(err) => {
+                error!("failed to load tags for download: {}, err: {}", url, err);
+                self.settings.download_path.clone()
+            }
+        }
+    }
+
+    /// Where the download's yt-dlp output is kept, if it's been run.
+    pub fn get_log_path(&self, id: i64) -> Option<PathBuf> {
+        self.downloads
+            .iter()
+            .any(|entry| entry.id == id)
+            .then(|| logs::path(&logs::dir(&self.settings.download_path), id))
+            .filter(|path| path.exists())
+    }
+
+    /// The download's own working directory, named the first time it's asked for.
+    fn work_dir(&self, url: &Url) -> PathBuf {
+        let name = match self.downloads.get_mut(url) {
+            Some(mut download) => download
+                .work_dir
+                .get_or_insert_with(|| Uuid::new_v4().to_string())
+                .clone(),
+            None => Uuid::new_v4().to_string(),
+        };
+        storage::work_dir(&self.settings.download_path, &name)
+    }
+
+    /// Whether a download of `estimated_size` bytes fits on the download disk as it is now.
+    pub fn check_free_space(&self, estimated_size: f64) -> std::result::Result<(), Shortfall> {
+        storage::check_free_space(estimated_size as u64, &self.settings.download_path)
+    }
+
+    fn resolve_file_path(&self, file_path: PathBuf) -> PathBuf {
+        storage::resolve_file_path(&self.settings.download_path, file_path)
+    }
+
+    async fn get_attempt_history(&self, id: i64) -> Vec<AttemptRecord> {
+        let rows = sqlx::query!(
+            r#"SELECT attempt, status, error, transient, started_at as "started_at: DateTime<Utc>", finished_at as "finished_at: DateTime<Utc>"
+            FROM DownloadAttempt WHERE download_id = $1 ORDER BY attempt"#,
+            id
+        )
+        .fetch_all(&self.db)
+        .await;
+
+        match rows {
+            Ok(rows) => rows
+                .into_iter()
+                .map(|row| AttemptRecord {
+                    attempt: row.attempt,
+                    status: Status::from(row.status),
+                    error: row.error,
+                    transient: row.transient,
+                    started_at: row.started_at,
+                    finished_at: row.finished_at,
+                })
+                .collect(),
+            Err(err) => {
+                error!("failed to load attempts for download: {}, err: {}", id, err);
+                Vec::new()
+            }
+        }
+    }
+
+    async fn get_link_paths(&self, id: i64) -> Vec<PathBuf> {
+        match links::paths(&self.db, id).await {
+            Ok(paths) => paths,
+            Err(err) => {
+                error!("failed to load links for download: {}, err: {}", id, err);
+                Vec::new()
+            }
+        }
+    }
+
+    async fn get_tag_names(&self, id: i64) -> Vec<String> {
+        match tagging::for_download(&self.db, id).await {
+            Ok(tags) => tags.into_iter().map(|tag| tag.name).collect(),
+            Err(err) => {
+                error!("failed to load tags for download: {}, err: {}", id, err);
+                Vec::new()
+            }
+        }
+    }
+
+    /// The download's tags, or `None` if there's no download with this id.
+    pub async fn get_tags(&self, id: i64) -> sqlx::Result<Option<Vec<Tag>>> {
+        if !self.downloads.iter().any(|entry| entry.id == id) {
+            return Ok(None);
+        }
+        tagging::for_download(&self.db, id).await.map(Some)
+    }
+
+    /// Replaces the download's tags, which picks its subdirectory from the next attempt on.
+    /// Returns `None` if there's no download with this id.
+    pub async fn set_tags(&self, id: i64, names: &[String]) -> sqlx::Result<Option<Vec<Tag>>> {
+        if !self.downloads.iter().any(|entry| entry.id == id) {
+            return Ok(None);
+        }
+        tagging::set(&self.db, id, names).await?;
+        tagging::for_download(&self.db, id).await.map(Some)
+    }
+
+    /// Hands the queue the download windows as they are in the config.
+    pub async fn reload_windows(&self) {
+        match windows::load(&self.db).await {
+            Ok(windows) => self.queue.set_windows(windows),
+            Err(err) => error!("failed to load download windows, err: {}", err),
+        }
+    }
+
+    /// Domains held back for throttling downloads, see [`DownloadQueue::throttle`].
+    pub fn throttles(&self) -> Vec<DomainThrottle> {
+        self.queue.throttles()
+    }
+
+    /// Queued downloads in the order they will start.
+    pub async fn get_queue(&self) -> Vec<DownloadInfo> {
+        let mut queue = Vec::new();
+        for url in self.queue.waiting() {
+            if let Some(info) = self.get_download(&url).await {
+                queue.push(info);
+            }
+        }
+        queue
+    }
+
+    /// Every download that hasn't run to the end yet: the queue in order, then the scheduled,
+    /// paused and interrupted ones oldest first.
+    pub async fn get_pending(&self) -> Vec<DownloadInfo> {
+        let mut pending = self.get_queue().await;
+        let mut waiting: Vec<DownloadInfo> = self
+            .downloads
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry.status,
+                    Status::Interrupted | Status::Paused | Status::Scheduled
+                )
+            })
+            .map(|entry| entry.info(entry.key()))
+            .collect();
+        waiting.sort_by_key(|download| download.id);
+        pending.extend(waiting);
+        pending
+    }
+
+    /// Puts queued downloads in the order of `urls`. They trade places among themselves, so
+    /// downloads left out of `urls` keep theirs. One moved above a download with a higher
+    /// priority is raised to that priority, as priority still comes first in the queue.
+    /// # Errors
+    /// Possible error variants are: NotQueued
+    pub async fn reorder_queue(&self, urls: &[Url]) -> Result<()> {
+        let waiting = self.queue.waiting();
+        if urls.iter().any(|url| !waiting.contains(url)) {
+            return Err(Error::NotQueued);
+        }
+
+        let mut places: Vec<(i64, i64)> = urls
+            .iter()
+            .filter_map(|url| {
+                let download = self.downloads.get(url)?;
+                Some((download.options.priority, download.queue_rank))
+            })
+            .collect();
+        let mut ranks: Vec<i64> = places.iter().map(|(_, rank)| *rank).collect();
+        ranks.sort_unstable();
+        // Priorities can't increase down the new order.
+        for index in (0..places.len().saturating_sub(1)).rev() {
+            places[index].0 = places[index].0.max(places[index + 1].0);
+        }
+
+        for ((url, (priority, _)), rank) in urls.iter().zip(places).zip(ranks) {
+            if let Some(mut download) = self.downloads.get_mut(url) {
+                download.options.priority = priority;
+                download.queue_rank = rank;
+            }
+            self.queue.set_place(url, priority, rank);
+            self.persist_download(url).await;
+        }
+
+        Ok(())
+    }
+
+    fn queue_rank(&self, url: &Url) -> i64 {
+        self.downloads
+            .get(url)
+            .map_or(0, |download| download.queue_rank)
+    }
+
+    /// One page of finished downloads, read from the db so the whole history needn't be walked.
+    pub async fn get_history(
+        &self,
+        statuses: &[Status],
+        tag: Option<&str>,
+        starred: Option<bool>,
+        sort: HistorySort,
+        page: u32,
+        per_page: u32,
+    ) -> sqlx::Result<HistoryPage> {
+        let (urls, total) =
+            history::page(&self.db, statuses, tag, starred, sort, page, per_page).await?;
+        let downloads = urls
+            .iter()
+            .filter_map(|url| Url::parse(url).ok())
+            .filter_map(|url| self.downloads.get(&url).map(|entry| entry.info(&url)))
+            .collect();
+
+        Ok(HistoryPage {
+            downloads,
+            page,
+            per_page,
+            total,
+        })
+    }
+
+    /// The ids of the downloads `filter` lets through, looked up in the db's indexes.
+    pub async fn filter_downloads(&self, filter: &DownloadFilter) -> sqlx::Result<HashSet<i64>> {
+        filters::matching_ids(&self.db, filter).await
+    }
+
+    pub async fn get_downloads(&self) -> Vec<DownloadInfo> {
+        let waiting = self.queue.waiting();
+        self.downloads
+            .iter()
+            .map(|entry| {
+                let mut info = entry.info(entry.key());
+                info.queue_position = waiting.iter().position(|waiting| waiting == entry.key());
+                info
+            })
+            .collect()
+    }
+
+    /// Counts of downloads by where they are and the progress of the running ones, leaving out
+    /// urls, titles and file names.
+    pub fn get_public_status(&self) -> PublicStatus {
+        let count = |statuses: &[Status]| {
+            self.downloads
+                .iter()
+                .filter(|entry| statuses.contains(&entry.status))
+                .count()
+        };
+        let mut running: Vec<(i64, PublicProgress)> = self
+            .downloads
+            .iter()
+            .filter(|entry| matches!(entry.status, Status::Running | Status::Stalled))
+            .filter_map(|entry| {
+                let progress = entry.progress.as_ref()?;
+                Some((
+                    entry.id,
+                    PublicProgress {
+                        percent: progress.percent.clone(),
+                        speed: progress.speed.clone(),
+                        eta: progress.eta.clone(),
+                    },
+                ))
+            })
+            .collect();
+        running.sort_by_key(|(id, _)| *id);
+
+        PublicStatus {
+            running: count(&[Status::Running, Status::Stalled]),
+            queued: count(&[Status::Checking, Status::Queued, Status::Scheduled]),
+            completed: count(&[Status::Completed]),
+            failed: count(&[Status::Failed]),
+            progress: running.into_iter().map(|(_, progress)| progress).collect(),
+        }
+    }
+
+    pub fn download_counts(&self) -> DownloadCounts {
+        let mut counts = DownloadCounts::default();
+        for entry in self.downloads.iter() {
+            counts.total += 1;
+            match entry.status {
+                Status::Running | Status::Stalled => counts.running += 1,
+                Status::Checking | Status::Queued | Status::Scheduled => counts.queued += 1,
+                Status::Paused | Status::Interrupted | Status::TimedOut => counts.paused += 1,
+                Status::Completed => counts.completed += 1,
+                Status::Failed => counts.failed += 1,
+                Status::Canceled => counts.canceled += 1,
+                Status::None => {}
+            }
+        }
+        counts
+    }
+
+    /// Samples cpu and memory of every running yt-dlp child and its descendants.
+    pub async fn get_process_usage(&self) -> Vec<DownloadUsage> {
+        let pids: Vec<(Url, u32)> = self
+            .downloads
+            .iter()
+            .filter_map(|entry| Some((entry.key().clone(), entry.pid?)))
+            .collect();
+
+        future::join_all(pids.into_iter().map(|(url, pid)| async move {
+            process::sample(pid, PROCESS_SAMPLE_WINDOW)
+                .await
+                .map(|usage| DownloadUsage { url, usage })
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    pub async fn get_urls(&self) -> Result<Vec<Url>> {
+        Ok(self
+            .downloads
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect())
+    }
+
+    async fn halt_download(&self, url: &Url, signal: Signal) -> Result<Status> {
+        let tx = match self.downloads.get(url) {
+            Some(download) => match (&download.status, &download.tx) {
+                (
+                    Status::Queued | Status::Running | Status::Scheduled | Status::Stalled,
+                    Some(tx),
+                ) => tx.clone(),
+                _ => return Err(Error::NotDownloading),
+            },
+            None => return Err(Error::NotDownloading),
+        };
+
+        let status = match signal {
+            Signal::Cancel => Status::Canceled,
+            Signal::Pause => Status::Paused,
+        };
+
+        match tx.send(signal).await {
+            Ok(_) => {
+                if let Some(mut download) = self.downloads.get_mut(url) {
+                    download.status = status.clone();
+                    download.tx = None;
+                }
+                Ok(status)
+            }
+            Err(_) => Err(Error::FailedToHalt),
+        }
+    }
+
+    /// Sends `signal` to every download that is running or waiting to, returning how each went.
+    pub async fn halt_all(&self, signal: Signal) -> BTreeMap<Url, Result<Status>> {
+        let urls: Vec<Url> = self
+            .downloads
+            .iter()
+            .filter(|entry| entry.tx.is_some())
+            .filter(|entry| {
+                matches!(
+                    entry.status,
+                    Status::Queued | Status::Running | Status::Scheduled | Status::Stalled
+                )
+            })
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        let mut results = BTreeMap::new();
+        for url in urls {
+            let result = self.halt_download(&url, signal.clone()).await;
+            results.insert(url, result);
+        }
+        results
+    }
+
+    /// Stops taking new downloads, pauses the queued and running ones and writes everything to the
+    /// db. The paused downloads are left interrupted so they resume with the next start.
+    pub async fn shutdown(&self) {
+        if self.shutdown.send_replace(true) {
+            return;
+        }
+        info!("shutting down the download manager");
+
+        let urls: Vec<Url> = self
+            .downloads
+            .iter()
+            .filter(|entry| entry.tx.is_some())
+            .filter(|entry| {
+                matches!(
+                    entry.status,
+                    Status::Queued | Status::Running | Status::Stalled
+                )
+            })
+            .map(|entry| entry.key().clone())
+            .collect();
+        let mut halted = Vec::new();
+        for url in urls {
+            match self.halt_download(&url, Signal::Pause).await {
+                Ok(_) => halted.push(url),
+                Err(err) => warn!("couldn't pause download: {}, err: {}", url, err),
+            }
+        }
+
+        // Each download finishes itself once its yt-dlp process has exited.
+        let deadline = Instant::now() + SHUTDOWN_DRAIN_TIMEOUT;
+        while halted.iter().any(|url| {
+            self.downloads
+                .get(url)
+                .is_some_and(|download| download.finished_at.is_none())
+        }) {
+            if Instant::now() >= deadline {
+                warn!(
+                    "gave up waiting for yt-dlp to exit after {:?}",
+                    SHUTDOWN_DRAIN_TIMEOUT
+                );
+                break;
+            }
+            tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+        }
+
+        for url in &halted {
+            if let Some(mut download) = self.downloads.get_mut(url) {
+                download.last_error = Some(String::from(SHUTDOWN_ERROR));
+                download.failure_cause = Some(FailureCause::Interrupted);
+                download.status = Status::Interrupted;
+            }
+            self.persist_download(url).await;
+        }
+        self.checkpoint().await;
+        if !self.flush_pending_writes().await {
+            error!("couldn't write every download to the db before shutting down");
+        }
+        info!("paused {} downloads for shutdown", halted.len());
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Flips to `true` once a shutdown has started.
+    pub fn shutdown_signal(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    pub async fn pause_download(&self, url: Url) -> Result<Status> {
+        self.halt_download(&url, Signal::Pause).await
+    }
+
+    /// Marks a download as pinned, exempting it from any cleanup or quota eviction.
+    /// # Errors
+    /// Possible error variants are: DownloadNotPresent
+    pub async fn set_pinned(&self, url: &Url, pinned: bool) -> Result<()> {
+        match self.downloads.get_mut(url) {
+            Some(mut download) => {
+                download.pinned = pinned;
+                drop(download);
+                self.persist_download(url).await;
+                Ok(())
+            }
+            None => Err(Error::DownloadNotPresent),
+        }
+    }
+
+    /// Stars or unstars a download, returning whether it's starred now or `None` if there's no
+    /// download with that id.
+    pub async fn toggle_starred(&self, id: i64) -> Option<bool> {
+        let url = self
+            .downloads
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| entry.key().clone())?;
+        let starred = {
+            let mut download = self.downloads.get_mut(&url)?;
+            download.starred = !download.starred;
+            download.starred
+        };
+        self.persist_download(&url).await;
+        Some(starred)
+    }
+
+    /// Changes the priority of a download, reordering the queue if it's still waiting.
+    /// # Errors
+    /// Possible error variants are: DownloadNotPresent
+    pub async fn set_priority(&self, url: &Url, priority: i64) -> Result<()> {
+        match self.downloads.get_mut(url) {
+            Some(mut download) => {
+                download.options.priority = priority;
+                let rank = download.queue_rank;
+                drop(download);
+                self.queue.set_place(url, priority, rank);
+                self.persist_download(url).await;
+                Ok(())
+            }
+            None => Err(Error::DownloadNotPresent),
+        }
+    }
 }
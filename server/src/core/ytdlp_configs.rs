@@ -0,0 +1,222 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The options a config file can set, by their full names, and whether each takes a value. All
+/// of them only tune how yt-dlp fetches and what it writes alongside or into the file, never
+/// where files go, what else runs or which other files are read, see [`forbidden_option`].
+const ALLOWED_OPTIONS: &[(&str, bool)] = &[
+    ("--abort-on-unavailable-fragments", false),
+    ("--add-header", true),
+    ("--audio-quality", true),
+    ("--concurrent-fragments", true),
+    ("--convert-subs", true),
+    ("--convert-thumbnails", true),
+    ("--embed-chapters", false),
+    ("--embed-metadata", false),
+    ("--embed-subs", false),
+    ("--embed-thumbnail", false),
+    ("--extractor-args", true),
+    ("--extractor-retries", true),
+    ("--force-ipv4", false),
+    ("--force-ipv6", false),
+    ("--fragment-retries", true),
+    ("--http-chunk-size", true),
+    ("--live-from-start", false),
+    ("--mark-watched", false),
+    ("--match-filters", true),
+    ("--max-filesize", true),
+    ("--max-sleep-interval", true),
+    ("--min-filesize", true),
+    ("--no-embed-chapters", false),
+    ("--no-embed-metadata", false),
+    ("--no-embed-subs", false),
+    ("--no-embed-thumbnail", false),
+    ("--no-mtime", false),
+    ("--no-playlist", false),
+    ("--parse-metadata", true),
+    ("--playlist-items", true),
+    ("--prefer-free-formats", false),
+    ("--referer", true),
+    ("--replace-in-metadata", true),
+    ("--restrict-filenames", false),
+    ("--retries", true),
+    ("--retry-sleep", true),
+    ("--skip-unavailable-fragments", false),
+    ("--sleep-interval", true),
+    ("--sleep-requests", true),
+    ("--sleep-subtitles", true),
+    ("--socket-timeout", true),
+    ("--sponsorblock-mark", true),
+    ("--sponsorblock-remove", true),
+    ("--sub-format", true),
+    ("--sub-langs", true),
+    ("--throttled-rate", true),
+    ("--user-agent", true),
+    ("--windows-filenames", false),
+    ("--write-auto-subs", false),
+    ("--write-description", false),
+    ("--write-info-json", false),
+    ("--write-subs", false),
+    ("--write-thumbnail", false),
+    ("--xattrs", false),
+    ("--yes-playlist", false),
+];
+
+/// A yt-dlp config file downloads can be run with.
+#[derive(Clone, Debug, Serialize)]
+pub struct ConfigFile {
+    pub name: String,
+    pub size: u64,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Forbidden(String),
+    Io(io::Error),
+}
+
+/// Names are used as file names, so they're kept to letters, digits, `-` and `_`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.conf", name))
+}
+
+/// The first word in `contents` a config file can't hold, if any. Anything but an option from
+/// [`ALLOWED_OPTIONS`] by its full name, and the value it takes, is refused: yt-dlp would also
+/// take short options, shortened long ones and bare words as urls. A quote left open is refused
+/// as well, as yt-dlp can't read the file.
+pub fn forbidden_option(contents: &str) -> Option<String> {
+    let words = match split(contents) {
+        Ok(words) => words,
+        Err(unfinished) => return Some(unfinished),
+    };
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        let (option, value) = match word.split_once('=') {
+            Some((option, value)) => (option, Some(value)),
+            None => (word.as_str(), None),
+        };
+        match ALLOWED_OPTIONS
+            .iter()
+            .find(|(allowed, _)| *allowed == option)
+        {
+            Some((_, true)) => {
+                if value.is_none() {
+                    words.next();
+                }
+            }
+            Some((_, false)) if value.is_none() => {}
+            _ => return Some(option.to_string()),
+        }
+    }
+    None
+}
+
+/// Splits `contents` into words the way yt-dlp reads config files, with Python's `shlex` in
+/// POSIX mode: quotes and backslashes group and escape, and `#` outside quotes comments out the
+/// rest of the line. A quote left open is given back as the unfinished word.
+fn split(contents: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = contents.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\r' | '\n' => {
+                words.extend(word.take());
+            }
+            '#' => {
+                words.extend(word.take());
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.next());
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(format!("'{}", word)),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) if c == '"' || c == '\\' => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(format!("\"{}", word)),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(format!("\"{}", word)),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+pub async fn list(dir: &Path) -> io::Result<Vec<ConfigFile>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut configs = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let Some(name) = path.file_stem().and_then(|name| name.to_str()).filter(|_| {
+            path.extension()
+                .is_some_and(|extension| extension == "conf")
+        }) else {
+            continue;
+        };
+        let metadata = entry.metadata().await?;
+        configs.push(ConfigFile {
+            name: name.to_string(),
+            size: metadata.len(),
+            modified_at: metadata.modified().ok().map(DateTime::from),
+        });
+    }
+    configs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(configs)
+}
+
+/// Checks and writes a config file, replacing one with the same name.
+pub async fn save(dir: &Path, name: &str, contents: &str) -> Result<(), ConfigError> {
+    if let Some(option) = forbidden_option(contents) {
+        return Err(ConfigError::Forbidden(option));
+    }
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(ConfigError::Io)?;
+    tokio::fs::write(path(dir, name), contents)
+        .await
+        .map_err(ConfigError::Io)
+}
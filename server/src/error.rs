@@ -1,26 +1,33 @@
-// use axum::{
-//     http::StatusCode,
-//     response::{IntoResponse, Response},
-// };
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 
-// #[derive(Debug)]
-// pub enum Error {
-//     Io(String),
-//     SomeError,
-// }
+use crate::core::messages::Message;
 
-// impl From<std::io::Error> for Error {
-//     fn from(err: std::io::Error) -> Self {
-//         Error::Io(err.to_string())
-//     }
-// }
+/// An error response, sent as the message key, its parameters and the rendered text.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: Message,
+}
 
-// impl IntoResponse for Error {
-//     fn into_response(self) -> Response {
-//         let body = match self {
-//             Error::Io(s) => s,
-//             Error::SomeError => "Some error occured.".to_string(),
-//         };
-//         (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
-//     }
-// }
+impl ApiError {
+    pub fn new(status: StatusCode, message: Message) -> ApiError {
+        ApiError { status, message }
+    }
+
+    pub fn internal(err: impl ToString) -> ApiError {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Message::new("internal").with("error", err),
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.message)).into_response()
+    }
+}
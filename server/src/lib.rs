@@ -1,6 +1,176 @@
-use tracing::{error, trace};
+use chrono::Utc;
+use sqlx::migrate::{Migration, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{error, info, trace, warn};
 
-pub async fn create_default_config(db: &sqlx::SqlitePool) {
+use crate::core::backup;
+
+pub mod api;
+pub mod core;
+pub mod error;
+
+/// The migrations built into this version.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Listings and other heavy reads go through `read` so they never queue behind writes.
+#[derive(Clone)]
+pub struct Database {
+    pub read: SqlitePool,
+    pub write: SqlitePool,
+    /// The database file, for backups and restores.
+    pub path: PathBuf,
+}
+
+/// What happens at boot when the image ships migrations the database hasn't run yet.
+#[derive(Clone, Debug)]
+pub struct MigrationSettings {
+    /// Copy the database file aside before migrating it.
+    pub backup: bool,
+    /// Stop instead of migrating, for operators who run migrations by hand.
+    pub refuse_pending: bool,
+}
+
+impl Default for MigrationSettings {
+    fn default() -> Self {
+        MigrationSettings {
+            backup: true,
+            refuse_pending: false,
+        }
+    }
+}
+
+/// How every connection is tuned, each field sets the SQLite pragma of the same name.
+#[derive(Clone, Debug)]
+pub struct PragmaSettings {
+    /// How long a write waits on a lock held elsewhere before failing with `database is locked`.
+    pub busy_timeout: Duration,
+    /// WAL lets the read pool carry on while a download writes its status.
+    pub journal_mode: SqliteJournalMode,
+    pub synchronous: SqliteSynchronous,
+}
+
+impl Default for PragmaSettings {
+    fn default() -> Self {
+        PragmaSettings {
+            busy_timeout: Duration::from_secs(5),
+            journal_mode: SqliteJournalMode::Wal,
+            synchronous: SqliteSynchronous::Normal,
+        }
+    }
+}
+
+impl Database {
+    /// Opens both pools, creating the database and running migrations on the writer first.
+    /// Only SQLite is supported, the queries are checked against its schema at build time.
+    pub async fn connect(
+        db_url: &str,
+        read_connections: u32,
+        migrations: &MigrationSettings,
+        pragmas: &PragmaSettings,
+    ) -> Database {
+        // Anything else would be read as a file name, the scheme alone keeps credentials out of the log.
+        if !db_url.starts_with("sqlite:") {
+            let scheme = db_url.split_once(':').map_or(db_url, |(scheme, _)| scheme);
+            panic!(
+                "DB_URL has to be a sqlite url like sqlite://sqlite.db, {} databases aren't supported",
+                scheme
+            );
+        }
+        let options = SqliteConnectOptions::from_str(db_url)
+            .unwrap()
+            .journal_mode(pragmas.journal_mode)
+            .synchronous(pragmas.synchronous)
+            .busy_timeout(pragmas.busy_timeout);
+        if !matches!(pragmas.journal_mode, SqliteJournalMode::Wal) {
+            warn!("DB_JOURNAL_MODE isn't wal, reads will wait on writes and can hit `database is locked`");
+        }
+        let path = options.get_filename().to_path_buf();
+        backup::apply_staged(&path);
+        // A single writer avoids SQLITE_BUSY between our own connections.
+        let write = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.clone().create_if_missing(true))
+            .await
+            .expect("could create/connect with to the sqlite database.");
+        prepare_migrations(&write, &MIGRATOR, &path, migrations).await;
+        MIGRATOR
+            .run(&write)
+            .await
+            .expect("failed to run migrations on db.");
+        create_default_config(&write).await;
+        let read = SqlitePoolOptions::new()
+            .max_connections(read_connections.max(1))
+            .connect_with(options.read_only(true).pragma("cache_size", "-16000"))
+            .await
+            .expect("could connect to the sqlite database for reads.");
+
+        Database { read, write, path }
+    }
+}
+
+/// Logs the migrations about to run, then refuses or backs up as configured. A brand new
+/// database has nothing to lose so it is always migrated.
+async fn prepare_migrations(
+    db: &SqlitePool,
+    migrator: &Migrator,
+    path: &Path,
+    settings: &MigrationSettings,
+) {
+    // The table doesn't exist until the first migration, which reads as nothing applied.
+    let applied: HashSet<i64> = sqlx::query_scalar("SELECT version FROM _sqlx_migrations")
+        .fetch_all(db)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let pending: Vec<&Migration> = migrator
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect();
+
+    if pending.is_empty() || applied.is_empty() {
+        return;
+    }
+    for migration in &pending {
+        info!(
+            "pending migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+
+    if settings.refuse_pending {
+        panic!(
+            "{} migrations are pending and REFUSE_PENDING_MIGRATIONS is set, run them before starting",
+            pending.len()
+        );
+    }
+    if settings.backup {
+        backup_database(db, path).await;
+    }
+}
+
+/// Writes a consistent copy of the database next to it, named after the time it was taken.
+async fn backup_database(db: &SqlitePool, path: &Path) {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}.bak", Utc::now().format("%Y%m%d%H%M%S")));
+    let backup = PathBuf::from(backup);
+
+    match backup::snapshot(db, &backup).await {
+        Ok(()) => info!(
+            "backed up the database before migrating to: {}",
+            backup.display()
+        ),
+        Err(err) => panic!("failed to back up the database before migrating: {}", err),
+    }
+}
+
+pub async fn create_default_config(db: &SqlitePool) {
     match sqlx::query!(
         r#"INSERT INTO Config (
             id,
@@ -2,46 +2,270 @@ use axum::{
     http::{HeaderName, Method},
     Router,
 };
+use chrono::NaiveTime;
+use chrono_tz::Tz;
 use serde::Deserialize;
-use server::create_default_config;
-use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
-use std::{io::Error, str::FromStr};
+use server::api;
+use server::core::{clock, messages};
+use server::{Database, MigrationSettings, PragmaSettings};
+use sqlx::sqlite::{SqliteJournalMode, SqliteSynchronous};
+use std::{io::Error, net::SocketAddr, path::Path, str::FromStr, time::Duration};
+use tokio::signal::unix::{signal, SignalKind};
 use tower_http::{
     cors::{Any, CorsLayer},
     services::ServeDir,
 };
-use tracing::Level;
+use tracing::{info, Level};
+use url::Url;
 
-mod api;
-mod core;
-mod error;
+use server::core::logs::LogLimits;
+use server::core::transcode::{IoClass, IoLimits};
+use server::core::upgrade::ScanSettings;
+use server::core::worker_mode::{self, WorkerSettings};
+use server::core::ytdlp::ClientSettings;
 
 // <----- Args - Environmental Variables ----->
 
 #[derive(Deserialize, Debug)]
 struct Args {
+    #[serde(default = "default_checkpoint_interval_secs")]
+    checkpoint_interval_secs: u64,
+    #[serde(default = "default_cookies_dir")]
+    cookies_dir: String,
+    #[serde(default = "default_db_busy_timeout_ms")]
+    db_busy_timeout_ms: u64,
+    #[serde(default = "default_db_journal_mode")]
+    db_journal_mode: String,
     #[serde(default = "default_db_url")]
     db_url: String,
+    #[serde(default = "default_db_read_connections")]
+    db_read_connections: u32,
+    #[serde(default = "default_db_synchronous")]
+    db_synchronous: String,
+    #[serde(default)]
+    debug_endpoints: bool,
+    #[serde(default = "default_download_archive_path")]
+    download_archive_path: String,
     #[serde(default = "default_download_location")]
     download_location: String,
+    #[serde(default = "default_event_buffer_size")]
+    event_buffer_size: usize,
+    #[serde(default = "default_ffmpeg_path")]
+    ffmpeg_path: String,
+    #[serde(default = "default_ffprobe_path")]
+    ffprobe_path: String,
+    instance_name: Option<String>,
     #[serde(default = "default_log_level")]
     log_level: String,
+    #[serde(default = "default_log_max_bytes")]
+    log_max_bytes: u64,
+    #[serde(default = "default_log_max_lines")]
+    log_max_lines: u64,
+    #[serde(default = "default_log_tail_lines")]
+    log_tail_lines: usize,
+    #[serde(default = "default_logs_max_total_bytes")]
+    logs_max_total_bytes: u64,
+    #[serde(default = "default_max_concurrent_downloads")]
+    max_concurrent_downloads: usize,
+    #[serde(default = "default_max_download_attempts")]
+    max_download_attempts: u32,
+    message_catalog_dir: Option<String>,
+    #[serde(default = "default_migration_backup")]
+    migration_backup: bool,
+    #[serde(default = "default_message_locale")]
+    message_locale: String,
+    #[serde(default = "default_options_check_url")]
+    options_check_url: String,
+    postprocess_io_class: Option<IoClass>,
+    postprocess_read_rate: Option<f64>,
+    #[serde(default)]
+    preview_frames: u32,
+    #[serde(default = "default_probe_timeout_secs")]
+    probe_timeout_secs: u64,
+    #[serde(default)]
+    public_status: bool,
+    #[serde(default)]
+    refuse_pending_migrations: bool,
+    #[serde(default = "default_request_timeout_secs")]
+    request_timeout_secs: u64,
+    #[serde(default = "default_resume_interrupted")]
+    resume_interrupted: bool,
+    #[serde(default = "default_retry_backoff_secs")]
+    retry_backoff_secs: u64,
+    #[serde(default = "default_slow_request_timeout_secs")]
+    slow_request_timeout_secs: u64,
+    #[serde(default)]
+    stall_retry: bool,
+    #[serde(default = "default_stall_timeout_secs")]
+    stall_timeout_secs: u64,
+    #[serde(default = "default_throttle_cooldown_secs")]
+    throttle_cooldown_secs: u64,
+    #[serde(default = "default_timezone")]
+    timezone: String,
+    #[serde(default)]
+    upgrade_auto_enqueue: bool,
+    upgrade_max_bytes: Option<f64>,
+    #[serde(default)]
+    upgrade_min_improvement_percent: f64,
+    #[serde(default = "default_upgrade_scan_delay_secs")]
+    upgrade_scan_delay_secs: u64,
+    upgrade_scan_at: Option<String>,
+    upgrade_scan_interval_hours: Option<u64>,
+    #[serde(default = "default_websocket_max_connections")]
+    websocket_max_connections: usize,
+    #[serde(default = "default_websocket_max_per_ip")]
+    websocket_max_per_ip: usize,
+    #[serde(default = "default_worker_max_jobs")]
+    worker_max_jobs: usize,
+    worker_name: Option<String>,
+    worker_primary_url: Option<String>,
+    worker_token: Option<String>,
+    #[serde(default = "default_ytdlp_config_dir")]
+    ytdlp_config_dir: String,
     #[serde(default = "default_ytdlp_path")]
     ytdlp_path: String,
 }
 
+fn default_checkpoint_interval_secs() -> u64 {
+    30
+}
+
+fn default_cookies_dir() -> String {
+    String::from("cookies")
+}
+
+fn default_db_busy_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_db_journal_mode() -> String {
+    String::from("wal")
+}
+
 fn default_db_url() -> String {
     String::from("sqlite://sqlite.db")
 }
 
+fn default_db_read_connections() -> u32 {
+    4
+}
+
+fn default_db_synchronous() -> String {
+    String::from("normal")
+}
+
+fn default_download_archive_path() -> String {
+    String::from("download-archive.txt")
+}
+
 fn default_download_location() -> String {
     String::from("/downloads/")
 }
 
+fn default_event_buffer_size() -> usize {
+    LogLimits::default().event_buffer
+}
+
+fn default_ffmpeg_path() -> String {
+    String::from("ffmpeg")
+}
+
+fn default_ffprobe_path() -> String {
+    String::from("ffprobe")
+}
+
 fn default_log_level() -> String {
     String::from("info")
 }
 
+fn default_log_max_bytes() -> u64 {
+    LogLimits::default().max_bytes
+}
+
+fn default_log_max_lines() -> u64 {
+    LogLimits::default().max_lines
+}
+
+fn default_log_tail_lines() -> usize {
+    LogLimits::default().tail_lines
+}
+
+fn default_logs_max_total_bytes() -> u64 {
+    LogLimits::default().total_max_bytes
+}
+
+fn default_max_concurrent_downloads() -> usize {
+    3
+}
+
+fn default_max_download_attempts() -> u32 {
+    3
+}
+
+fn default_message_locale() -> String {
+    String::from("en")
+}
+
+fn default_migration_backup() -> bool {
+    true
+}
+
+fn default_options_check_url() -> String {
+    String::from("https://www.youtube.com/watch?v=jNQXAC9IVRw")
+}
+
+fn default_probe_timeout_secs() -> u64 {
+    30
+}
+
+fn default_request_timeout_secs() -> u64 {
+    60
+}
+
+fn default_resume_interrupted() -> bool {
+    true
+}
+
+fn default_retry_backoff_secs() -> u64 {
+    10
+}
+
+fn default_slow_request_timeout_secs() -> u64 {
+    30 * 60
+}
+
+fn default_stall_timeout_secs() -> u64 {
+    10 * 60
+}
+
+fn default_throttle_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_timezone() -> String {
+    String::from("UTC")
+}
+
+fn default_upgrade_scan_delay_secs() -> u64 {
+    5
+}
+
+fn default_websocket_max_connections() -> usize {
+    256
+}
+
+fn default_websocket_max_per_ip() -> usize {
+    16
+}
+
+fn default_worker_max_jobs() -> usize {
+    1
+}
+
+fn default_ytdlp_config_dir() -> String {
+    String::from("ytdlp-configs")
+}
+
 fn default_ytdlp_path() -> String {
     String::from("yt-dlp")
 }
@@ -63,32 +287,158 @@ async fn main() -> Result<(), Error> {
         )
         .init();
 
-    let options = SqliteConnectOptions::from_str(&args.db_url)
-        .unwrap()
-        .create_if_missing(true);
-    let db = SqlitePool::connect_with(options)
-        .await
-        .expect("could create/connect with to the sqlite database.");
-    sqlx::migrate!("./migrations")
-        .run(&db)
-        .await
-        .expect("failed to run migrations on db.");
-    create_default_config(&db).await;
+    clock::init(Tz::from_str(&args.timezone).expect("couldn't parse timezone as an IANA name"));
+    messages::init(
+        args.message_catalog_dir.as_deref().map(Path::new),
+        &args.message_locale,
+    );
+
+    // A worker runs downloads for the primary at WORKER_PRIMARY_URL and has no database or api.
+    if let Some(primary_url) = args.worker_primary_url {
+        let settings = WorkerSettings {
+            download_path: args.download_location.into(),
+            max_jobs: args.worker_max_jobs.max(1),
+            name: args
+                .worker_name
+                .or_else(|| std::env::var("HOSTNAME").ok())
+                .unwrap_or_else(|| String::from("worker")),
+            primary_url: Url::parse(&primary_url)
+                .expect("couldn't parse worker_primary_url as a url"),
+            token: args
+                .worker_token
+                .expect("worker_token has to be set to the primary's to run as a worker"),
+            ytdlp_path: args.ytdlp_path,
+        };
+        tokio::select! {
+            _ = worker_mode::run(settings) => {}
+            _ = shutdown_requested() => {}
+        }
+        info!("worker shut down");
+        return Ok(());
+    }
+
+    let migrations = MigrationSettings {
+        backup: args.migration_backup,
+        refuse_pending: args.refuse_pending_migrations,
+    };
+    let pragmas = PragmaSettings {
+        busy_timeout: Duration::from_millis(args.db_busy_timeout_ms),
+        journal_mode: SqliteJournalMode::from_str(&args.db_journal_mode).expect(
+            "couldn't parse db_journal_mode, use wal, delete, truncate, persist, memory or off",
+        ),
+        synchronous: SqliteSynchronous::from_str(&args.db_synchronous)
+            .expect("couldn't parse db_synchronous, use off, normal, full or extra"),
+    };
+    let db = Database::connect(
+        &args.db_url,
+        args.db_read_connections,
+        &migrations,
+        &pragmas,
+    )
+    .await;
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
         .allow_origin(Any)
         .allow_headers([HeaderName::from_static("content-type")]);
+    let client_settings = ClientSettings {
+        checkpoint_interval: Duration::from_secs(args.checkpoint_interval_secs.max(1)),
+        cookies_dir: args.cookies_dir.into(),
+        download_archive_path: args.download_archive_path.into(),
+        download_path: args.download_location.into(),
+        ffmpeg_path: args.ffmpeg_path,
+        ffprobe_path: args.ffprobe_path,
+        log_limits: LogLimits {
+            max_bytes: args.log_max_bytes,
+            max_lines: args.log_max_lines,
+            total_max_bytes: args.logs_max_total_bytes,
+            tail_lines: args.log_tail_lines,
+            event_buffer: args.event_buffer_size,
+        },
+        max_attempts: args.max_download_attempts.max(1),
+        max_concurrent_downloads: args.max_concurrent_downloads,
+        options_check_url: Url::parse(&args.options_check_url)
+            .expect("couldn't parse options_check_url as a url"),
+        postprocess_limits: IoLimits {
+            io_class: args.postprocess_io_class,
+            read_rate: args
+                .postprocess_read_rate
+                .filter(|read_rate| *read_rate > 0.0),
+        },
+        preview_frames: args.preview_frames,
+        probe_timeout: Duration::from_secs(args.probe_timeout_secs),
+        retry_backoff: Duration::from_secs(args.retry_backoff_secs.max(1)),
+        stall_retry: args.stall_retry,
+        stall_timeout: match args.stall_timeout_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        },
+        throttle_cooldown: Duration::from_secs(args.throttle_cooldown_secs.max(1)),
+        worker_token: args.worker_token.filter(|token| !token.is_empty()),
+        ytdlp_config_dir: args.ytdlp_config_dir.into(),
+        ytdlp_path: args.ytdlp_path,
+    };
+    let upgrade_scan = api::UpgradeScanConfig {
+        at: args.upgrade_scan_at.as_deref().map(|at| {
+            NaiveTime::parse_from_str(at, "%H:%M").expect("couldn't parse upgrade_scan_at as HH:MM")
+        }),
+        delay: Duration::from_secs(args.upgrade_scan_delay_secs),
+        interval: args
+            .upgrade_scan_interval_hours
+            .map(|hours| Duration::from_secs(hours * 60 * 60)),
+        settings: ScanSettings {
+            auto_enqueue: args.upgrade_auto_enqueue,
+            min_improvement_percent: args.upgrade_min_improvement_percent,
+            max_upgrade_bytes: args.upgrade_max_bytes,
+        },
+    };
     let static_dir = ServeDir::new("static");
+    let api_settings = api::ApiSettings {
+        debug_endpoints: args.debug_endpoints,
+        instance_name: args.instance_name,
+        public_status: args.public_status,
+        resume_interrupted: args.resume_interrupted,
+        timeouts: api::RequestTimeouts {
+            request: Duration::from_secs(args.request_timeout_secs.max(1)),
+            slow_request: Duration::from_secs(args.slow_request_timeout_secs.max(1)),
+        },
+        upgrade_scan,
+        websocket_limits: api::WebsocketLimits {
+            max_connections: args.websocket_max_connections,
+            max_per_ip: args.websocket_max_per_ip,
+        },
+    };
+    let (api, shutdown) = api::routes(db.clone(), client_settings, api_settings).await;
     let app = Router::new()
-        .nest(
-            "/api",
-            api::routes(db, args.ytdlp_path, args.download_location.into()).await,
-        )
+        .nest("/api", api)
         .fallback_service(static_dir)
         .layer(cors);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown_requested().await;
+        shutdown.run().await;
+    })
+    .await?;
+
+    db.write.close().await;
+    db.read.close().await;
+    info!("shut down cleanly");
 
     Ok(())
 }
+
+/// Resolves on the first SIGTERM, as sent by `docker stop`, or SIGINT.
+async fn shutdown_requested() {
+    let mut terminate = signal(SignalKind::terminate()).expect("couldn't listen for SIGTERM");
+    let mut interrupt = signal(SignalKind::interrupt()).expect("couldn't listen for SIGINT");
+
+    let name = tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    };
+    info!("received {}, shutting down", name);
+}
@@ -0,0 +1,321 @@
+//! Boots the api against a throwaway database and download folder, with the fake yt-dlp in
+//! `tests/fixtures` standing in for the real one so nothing touches the network.
+
+// Each test binary only uses some of the helpers.
+#![allow(dead_code)]
+
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode};
+use axum::Router;
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use server::api::{
+    self, ApiSettings, RequestTimeouts, Shutdown, UpgradeScanConfig, WebsocketLimits,
+};
+use server::core::logs::LogLimits;
+use server::core::transcode::{IoClass, IoLimits};
+use server::core::upgrade::ScanSettings;
+use server::core::ytdlp::ClientSettings;
+use server::{Database, MigrationSettings, PragmaSettings};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tower::ServiceExt;
+use url::Url;
+
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+/// What the test workers register with.
+pub const WORKER_TOKEN: &str = "test-worker-token";
+
+pub struct TestApp {
+    pub download_dir: PathBuf,
+    pub shutdown: Shutdown,
+    router: Router,
+    dir: TempDir,
+}
+
+impl TestApp {
+    pub async fn spawn() -> TestApp {
+        TestApp::boot(TempDir::new().expect("couldn't create a temp dir")).await
+    }
+
+    /// Boots a fresh api over the same database and downloads, as a container restart would.
+    /// The old api should be shut down first so its downloads don't run alongside the new ones.
+    pub async fn restart(self) -> TestApp {
+        TestApp::boot(self.dir).await
+    }
+
+    /// The files in every download's work dir, where yt-dlp keeps fragments and partial files.
+    pub fn work_files(&self) -> Vec<PathBuf> {
+        std::fs::read_dir(self.download_dir.join(".tmp"))
+            .into_iter()
+            .flatten()
+            .filter_map(|work_dir| std::fs::read_dir(work_dir.ok()?.path()).ok())
+            .flatten()
+            .filter_map(|file| Some(file.ok()?.path()))
+            .collect()
+    }
+
+    /// Whether some download's work dir holds `name`.
+    pub fn has_work_file(&self, name: &str) -> bool {
+        self.work_files()
+            .iter()
+            .any(|file| file.file_name().is_some_and(|file_name| file_name == name))
+    }
+
+    /// Where the stored yt-dlp config files live.
+    pub fn ytdlp_config_dir(&self) -> PathBuf {
+        self.dir.path().join("ytdlp-configs")
+    }
+
+    /// Serves the api on a local port, for clients that need a real connection like workers.
+    pub async fn serve(&self) -> Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("couldn't bind a local port");
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let router = self.router.clone();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+
+    pub fn db_url(&self) -> String {
+        format!("sqlite://{}", self.dir.path().join("test.db").display())
+    }
+
+    async fn boot(dir: TempDir) -> TestApp {
+        let download_dir = dir.path().join("downloads");
+        std::fs::create_dir_all(&download_dir).expect("couldn't create the download dir");
+
+        let db_url = format!("sqlite://{}", dir.path().join("test.db").display());
+        let db = Database::connect(
+            &db_url,
+            2,
+            &MigrationSettings::default(),
+            &PragmaSettings::default(),
+        )
+        .await;
+        let settings = ClientSettings {
+            checkpoint_interval: Duration::from_secs(1),
+            cookies_dir: dir.path().join("cookies"),
+            download_archive_path: dir.path().join("download-archive.txt"),
+            download_path: download_dir.clone(),
+            ffmpeg_path: fake_ffmpeg_path(),
+            ffprobe_path: fake_ffprobe_path(),
+            log_limits: LogLimits {
+                max_bytes: 1_000_000,
+                max_lines: 200,
+                total_max_bytes: 8_000,
+                tail_lines: 50,
+                event_buffer: 100,
+            },
+            max_attempts: 3,
+            max_concurrent_downloads: 2,
+            options_check_url: Url::parse(&fake_url("options-check", "")).unwrap(),
+            postprocess_limits: IoLimits {
+                io_class: Some(IoClass::Idle),
+                read_rate: Some(4.0),
+            },
+            preview_frames: 4,
+            probe_timeout: Duration::from_secs(5),
+            retry_backoff: Duration::from_millis(50),
+            stall_retry: true,
+            stall_timeout: Some(Duration::from_millis(400)),
+            throttle_cooldown: Duration::from_millis(300),
+            worker_token: Some(String::from(WORKER_TOKEN)),
+            ytdlp_config_dir: dir.path().join("ytdlp-configs"),
+            ytdlp_path: fake_ytdlp_path(),
+        };
+        let upgrade_scan = UpgradeScanConfig {
+            at: None,
+            delay: Duration::ZERO,
+            interval: None,
+            settings: ScanSettings::default(),
+        };
+
+        let api_settings = ApiSettings {
+            debug_endpoints: false,
+            instance_name: Some(String::from("test")),
+            public_status: true,
+            resume_interrupted: false,
+            timeouts: RequestTimeouts {
+                request: Duration::from_secs(2),
+                slow_request: Duration::from_secs(30),
+            },
+            upgrade_scan,
+            websocket_limits: WebsocketLimits {
+                max_connections: 8,
+                max_per_ip: 4,
+            },
+        };
+        let (api, shutdown) = api::routes(db, settings, api_settings).await;
+        TestApp {
+            download_dir,
+            shutdown,
+            router: Router::new().nest("/api", api),
+            dir,
+        }
+    }
+
+    pub async fn delete(&self, path: &str) -> (StatusCode, Value) {
+        self.request(Method::DELETE, path, None).await
+    }
+
+    pub async fn get(&self, path: &str) -> (StatusCode, Value) {
+        self.request(Method::GET, path, None).await
+    }
+
+    /// Fetches a body that isn't json, along with its content type.
+    pub async fn get_bytes(&self, path: &str) -> (StatusCode, String, Vec<u8>) {
+        let request = Request::builder()
+            .uri(path)
+            .body(Body::empty())
+            .expect("couldn't build the request");
+        let response = self
+            .router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("router failed");
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let bytes = response
+            .into_body()
+            .collect()
+            .await
+            .expect("couldn't read the body")
+            .to_bytes();
+
+        (status, content_type, bytes.to_vec())
+    }
+
+    pub async fn post(&self, path: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::POST, path, Some(body)).await
+    }
+
+    /// Posts a body that isn't json, e.g. a file upload.
+    pub async fn post_bytes(&self, path: &str, body: Vec<u8>) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header("content-type", "application/octet-stream")
+            .body(Body::from(body))
+            .expect("couldn't build the request");
+        self.send(request).await
+    }
+
+    pub async fn put(&self, path: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::PUT, path, Some(body)).await
+    }
+
+    /// Puts a plain text body, e.g. a config file.
+    pub async fn put_text(&self, path: &str, body: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(path)
+            .header("content-type", "text/plain")
+            .body(Body::from(body.to_string()))
+            .expect("couldn't build the request");
+        self.send(request).await
+    }
+
+    /// Submits a download of `url` saved under `name`.
+    pub async fn submit(&self, url: &str, name: &str) -> StatusCode {
+        let (status, _) = self
+            .post(
+                "/api/download",
+                json!({
+                    "url": url,
+                    "options": { "container": "mp4", "name_format": name, "quality": "720" },
+                }),
+            )
+            .await;
+        status
+    }
+
+    /// Polls the download list until `url` matches `done`, panicking after a few seconds.
+    pub async fn wait_for(&self, url: &str, done: impl Fn(&Value) -> bool) -> Value {
+        let started = Instant::now();
+        loop {
+            let (_, downloads) = self.get("/api/download").await;
+            let download = downloads
+                .as_array()
+                .and_then(|downloads| downloads.iter().find(|download| download["url"] == url));
+            if let Some(download) = download.filter(|download| done(download)) {
+                return download.clone();
+            }
+            assert!(
+                started.elapsed() < WAIT_TIMEOUT,
+                "timed out waiting on {}, last seen: {:?}",
+                url,
+                download
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    pub async fn wait_for_status(&self, url: &str, status: &str) -> Value {
+        self.wait_for(url, |download| download["status"] == status)
+            .await
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder().method(method).uri(path);
+        let request = match body {
+            Some(body) => request
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .expect("couldn't build the request");
+        self.send(request).await
+    }
+
+    async fn send(&self, request: Request<Body>) -> (StatusCode, Value) {
+        let response = self
+            .router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("router failed");
+        let status = response.status();
+        let bytes = response
+            .into_body()
+            .collect()
+            .await
+            .expect("couldn't read the body")
+            .to_bytes();
+
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+}
+
+/// A url the fake yt-dlp accepts, scripted by `query`. See the fixture for the options.
+pub fn fake_url(name: &str, query: &str) -> String {
+    format!("https://fake.test/{}?{}", name, query)
+}
+
+pub fn fake_ytdlp_path() -> String {
+    format!("{}/tests/fixtures/fake-ytdlp", env!("CARGO_MANIFEST_DIR"))
+}
+
+fn fake_ffmpeg_path() -> String {
+    format!("{}/tests/fixtures/fake-ffmpeg", env!("CARGO_MANIFEST_DIR"))
+}
+
+fn fake_ffprobe_path() -> String {
+    format!("{}/tests/fixtures/fake-ffprobe", env!("CARGO_MANIFEST_DIR"))
+}
@@ -0,0 +1,1930 @@
+mod common;
+
+use axum::http::StatusCode;
+use chrono::{TimeDelta, Utc};
+use common::{fake_url, fake_ytdlp_path, TestApp, WORKER_TOKEN};
+use serde_json::{json, Value};
+use server::core::worker_mode::{self, WorkerSettings};
+
+#[tokio::test]
+async fn download_runs_to_completion() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("complete", "steps=3");
+
+    assert_eq!(app.submit(&url, "complete").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+
+    assert_eq!(download["attempts"], 1);
+    assert_eq!(download["format_id"], "137+140");
+    assert_eq!(download["title"], "Fake video");
+    assert_eq!(download["uploader"], "Fake Channel");
+    assert_eq!(download["duration_secs"], 212.5);
+    assert_eq!(download["upload_date"], "2024-01-02");
+    assert_eq!(download["thumbnail_url"], "https://fake.test/complete.jpg");
+    assert!(app.download_dir.join("complete.mp4").exists());
+    assert!(!app.download_dir.join("complete.f137.mp4.part").exists());
+    // The work dir goes once the download is done with it.
+    assert!(app.work_files().is_empty(), "{:?}", app.work_files());
+
+    let (status, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(detail["progress"]["percent"], "100.0");
+    assert_eq!(detail["attempt_history"].as_array().map(Vec::len), Some(1));
+    assert_eq!(detail["media"]["video_codec"], "h264");
+    assert_eq!(detail["media"]["height"], 1080);
+
+    let (_, not_h264) = app.get("/api/download?not_video_codec=h264").await;
+    assert_eq!(not_h264, json!([]));
+    let (_, h264) = app.get("/api/download?video_codec=H264").await;
+    assert_eq!(h264.as_array().map(Vec::len), Some(1));
+}
+
+#[tokio::test]
+async fn canceled_download_cleans_up_partial_files() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("cancel", "steps=200&delay=0.05");
+
+    assert_eq!(app.submit(&url, "cancel").await, StatusCode::CREATED);
+    // The part file is written before yt-dlp reports a format.
+    app.wait_for(&url, |download| !download["format_id"].is_null())
+        .await;
+    assert!(app.has_work_file("cancel.f137.mp4.part"));
+
+    let (status, _) = app.post("/api/download/cancel", json!(url)).await;
+    assert_eq!(status, StatusCode::OK);
+    // The status flips as soon as the signal is sent, finished_at is set once cleanup is done.
+    app.wait_for(&url, |download| {
+        download["status"] == "Canceled" && !download["finished_at"].is_null()
+    })
+    .await;
+
+    let leftovers: Vec<_> = std::fs::read_dir(&app.download_dir)
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().contains("cancel"))
+        .collect();
+    assert!(leftovers.is_empty(), "left behind: {:?}", leftovers);
+    assert!(app.work_files().is_empty(), "{:?}", app.work_files());
+}
+
+#[tokio::test]
+async fn transient_failure_is_retried() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("flaky", "steps=2&fail=HTTP+Error+503&fail_times=1");
+
+    assert_eq!(app.submit(&url, "flaky").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+
+    assert_eq!(download["attempts"], 2);
+    assert_eq!(download["last_error"], "HTTP Error 503");
+    assert!(download["failed_at"].is_null());
+}
+
+#[tokio::test]
+async fn throttled_domains_are_held_back_until_they_recover() {
+    let app = TestApp::spawn().await;
+    let url = fake_url(
+        "throttled",
+        "steps=2&fail=HTTP+Error+429:+Too+Many+Requests&fail_times=1",
+    );
+
+    assert_eq!(app.submit(&url, "throttled").await, StatusCode::CREATED);
+    let started = std::time::Instant::now();
+    let throttles = loop {
+        let (_, throttles) = app.get("/api/download/queue/throttles").await;
+        if throttles != json!([]) {
+            break throttles;
+        }
+        assert!(
+            started.elapsed().as_secs() < 5,
+            "fake.test wasn't throttled"
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    };
+    assert_eq!(throttles[0]["domain"], "fake.test");
+    assert_eq!(throttles[0]["max_running"], 1);
+    assert_eq!(throttles[0]["strikes"], 1);
+
+    // The retry waits out the cooldown, and going through lifts the throttle.
+    let download = app.wait_for_status(&url, "Completed").await;
+    assert_eq!(download["attempts"], 2);
+    let (_, throttles) = app.get("/api/download/queue/throttles").await;
+    assert_eq!(throttles, json!([]));
+}
+
+#[tokio::test]
+async fn permanent_failure_is_not_retried() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("gone", "steps=2&fail=HTTP+Error+404");
+
+    assert_eq!(app.submit(&url, "gone").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Failed").await;
+
+    assert_eq!(download["attempts"], 1);
+    assert_eq!(download["retries_exhausted"], false);
+    assert!(!download["failed_at"].is_null());
+    assert_eq!(download["failure_cause"], "not_found");
+
+    // Why it failed outlives the process.
+    let app = app.restart().await;
+    let restored = app.wait_for_status(&url, "Failed").await;
+    assert_eq!(restored["last_error"], "HTTP Error 404");
+    assert_eq!(restored["failure_cause"], "not_found");
+    assert_eq!(restored["failed_at"], download["failed_at"]);
+}
+
+#[tokio::test]
+async fn failures_are_sorted_by_cause() {
+    let app = TestApp::spawn().await;
+    let private = fake_url("private", "steps=2&fail=Private+video");
+    let other = fake_url("other", "steps=2&fail=Postprocessing:+Conversion+failed");
+
+    assert_eq!(app.submit(&private, "private").await, StatusCode::CREATED);
+    assert_eq!(app.submit(&other, "other").await, StatusCode::CREATED);
+
+    let private = app.wait_for_status(&private, "Failed").await;
+    assert_eq!(private["failure_cause"], "private");
+    assert_eq!(private["attempts"], 1);
+    let other = app.wait_for_status(&other, "Failed").await;
+    assert_eq!(other["failure_cause"], "other");
+}
+
+#[tokio::test]
+async fn unavailable_url_is_rejected() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("missing", "unavailable=1");
+
+    assert_eq!(app.submit(&url, "missing").await, StatusCode::BAD_REQUEST);
+    let (_, downloads) = app.get("/api/download").await;
+    assert_eq!(downloads, json!([]));
+}
+
+#[tokio::test]
+async fn batch_rejects_repeats() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("batch", "steps=1");
+    let options = json!({ "container": "mp4", "name_format": "batch", "quality": "720" });
+
+    let (status, results) = app
+        .post(
+            "/api/download/batch",
+            json!([{ "url": url, "options": options }, { "url": url, "options": options }]),
+        )
+        .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(results[0]["accepted"], true);
+    assert_eq!(results[1]["accepted"], false);
+    assert_eq!(results[1]["reason"]["key"], "download.duplicate");
+    app.wait_for_status(&url, "Completed").await;
+}
+
+#[tokio::test]
+async fn scheduled_download_waits_for_its_start_time() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("later", "steps=1");
+    let start_at = Utc::now() + TimeDelta::milliseconds(500);
+
+    let (status, _) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": url,
+                "options": { "container": "mp4", "name_format": "later", "quality": "720" },
+                "start_at": start_at,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let download = app.wait_for_status(&url, "Scheduled").await;
+    assert_eq!(download["attempts"], 0);
+    app.wait_for_status(&url, "Completed").await;
+    assert!(Utc::now() >= start_at);
+}
+
+#[tokio::test]
+async fn invalid_rate_limit_is_rejected() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("limited", "steps=1");
+
+    let (status, error) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": url,
+                "options": {
+                    "container": "mp4",
+                    "name_format": "limited",
+                    "quality": "720",
+                    "rate_limit": "fast",
+                },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "rate_limit.invalid");
+
+    let (status, _) = app
+        .post("/api/config/rate-limit", json!({ "rate_limit": "500K" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.submit(&url, "limited").await, StatusCode::CREATED);
+    app.wait_for_status(&url, "Completed").await;
+}
+
+#[tokio::test]
+async fn running_download_restarts_when_its_rate_limit_changes() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("throttled", "steps=100&delay=0.05");
+
+    assert_eq!(app.submit(&url, "throttled").await, StatusCode::CREATED);
+    let download = app
+        .wait_for(&url, |download| !download["format_id"].is_null())
+        .await;
+
+    let (status, _) = app
+        .post(
+            "/api/config/bandwidth",
+            json!({ "windows": [{ "start": "00:00:00", "end": "00:00:00", "rate_limit": "1M" }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let detail_path = format!("/api/download/{}", download["id"]);
+    let started = std::time::Instant::now();
+    loop {
+        let (_, detail) = app.get(&detail_path).await;
+        let log_tail = detail["log_tail"].as_array().cloned().unwrap_or_default();
+        if log_tail.contains(&json!("[fake] rate limit: 1M")) {
+            assert_eq!(detail["attempts"], 1);
+            break;
+        }
+        assert!(started.elapsed().as_secs() < 5, "yt-dlp wasn't restarted");
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    app.post("/api/download/cancel", json!(url)).await;
+}
+
+#[tokio::test]
+async fn matching_policy_remuxes_completed_download() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("remux", "steps=1");
+
+    let (status, _) = app
+        .post(
+            "/api/policy",
+            json!({ "video_codec": "h264", "action": "remux", "target": "mkv" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    assert_eq!(app.submit(&url, "remux").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+
+    assert_eq!(download["media"]["container"], "mkv");
+    assert!(app.download_dir.join("remux.mkv").exists());
+    assert!(!app.download_dir.join("remux.mp4").exists());
+
+    // Both the remux and yt-dlp's merge are held to the post-processing io limits.
+    let remuxed = std::fs::read_to_string(app.download_dir.join("remux.mkv")).unwrap();
+    assert!(remuxed.contains("readrate=4"), "{}", remuxed);
+    assert!(remuxed.contains("io_class=idle"), "{}", remuxed);
+    let (_, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    let log_tail = detail["log_tail"].as_array().cloned().unwrap_or_default();
+    assert!(log_tail.contains(&json!(
+        "[fake] postprocessor args: Merger+ffmpeg_i:-readrate 4"
+    )));
+}
+
+#[tokio::test]
+async fn subtitles_are_converted_alongside_the_originals() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("subbed", "steps=1");
+
+    let (status, _) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": url,
+                "options": {
+                    "container": "mp4",
+                    "name_format": "subbed",
+                    "quality": "720",
+                    "subtitle_format": "srt",
+                },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+
+    let (_, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    let sidecars = detail["sidecars"].to_string();
+    assert!(sidecars.contains("subbed.en.vtt"), "{}", sidecars);
+    assert!(sidecars.contains("subbed.en.srt"), "{}", sidecars);
+}
+
+#[tokio::test]
+async fn chapters_are_split_into_tagged_tracks() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("album", "steps=1");
+
+    let (status, _) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": url,
+                "options": {
+                    "container": "mp4",
+                    "name_format": "album",
+                    "quality": "720",
+                    "split_chapters": true,
+                },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    app.wait_for_status(&url, "Completed").await;
+
+    let album = app.download_dir.join("Fake video");
+    let outro = std::fs::read_to_string(album.join("02 - Outro.mp4")).unwrap();
+    assert!(outro.contains("track=2/2"), "{}", outro);
+    assert!(outro.contains("album=Fake video"), "{}", outro);
+    assert!(outro.contains("title=Outro"), "{}", outro);
+    assert!(album.join("01 - Intro.mp4").exists());
+}
+
+#[tokio::test]
+async fn shutdown_interrupts_running_downloads_and_refuses_new_ones() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("shutdown", "steps=200&delay=0.05");
+
+    assert_eq!(app.submit(&url, "shutdown").await, StatusCode::CREATED);
+    app.wait_for(&url, |download| !download["format_id"].is_null())
+        .await;
+    app.shutdown.run().await;
+
+    let download = app.wait_for_status(&url, "Interrupted").await;
+    assert!(download["finished_at"].is_string());
+    assert!(app.has_work_file("shutdown.f137.mp4.part"));
+
+    let late = fake_url("late", "steps=1");
+    assert_eq!(
+        app.submit(&late, "late").await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+}
+
+#[tokio::test]
+async fn downloads_left_running_by_a_crash_resume_at_startup() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("crash", "steps=200&delay=0.05");
+
+    assert_eq!(app.submit(&url, "crash").await, StatusCode::CREATED);
+    app.wait_for(&url, |download| !download["format_id"].is_null())
+        .await;
+    let (status, _) = app.post("/api/config/auto-resume/true", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    app.shutdown.run().await;
+
+    // A crash leaves the row as it was last checkpointed.
+    let db = sqlx::SqlitePool::connect(&app.db_url()).await.unwrap();
+    sqlx::query("UPDATE Download SET status = 'Running', finished_at = NULL")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let app = app.restart().await;
+    let download = app
+        .wait_for(&url, |download| {
+            download["status"] == "Running" && !download["format_id"].is_null()
+        })
+        .await;
+    assert_eq!(download["last_error"], "interrupted by a server restart");
+    assert_eq!(download["title"], "Fake video");
+
+    let (_, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    let log_tail = detail["log_tail"].to_string();
+    assert!(
+        log_tail.contains("continuing from partial files"),
+        "{}",
+        log_tail
+    );
+}
+
+#[tokio::test]
+async fn extracted_audio_is_tagged_from_the_template() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("audio", "steps=1");
+    let options = |tag_template: serde_json::Value| {
+        json!({
+            "url": url,
+            "options": {
+                "container": "mp4",
+                "name_format": "audio",
+                "quality": "720",
+                "audio_format": "mp3",
+                "tag_template": tag_template,
+            },
+        })
+    };
+
+    let (status, body) = app
+        .post("/api/download", options(json!({ "Artist": "%(channel)s" })))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["key"], "tags.invalid_name");
+
+    let (status, _) = app
+        .post(
+            "/api/download",
+            options(json!({ "artist": "%(channel)s", "date": "" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+    assert!(app.download_dir.join("audio.mp3").exists());
+
+    let (_, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    let log_tail = detail["log_tail"].to_string();
+    assert!(
+        log_tail.contains("%(channel)s:%(meta_artist)s"),
+        "{}",
+        log_tail
+    );
+    assert!(
+        log_tail.contains("%(track,title)s:%(meta_title)s"),
+        "{}",
+        log_tail
+    );
+    assert!(!log_tail.contains("meta_date"), "{}", log_tail);
+}
+
+#[tokio::test]
+async fn other_links_to_the_same_video_are_rejected() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("watch", "id=abc123&steps=200&delay=0.05");
+
+    assert_eq!(app.submit(&url, "watch").await, StatusCode::CREATED);
+    let download = app
+        .wait_for(&url, |download| download["status"] == "Running")
+        .await;
+    assert_eq!(download["video_id"], "Fake:abc123");
+
+    let short_link = fake_url("short", "id=abc123&t=30");
+    let (status, body) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": short_link,
+                "options": { "container": "mp4", "name_format": "short", "quality": "720" },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["key"], "download.same_video");
+    assert_eq!(body["params"]["url"], url);
+    assert_eq!(body["params"]["id"], download["id"].to_string());
+}
+
+#[tokio::test]
+async fn links_to_the_same_video_sent_together_only_start_once() {
+    let app = TestApp::spawn().await;
+    let submit = |url: String| {
+        app.post(
+            "/api/download",
+            json!({
+                "url": url,
+                "options": { "container": "mp4", "name_format": "same", "quality": "720" },
+            }),
+        )
+    };
+
+    let ((first, _), (second, _)) = tokio::join!(
+        submit(fake_url("watch", "id=def456&steps=200&delay=0.05")),
+        submit(fake_url("short", "id=def456"))
+    );
+    let mut statuses = [first, second];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::CREATED, StatusCode::CONFLICT]);
+}
+
+#[tokio::test]
+async fn videos_without_a_thumbnail_get_a_storyboard() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("preview", "steps=1");
+
+    assert_eq!(app.submit(&url, "preview").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+    assert!(app.download_dir.join("preview.preview.jpg").exists());
+
+    let (status, content_type, _) = app
+        .get_bytes(&format!("/api/download/{}/thumbnail", download["id"]))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "image/jpeg");
+
+    let (status, _, _) = app.get_bytes("/api/download/9999/thumbnail").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn duplicates_are_marked_when_allowed() {
+    let app = TestApp::spawn().await;
+    let (status, _) = app
+        .post(
+            "/api/config/duplicates",
+            json!({ "policy": "allow", "perceptual_hash": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let original = fake_url("original", "id=v1&steps=1");
+    assert_eq!(app.submit(&original, "original").await, StatusCode::CREATED);
+    let original = app.wait_for_status(&original, "Completed").await;
+    assert!(original["duplicate_of"].is_null());
+
+    // Same video id under another url, then a different id with an identical frame.
+    let share_link = fake_url("share", "id=v1&steps=1");
+    let mirror = fake_url("mirror", "id=v2&steps=1");
+    for (url, name) in [(&share_link, "share"), (&mirror, "mirror")] {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+        let download = app.wait_for_status(url, "Completed").await;
+        assert_eq!(download["duplicate_of"], original["id"], "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn archived_videos_are_skipped() {
+    let app = TestApp::spawn().await;
+    let (status, _) = app.post("/api/config/archive/true", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let first = fake_url("first", "id=a1&steps=1");
+    assert_eq!(app.submit(&first, "first").await, StatusCode::CREATED);
+    app.wait_for_status(&first, "Completed").await;
+    assert!(app.download_dir.join("first.mp4").exists());
+    let (status, archive) = app.get("/api/archive").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(archive, json!(["fake a1"]));
+
+    let (status, body) = app.put("/api/archive", json!(["fake a1", "b2"])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["key"], "archive.invalid_entry");
+    let (status, _) = app.put("/api/archive", json!(["fake a1", "fake b2"])).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let archived = fake_url("archived", "id=b2&steps=1");
+    assert_eq!(app.submit(&archived, "archived").await, StatusCode::CREATED);
+    app.wait_for_status(&archived, "Completed").await;
+    assert!(!app.download_dir.join("archived.mp4").exists());
+}
+
+#[tokio::test]
+async fn submitted_urls_are_stored_in_canonical_form() {
+    let app = TestApp::spawn().await;
+    // The fake takes the video id from the path, which every watch url shares.
+    let (status, _) = app
+        .post("/api/config/duplicates", json!({ "policy": "allow" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let tracked = fake_url("tracked", "steps=1&utm_source=feed&fbclid=abc");
+    assert_eq!(app.submit(&tracked, "tracked").await, StatusCode::CREATED);
+    app.wait_for_status(&fake_url("tracked", "steps=1"), "Completed")
+        .await;
+
+    let short = "https://youtu.be/dQw4w9WgXcQ?si=share&t=42";
+    assert_eq!(app.submit(short, "short").await, StatusCode::CREATED);
+    app.wait_for_status(
+        "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42",
+        "Completed",
+    )
+    .await;
+
+    let shorts = "https://m.youtube.com/shorts/jNQXAC9IVRw?feature=share";
+    assert_eq!(app.submit(shorts, "shorts").await, StatusCode::CREATED);
+    app.wait_for_status("https://www.youtube.com/watch?v=jNQXAC9IVRw", "Completed")
+        .await;
+}
+
+#[tokio::test]
+async fn shorts_and_clips_follow_the_short_form_policy() {
+    let app = TestApp::spawn().await;
+
+    // Rewritten by default, the clip to the video the check traced it back to.
+    let clip = "https://www.youtube.com/clip/UgkxFakeClip?id=jNQXAC9IVRw";
+    assert_eq!(app.submit(clip, "clip").await, StatusCode::CREATED);
+    app.wait_for_status("https://www.youtube.com/watch?v=jNQXAC9IVRw", "Completed")
+        .await;
+
+    let shorts = "https://www.youtube.com/shorts/dQw4w9WgXcQ";
+    let (status, _) = app.post("/api/config/short-form/reject", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": shorts,
+                "options": { "container": "mp4", "name_format": "shorts", "quality": "720" },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["key"], "url.short_form");
+
+    let (status, _) = app.post("/api/config/short-form/keep", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.submit(shorts, "shorts").await, StatusCode::CREATED);
+    app.wait_for_status(shorts, "Completed").await;
+}
+
+#[tokio::test]
+async fn header_rules_are_sent_to_matching_domains() {
+    let app = TestApp::spawn().await;
+    let rules = [
+        json!({ "user_agent": "Global/1.0", "headers": { "X-Everywhere": "1" } }),
+        json!({ "domain": "fake.test", "user_agent": "Fake/2.0" }),
+        json!({ "domain": "other.test", "headers": { "X-Other": "1" } }),
+    ];
+    for rule in rules {
+        let (status, _) = app.post("/api/headers", rule).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let (status, body) = app
+        .post(
+            "/api/headers",
+            json!({ "domain": "https://fake.test/", "user_agent": "Nope" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["key"], "headers.invalid_domain");
+
+    let url = fake_url("headers", "steps=1");
+    assert_eq!(app.submit(&url, "headers").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+    let (_, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    let log_tail = detail["log_tail"].as_array().cloned().unwrap_or_default();
+    assert!(log_tail.contains(&json!("[fake] user agent: Fake/2.0")));
+    assert!(log_tail.contains(&json!("[fake] header: X-Everywhere:1")));
+    assert!(!log_tail.contains(&json!("[fake] header: X-Other:1")));
+}
+
+#[tokio::test]
+async fn stalled_downloads_are_flagged_then_retried() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("stuck", "steps=2&stall_times=1");
+
+    assert_eq!(app.submit(&url, "stuck").await, StatusCode::CREATED);
+    app.wait_for_status(&url, "Stalled").await;
+    let download = app.wait_for_status(&url, "Completed").await;
+
+    assert_eq!(download["attempts"], 2);
+    let last_error = download["last_error"].as_str().unwrap_or_default();
+    assert!(
+        last_error.starts_with("stalled with no output"),
+        "{}",
+        last_error
+    );
+}
+
+#[tokio::test]
+async fn downloads_impersonate_a_supported_browser() {
+    let app = TestApp::spawn().await;
+    let (status, body) = app.get("/api/config/impersonate").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["targets"], json!(["chrome-124"]));
+
+    let (status, body) = app
+        .post("/api/config/impersonate", json!({ "target": "safari" }))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["key"], "impersonate.unknown_target");
+    let (status, _) = app
+        .post("/api/config/impersonate", json!({ "target": "chrome" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let url = fake_url("impersonated", "steps=1");
+    assert_eq!(app.submit(&url, "impersonated").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+    let (_, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    let log_tail = detail["log_tail"].as_array().cloned().unwrap_or_default();
+    assert!(log_tail.contains(&json!("[fake] impersonating: chrome")));
+}
+
+#[tokio::test]
+async fn downloads_past_their_time_limit_are_timed_out() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("slow", "steps=200&delay=0.05");
+    let submit = |max_duration_secs: serde_json::Value| {
+        json!({
+            "url": url,
+            "options": {
+                "container": "mp4",
+                "name_format": "slow",
+                "quality": "720",
+                "max_duration_secs": max_duration_secs,
+            },
+        })
+    };
+
+    let (status, body) = app.post("/api/download", submit(json!(0))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["key"], "download.invalid_max_duration");
+
+    let (status, _) = app.post("/api/download", submit(json!(1))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "TimedOut").await;
+    assert_eq!(download["last_error"], "ran past its limit of 1s");
+    assert!(app.has_work_file("slow.f137.mp4.part"));
+
+    // Submitting it again picks up the partial files.
+    let (status, _) = app.post("/api/download", submit(json!(null))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Running").await;
+    let detail_path = format!("/api/download/{}", download["id"]);
+    let started = std::time::Instant::now();
+    loop {
+        let (_, detail) = app.get(&detail_path).await;
+        let log_tail = detail["log_tail"].as_array().cloned().unwrap_or_default();
+        if log_tail.contains(&json!("[fake] continuing from partial files")) {
+            break;
+        }
+        assert!(
+            started.elapsed().as_secs() < 5,
+            "partial files weren't picked up"
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    app.post("/api/download/cancel", json!(url)).await;
+}
+
+#[tokio::test]
+async fn history_pages_through_finished_downloads() {
+    let app = TestApp::spawn().await;
+    let urls: Vec<String> = ["first", "second", "third"]
+        .iter()
+        .map(|name| fake_url(name, "steps=1"))
+        .collect();
+    for (url, name) in urls.iter().zip(["first", "second", "third"]) {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+        app.wait_for_status(url, "Completed").await;
+    }
+    let gone = fake_url("gone", "fail=HTTP+Error+404");
+    assert_eq!(app.submit(&gone, "gone").await, StatusCode::CREATED);
+    app.wait_for_status(&gone, "Failed").await;
+
+    let (status, page) = app
+        .get("/api/download/history?status=Completed&per_page=2")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 3);
+    assert_eq!(page["downloads"][0]["url"], urls[2]);
+    assert_eq!(page["downloads"][1]["url"], urls[1]);
+
+    let (_, page) = app
+        .get("/api/download/history?status=Completed&per_page=2&page=2")
+        .await;
+    assert_eq!(page["downloads"].as_array().map(Vec::len), Some(1));
+    assert_eq!(page["downloads"][0]["url"], urls[0]);
+
+    let (_, page) = app.get("/api/download/history?sort=oldest").await;
+    assert_eq!(page["total"], 4);
+    assert_eq!(page["downloads"][0]["url"], urls[0]);
+    assert_eq!(page["downloads"][3]["url"], gone);
+
+    let (status, _) = app.get("/api/download/history?status=Running").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn downloads_sharing_a_name_keep_their_partial_files_apart() {
+    let app = TestApp::spawn().await;
+    let urls = [
+        fake_url("first-upload", "steps=200&delay=0.05"),
+        fake_url("second-upload", "steps=200&delay=0.05"),
+    ];
+    for url in &urls {
+        assert_eq!(app.submit(url, "same-title").await, StatusCode::CREATED);
+        app.wait_for(url, |download| !download["format_id"].is_null())
+            .await;
+    }
+
+    let parts: Vec<_> = app
+        .work_files()
+        .into_iter()
+        .filter(|file| file.ends_with("same-title.f137.mp4.part"))
+        .collect();
+    assert_eq!(parts.len(), 2, "{:?}", parts);
+    assert_ne!(parts[0].parent(), parts[1].parent());
+
+    for url in &urls {
+        app.post("/api/download/cancel", json!(url)).await;
+        app.wait_for(url, |download| !download["finished_at"].is_null())
+            .await;
+    }
+    assert!(app.work_files().is_empty(), "{:?}", app.work_files());
+}
+
+#[tokio::test]
+async fn tagged_downloads_land_in_their_tag_subdirectory() {
+    let app = TestApp::spawn().await;
+    let (status, _) = app
+        .post(
+            "/api/tags",
+            json!({ "name": "music", "subdirectory": "../music" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app
+        .post(
+            "/api/tags",
+            json!({ "name": "music", "subdirectory": "music" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let tagged = fake_url("tagged", "steps=1");
+    let (status, _) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": tagged,
+                "options": { "container": "mp4", "name_format": "tagged", "quality": "720" },
+                "tags": ["music", "favorites"],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let download = app.wait_for_status(&tagged, "Completed").await;
+    assert!(app.download_dir.join("music").join("tagged.mp4").exists());
+
+    let untagged = fake_url("untagged", "steps=1");
+    assert_eq!(app.submit(&untagged, "untagged").await, StatusCode::CREATED);
+    app.wait_for_status(&untagged, "Completed").await;
+    assert!(app.download_dir.join("untagged.mp4").exists());
+
+    let tags_path = format!("/api/download/{}/tags", download["id"]);
+    let (_, tags) = app.get(&tags_path).await;
+    assert_eq!(tags[0]["name"], "favorites");
+    assert_eq!(tags[1]["name"], "music");
+
+    let (_, page) = app.get("/api/download/history?tag=music").await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["downloads"][0]["url"], tagged);
+
+    let (status, tags) = app.put(&tags_path, json!(["lectures"])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tags.as_array().map(Vec::len), Some(1));
+    let (_, page) = app.get("/api/download/history?tag=music").await;
+    assert_eq!(page["total"], 0);
+    let (_, page) = app.get("/api/download/history?tag=lectures").await;
+    assert_eq!(page["total"], 1);
+}
+
+#[tokio::test]
+async fn deleting_a_download_can_take_its_files_with_it() {
+    let app = TestApp::spawn().await;
+    let kept = fake_url("kept", "steps=1");
+    let removed = fake_url("removed", "steps=1");
+    for (url, name) in [(&kept, "kept"), (&removed, "removed")] {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+    }
+    let kept = app.wait_for_status(&kept, "Completed").await;
+    let removed = app.wait_for_status(&removed, "Completed").await;
+    std::fs::write(app.download_dir.join("removed.en.vtt"), "WEBVTT").unwrap();
+
+    let (status, result) = app.delete(&format!("/api/download/{}", kept["id"])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["removed_files"], json!([]));
+    assert!(app.download_dir.join("kept.mp4").exists());
+
+    let path = format!("/api/download/{}?remove_files=true", removed["id"]);
+    let (status, result) = app.delete(&path).await;
+    assert_eq!(status, StatusCode::OK);
+    let removed_files = result["removed_files"].as_array().unwrap();
+    assert!(removed_files.iter().any(|file| file
+        .as_str()
+        .is_some_and(|file| file.ends_with("removed.en.vtt"))));
+    let left: Vec<_> = std::fs::read_dir(&app.download_dir)
+        .unwrap()
+        .filter_map(|file| file.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with("removed"))
+        .collect();
+    assert!(left.is_empty(), "{:?}", left);
+
+    let (_, downloads) = app.get("/api/download").await;
+    assert_eq!(downloads, json!([]));
+    let (status, _) = app.delete(&path).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let running = fake_url("running", "steps=200&delay=0.05");
+    assert_eq!(app.submit(&running, "running").await, StatusCode::CREATED);
+    let running = app.wait_for_status(&running, "Running").await;
+    let (status, _) = app
+        .delete(&format!("/api/download/{}", running["id"]))
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn deleting_a_download_leaves_files_sharing_its_name_alone() {
+    let app = TestApp::spawn().await;
+    let clip = fake_url("clip", "steps=1");
+    let other = fake_url("clip-2", "steps=1");
+    for (url, name) in [(&clip, "clip"), (&other, "clip-2")] {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+    }
+    let clip = app.wait_for_status(&clip, "Completed").await;
+    app.wait_for_status(&other, "Completed").await;
+    std::fs::write(app.download_dir.join("clip (1).webm"), "data").unwrap();
+
+    let path = format!("/api/download/{}?remove_files=true", clip["id"]);
+    let (status, _) = app.delete(&path).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!app.download_dir.join("clip.mp4").exists());
+    assert!(!app.download_dir.join("clip.info.json").exists());
+    for name in ["clip-2.mp4", "clip-2.info.json", "clip (1).webm"] {
+        assert!(app.download_dir.join(name).exists(), "{} was removed", name);
+    }
+}
+
+#[tokio::test]
+async fn completed_downloads_are_linked_into_library_folders() {
+    let app = TestApp::spawn().await;
+    let libraries = app.download_dir.parent().unwrap();
+    let archive = libraries.join("Archive");
+    let plex = libraries.join("Plex").join("YouTube");
+    let url = fake_url("linked", "steps=1");
+    let submit = |library_links| {
+        app.post(
+            "/api/download",
+            json!({
+                "url": url,
+                "options": {
+                    "container": "mp4",
+                    "name_format": "linked",
+                    "quality": "720",
+                    "library_links": library_links,
+                },
+            }),
+        )
+    };
+
+    let (status, _) = submit(json!([{ "dir": "Archive", "kind": "hardlink" }])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = submit(json!([
+        { "dir": archive, "kind": "hardlink" },
+        { "dir": plex, "kind": "symlink" },
+    ]))
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+
+    let file = app.download_dir.join("linked.mp4");
+    assert_eq!(
+        std::fs::read(archive.join("linked.mp4")).unwrap(),
+        std::fs::read(&file).unwrap()
+    );
+    assert_eq!(std::fs::read_link(plex.join("linked.mp4")).unwrap(), file);
+    let (_, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    assert_eq!(detail["library_links"].as_array().map(Vec::len), Some(2));
+
+    let (status, _) = app
+        .delete(&format!(
+            "/api/download/{}?remove_files=true",
+            download["id"]
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!file.exists());
+    assert!(!archive.join("linked.mp4").exists());
+    assert!(plex.join("linked.mp4").symlink_metadata().is_err());
+}
+
+#[tokio::test]
+async fn public_status_leaves_out_what_is_being_downloaded() {
+    let app = TestApp::spawn().await;
+    let done = fake_url("secret-done", "steps=1");
+    assert_eq!(app.submit(&done, "secret-done").await, StatusCode::CREATED);
+    app.wait_for_status(&done, "Completed").await;
+    let running = fake_url("secret-running", "steps=200&delay=0.05");
+    assert_eq!(
+        app.submit(&running, "secret-running").await,
+        StatusCode::CREATED
+    );
+    app.wait_for(&running, |download| !download["format_id"].is_null())
+        .await;
+
+    let (status, public) = app.get("/api/public/status").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(public["running"], 1);
+    assert_eq!(public["completed"], 1);
+    assert_eq!(public["progress"].as_array().map(Vec::len), Some(1));
+    assert!(!public.to_string().contains("secret"), "{}", public);
+
+    app.post("/api/download/cancel", json!(running)).await;
+}
+
+#[tokio::test]
+async fn backups_restore_over_the_database_on_the_next_start() {
+    let app = TestApp::spawn().await;
+    let kept = fake_url("kept", "steps=1");
+    assert_eq!(app.submit(&kept, "kept").await, StatusCode::CREATED);
+    app.wait_for_status(&kept, "Completed").await;
+
+    let (status, content_type, backup) = app.get_bytes("/api/admin/backup").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/vnd.sqlite3");
+
+    let later = fake_url("later", "steps=1");
+    assert_eq!(app.submit(&later, "later").await, StatusCode::CREATED);
+    app.wait_for_status(&later, "Completed").await;
+
+    let (status, error) = app
+        .post_bytes("/api/admin/restore", b"not a database".to_vec())
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "restore.not_database");
+    let (status, _) = app.post_bytes("/api/admin/restore", backup).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    app.shutdown.run().await;
+    let app = app.restart().await;
+    app.wait_for_status(&kept, "Completed").await;
+    let (_, downloads) = app.get("/api/download").await;
+    assert!(!downloads.to_string().contains("later"), "{}", downloads);
+}
+
+#[tokio::test]
+async fn slow_handlers_time_out_unless_their_route_allows_for_it() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("slow-probe", "probe_delay=3");
+
+    let (status, error) = app.post("/api/download/check", json!(url)).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(error["key"], "request.timed_out");
+
+    let (status, checks) = app.post("/api/download/check-batch", json!([url])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(checks[0]["available"], true);
+}
+
+#[tokio::test]
+async fn history_is_pruned_past_its_limits() {
+    let app = TestApp::spawn().await;
+    let mut urls = Vec::new();
+    for name in ["pruned-1", "pruned-2", "pruned-3"] {
+        let url = fake_url(name, "steps=1");
+        assert_eq!(app.submit(&url, name).await, StatusCode::CREATED);
+        app.wait_for_status(&url, "Completed").await;
+        urls.push(url);
+    }
+    app.post(
+        "/api/download/pin",
+        json!({ "url": urls[0], "pinned": true }),
+    )
+    .await;
+
+    let (status, error) = app
+        .post("/api/config/retention", json!({ "max_rows": 0 }))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "retention.invalid_limit");
+    let (status, _) = app
+        .post(
+            "/api/config/retention",
+            json!({ "max_rows": 1, "remove_files": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The oldest unpinned download goes, the pinned one stays whatever its age.
+    let started = std::time::Instant::now();
+    loop {
+        let (_, downloads) = app.get("/api/download").await;
+        if downloads.as_array().map(Vec::len) == Some(2) {
+            assert!(!downloads.to_string().contains("pruned-2"), "{}", downloads);
+            break;
+        }
+        assert!(started.elapsed().as_secs() < 5, "{}", downloads);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(app.download_dir.join("pruned-1.mp4").exists());
+    assert!(!app.download_dir.join("pruned-2.mp4").exists());
+    assert!(app.download_dir.join("pruned-3.mp4").exists());
+
+    let (_, config) = app.get("/api/config").await;
+    assert_eq!(config["history_max_rows"], 1);
+}
+
+#[tokio::test]
+async fn pruning_leaves_files_sharing_a_pruned_name_alone() {
+    let app = TestApp::spawn().await;
+    for name in ["shared", "shared-2"] {
+        let url = fake_url(name, "steps=1");
+        assert_eq!(app.submit(&url, name).await, StatusCode::CREATED);
+        app.wait_for_status(&url, "Completed").await;
+    }
+
+    let (status, _) = app
+        .post(
+            "/api/config/retention",
+            json!({ "max_rows": 1, "remove_files": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let started = std::time::Instant::now();
+    while app.download_dir.join("shared.mp4").exists() {
+        assert!(started.elapsed().as_secs() < 5, "shared wasn't pruned");
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(!app.download_dir.join("shared.info.json").exists());
+    assert!(app.download_dir.join("shared-2.mp4").exists());
+    assert!(app.download_dir.join("shared-2.info.json").exists());
+}
+
+#[tokio::test]
+async fn ytdlp_output_is_kept_for_each_download() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("logged", "steps=2&fail=HTTP+Error+404");
+    assert_eq!(app.submit(&url, "logged").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Failed").await;
+
+    let (status, content_type, log) = app
+        .get_bytes(&format!("/api/download/{}/log", download["id"]))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(content_type.starts_with("text/plain"));
+    let log = String::from_utf8(log).unwrap();
+    assert!(log.contains("--- attempt 1 at "), "{}", log);
+    assert!(log.contains("[download]"), "{}", log);
+    assert!(log.contains("ERROR: HTTP Error 404"), "{}", log);
+
+    app.delete(&format!("/api/download/{}", download["id"]))
+        .await;
+    let (status, _, _) = app
+        .get_bytes(&format!("/api/download/{}/log", download["id"]))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn starred_downloads_can_be_filtered_in_the_history() {
+    let app = TestApp::spawn().await;
+    let favorite = fake_url("favorite", "steps=1");
+    let other = fake_url("other", "steps=1");
+    for (url, name) in [(&favorite, "favorite"), (&other, "other")] {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+    }
+    let download = app.wait_for_status(&favorite, "Completed").await;
+    app.wait_for_status(&other, "Completed").await;
+    assert_eq!(download["starred"], false);
+
+    let star = format!("/api/download/{}/star", download["id"]);
+    let (_, result) = app.post(&star, json!(null)).await;
+    assert_eq!(result["starred"], true);
+    let (_, result) = app.post(&star, json!(null)).await;
+    assert_eq!(result["starred"], false);
+    let (_, result) = app.post(&star, json!(null)).await;
+    assert_eq!(result["starred"], true);
+
+    let app = app.restart().await;
+    let (_, page) = app.get("/api/download/history?starred=true").await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["downloads"][0]["url"], favorite.as_str());
+    assert_eq!(page["downloads"][0]["starred"], true);
+    let (_, page) = app.get("/api/download/history?starred=false").await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["downloads"][0]["url"], other.as_str());
+
+    let (status, error) = app.post("/api/download/999/star", json!(null)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error["key"], "download.unknown");
+}
+
+#[tokio::test]
+async fn formats_are_listed_for_a_quality_picker() {
+    let app = TestApp::spawn().await;
+
+    let (status, listing) = app
+        .post(
+            "/api/download/formats",
+            json!(fake_url("picker", "formats=1")),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listing["title"], "Fake video");
+    let ids: Vec<_> = listing["formats"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|format| format["format_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, ["140", "136", "248"]);
+    let hd = &listing["formats"][1];
+    assert_eq!(hd["width"], 1280);
+    assert_eq!(hd["height"], 720);
+    assert_eq!(hd["vcodec"], "avc1.4d401f");
+    assert_eq!(hd["filesize_approx"], 31000000.0);
+
+    let (status, error) = app
+        .post(
+            "/api/download/formats",
+            json!(fake_url("gone", "unavailable=1")),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(error["key"], "download.formats_failed");
+}
+
+#[tokio::test]
+async fn quality_container_and_name_format_reach_ytdlp() {
+    let app = TestApp::spawn().await;
+    let submit = |quality: &str, container: &str, name_format: &str| {
+        json!({
+            "url": fake_url("picked", "steps=1"),
+            "options": { "container": container, "name_format": name_format, "quality": quality },
+        })
+    };
+
+    for (request, key) in [
+        (submit("high", "mp4", "clip"), "download.invalid_quality"),
+        (submit("720p", "exe", "clip"), "download.invalid_container"),
+        (
+            submit("720p", "mp4", "../clip"),
+            "download.invalid_name_format",
+        ),
+        (
+            submit("720p", "mp4", "/tmp/clip"),
+            "download.invalid_name_format",
+        ),
+    ] {
+        let (status, error) = app.post("/api/download", request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["key"], key);
+    }
+
+    // As the web client sends them.
+    let (status, _) = app
+        .post("/api/download", submit("1080p", "MKV", "picked/%(title)s"))
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let url = fake_url("picked", "steps=1");
+    let download = app.wait_for_status(&url, "Completed").await;
+
+    let (_, _, log) = app
+        .get_bytes(&format!("/api/download/{}/log", download["id"]))
+        .await;
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.contains("format: bestvideo[height<=1080]+bestaudio/best[height<=1080]/best"),
+        "{}",
+        log
+    );
+    assert!(log.contains("merge output format: mkv"), "{}", log);
+    assert!(app.download_dir.join("picked").is_dir());
+}
+
+#[tokio::test]
+async fn downloads_run_with_a_stored_ytdlp_config() {
+    let app = TestApp::spawn().await;
+
+    let (status, error) = app
+        .put_text("/api/ytdlp-configs/sneaky", "--embed-chapters\n--exec rm\n")
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "ytdlp_config.forbidden_option");
+    assert_eq!(error["params"]["option"], "--exec");
+    let (status, _) = app
+        .put_text("/api/ytdlp-configs/../escape", "--embed-chapters\n")
+        .await;
+    assert_ne!(status, StatusCode::OK);
+    // yt-dlp reads quotes, takes shortened options and treats bare words as urls.
+    let sneaky = [
+        ("\"--exec\" rm\n", "--exec"),
+        ("'--ex'ec rm\n", "--exec"),
+        ("--exe rm\n", "--exe"),
+        ("--netrc-cmd=id\n", "--netrc-cmd"),
+        ("-o /etc/passwd\n", "-o"),
+        ("--embed-chapters=yes\n", "--embed-chapters"),
+        (
+            "--sub-langs en https://fake.test/other\n",
+            "https://fake.test/other",
+        ),
+        ("--embed-chapters # \"\n\"--exec\"\n", "--exec"),
+        ("--user-agent \"unfinished\n", "\"unfinished\n"),
+    ];
+    for (contents, option) in sneaky {
+        let (status, error) = app
+            .post_bytes("/api/ytdlp-configs/validate", contents.into())
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", contents);
+        assert_eq!(error["params"]["option"], option, "{}", contents);
+    }
+    let (status, _) = app
+        .post_bytes(
+            "/api/ytdlp-configs/validate",
+            "--sub-langs \"en,de\" --retries 3 --user-agent '--exec'\n".into(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A config written behind the api's back is checked before yt-dlp reads it.
+    std::fs::create_dir_all(app.ytdlp_config_dir()).unwrap();
+    std::fs::write(app.ytdlp_config_dir().join("planted.conf"), "--exec rm\n").unwrap();
+    let (status, error) = app
+        .post(
+            "/api/download/options/validate",
+            json!({ "options": {
+                "container": "mp4",
+                "name_format": "planted",
+                "quality": "best",
+                "ytdlp_config": "planted",
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["params"]["option"], "--exec");
+    std::fs::remove_file(app.ytdlp_config_dir().join("planted.conf")).unwrap();
+
+    let (status, _) = app
+        .put_text(
+            "/api/ytdlp-configs/chapters",
+            "# Keeps chapter markers\n--embed-chapters\n",
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, configs) = app.get("/api/ytdlp-configs").await;
+    assert_eq!(configs.as_array().unwrap().len(), 1);
+    assert_eq!(configs[0]["name"], "chapters");
+
+    let url = fake_url("configured", "steps=1");
+    let (status, _) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": url,
+                "options": {
+                    "container": "mp4",
+                    "name_format": "configured",
+                    "quality": "best",
+                    "ytdlp_config": "chapters",
+                },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+
+    let (_, _, log) = app
+        .get_bytes(&format!("/api/download/{}/log", download["id"]))
+        .await;
+    let log = String::from_utf8(log).unwrap();
+    assert!(log.contains("chapters.conf"), "{}", log);
+}
+
+#[tokio::test]
+async fn only_allowed_extra_args_reach_ytdlp() {
+    let app = TestApp::spawn().await;
+    let submit = |extra_args: Value| {
+        json!({
+            "url": fake_url("extras", "steps=1"),
+            "options": {
+                "container": "mp4",
+                "name_format": "extras",
+                "quality": "best",
+                "extra_args": extra_args,
+            },
+        })
+    };
+
+    let (status, error) = app
+        .post(
+            "/api/download",
+            submit(json!(["--embed-thumbnail", "--exec"])),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "download.invalid_extra_arg");
+    assert_eq!(error["params"]["arg"], "--exec");
+
+    let (status, _) = app
+        .post(
+            "/api/download",
+            submit(json!(["--embed-thumbnail", "--no-mtime"])),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let url = fake_url("extras", "steps=1");
+    let download = app.wait_for_status(&url, "Completed").await;
+    assert_eq!(
+        download["options"]["extra_args"],
+        json!(["--embed-thumbnail", "--no-mtime"])
+    );
+
+    let (_, _, log) = app
+        .get_bytes(&format!("/api/download/{}/log", download["id"]))
+        .await;
+    let log = String::from_utf8(log).unwrap();
+    assert!(log.contains("extra arg: --embed-thumbnail"), "{}", log);
+    assert!(log.contains("extra arg: --no-mtime"), "{}", log);
+}
+
+#[tokio::test]
+async fn options_are_dry_run_before_they_are_saved() {
+    let app = TestApp::spawn().await;
+    let options = json!({ "container": "mp4", "name_format": "checked", "quality": "720p" });
+
+    let (status, simulation) = app
+        .post(
+            "/api/download/options/validate",
+            json!({ "options": options }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(simulation["valid"], true, "{}", simulation);
+    assert!(simulation["url"]
+        .as_str()
+        .unwrap()
+        .contains("options-check"));
+
+    let with_subs = json!({
+        "container": "mp4",
+        "name_format": "checked",
+        "quality": "720p",
+        "subtitle_format": "srt",
+    });
+    let (_, simulation) = app
+        .post(
+            "/api/download/options/validate",
+            json!({ "options": with_subs }),
+        )
+        .await;
+    assert_eq!(simulation["valid"], true, "{}", simulation);
+    assert_eq!(
+        simulation["warnings"],
+        json!(["There are no subtitles for the requested languages"])
+    );
+
+    app.put_text("/api/ytdlp-configs/broken", "--retries bogus\n")
+        .await;
+    let broken = json!({
+        "container": "mp4",
+        "name_format": "checked",
+        "quality": "720p",
+        "ytdlp_config": "broken",
+    });
+    let (status, simulation) = app
+        .post(
+            "/api/download/options/validate",
+            json!({ "options": broken, "url": fake_url("checked", "") }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(simulation["valid"], false);
+    assert_eq!(
+        simulation["errors"],
+        json!(["invalid retry count \"bogus\" specified"])
+    );
+
+    let (status, error) = app
+        .post(
+            "/api/download/options/validate",
+            json!({ "options": { "container": "exe", "name_format": "checked", "quality": "720p" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "download.invalid_container");
+}
+
+#[tokio::test]
+async fn downloads_send_stored_cookies() {
+    let app = TestApp::spawn().await;
+
+    let (status, error) = app
+        .put_text("/api/cookies/example.com", "not a cookie\n")
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "cookies.invalid_line");
+    assert_eq!(error["params"]["line"], "1");
+
+    let cookies = "# Netscape HTTP Cookie File\n\
+        .fake.test\tTRUE\t/\tTRUE\t0\tsession\tabc\n\
+        #HttpOnly_.fake.test\tTRUE\t/\tTRUE\t0\tlogin\tdef\n";
+    let (status, _) = app.put_text("/api/cookies/fake.test", cookies).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, files) = app.get("/api/cookies").await;
+    assert_eq!(files[0]["name"], "fake.test");
+    assert_eq!(files[0]["cookies"], 2);
+    assert_eq!(files[0]["domains"], json!(["fake.test"]));
+
+    let url = fake_url("members-only", "steps=1");
+    let (status, _) = app
+        .post(
+            "/api/download",
+            json!({
+                "url": url,
+                "options": {
+                    "container": "mp4",
+                    "name_format": "members-only",
+                    "quality": "best",
+                    "cookies": "fake.test",
+                },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+    let (_, _, log) = app
+        .get_bytes(&format!("/api/download/{}/log", download["id"]))
+        .await;
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.contains("cookies: ") && log.contains("fake.test.txt"),
+        "{}",
+        log
+    );
+
+    let (status, _) = app.delete("/api/cookies/fake.test").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.delete("/api/cookies/fake.test").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn downloads_read_cookies_from_the_configured_browser() {
+    let app = TestApp::spawn().await;
+    let profile = tempfile::tempdir().unwrap();
+
+    let (status, error) = app
+        .post(
+            "/api/config/cookies-from-browser",
+            json!({ "browser": "netscape" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "cookies.unknown_browser");
+
+    let spec = format!("firefox:{}", profile.path().display());
+    let (status, error) = app
+        .post(
+            "/api/config/cookies-from-browser",
+            json!({ "browser": spec }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "cookies.browser_profile_missing");
+
+    std::fs::write(profile.path().join("cookies.sqlite"), b"").unwrap();
+    let (status, _) = app
+        .post(
+            "/api/config/cookies-from-browser",
+            json!({ "browser": spec }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, check) = app.get("/api/config/cookies-from-browser").await;
+    assert_eq!(check["browser"], "firefox");
+    assert_eq!(check["readable"], true);
+
+    let url = fake_url("browser-cookies", "steps=1");
+    app.submit(&url, "browser-cookies").await;
+    let download = app.wait_for_status(&url, "Completed").await;
+    let (_, _, log) = app
+        .get_bytes(&format!("/api/download/{}/log", download["id"]))
+        .await;
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.contains(&format!("cookies from browser: {}", spec)),
+        "{}",
+        log
+    );
+}
+
+#[tokio::test]
+async fn downloads_go_through_the_configured_or_their_own_proxy() {
+    let app = TestApp::spawn().await;
+
+    let (status, error) = app
+        .post(
+            "/api/config/proxy",
+            json!({ "proxy_url": "ftp://10.0.0.2" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "proxy.invalid");
+    let (status, _) = app
+        .post(
+            "/api/config/proxy",
+            json!({ "proxy_url": "socks5://10.0.0.2:1080" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let cases = [
+        ("configured", None, Some("socks5://10.0.0.2:1080")),
+        (
+            "own",
+            Some("http://10.0.0.3:8080"),
+            Some("http://10.0.0.3:8080"),
+        ),
+        ("direct", Some(""), None),
+    ];
+    for (name, proxy, expected) in cases {
+        let url = fake_url(name, "steps=1");
+        let (status, _) = app
+            .post(
+                "/api/download",
+                json!({
+                    "url": url,
+                    "options": {
+                        "container": "mp4",
+                        "name_format": name,
+                        "quality": "best",
+                        "proxy": proxy,
+                    },
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let download = app.wait_for_status(&url, "Completed").await;
+        let (_, _, log) = app
+            .get_bytes(&format!("/api/download/{}/log", download["id"]))
+            .await;
+        let log = String::from_utf8(log).unwrap();
+        match expected {
+            Some(expected) => assert!(log.contains(&format!("proxy: {}", expected)), "{}", log),
+            None => assert!(!log.contains("proxy: "), "{}", log),
+        }
+    }
+}
+
+#[tokio::test]
+async fn downloads_fake_the_configured_or_their_own_country() {
+    let app = TestApp::spawn().await;
+
+    let (status, error) = app
+        .post("/api/config/geo-bypass", json!({ "country": "USA" }))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "geo_bypass.invalid_country");
+    let (status, _) = app
+        .post("/api/config/geo-bypass", json!({ "country": "US" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let cases = [
+        ("configured", None, "xff: US"),
+        ("own", Some("DE"), "xff: DE"),
+        ("off", Some(""), "xff: never"),
+    ];
+    for (name, country, expected) in cases {
+        let url = fake_url(name, "steps=1");
+        let (status, _) = app
+            .post(
+                "/api/download",
+                json!({
+                    "url": url,
+                    "options": {
+                        "container": "mp4",
+                        "name_format": name,
+                        "quality": "best",
+                        "geo_bypass_country": country,
+                    },
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let download = app.wait_for_status(&url, "Completed").await;
+        let (_, _, log) = app
+            .get_bytes(&format!("/api/download/{}/log", download["id"]))
+            .await;
+        let log = String::from_utf8(log).unwrap();
+        assert!(log.contains(expected), "{}", log);
+    }
+}
+
+#[tokio::test]
+async fn downloads_list_filters_by_creation_time_size_and_domain() {
+    let app = TestApp::spawn().await;
+    let before = Utc::now() - TimeDelta::minutes(1);
+    let fake = fake_url("filtered", "steps=1");
+    let other = String::from("https://clips.other.test/other?steps=1");
+    for (url, name) in [(&fake, "filtered"), (&other, "other")] {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+        app.wait_for_status(url, "Completed").await;
+    }
+    let after = Utc::now() + TimeDelta::minutes(1);
+
+    let urls = |downloads: Value| {
+        let mut urls: Vec<String> = downloads
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|download| download["url"].as_str().unwrap().to_string())
+            .collect();
+        urls.sort();
+        urls
+    };
+    let both = vec![other.clone(), fake.clone()];
+    let cases = [
+        (
+            format!("created_after={}", before.format("%FT%TZ")),
+            both.clone(),
+        ),
+        (format!("created_after={}", after.format("%FT%TZ")), vec![]),
+        (
+            format!("created_before={}", before.format("%FT%TZ")),
+            vec![],
+        ),
+        // The fake writes five bytes.
+        (String::from("min_size=5&max_size=5"), both.clone()),
+        (String::from("min_size=6"), vec![]),
+        (String::from("domain=fake.test"), vec![fake.clone()]),
+        (String::from("domain=other.test"), vec![other.clone()]),
+        (String::from("domain=ther.test"), vec![]),
+        (String::from("domain=fake.test&min_size=6"), vec![]),
+    ];
+    for (query, expected) in cases {
+        let (status, downloads) = app.get(&format!("/api/download?{}", query)).await;
+        assert_eq!(status, StatusCode::OK, "{}", query);
+        assert_eq!(urls(downloads), expected, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn oversized_logs_are_capped_and_trashed() {
+    let app = TestApp::spawn().await;
+
+    let chatty = fake_url("chatty", "steps=300&delay=0");
+    app.submit(&chatty, "chatty").await;
+    let chatty = app.wait_for_status(&chatty, "Completed").await;
+    let quiet = fake_url("quiet", "steps=1");
+    app.submit(&quiet, "quiet").await;
+    let quiet = app.wait_for_status(&quiet, "Completed").await;
+
+    let chatty_log = format!("/api/download/{}/log", chatty["id"]);
+    let (_, _, log) = app.get_bytes(&chatty_log).await;
+    let log = String::from_utf8(log).unwrap();
+    assert_eq!(log.lines().count(), 200);
+    assert!(log.ends_with("--- log is full, later output is left out ---\n"));
+
+    // Both logs together are past the total the test app allows, so the older one goes.
+    let (status, _) = app.post("/api/jobs/log-retention/run", json!(null)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while app.get_bytes(&chatty_log).await.0 != StatusCode::NOT_FOUND {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "the log wasn't trashed"
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let (status, _, _) = app
+        .get_bytes(&format!("/api/download/{}/log", quiet["id"]))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let db = sqlx::SqlitePool::connect(&app.db_url()).await.unwrap();
+    let progress: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM DownloadProgress")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(progress, 0);
+}
+
+#[tokio::test]
+async fn instance_stats_keep_their_id_across_restarts() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("counted", "steps=1");
+    app.submit(&url, "counted").await;
+    app.wait_for_status(&url, "Completed").await;
+
+    let (status, stats) = app.get("/api/instance").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stats["schema"], 1);
+    assert_eq!(stats["instance"]["name"], "test");
+    assert_eq!(stats["instance"]["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(stats["downloads"]["total"], 1);
+    assert_eq!(stats["downloads"]["completed"], 1);
+    assert!(stats["storage"]["available_bytes"].is_u64());
+    let (_, public) = app.get("/api/public/instance").await;
+    assert_eq!(public["instance"]["id"], stats["instance"]["id"]);
+
+    app.shutdown.run().await;
+    let app = app.restart().await;
+    let (_, restarted) = app.get("/api/instance").await;
+    assert_eq!(restarted["instance"]["id"], stats["instance"]["id"]);
+}
+
+#[tokio::test]
+async fn downloads_run_on_a_registered_worker() {
+    let app = TestApp::spawn().await;
+    let (status, error) = app
+        .post(
+            "/api/workers/register",
+            json!({ "name": "intruder", "max_jobs": 1 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error["key"], "worker.unauthorized");
+
+    let worker_dir = tempfile::tempdir().unwrap();
+    tokio::spawn(worker_mode::run(WorkerSettings {
+        download_path: worker_dir.path().to_path_buf(),
+        max_jobs: 1,
+        name: String::from("remote"),
+        primary_url: app.serve().await,
+        token: String::from(WORKER_TOKEN),
+        ytdlp_path: fake_ytdlp_path(),
+    }));
+    let started = std::time::Instant::now();
+    loop {
+        let (_, workers) = app.get("/api/workers").await;
+        if workers[0]["name"] == "remote" {
+            break;
+        }
+        assert!(
+            started.elapsed().as_secs() < 5,
+            "the worker didn't register"
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    let url = fake_url("remote", "steps=3");
+    app.submit(&url, "remote").await;
+    let download = app.wait_for_status(&url, "Completed").await;
+    assert_eq!(download["worker"], "remote");
+    assert_eq!(
+        std::fs::read_to_string(app.download_dir.join("remote.mp4")).unwrap(),
+        "data\n"
+    );
+    let (_, detail) = app.get(&format!("/api/download/{}", download["id"])).await;
+    assert_eq!(detail["progress"]["percent"], "100.0");
+    assert!(app.work_files().is_empty(), "{:?}", app.work_files());
+    // The worker clears its copy once the primary has the files.
+    let started = std::time::Instant::now();
+    while std::fs::read_dir(worker_dir.path())
+        .unwrap()
+        .next()
+        .is_some()
+    {
+        assert!(started.elapsed().as_secs() < 5, "the worker kept its files");
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn upgrades_fetch_a_better_copy_into_the_same_download() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("upgrade", "steps=1&formats=1");
+
+    assert_eq!(app.submit(&url, "upgrade").await, StatusCode::CREATED);
+    let download = app.wait_for_status(&url, "Completed").await;
+    let id = download["id"].clone();
+    app.put(&format!("/api/download/{}/tags", id), json!(["keep"]))
+        .await;
+    app.post(&format!("/api/download/{}/star", id), json!(null))
+        .await;
+
+    let (status, report) = app.post("/api/download/upgrade", json!(url)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["upgradeable"], true);
+    let app = app.restart().await;
+    let download = app.wait_for_status(&url, "Completed").await;
+    assert_eq!(download["upgradeable"], true);
+
+    let (status, scan) = app
+        .post(
+            "/api/download/upgrade/scan",
+            json!({ "auto_enqueue": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(scan["enqueued"], json!([url]));
+    let download = app
+        .wait_for(&url, |download| {
+            download["status"] == "Completed" && download["upgradeable"] == false
+        })
+        .await;
+    assert_eq!(download["id"], id);
+    assert_eq!(download["starred"], true);
+    assert_eq!(download["attempts"], 2);
+
+    let (_, tags) = app.get(&format!("/api/download/{}/tags", id)).await;
+    assert_eq!(tags[0]["name"], "keep");
+    let (_, detail) = app.get(&format!("/api/download/{}", id)).await;
+    let log_tail = detail["log_tail"].to_string();
+    assert!(
+        log_tail.contains("[fake] extra arg: --force-overwrites"),
+        "{}",
+        log_tail
+    );
+    assert!(app.download_dir.join("upgrade.mp4").exists());
+}
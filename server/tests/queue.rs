@@ -0,0 +1,195 @@
+mod common;
+
+use axum::http::StatusCode;
+use chrono::{TimeDelta, Utc};
+use common::{fake_url, TestApp};
+use serde_json::json;
+
+#[tokio::test]
+async fn reordered_downloads_trade_places() {
+    let app = TestApp::spawn().await;
+    // Both worker slots are held for the length of the test.
+    for name in ["busy-1", "busy-2"] {
+        let url = fake_url(name, "steps=200&delay=0.05");
+        assert_eq!(app.submit(&url, name).await, StatusCode::CREATED);
+        app.wait_for_status(&url, "Running").await;
+    }
+    let queued: Vec<String> = ["a", "b", "c"]
+        .iter()
+        .map(|name| fake_url(name, "steps=1"))
+        .collect();
+    for (url, name) in queued.iter().zip(["a", "b", "c"]) {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+        app.wait_for(url, |download| !download["queue_position"].is_null())
+            .await;
+    }
+
+    let (status, queue) = app
+        .post(
+            "/api/download/queue/reorder",
+            json!({ "urls": [queued[2], queued[0]] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let order: Vec<&str> = queue
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|download| download["url"].as_str())
+        .collect();
+    assert_eq!(order, [&queued[2], &queued[1], &queued[0]]);
+
+    let (status, error) = app
+        .post(
+            "/api/download/queue/reorder",
+            json!({ "urls": [fake_url("busy-1", "steps=200&delay=0.05")] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error["key"], "queue.not_queued");
+}
+
+#[tokio::test]
+async fn bulk_pause_resume_and_cancel() {
+    let app = TestApp::spawn().await;
+    let urls: Vec<String> = ["bulk-1", "bulk-2", "bulk-3"]
+        .iter()
+        .map(|name| fake_url(name, "steps=200&delay=0.05"))
+        .collect();
+    for (url, name) in urls.iter().zip(["bulk-1", "bulk-2", "bulk-3"]) {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+        app.wait_for(url, |download| download["status"] != "Checking")
+            .await;
+    }
+
+    let (_, paused) = app.post("/api/download/pause-all", json!(null)).await;
+    for url in &urls {
+        assert_eq!(paused[url]["status"], "Paused");
+        app.wait_for(url, |download| !download["finished_at"].is_null())
+            .await;
+    }
+
+    let (_, resumed) = app.post("/api/download/resume-all", json!(null)).await;
+    for url in &urls {
+        assert_eq!(resumed[url]["status"], "Queued");
+        app.wait_for(url, |download| download["finished_at"].is_null())
+            .await;
+    }
+
+    let (_, canceled) = app.post("/api/download/cancel-all", json!(null)).await;
+    for url in &urls {
+        assert_eq!(canceled[url]["status"], "Canceled");
+        app.wait_for_status(url, "Canceled").await;
+    }
+}
+
+#[tokio::test]
+async fn exported_queue_imports_into_another_instance() {
+    let app = TestApp::spawn().await;
+    for name in ["busy-1", "busy-2"] {
+        let url = fake_url(name, "steps=200&delay=0.05");
+        assert_eq!(app.submit(&url, name).await, StatusCode::CREATED);
+        app.wait_for_status(&url, "Running").await;
+    }
+    let queued: Vec<String> = ["a", "b"]
+        .iter()
+        .map(|name| fake_url(name, "steps=1"))
+        .collect();
+    for (url, name) in queued.iter().zip(["a", "b"]) {
+        assert_eq!(app.submit(url, name).await, StatusCode::CREATED);
+        app.wait_for(url, |download| !download["queue_position"].is_null())
+            .await;
+    }
+
+    // Running downloads aren't pending, only the two waiting behind them are exported.
+    let (status, export) = app.get("/api/download/queue/export").await;
+    assert_eq!(status, StatusCode::OK);
+    let urls: Vec<&str> = export
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|request| request["url"].as_str())
+        .collect();
+    assert_eq!(urls, [&queued[0], &queued[1]]);
+    assert_eq!(export[1]["options"]["name_format"], "b");
+
+    let other = TestApp::spawn().await;
+    let (status, results) = other.post("/api/download/queue/import", export).await;
+    assert_eq!(status, StatusCode::OK);
+    for (result, url) in results.as_array().unwrap().iter().zip(&queued) {
+        assert_eq!(result["accepted"], true, "{}", url);
+        other.wait_for_status(url, "Completed").await;
+    }
+}
+
+#[tokio::test]
+async fn downloads_wait_for_their_domain_window_to_open() {
+    let app = TestApp::spawn().await;
+    let now = Utc::now();
+    let closed = json!({
+        "domain": "fake.test",
+        "start": (now + TimeDelta::hours(2)).format("%H:%M:%S").to_string(),
+        "end": (now + TimeDelta::hours(3)).format("%H:%M:%S").to_string(),
+    });
+    let (status, _) = app
+        .post(
+            "/api/config/download-windows",
+            json!({ "windows": [closed] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let url = fake_url("night-only", "steps=1");
+    assert_eq!(app.submit(&url, "night-only").await, StatusCode::CREATED);
+    let download = app
+        .wait_for(&url, |download| !download["queue_position"].is_null())
+        .await;
+    assert_eq!(download["status"], "Queued");
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let download = app.wait_for_status(&url, "Queued").await;
+    assert_eq!(download["queue_position"], 0);
+
+    // Opening the window lets it through without waiting for the next check.
+    let open = json!({ "domain": "fake.test", "start": "00:00:00", "end": "00:00:00" });
+    let (status, _) = app
+        .post("/api/config/download-windows", json!({ "windows": [open] }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    app.wait_for_status(&url, "Completed").await;
+
+    let bad = json!({ "domain": "https://fake.test", "start": "01:00:00", "end": "06:00:00" });
+    let (status, _) = app
+        .post("/api/config/download-windows", json!({ "windows": [bad] }))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn downloads_wait_for_space_other_downloads_set_aside() {
+    let app = TestApp::spawn().await;
+    let free = fs4::available_space(&app.download_dir).unwrap();
+
+    let too_big = fake_url("too-big", &format!("size={}", free * 2));
+    assert_eq!(
+        app.submit(&too_big, "too-big").await,
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+
+    // Each fits on its own, not both at once.
+    let size = free / 10 * 6;
+    let first = fake_url("first", &format!("size={}&steps=200&delay=0.05", size));
+    let second = fake_url("second", &format!("size={}&steps=1", size));
+    assert_eq!(app.submit(&first, "first").await, StatusCode::CREATED);
+    app.wait_for_status(&first, "Running").await;
+    assert_eq!(app.submit(&second, "second").await, StatusCode::CREATED);
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let (_, downloads) = app.get("/api/download").await;
+    let waiting = downloads
+        .as_array()
+        .and_then(|downloads| downloads.iter().find(|download| download["url"] == second))
+        .unwrap();
+    assert_eq!(waiting["status"], "Queued");
+
+    app.post("/api/download/cancel", json!(first)).await;
+    app.wait_for_status(&second, "Completed").await;
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{fake_url, TestApp};
+use serde_json::json;
+
+#[tokio::test]
+async fn schedule_runs_skip_fetched_items() {
+    let app = TestApp::spawn().await;
+    let url = fake_url("recurring", "steps=1");
+    let options = json!({ "container": "mp4", "name_format": "recurring", "quality": "720" });
+
+    let (status, error) = app
+        .post(
+            "/api/schedule",
+            json!({ "url": url, "cron": "every day", "options": options }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["key"], "schedule.invalid_cron");
+
+    let (status, schedule) = app
+        .post(
+            "/api/schedule",
+            json!({ "url": url, "cron": "0 3 * * *", "options": options }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(schedule["next_run"]["utc"].is_string());
+
+    let run = format!("/api/schedule/{}/run", schedule["id"]);
+    let (status, result) = app.post(&run, json!(null)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["enqueued"], 1);
+    app.wait_for_status(&url, "Completed").await;
+
+    let (_, result) = app.post(&run, json!(null)).await;
+    assert_eq!(result["enqueued"], 0);
+}
+
+#[tokio::test]
+async fn background_jobs_can_be_paused_and_run_on_demand() {
+    let app = TestApp::spawn().await;
+
+    let (status, jobs) = app.get("/api/jobs").await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<_> = jobs
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|job| job["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["history-retention", "log-retention", "schedules"]);
+
+    let (status, job) = app
+        .post("/api/jobs/history-retention/pause", json!(null))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(job["paused"], true);
+    assert!(job["next_run_at"].is_null());
+    let last_run_at = job["last_run_at"].clone();
+
+    // Paused jobs can still be run by hand.
+    let (status, _) = app
+        .post("/api/jobs/history-retention/run", json!(null))
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    loop {
+        let (_, job) = app.get("/api/jobs/history-retention").await;
+        if job["last_run_at"] != last_run_at && job["running"] == false {
+            assert_eq!(job["paused"], true);
+            assert!(job["last_error"].is_null());
+            break;
+        }
+        assert!(
+            std::time::Instant::now() < deadline,
+            "job never ran: {}",
+            job
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    let (_, job) = app
+        .post("/api/jobs/history-retention/resume", json!(null))
+        .await;
+    assert_eq!(job["paused"], false);
+    assert!(job["next_run_at"]["utc"].is_string());
+
+    let (status, error) = app.post("/api/jobs/missing/run", json!(null)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error["key"], "job.unknown");
+}
+
+#[tokio::test]
+async fn failing_job_runs_are_recorded() {
+    let app = TestApp::spawn().await;
+    // Lets the run made at startup finish first, so it can't hold up the ones asked for below.
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    loop {
+        let (_, job) = app.get("/api/jobs/schedules").await;
+        if job["last_run_at"].is_object() && job["running"] == false {
+            break;
+        }
+        assert!(std::time::Instant::now() < deadline, "{}", job);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let options = json!({ "container": "mp4", "name_format": "broken", "quality": "720" });
+    let (status, _) = app
+        .post(
+            "/api/schedule",
+            json!({
+                "url": fake_url("broken", "unavailable=1"),
+                "cron": "0 3 * * *",
+                "options": options,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let db = sqlx::SqlitePool::connect(&app.db_url()).await.unwrap();
+    for run in 1..=3 {
+        // Backdated so the schedule is due on the next poll.
+        sqlx::query(
+            "UPDATE Schedule SET created_at = '2000-01-01T00:00:00+00:00', last_run_at = NULL",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        let (status, _) = app.post("/api/jobs/schedules/run", json!(null)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            let (_, job) = app.get("/api/jobs/schedules").await;
+            if job["consecutive_failures"] == run && job["running"] == false {
+                assert_eq!(job["last_error"], "1 schedules failed to run");
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "{}", job);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+    }
+
+    let (_, runs) = app.get("/api/jobs/schedules/runs?limit=3").await;
+    let runs = runs.as_array().unwrap();
+    assert_eq!(runs.len(), 3);
+    assert!(runs.iter().all(|run| run["succeeded"] == false));
+    assert!(runs[0]["duration_ms"].as_i64().unwrap() >= 0);
+
+    // The runs outlive a restart.
+    let app = app.restart().await;
+    let (_, runs) = app.get("/api/jobs/schedules/runs").await;
+    let failed = runs
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|run| run["succeeded"] == false)
+        .count();
+    assert_eq!(failed, 3, "{}", runs);
+}
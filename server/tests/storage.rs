@@ -0,0 +1,72 @@
+use server::core::storage;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn touch(dir: &TempDir, names: &[&str]) {
+    for name in names {
+        fs::write(dir.path().join(name), "data").unwrap();
+    }
+}
+
+fn names(mut files: Vec<PathBuf>) -> Vec<String> {
+    files.sort();
+    files
+        .iter()
+        .filter_map(|file| Some(file.file_name()?.to_string_lossy().into_owned()))
+        .collect()
+}
+
+#[test]
+fn sidecars_are_only_files_named_after_the_file() {
+    let dir = TempDir::new().unwrap();
+    touch(
+        &dir,
+        &[
+            "clip.mp4",
+            "clip.en.vtt",
+            "clip.info.json",
+            "clip.f137.mp4.part",
+            "clip-2.mp4",
+            "clip-2.info.json",
+            "clip (1).webm",
+            "clipped.mp4",
+        ],
+    );
+
+    let sidecars = storage::sidecar_files(&dir.path().join("clip.mp4"));
+    assert_eq!(names(sidecars), ["clip.en.vtt", "clip.info.json"]);
+}
+
+#[tokio::test]
+async fn removing_files_reports_the_ones_that_were_there() {
+    let dir = TempDir::new().unwrap();
+    touch(&dir, &["kept.mp4", "removed.mp4"]);
+
+    let removed = storage::remove_files(vec![
+        dir.path().join("removed.mp4"),
+        dir.path().join("missing.mp4"),
+    ])
+    .await;
+    assert_eq!(names(removed), ["removed.mp4"]);
+    assert!(dir.path().join("kept.mp4").exists());
+    assert!(!dir.path().join("removed.mp4").exists());
+}
+
+#[tokio::test]
+async fn work_dirs_are_removed_with_what_is_left_in_them() {
+    let dir = TempDir::new().unwrap();
+    let work_dir = storage::work_dir(dir.path(), "abc");
+    let other = storage::work_dir(dir.path(), "def");
+    for work_dir in [&work_dir, &other] {
+        fs::create_dir_all(work_dir).unwrap();
+        fs::write(work_dir.join("clip.f137.mp4.part"), "data").unwrap();
+    }
+    assert!(work_dir.starts_with(dir.path()));
+
+    storage::remove_work_dir(dir.path(), "abc").await;
+    assert!(!work_dir.exists());
+    assert!(other.join("clip.f137.mp4.part").exists());
+    // Already gone is fine.
+    storage::remove_work_dir(dir.path(), "abc").await;
+}